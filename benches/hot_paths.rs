@@ -0,0 +1,182 @@
+//! Benchmarks of the work done on every keystroke, on a generated file of 12k lines.
+//!
+//! The backend is driven through its `LanguageServer` methods, without a transport, so
+//! the numbers are those of the handlers: `on_change` through `didOpen`, which compiles
+//! the document and publishes its diagnostics, the semantic tokens of a version that
+//! hasn't been tokenized yet, and the completion of a field access.
+//!
+//! Run them with `cargo bench --bench hot_paths`, and compare before and after a change
+//! with `-- --save-baseline` and `-- --baseline`.
+
+use std::cell::Cell;
+use std::fmt::Write;
+use std::hint::black_box;
+use std::sync::Arc;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use futures_util::{SinkExt, StreamExt};
+use l_language_server::{Backend, Lifecycle, Metrics};
+use tokio::runtime::Runtime;
+use tower_lsp_server::jsonrpc::Response;
+use tower_lsp_server::ls_types::{
+    CompletionParams, DidOpenTextDocumentParams, InitializeParams, PartialResultParams, Position,
+    SemanticTokensParams, TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams,
+    Uri, WorkDoneProgressParams,
+};
+use tower_lsp_server::{LanguageServer, LspService};
+
+/// Number of functions of the generated file, six lines each.
+const FUNCTIONS: usize = 2000;
+
+/// Generate an L file of [`FUNCTIONS`] functions, each calling the previous one.
+fn generate() -> String {
+    let mut text = String::from("struct Point {\n    x: int,\n    y: int,\n}\n\n");
+    for i in 0..FUNCTIONS {
+        let result = match i.checked_sub(1) {
+            Some(previous) => format!("f{previous}(next, total)"),
+            None => "total".to_string(),
+        };
+        let _ = write!(
+            text,
+            "fn f{i}(p: Point, n: int) -> int {{\n    \
+                 let total = p.x + p.y + n;\n    \
+                 let next = Point {{ x: total, y: p.y }};\n    \
+                 return {result};\n\
+             }}\n\n"
+        );
+    }
+    text
+}
+
+/// Position right after the first `p.` following `needle`, in a file of ASCII text.
+fn position_after(text: &str, needle: &str) -> Position {
+    let start = text.find(needle).expect("the needle occurs in the text");
+    let offset = start + text[start..].find("p.x").expect("the function reads p.x") + 2;
+    let line = text[..offset].matches('\n').count();
+    let line_start = text[..offset].rfind('\n').map_or(0, |index| index + 1);
+    Position::new(
+        u32::try_from(line).expect("the line fits"),
+        u32::try_from(offset - line_start).expect("the column fits"),
+    )
+}
+
+/// A backend with the generated file, and the runtime it runs on.
+struct Fixture {
+    /// Runtime running the handlers and the client side of the connection
+    runtime: Runtime,
+    /// The service holding the backend
+    service: LspService<Backend>,
+    /// URI of the generated file
+    uri: Uri,
+    /// Text of the generated file
+    text: String,
+    /// Version of the last `didOpen`
+    version: Cell<i32>,
+}
+
+impl Fixture {
+    /// Create an initialized backend whose requests to the client are answered with
+    /// `null`.
+    fn new() -> Self {
+        let runtime = Runtime::new().expect("the runtime starts");
+        let (service, socket) = Backend::service(|client| {
+            Backend::new(
+                client,
+                false,
+                Arc::new(Lifecycle::default()),
+                Arc::new(Metrics::default()),
+            )
+        });
+        runtime.spawn(async move {
+            let (mut requests, mut responses) = socket.split();
+            while let Some(request) = requests.next().await {
+                if let Some(id) = request.id() {
+                    let response = Response::from_ok(id.clone(), serde_json::Value::Null);
+                    if responses.send(response).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        runtime
+            .block_on(service.inner().initialize(InitializeParams::default()))
+            .expect("the backend initializes");
+        let fixture = Self {
+            runtime,
+            service,
+            uri: "file:///bench/generated.l"
+                .parse()
+                .expect("the URI is valid"),
+            text: generate(),
+            version: Cell::new(0),
+        };
+        fixture.open();
+        fixture
+    }
+
+    /// Open a new version of the generated file, compiling it.
+    fn open(&self) {
+        self.version.set(self.version.get() + 1);
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                self.uri.clone(),
+                "l".to_string(),
+                self.version.get(),
+                self.text.clone(),
+            ),
+        };
+        self.runtime.block_on(self.service.inner().did_open(params));
+    }
+}
+
+fn on_change(c: &mut Criterion) {
+    let fixture = Fixture::new();
+    c.bench_function("on_change", |b| b.iter(|| fixture.open()));
+}
+
+fn semantic_tokens(c: &mut Criterion) {
+    let fixture = Fixture::new();
+    c.bench_function("build_semantic_tokens", |b| {
+        // The tokens are cached per version, so each run gets a new one
+        b.iter_batched(
+            || fixture.open(),
+            |()| {
+                let params = SemanticTokensParams {
+                    work_done_progress_params: WorkDoneProgressParams::default(),
+                    partial_result_params: PartialResultParams::default(),
+                    text_document: TextDocumentIdentifier::new(fixture.uri.clone()),
+                };
+                let tokens = fixture
+                    .runtime
+                    .block_on(fixture.service.inner().semantic_tokens_full(params));
+                black_box(tokens)
+            },
+            BatchSize::PerIteration,
+        );
+    });
+}
+
+fn completion(c: &mut Criterion) {
+    let fixture = Fixture::new();
+    let position = position_after(&fixture.text, &format!("fn f{}(", FUNCTIONS / 2));
+    c.bench_function("get_completion", |b| {
+        b.iter(|| {
+            let params = CompletionParams {
+                text_document_position: TextDocumentPositionParams::new(
+                    TextDocumentIdentifier::new(fixture.uri.clone()),
+                    position,
+                ),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+                context: None,
+            };
+            let items = fixture
+                .runtime
+                .block_on(fixture.service.inner().completion(params));
+            black_box(items)
+        });
+    });
+}
+
+criterion_group!(benches, on_change, semantic_tokens, completion);
+criterion_main!(benches);
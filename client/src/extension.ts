@@ -22,6 +22,7 @@ import {
   StatusBarAlignment,
   StatusBarItem,
   ThemeColor,
+  Uri,
   window,
   workspace,
 } from "vscode";
@@ -32,6 +33,7 @@ import {
   Executable,
   LanguageClient,
   LanguageClientOptions,
+  Location,
   ServerOptions,
   State,
 } from "vscode-languageclient/node";
@@ -97,6 +99,19 @@ export async function activate(context: ExtensionContext) {
   const config = workspace.getConfiguration("l-language-server");
   const maxProblems = config.get<number>("maxNumberOfProblems", 100);
   const customServerPath = config.get<string>("serverPath", "");
+  const stdlibPath = config.get<string>("stdlibPath", "");
+  const grammarPath = config.get<string>("grammarPath", "");
+  const debounceMs = config.get<number>("debounceMs", 200);
+  const memoryBudgetMb = config.get<number>("memoryBudgetMb", 256);
+  const keepWorkspaceDiagnostics = config.get<boolean>("keepWorkspaceDiagnostics", true);
+  const lockAuditMessages = config.get<boolean>("lockAuditMessages", false);
+  const disabledCompletionProviders = config.get<string[]>("disabledCompletionProviders", []);
+  const hideMatchingParameterHints = config.get<boolean>("hideMatchingParameterHints", true);
+  const variableTypeHints = config.get<boolean>("variableTypeHints", true);
+  const returnTypeHints = config.get<boolean>("returnTypeHints", true);
+  const formatWidth = config.get<number>("formatWidth", 80);
+  const diagnosticSeverity = config.get<Record<string, string>>("diagnosticSeverity", {});
+  const disabledFeatures = config.get<string[]>("disabledFeatures", []);
 
   // Try to locate the server executable
   let serverCommand: string | undefined;
@@ -203,6 +218,7 @@ export async function activate(context: ExtensionContext) {
   // Configure the server executable
   const run: Executable = {
     command: serverCommand,
+    args: ["serve"],
     options: {
       env: {
         ...process.env,
@@ -222,10 +238,9 @@ export async function activate(context: ExtensionContext) {
     // Register the server for plain text documents with the 'l' language
     documentSelector: [{ scheme: "file", language: "l" }],
 
-    // Synchronize configuration and file changes with the server
+    // Synchronize configuration changes with the server. File watchers for `.l` files
+    // are registered dynamically by the server itself.
     synchronize: {
-      // Notify the server about file changes to '.l files contained in the workspace
-      fileEvents: workspace.createFileSystemWatcher("**/*.l"),
       // Notify the server about configuration changes
       configurationSection: "l-language-server",
     },
@@ -236,6 +251,22 @@ export async function activate(context: ExtensionContext) {
     // Initialization options for the server
     initializationOptions: {
       maxProblems,
+      stdlibPath,
+      grammarPath,
+      debounceMs,
+      memoryBudgetMb,
+      keepWorkspaceDiagnostics,
+      lockAuditMessages,
+      disabledCompletionProviders,
+      hideMatchingParameterHints,
+      variableTypeHints,
+      returnTypeHints,
+      formatWidth,
+      diagnosticSeverity,
+      disabledFeatures,
+      // Enabled opt-in analyses and the symbol index are remembered per workspace below
+      // this directory
+      cacheDirectory: context.globalStorageUri.fsPath,
     },
 
     // Error handling and reconnection options
@@ -289,6 +320,36 @@ export async function activate(context: ExtensionContext) {
     commands.registerCommand("l-language.restartServer", async () => {
       await restartServer();
     }),
+    commands.registerCommand("l-language.openExample", openExample),
+    commands.registerCommand("l-language.gotoFirstError", gotoFirstError),
+    commands.registerCommand("l-language.whatsNew", showWhatsNew),
+    commands.registerCommand("l-language.completionStats", showCompletionStats),
+    commands.registerCommand("l-language.serverStatus", showServerStatus),
+  );
+
+  // Offer to show what a newer server version added after an upgrade
+  context.subscriptions.push(
+    client.onNotification("l/serverUpgraded", async (whatsNew: WhatsNew) => {
+      const choice = await window.showInformationMessage(
+        `L Language Server was upgraded from ${whatsNew.previousVersion} to ${whatsNew.version}`,
+        "What's New",
+      );
+      if (choice) {
+        showWhatsNew(whatsNew);
+      }
+    }),
+  );
+
+  // Serve virtual documents generated by the server, such as AST dumps
+  context.subscriptions.push(
+    workspace.registerTextDocumentContentProvider("l-ast", {
+      provideTextDocumentContent: async (uri) => {
+        const content = await client.sendRequest<string | null>("l/virtualDocument", {
+          uri: uri.toString(),
+        });
+        return content ?? "";
+      },
+    }),
   );
 
   // Start the language client
@@ -402,6 +463,27 @@ function handleConfigurationChange(event: ConfigurationChangeEvent) {
       return;
     }
 
+    // The server reloads the stdlib and grammar itself when their paths change, and
+    // picks up a new debounce delay, diagnostics retention, completion providers and
+    // parameter hints on its own
+    if (
+      event.affectsConfiguration("l-language-server.stdlibPath") ||
+      event.affectsConfiguration("l-language-server.grammarPath") ||
+      event.affectsConfiguration("l-language-server.debounceMs") ||
+      event.affectsConfiguration("l-language-server.memoryBudgetMb") ||
+      event.affectsConfiguration("l-language-server.keepWorkspaceDiagnostics") ||
+      event.affectsConfiguration("l-language-server.disabledCompletionProviders") ||
+      event.affectsConfiguration("l-language-server.hideMatchingParameterHints") ||
+      event.affectsConfiguration("l-language-server.variableTypeHints") ||
+      event.affectsConfiguration("l-language-server.returnTypeHints") ||
+      event.affectsConfiguration("l-language-server.formatWidth") ||
+      event.affectsConfiguration("l-language-server.diagnosticSeverity") ||
+      event.affectsConfiguration("l-language-server.disabledFeatures")
+    ) {
+      outputChannel.appendLine("[INFO] Server-managed setting changed, no restart needed");
+      return;
+    }
+
     outputChannel.appendLine("[INFO] Configuration changed, scheduling server restart");
 
     // Clear existing timer if any
@@ -417,6 +499,216 @@ function handleConfigurationChange(event: ConfigurationChangeEvent) {
   }
 }
 
+/**
+ * An example program bundled with the server.
+ */
+interface Example {
+  name: string;
+  description: string;
+}
+
+/**
+ * Let the user pick a bundled example and open it as a new file.
+ */
+async function openExample() {
+  if (!client || client.state !== State.Running) {
+    window.showWarningMessage("The L Language Server is not running");
+    return;
+  }
+
+  const examples = await commands.executeCommand<Example[]>("l.browseExamples");
+  const picked = await window.showQuickPick(
+    (examples ?? []).map((example) => ({
+      label: example.name,
+      detail: example.description,
+    })),
+    { placeHolder: "Select an example to open" },
+  );
+  if (!picked) {
+    return;
+  }
+
+  const result = await commands.executeCommand<{ uri: string; applied: boolean }>(
+    "l.openExample",
+    { name: picked.label },
+  );
+  if (result?.applied) {
+    await window.showTextDocument(Uri.parse(result.uri));
+  }
+}
+
+/**
+ * Jump to the first error of the active document, or of the workspace if the active
+ * document has none.
+ */
+async function gotoFirstError() {
+  if (!client || client.state !== State.Running) {
+    window.showWarningMessage("The L Language Server is not running");
+    return;
+  }
+
+  const active = window.activeTextEditor?.document;
+  let location: Location | null | undefined;
+  if (active?.languageId === "l") {
+    location = await commands.executeCommand<Location | null>("l.gotoFirstError", {
+      uri: active.uri.toString(),
+    });
+  }
+  location ??= await commands.executeCommand<Location | null>("l.gotoFirstError");
+  if (!location) {
+    window.showInformationMessage("No errors found");
+    return;
+  }
+
+  await window.showTextDocument(Uri.parse(location.uri), {
+    selection: client.protocol2CodeConverter.asRange(location.range),
+  });
+}
+
+/**
+ * What an upgrade of the server added.
+ */
+interface WhatsNew {
+  previousVersion: string;
+  version: string;
+  capabilities: string[];
+  commands: string[];
+  settings: string[];
+}
+
+/**
+ * List what the last upgrade of the server added in the output channel.
+ */
+async function showWhatsNew(whatsNew?: WhatsNew) {
+  if (!whatsNew) {
+    if (!client || client.state !== State.Running) {
+      window.showWarningMessage("The L Language Server is not running");
+      return;
+    }
+    whatsNew = (await commands.executeCommand<WhatsNew | null>("l.whatsNew")) ?? undefined;
+  }
+  if (!whatsNew) {
+    window.showInformationMessage("The L Language Server hasn't been upgraded yet");
+    return;
+  }
+
+  const section = (title: string, items: string[]) =>
+    items.length > 0 ? [`${title}:`, ...items.map((item) => `  ${item}`)] : [];
+  outputChannel.appendLine(
+    [
+      `What's new in L Language Server ${whatsNew.version} (since ${whatsNew.previousVersion})`,
+      ...section("Capabilities", whatsNew.capabilities),
+      ...section("Commands", whatsNew.commands),
+      ...section("Settings", whatsNew.settings.map((key) => `l-language-server.${key}`)),
+    ].join("\n"),
+  );
+  outputChannel.show(true);
+}
+
+/**
+ * Timing statistics of a completion provider of the server.
+ */
+interface ProviderStats {
+  provider: string;
+  runs: number;
+  skipped: number;
+  averageMs: number;
+  maxMs: number;
+}
+
+/**
+ * List how long each completion provider of the server takes in the output channel.
+ */
+async function showCompletionStats() {
+  if (!client || client.state !== State.Running) {
+    window.showWarningMessage("The L Language Server is not running");
+    return;
+  }
+  const stats = (await commands.executeCommand<ProviderStats[]>("l.completionStats")) ?? [];
+  outputChannel.appendLine(
+    [
+      "Completion providers:",
+      ...stats.map(
+        (provider) =>
+          `  ${provider.provider}: ${provider.runs} runs, ${provider.skipped} skipped, ` +
+          `${provider.averageMs.toFixed(2)} ms average, ${provider.maxMs.toFixed(2)} ms max`,
+      ),
+    ].join("\n"),
+  );
+  outputChannel.show(true);
+}
+
+/**
+ * Timing statistics of an operation of the server.
+ */
+interface TimingStats {
+  name: string;
+  count: number;
+  averageMs: number;
+  maxMs: number;
+}
+
+/**
+ * What the documents stored by the server take, against its memory budget.
+ */
+interface MemoryStats {
+  budgetBytes: number;
+  pinnedDocuments: number;
+  closedDocuments: number;
+  closedBytes: number;
+  evicted: number;
+  indexedFiles: number;
+}
+
+/**
+ * Status of the server, as returned by `l.serverStatus`.
+ */
+interface ServerStatus {
+  version: string;
+  uptimeMs: number;
+  memoryBytes: number | null;
+  documents: number;
+  memory: MemoryStats;
+  requests: TimingStats[];
+  compile: TimingStats;
+}
+
+/**
+ * Show the memory, documents and timings of the server in the output channel.
+ */
+async function showServerStatus() {
+  if (!client || client.state !== State.Running) {
+    window.showWarningMessage("The L Language Server is not running");
+    return;
+  }
+  const status = await commands.executeCommand<ServerStatus>("l.serverStatus");
+  if (!status) {
+    return;
+  }
+  const timing = (stats: TimingStats) =>
+    `  ${stats.name}: ${stats.count} runs, ${stats.averageMs.toFixed(2)} ms average, ` +
+    `${stats.maxMs.toFixed(2)} ms max`;
+  const mebibytes = (bytes: number) => `${(bytes / 1048576).toFixed(1)} MiB`;
+  const memory = status.memoryBytes === null ? "unknown" : mebibytes(status.memoryBytes);
+  const budget = status.memory;
+  outputChannel.appendLine(
+    [
+      `L Language Server ${status.version}, up for ${Math.round(status.uptimeMs / 1000)} s`,
+      `Memory: ${memory}`,
+      `Documents: ${status.documents}`,
+      `  Open or stdlib: ${budget.pinnedDocuments}`,
+      `  Closed: ${budget.closedDocuments}, ${mebibytes(budget.closedBytes)} of ` +
+        `${mebibytes(budget.budgetBytes)} budget, ${budget.evicted} evicted`,
+      `  Indexed files: ${budget.indexedFiles}`,
+      "Compilation:",
+      timing(status.compile),
+      "Requests:",
+      ...status.requests.map(timing),
+    ].join("\n"),
+  );
+  outputChannel.show(true);
+}
+
 /**
  * Restart the language server.
  */
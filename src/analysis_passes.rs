@@ -0,0 +1,243 @@
+//! Opt-in analysis passes run on demand through the `l/runAnalysis` request.
+//!
+//! These passes are too expensive or too noisy to run on every keystroke, so they are
+//! not part of the diagnostics published by `on_change`. Each pass reports findings as
+//! byte spans into the document text, which the backend converts to diagnostics.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use l_lang::SymbolKind;
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::ls_types::{Diagnostic, TextDocumentIdentifier, Uri};
+
+use crate::document_store::Document;
+use crate::lexer::tokenize;
+
+/// Name of the custom request running a single analysis pass.
+pub const RUN_ANALYSIS_METHOD: &str = "l/runAnalysis";
+
+/// Cyclomatic complexity above which a function is reported.
+const COMPLEXITY_THRESHOLD: usize = 10;
+
+/// Minimum number of consecutive significant lines reported as duplicated code.
+const MIN_DUPLICATE_LINES: usize = 4;
+
+/// An analysis pass that can be run on demand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnalysisPass {
+    /// Symbols that are never referenced
+    DeadCode,
+    /// Functions with too many branches
+    Complexity,
+    /// Repeated blocks of code
+    Duplicates,
+}
+
+impl AnalysisPass {
+    /// The name of the pass, as used in requests and diagnostic codes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DeadCode => "deadCode",
+            Self::Complexity => "complexity",
+            Self::Duplicates => "duplicates",
+        }
+    }
+
+    /// Run the pass on a single document.
+    pub fn run(self, document: &Document) -> Vec<Finding> {
+        match self {
+            Self::DeadCode => dead_code(document),
+            Self::Complexity => complexity(&document.rope.to_string()),
+            Self::Duplicates => duplicates(&document.rope.to_string()),
+        }
+    }
+}
+
+/// The documents an analysis pass runs on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnalysisScope {
+    /// Only the given document
+    #[default]
+    File,
+    /// Every document known to the server
+    Workspace,
+}
+
+/// Parameters of the `l/runAnalysis` request.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunAnalysisParams {
+    /// The pass to run
+    pub pass: AnalysisPass,
+    /// The documents to run the pass on
+    #[serde(default)]
+    pub scope: AnalysisScope,
+    /// The document to analyze, required for the file scope
+    pub text_document: Option<TextDocumentIdentifier>,
+}
+
+/// Diagnostics reported by an analysis pass for a single document.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentAnalysis {
+    /// The analyzed document
+    pub uri: Uri,
+    /// Diagnostics reported by the pass
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Result of the `l/runAnalysis` request.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunAnalysisResult {
+    /// The pass that was run
+    pub pass: AnalysisPass,
+    /// Diagnostics of every analyzed document
+    pub documents: Vec<DocumentAnalysis>,
+}
+
+/// A single issue reported by an analysis pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Byte range of the issue in the document text
+    pub span: Range<usize>,
+    /// Human-readable description of the issue
+    pub message: String,
+}
+
+/// Report functions, variables, parameters and structs that are never referenced.
+fn dead_code(document: &Document) -> Vec<Finding> {
+    let semantic = &document.analysis.semantic;
+    semantic
+        .symbol_spans
+        .iter_enumerated()
+        .filter_map(|(symbol_id, span)| {
+            let kind = match semantic.get_symbol_kind(symbol_id) {
+                SymbolKind::Function => "function",
+                SymbolKind::Variable => "variable",
+                SymbolKind::Parameter => "parameter",
+                SymbolKind::Struct => "struct",
+                SymbolKind::Field => return None,
+            };
+            let range = span.start as usize..span.end as usize;
+            let name = document.symbol_name(symbol_id)?;
+            // `main` is the entry point and unused parameters are often intentional
+            // when prefixed with an underscore
+            if name == "main" || name.starts_with('_') {
+                return None;
+            }
+            if !semantic.get_symbol_references(symbol_id).is_empty() {
+                return None;
+            }
+            Some(Finding {
+                span: range,
+                message: format!("{kind} `{name}` is never used"),
+            })
+        })
+        .collect()
+}
+
+/// Report functions whose cyclomatic complexity exceeds [`COMPLEXITY_THRESHOLD`].
+///
+/// The complexity is computed from the tokens of the function body: one plus the
+/// number of `if` branches and short-circuiting operators.
+fn complexity(text: &str) -> Vec<Finding> {
+    let tokens = tokenize(text);
+    let mut findings = Vec::new();
+
+    let mut index = 0;
+    while index < tokens.len() {
+        if tokens[index].text != "fn" {
+            index += 1;
+            continue;
+        }
+        let Some(name) = tokens.get(index + 1) else {
+            break;
+        };
+        let Some(body_start) = tokens[index..]
+            .iter()
+            .position(|token| token.text == "{")
+            .map(|offset| index + offset)
+        else {
+            break;
+        };
+
+        let mut depth = 0usize;
+        let mut branches = 0;
+        let mut end = tokens.len();
+        for (offset, token) in tokens[body_start..].iter().enumerate() {
+            match token.text {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = body_start + offset + 1;
+                        break;
+                    }
+                }
+                "if" | "&&" | "||" => branches += 1,
+                _ => {}
+            }
+        }
+
+        let complexity = 1 + branches;
+        if complexity > COMPLEXITY_THRESHOLD {
+            findings.push(Finding {
+                span: name.span.clone(),
+                message: format!(
+                    "function `{}` has a cyclomatic complexity of {complexity} (threshold {COMPLEXITY_THRESHOLD})",
+                    name.text
+                ),
+            });
+        }
+        index = end;
+    }
+    findings
+}
+
+/// Report blocks of at least [`MIN_DUPLICATE_LINES`] significant lines that repeat an
+/// earlier block of the document.
+///
+/// Lines are compared with surrounding whitespace removed; lines without any
+/// alphanumeric character (blank lines, lone braces) are ignored.
+fn duplicates(text: &str) -> Vec<Finding> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for (line_number, line) in text.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim();
+        if trimmed.chars().any(char::is_alphanumeric) {
+            let start = offset + (line.len() - line.trim_start().len());
+            lines.push((line_number, trimmed, start..start + trimmed.len()));
+        }
+        offset += line.len();
+    }
+
+    let mut first_seen: HashMap<Vec<&str>, usize> = HashMap::new();
+    let mut findings = Vec::new();
+    let mut index = 0;
+    while index + MIN_DUPLICATE_LINES <= lines.len() {
+        let window = &lines[index..index + MIN_DUPLICATE_LINES];
+        let key = window.iter().map(|(_, text, _)| *text).collect::<Vec<_>>();
+        match first_seen.get(&key) {
+            Some(&original) if original + MIN_DUPLICATE_LINES <= index => {
+                let (first_line, _, _) = lines[original];
+                let (last_line, _, _) = lines[original + MIN_DUPLICATE_LINES - 1];
+                findings.push(Finding {
+                    span: window[0].2.start..window[MIN_DUPLICATE_LINES - 1].2.end,
+                    message: format!("duplicate of lines {}-{}", first_line + 1, last_line + 1),
+                });
+                // Don't report every overlapping window of the same duplicated block
+                index += MIN_DUPLICATE_LINES;
+            }
+            Some(_) => index += 1,
+            None => {
+                first_seen.insert(key, index);
+                index += 1;
+            }
+        }
+    }
+    findings
+}
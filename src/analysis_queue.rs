@@ -0,0 +1,167 @@
+//! Per-document workers compiling the changes of documents.
+//!
+//! Each document is compiled by a worker task of its own, which takes the changes of
+//! the document in the order they were made. Changes that queue up while a compile runs
+//! are superseded by the latest one: only that one is compiled, and the others are
+//! answered with `None` so that their handlers stop without publishing anything. A burst
+//! of edits to a large file thus costs one compile after the running one, instead of one
+//! per edit.
+//!
+//! Compiles run on the blocking thread pool, so different documents are analyzed in
+//! parallel, and handlers of other requests keep running meanwhile. Workers live as
+//! long as their document is stored, see [`AnalysisQueue::close`].
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use dashmap::DashMap;
+use ropey::Rope;
+use tokio::sync::{mpsc, oneshot};
+use tower_lsp_server::ls_types::Uri;
+use tracing::debug;
+
+use crate::analyzer::{LLang, LanguageAnalyzer};
+use crate::document_store::{Document, normalize_uri};
+use crate::server_status::Metrics;
+
+/// A change of a document, waiting for its worker.
+#[derive(Debug)]
+struct Job {
+    /// The full text of the document
+    text: String,
+    /// The client-side version of the document, if known
+    version: Option<i32>,
+    /// Where the analyzed document is sent, or `None` once the change is superseded
+    reply: oneshot::Sender<Option<Document>>,
+}
+
+/// The analysis workers of the stored documents, keyed by normalized URI.
+#[derive(Debug)]
+pub struct AnalysisQueue {
+    /// The compiler, shared with the workers
+    analyzer: Arc<LLang>,
+    /// Where the compile times are recorded
+    metrics: Arc<Metrics>,
+    /// Queue of the worker of each document
+    workers: DashMap<Uri, mpsc::UnboundedSender<Job>>,
+}
+
+impl AnalysisQueue {
+    /// Create a queue compiling with `analyzer`, recording compile times in `metrics`.
+    pub fn new(analyzer: Arc<LLang>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            analyzer,
+            metrics,
+            workers: DashMap::new(),
+        }
+    }
+
+    /// Compile a version of a document, once the changes queued before it are done.
+    ///
+    /// Returns `None` if a later change to the document superseded this one before it
+    /// was compiled, or if the compiler panicked.
+    pub async fn analyze(&self, uri: &Uri, text: &str, version: Option<i32>) -> Option<Document> {
+        let (reply, analyzed) = oneshot::channel();
+        let mut job = Job {
+            text: text.to_string(),
+            version,
+            reply,
+        };
+        let uri = normalize_uri(uri).into_owned();
+        loop {
+            let worker = self
+                .workers
+                .entry(uri.clone())
+                .or_insert_with(|| self.spawn_worker())
+                .clone();
+            match worker.send(job) {
+                Ok(()) => break,
+                // The worker of a closed document ended while this change was sent
+                Err(mpsc::error::SendError(returned)) => {
+                    job = returned;
+                    self.workers
+                        .remove_if(&uri, |_, current| current.same_channel(&worker));
+                }
+            }
+        }
+        analyzed.await.ok().flatten()
+    }
+
+    /// Stop the worker of a document once its queued changes are done.
+    pub fn close(&self, uri: &Uri) {
+        self.workers.remove(normalize_uri(uri).as_ref());
+    }
+
+    /// Stop the workers of every document.
+    pub fn clear(&self) {
+        self.workers.clear();
+    }
+
+    /// Start the worker of a document, returning its queue.
+    fn spawn_worker(&self) -> mpsc::UnboundedSender<Job> {
+        let (queue, mut jobs) = mpsc::unbounded_channel::<Job>();
+        let analyzer = Arc::clone(&self.analyzer);
+        let metrics = Arc::clone(&self.metrics);
+        tokio::spawn(async move {
+            while let Some(mut job) = jobs.recv().await {
+                // Only the latest of the queued changes is worth compiling
+                while let Ok(newer) = jobs.try_recv() {
+                    let superseded = std::mem::replace(&mut job, newer);
+                    let _ = superseded.reply.send(None);
+                }
+                if job.reply.is_closed() {
+                    // The handler waiting for the change was cancelled
+                    continue;
+                }
+                let Job {
+                    text,
+                    version,
+                    reply,
+                } = job;
+                let analyzer = Arc::clone(&analyzer);
+                let metrics = Arc::clone(&metrics);
+                let compiled = tokio::task::spawn_blocking(move || {
+                    let started = Instant::now();
+                    let analysis = analyzer.compile(&text);
+                    metrics.record_compile(started.elapsed());
+                    Document::new(Rope::from_str(&text), analysis, version)
+                })
+                .await;
+                match compiled {
+                    Ok(document) => {
+                        let _ = reply.send(Some(document));
+                    }
+                    // The panic hook already logged the panic
+                    Err(err) => debug!("Compilation of version {version:?} failed: {err}"),
+                }
+            }
+        });
+        queue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::LLang;
+
+    #[tokio::test]
+    async fn queued_changes_are_superseded_by_the_latest() {
+        let queue = AnalysisQueue::new(Arc::new(LLang), Arc::new(Metrics::default()));
+        let uri = "file:///a.l".parse::<Uri>().expect("valid URI");
+        let (first, second, third) = tokio::join!(
+            queue.analyze(&uri, "fn a() {}", Some(1)),
+            queue.analyze(&uri, "fn b() {}", Some(2)),
+            queue.analyze(&uri, "fn c() {}", Some(3)),
+        );
+        assert!(first.is_none());
+        assert!(second.is_none());
+        let third = third.expect("the latest change is compiled");
+        assert_eq!(third.version, Some(3));
+        assert_eq!(third.rope.to_string(), "fn c() {}");
+
+        queue.close(&uri);
+        let reopened = queue.analyze(&uri, "fn d() {}", Some(1)).await;
+        assert_eq!(reopened.and_then(|document| document.version), Some(1));
+    }
+}
@@ -0,0 +1,152 @@
+//! The boundary between the server and the compiler of the language it serves.
+//!
+//! [`LanguageAnalyzer`] names the operations the plumbing of the server needs from a
+//! compiler: compiling a document, listing the symbols it defines, finding the
+//! references of a symbol, formatting it and collecting the spans of its semantic
+//! tokens. [`Backend`](crate::Backend) reaches `l_lang` through [`LLang`] for those.
+//!
+//! The backend isn't generic over the analyzer: the features specific to L, such as
+//! hover types, completion, inlay hints and the refactorings, read the semantic model
+//! of `l_lang` from the stored `CompileResult`. Porting the server to another language
+//! means an analyzer of its own and porting those features.
+
+use std::ops::Range;
+
+use l_lang::{CompileResult, SymbolKind, compile};
+
+use crate::format::format_source;
+use crate::semantic_tokens::TokenSpan;
+use crate::symbol_at::pick_symbol_at;
+
+/// A symbol defined in a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalyzedSymbol {
+    /// Byte span of the name at the definition
+    pub span: Range<usize>,
+    /// Index of the semantic token type of the symbol in the legend of the server
+    pub token_type: u32,
+}
+
+/// The definition of a symbol and the places it is referenced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolReferences {
+    /// Byte span of the name at the definition
+    pub definition: Range<usize>,
+    /// Byte spans of the references, in the order the compiler recorded them
+    pub references: Vec<Range<usize>>,
+}
+
+/// The compiler of a language, as used by the server.
+///
+/// Analyzers are shared by every handler and called from blocking tasks, so they keep
+/// no state between calls and leave caching to the document store.
+pub trait LanguageAnalyzer: Send + Sync + 'static {
+    /// The result of compiling a document
+    type Analysis: Send + Sync + 'static;
+
+    /// Compile the text of a document.
+    fn compile(&self, text: &str) -> Self::Analysis;
+
+    /// The symbols defined in a compiled document.
+    fn symbols(&self, analysis: &Self::Analysis) -> Vec<AnalyzedSymbol>;
+
+    /// The definition and references of the symbol defined or referenced at a byte
+    /// offset, if there is one.
+    fn references(&self, analysis: &Self::Analysis, offset: usize) -> Option<SymbolReferences>;
+
+    /// Format the text of a compiled document to fit in `width` columns.
+    fn format(&self, text: &str, analysis: &Self::Analysis, width: usize) -> String;
+
+    /// The semantic token spans of the symbols of a compiled document, at their
+    /// definitions and references.
+    ///
+    /// Keywords, literals, comments and operators are highlighted by the server from
+    /// the text, so they are left out.
+    fn tokens(&self, analysis: &Self::Analysis) -> Vec<TokenSpan>;
+}
+
+/// The analyzer of L, compiling with `l_lang`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LLang;
+
+impl LanguageAnalyzer for LLang {
+    type Analysis = CompileResult;
+
+    fn compile(&self, text: &str) -> CompileResult {
+        compile(text)
+    }
+
+    fn symbols(&self, analysis: &CompileResult) -> Vec<AnalyzedSymbol> {
+        let semantic = &analysis.semantic;
+        semantic
+            .symbol_spans
+            .iter_enumerated()
+            .map(|(symbol_id, span)| AnalyzedSymbol {
+                span: span.start as usize..span.end as usize,
+                token_type: symbol_kind_to_token_type(semantic.get_symbol_kind(symbol_id)),
+            })
+            .collect()
+    }
+
+    fn references(&self, analysis: &CompileResult, offset: usize) -> Option<SymbolReferences> {
+        let semantic = &analysis.semantic;
+        let symbol_id = pick_symbol_at(analysis, offset)?.symbol_id;
+        let definition = semantic.get_symbol_span(symbol_id);
+        let references = semantic
+            .get_symbol_references(symbol_id)
+            .into_iter()
+            .filter(|ref_id| *ref_id < semantic.reference_spans.len())
+            .map(|ref_id| {
+                let span = semantic.reference_spans[ref_id];
+                span.start as usize..span.end as usize
+            })
+            .collect();
+        Some(SymbolReferences {
+            definition: definition.start as usize..definition.end as usize,
+            references,
+        })
+    }
+
+    fn format(&self, text: &str, analysis: &CompileResult, width: usize) -> String {
+        format_source(text, analysis, width)
+    }
+
+    fn tokens(&self, analysis: &CompileResult) -> Vec<TokenSpan> {
+        let semantic = &analysis.semantic;
+        let definitions = self
+            .symbols(analysis)
+            .into_iter()
+            .map(|symbol| (symbol.span.start, symbol.span.len(), symbol.token_type));
+        // References take the kind of the symbol they resolve to
+        let references = semantic
+            .reference_spans
+            .iter_enumerated()
+            .filter_map(|(ref_id, span)| {
+                if ref_id >= semantic.references.len() {
+                    return None;
+                }
+                let symbol_id = semantic.references[ref_id]?;
+                let kind = semantic.get_symbol_kind(symbol_id);
+                Some((
+                    span.start as usize,
+                    (span.end - span.start) as usize,
+                    symbol_kind_to_token_type(kind),
+                ))
+            });
+        definitions.chain(references).collect()
+    }
+}
+
+/// Convert `SymbolKind` to semantic token type.
+///
+/// Token type indices correspond to `LEGEND_TYPE` order:
+/// 0: FUNCTION, 1: VARIABLE, 2: PARAMETER, 3: STRUCT, 4: PROPERTY (field)
+const fn symbol_kind_to_token_type(kind: SymbolKind) -> u32 {
+    match kind {
+        SymbolKind::Function => 0,
+        SymbolKind::Variable => 1,
+        SymbolKind::Parameter => 2,
+        SymbolKind::Struct => 3,
+        SymbolKind::Field => 4,
+    }
+}
@@ -0,0 +1,2159 @@
+//! The backend of a client connection: its state and the handlers of LSP messages.
+//!
+//! The [`LanguageServer`] implementation dispatches each message, and keeps the
+//! workspace, settings and command plumbing shared by every feature here. The language
+//! features themselves are implemented on [`Backend`] in the submodules, one per feature.
+
+mod code_actions;
+mod completion;
+mod diagnostics;
+mod formatting;
+mod inlay_hints;
+mod navigation;
+mod semantic_tokens;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use dashmap::{DashMap, DashSet};
+use ropey::Rope;
+use serde_json::Value;
+use tower_lsp_server::jsonrpc::{Error, Result};
+use tower_lsp_server::ls_types::notification::{DidChangeWatchedFiles, Notification};
+use tower_lsp_server::ls_types::{
+    ChangeAnnotation, ClientCapabilities, CodeAction, CodeActionKind, CodeActionOptions,
+    CodeActionOrCommand, CodeActionParams, CodeActionProviderCapability, CodeActionResponse,
+    CompletionItem, CompletionOptions, CompletionParams, CompletionResponse, ConfigurationItem,
+    CreateFile, DidChangeConfigurationParams, DidChangeTextDocumentParams,
+    DidChangeWatchedFilesParams, DidChangeWatchedFilesRegistrationOptions,
+    DidChangeWorkspaceFoldersParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DidSaveTextDocumentParams, DocumentFilter, DocumentFormattingParams, DocumentSelector,
+    ExecuteCommandOptions, ExecuteCommandParams, FileChangeType, FileOperationFilter,
+    FileOperationPattern, FileOperationPatternKind, FileOperationRegistrationOptions,
+    FileSystemWatcher, GlobPattern, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+    HoverParams, HoverProviderCapability, InitializeParams, InitializeResult, InitializedParams,
+    InlayHint, InlayHintLabel, InlayHintParams, InlayHintRegistrationOptions,
+    InlayHintServerCapabilities, InlayHintTooltip, Location, MarkupContent, MarkupKind,
+    MessageType, OneOf, Position, ProgressToken, Range, ReferenceParams, Registration,
+    RenameFilesParams, RenameParams, ResourceOp, ResourceOperationKind, SaveOptions,
+    SemanticTokens, SemanticTokensParams, SemanticTokensRangeParams, SemanticTokensRangeResult,
+    SemanticTokensResult, SemanticTokensServerCapabilities, ServerCapabilities, ShowDocumentParams,
+    SignatureHelp, SignatureHelpOptions, SignatureHelpParams, StaticRegistrationOptions,
+    TextDocumentRegistrationOptions, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextDocumentSyncOptions, TextDocumentSyncSaveOptions, TextEdit, Unregistration, Uri,
+    WorkDoneProgressOptions, WorkspaceEdit, WorkspaceFileOperationsServerCapabilities,
+    WorkspaceFoldersServerCapabilities, WorkspaceServerCapabilities, WorkspaceSymbolParams,
+    WorkspaceSymbolResponse,
+};
+use tower_lsp_server::{Client, ClientSocket, LanguageServer, LspService};
+use tracing::{debug, info};
+
+use self::diagnostics::TextDocumentChange;
+use self::formatting::apply_text_edits;
+use self::inlay_hints::inlay_hint_options;
+use self::semantic_tokens::{FULL_SEMANTIC_TOKENS_LIMIT, semantic_tokens_options};
+use crate::analysis_passes::{AnalysisPass, RUN_ANALYSIS_METHOD};
+use crate::analysis_queue::AnalysisQueue;
+use crate::analyzer::LLang;
+use crate::cancellation::run_cancellable;
+use crate::commands::Command;
+use crate::compat::ClientSupport;
+use crate::completion::{CompletionStats, resolve_item};
+use crate::debounce::Debouncer;
+use crate::diagnostics_history::DiagnosticsHistory;
+use crate::document_store::{DocSnapshot, DocumentStore, normalize_uri};
+use crate::document_text::{DOCUMENT_TEXT_METHOD, DocumentText, DocumentTextParams, content_hash};
+use crate::enabled_analyses::{EnabledAnalyses, cache_dir_from_settings};
+use crate::examples::{EXAMPLES, OpenExampleArgs, find_example};
+use crate::extract_function::EXTRACT_FUNCTION_KIND;
+use crate::extract_variable::EXTRACT_VARIABLE_KIND;
+use crate::feature_registration::{
+    DYNAMIC_FEATURES, registers_dynamically, registration_id, registration_method,
+};
+use crate::grammar::GrammarTable;
+use crate::lifecycle::Lifecycle;
+use crate::memory_budget::MemoryBudget;
+use crate::outgoing::OutgoingRequests;
+use crate::progress::ProgressReporter;
+use crate::project_config::{PROJECT_FILE, PROJECT_FILE_GLOB, Projects};
+use crate::refactor_journal::{JournalDocument, JournalEntry, RefactorJournal};
+use crate::scopes::SCOPES_METHOD;
+use crate::self_check::check_compiler;
+use crate::semantic_info::SEMANTIC_INFO_METHOD;
+use crate::server_meta::ServerMeta;
+use crate::server_status::{Metrics, SERVER_STATUS_METHOD, ServerStatus};
+use crate::settings::{Feature, SETTING_KEYS, SETTINGS_SECTION, ScopedSettings, Settings};
+use crate::signature_help::{RETRIGGER_CHARACTERS, TRIGGER_CHARACTERS, signature_help};
+use crate::stdlib::Stdlib;
+use crate::strict_protocol::{validate_position, validate_range};
+use crate::struct_constructor::GENERATE_CONSTRUCTOR_KIND;
+use crate::suggestions::{FIX_ALL_KIND, Suggestion, wants_kind};
+use crate::symbol_docs::{ResolveData, symbol_documentation};
+use crate::symbol_index::SymbolIndex;
+use crate::text_diff::unified_diff;
+use crate::text_pos::{Bounds, Encoding, TextPos};
+use crate::virtual_documents::{
+    VIRTUAL_DOCUMENT_METHOD, VirtualDocumentParams, VirtualDocuments, ast_uri,
+};
+use crate::whats_new::{CapabilitySet, ServerUpgraded, Upgrades};
+use crate::workspace_edit::{WorkspaceEditBuilder, text_edits};
+use crate::{collect_source_files, file_path_to_uri, uri_to_file_path};
+
+/// How long in-flight outgoing messages may take to settle during shutdown.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Registration id of the server-initiated watcher for L source files.
+const WATCHED_FILES_REGISTRATION_ID: &str = "l-watched-files";
+
+/// Glob pattern of the files watched for external changes.
+const WATCHED_FILES_GLOB: &str = "**/*.l";
+
+#[derive(Debug)]
+/// The backend implementation for the L language server.
+///
+/// This struct maintains the state of the language server, including:
+/// - Client connection for sending notifications and requests
+/// - Capabilities announced by the client during initialization
+/// - Workspace folders whose source files are indexed
+/// - The stdlib directory and the stdlib files loaded from it
+/// - The keyword table used by syntax-level features
+/// - Document store mapping URIs to their content and semantic analysis results
+/// - Diagnostics history used to compare against the last saved version
+/// - Settings of the client, and those it sent at initialization
+/// - Features registered dynamically, following the settings
+/// - Project configurations of `l.toml` files overriding the settings
+/// - Opt-in analysis passes enabled for the workspace
+/// - Timing of the completion providers
+/// - Virtual documents generated by the server, such as AST dumps
+/// - The set of documents currently open in the client
+/// - Documents whose inlay hints must be refreshed after a pending rename lands
+/// - Pending debounced recompilations
+/// - Per-document workers compiling changes in order
+/// - Journal of applied refactorings, used to undo them
+/// - In-flight outgoing requests and notifications to the client
+/// - What the last upgrade of the server added
+/// - Shutdown flag for graceful termination
+/// - Whether protocol violations are rejected instead of tolerated
+pub struct Backend {
+    /// The LSP client connection
+    client: Client,
+    /// The compiler of the language, shared with the blocking tasks of handlers
+    analyzer: Arc<LLang>,
+    /// Workers compiling the changes of each document in order
+    analysis_queue: AnalysisQueue,
+    /// Capabilities the client announced in the `initialize` request
+    client_capabilities: OnceLock<ClientCapabilities>,
+    /// Encoding of positions agreed on with the client in the `initialize` request
+    position_encoding: OnceLock<Encoding>,
+    /// Maps workspace folder URIs to their root directory on disk
+    workspace_folders: DashMap<Uri, PathBuf>,
+    /// Builtin/stdlib definitions loaded from an external directory
+    stdlib: Stdlib,
+    /// Keywords and other syntax-level data, reloadable at runtime
+    grammar: GrammarTable,
+    /// Maps document URIs to their text content and semantic analysis results
+    documents: DocumentStore,
+    /// Diagnostics published for each document, now and at its last save
+    diagnostics_history: DiagnosticsHistory,
+    /// Settings sent in `initializationOptions`, which later settings are merged over
+    initial_settings: OnceLock<Settings>,
+    /// Settings of the workspace and its folders, replaced as a whole when they change
+    settings: ArcSwap<ScopedSettings>,
+    /// Features registered with the client after initialization
+    registered_features: DashSet<Feature>,
+    /// Projects configured by `l.toml` files, found for the directories of documents
+    projects: Projects,
+    /// Analysis passes run on every change, remembered across sessions
+    enabled_analyses: EnabledAnalyses,
+    /// Size the documents of closed files may take, and what it evicted
+    memory_budget: MemoryBudget,
+    /// Symbols of the analyzed files, searched by `workspace/symbol` and remembered
+    /// across sessions
+    symbol_index: SymbolIndex,
+    /// Timing of the completion providers, returned by `l.completionStats`
+    completion_stats: CompletionStats,
+    /// Counts and latencies of the messages handled and compilations run, returned by
+    /// `l.serverStatus`
+    metrics: Arc<Metrics>,
+    /// In-memory documents served under server-specific URI schemes
+    virtual_documents: VirtualDocuments,
+    /// URIs of documents currently open in the client, whose buffer content takes
+    /// precedence over the file on disk
+    open_documents: DashSet<Uri>,
+    /// URIs of documents in which a parameter was renamed; their inlay hints are
+    /// refreshed once the client applied the rename edit
+    pending_hint_refresh: DashSet<Uri>,
+    /// Delays recompilation on change until the user stops typing
+    debouncer: Debouncer,
+    /// Refactorings handed to the client, most recent last
+    refactor_journal: RefactorJournal,
+    /// Outgoing traffic to the client, settled in order during shutdown
+    outgoing: OutgoingRequests,
+    /// What the last upgrade of the server added
+    upgrades: Upgrades,
+    /// Whether invalid positions and ranges are rejected instead of clamped
+    strict_protocol: bool,
+    /// Whether the client sent `shutdown`, set before the request reaches the handler
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl LanguageServer for Backend {
+    /// Initialize the language server.
+    ///
+    /// This method is called by the client when the server is first connected.
+    /// It returns the server capabilities, which inform the client about
+    /// which features the server supports.
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let _ = self.client_capabilities.set(params.capabilities);
+        if let Some(options) = &params.initialization_options {
+            let settings = Settings::from_value(options);
+            self.stdlib.set_root(settings.stdlib_path.clone());
+            self.grammar.set_path(settings.grammar_path.clone());
+            self.debouncer.set_delay(settings.debounce_delay);
+            self.memory_budget.set_budget(settings.memory_budget);
+            self.settings
+                .store(Arc::new(ScopedSettings::global(settings.clone())));
+            let _ = self.initial_settings.set(settings);
+            #[cfg(debug_assertions)]
+            if crate::lock_audit::lock_audit_messages_from_settings(options) {
+                self.documents
+                    .lock_audit()
+                    .set_client(Some(self.client.clone()));
+            }
+        }
+        if let Err(err) = self.grammar.reload() {
+            debug!("Using the embedded grammar: {err:#}");
+        }
+        for folder in params.workspace_folders.unwrap_or_default() {
+            if let Some(root) = uri_to_file_path(&folder.uri) {
+                self.workspace_folders
+                    .insert(normalize_uri(&folder.uri).into_owned(), root);
+            }
+        }
+        if let Some(cache_dir) = cache_dir_from_settings(params.initialization_options.as_ref()) {
+            let roots = self
+                .workspace_folders
+                .iter()
+                .map(|folder| folder.value().clone())
+                .collect::<Vec<_>>();
+            if let Err(err) = self.enabled_analyses.load(&cache_dir, &roots) {
+                debug!("Starting without previously enabled analyses: {err:#}");
+            }
+            if let Err(err) = self.symbol_index.load(&cache_dir, &roots) {
+                debug!("Starting without the previous symbol index: {err:#}");
+            }
+        }
+
+        let meta = ServerMeta::negotiate(self.client_capabilities.get());
+        let _ = self.position_encoding.set(meta.encoding());
+        let mut capabilities = ServerCapabilities {
+            position_encoding: Some(meta.position_encoding.clone()),
+            document_formatting_provider: Some(OneOf::Left(true)),
+            inlay_hint_provider: Some(OneOf::Right(InlayHintServerCapabilities::Options(
+                inlay_hint_options(),
+            ))),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            signature_help_provider: Some(SignatureHelpOptions {
+                trigger_characters: Some(TRIGGER_CHARACTERS.map(String::from).to_vec()),
+                retrigger_characters: Some(RETRIGGER_CHARACTERS.map(String::from).to_vec()),
+                work_done_progress_options: WorkDoneProgressOptions::default(),
+            }),
+            text_document_sync: Some(TextDocumentSyncCapability::Options(
+                TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::FULL),
+                    save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                        include_text: Some(true),
+                    })),
+                    ..Default::default()
+                },
+            )),
+            completion_provider: Some(CompletionOptions {
+                resolve_provider: Some(true),
+                trigger_characters: Some(self.grammar.get().trigger_characters.clone()),
+                work_done_progress_options: WorkDoneProgressOptions::default(),
+                all_commit_characters: None,
+                completion_item: None,
+            }),
+            execute_command_provider: Some(ExecuteCommandOptions {
+                commands: Command::NAMES
+                    .iter()
+                    .map(|name| (*name).to_string())
+                    .collect(),
+                work_done_progress_options: WorkDoneProgressOptions::default(),
+            }),
+
+            workspace: Some(WorkspaceServerCapabilities {
+                workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                    supported: Some(true),
+                    change_notifications: Some(OneOf::Left(true)),
+                }),
+                file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                    did_rename: Some(FileOperationRegistrationOptions {
+                        filters: vec![
+                            FileOperationFilter {
+                                scheme: Some("file".to_string()),
+                                pattern: FileOperationPattern {
+                                    glob: WATCHED_FILES_GLOB.to_string(),
+                                    matches: Some(FileOperationPatternKind::File),
+                                    options: None,
+                                },
+                            },
+                            FileOperationFilter {
+                                scheme: Some("file".to_string()),
+                                pattern: FileOperationPattern {
+                                    glob: "**".to_string(),
+                                    matches: Some(FileOperationPatternKind::Folder),
+                                    options: None,
+                                },
+                            },
+                        ],
+                    }),
+                    ..Default::default()
+                }),
+            }),
+            semantic_tokens_provider: Some(
+                SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
+                    semantic_tokens_options(),
+                ),
+            ),
+            definition_provider: Some(OneOf::Left(true)),
+            workspace_symbol_provider: Some(OneOf::Left(true)),
+            references_provider: Some(OneOf::Left(true)),
+            rename_provider: Some(OneOf::Left(true)),
+            code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    CodeActionKind::QUICKFIX,
+                    CodeActionKind::from(EXTRACT_FUNCTION_KIND),
+                    CodeActionKind::from(EXTRACT_VARIABLE_KIND),
+                    CodeActionKind::REFACTOR_INLINE,
+                    CodeActionKind::from(GENERATE_CONSTRUCTOR_KIND),
+                    CodeActionKind::REFACTOR_REWRITE,
+                    CodeActionKind::from(FIX_ALL_KIND),
+                ]),
+                ..Default::default()
+            })),
+            ..ServerCapabilities::default()
+        };
+        if let Some(cache_dir) = cache_dir_from_settings(params.initialization_options.as_ref()) {
+            let current = CapabilitySet::current(&capabilities, Command::NAMES, SETTING_KEYS);
+            if let Err(err) = self.upgrades.record(&cache_dir, current) {
+                debug!("Failed to record the server capabilities: {err:#}");
+            }
+        }
+        // Features the client registers dynamically are registered once initialized
+        let client_capabilities = self.client_capabilities.get();
+        if registers_dynamically(client_capabilities, Feature::InlayHints) {
+            capabilities.inlay_hint_provider = None;
+        }
+        if registers_dynamically(client_capabilities, Feature::SemanticTokens) {
+            capabilities.semantic_tokens_provider = None;
+        }
+
+        Ok(InitializeResult {
+            server_info: Some(meta.server_info()),
+            offset_encoding: Some(meta.offset_encoding()),
+            capabilities,
+        })
+    }
+
+    /// Notification that the client has finished initializing.
+    ///
+    /// This method is called after the client has received the result of the initialize request
+    /// and the client is ready to send requests.
+    async fn initialized(&self, _: InitializedParams) {
+        self.outgoing
+            .track(
+                self.client
+                    .log_message(MessageType::INFO, "server initialized!"),
+            )
+            .await;
+        self.check_compiler().await;
+        self.register_file_watchers().await;
+        if let Some(settings) = self.fetch_settings().await {
+            self.apply_settings(settings).await;
+        } else {
+            self.update_feature_registrations().await;
+        }
+
+        let roots = self
+            .workspace_folders
+            .iter()
+            .map(|folder| folder.value().clone())
+            .collect::<Vec<_>>();
+        for root in roots {
+            self.index_workspace_folder(root).await;
+        }
+        self.reload_stdlib().await;
+        if let Some(whats_new) = self.upgrades.take_fresh() {
+            debug!(
+                "Upgraded from {} to {}",
+                whats_new.previous_version, whats_new.version
+            );
+            self.outgoing
+                .track(self.client.send_notification::<ServerUpgraded>(whats_new))
+                .await;
+        }
+        debug!("initialized!");
+    }
+
+    /// Shutdown the language server.
+    ///
+    /// This method is called by the client when it wants to shut down the server.
+    /// The server should respond with Ok(()) and then exit.
+    async fn shutdown(&self) -> Result<()> {
+        debug!("Shutdown request received");
+
+        // Settle outgoing traffic before tearing down state, so no request or
+        // notification reaches the client after it has started closing the project
+        self.outgoing.close(SHUTDOWN_GRACE_PERIOD).await;
+
+        if let Err(err) = self.symbol_index.save() {
+            debug!("Failed to save the symbol index: {err:#}");
+        }
+
+        // Clear all stored data to free resources
+        let document_count = self.documents.len();
+        self.documents.clear();
+        self.analysis_queue.clear();
+        self.diagnostics_history.clear();
+        self.virtual_documents.clear();
+
+        debug!("Cleared {document_count} documents");
+        debug!("Server shutting down gracefully");
+        Ok(())
+    }
+
+    /// Called when a document is opened in the client.
+    ///
+    /// This notification is sent from the client to the server when a document is opened.
+    /// The server compiles the document and stores the results for later use.
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let uri = normalize_uri(&params.text_document.uri).into_owned();
+        self.debouncer.cancel(uri.as_str());
+        self.open_documents.insert(uri.clone());
+        self.documents
+            .note_version(&uri, params.text_document.version);
+        self.on_change(TextDocumentChange {
+            uri,
+            text: &params.text_document.text,
+            version: Some(params.text_document.version),
+        })
+        .await;
+        debug!("file opened!");
+    }
+
+    /// Called when the content of a document changes in the client.
+    ///
+    /// This notification is sent from the client to the server when a document is modified.
+    /// The server recompiles the document and updates its internal state.
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        // Check if content_changes is not empty to prevent panic
+        if params.content_changes.is_empty() {
+            debug!("Received empty content_changes, ignoring");
+            return;
+        }
+        let uri = normalize_uri(&params.text_document.uri).into_owned();
+        self.documents
+            .note_version(&uri, params.text_document.version);
+
+        // Wait for the user to stop typing; a newer change supersedes this one. Buffers
+        // that don't live on disk may never stop changing, so they are throttled instead
+        let version = Some(params.text_document.version);
+        let settled = if uri_to_file_path(&uri).is_some() {
+            self.debouncer.wait(uri.as_str(), version).await
+        } else {
+            let len = params.content_changes[0].text.len();
+            self.debouncer
+                .wait_volatile(uri.as_str(), version, len)
+                .await
+        };
+        if !settled {
+            debug!("Skipping superseded change to {}", uri.as_str());
+            return;
+        }
+
+        self.on_change(TextDocumentChange {
+            text: &params.content_changes[0].text,
+            uri: uri.clone(),
+            version: Some(params.text_document.version),
+        })
+        .await;
+
+        if self.pending_hint_refresh.remove(&uri).is_some() {
+            self.refresh_inlay_hints().await;
+        }
+    }
+
+    /// Called when a document is saved in the client.
+    ///
+    /// This notification is sent from the client to the server when a document is saved.
+    /// The server recompiles the document to ensure the saved version is analyzed.
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        let uri = normalize_uri(&params.text_document.uri).into_owned();
+        // The saved text includes any change still waiting to be compiled, so compile it
+        // now under that change's version. Saving doesn't change the version otherwise.
+        let pending_version = if params.text.is_some() {
+            self.debouncer.cancel(uri.as_str())
+        } else {
+            None
+        };
+        let version = pending_version.unwrap_or_else(|| {
+            self.documents
+                .get_snapshot(&uri)
+                .and_then(|doc| doc.version)
+        });
+        // If no text provided, use the stored document content
+        let Some(text) = params.text.or_else(|| {
+            self.documents
+                .get_snapshot(&uri)
+                .map(|doc| doc.rope.to_string())
+        }) else {
+            debug!("No stored content for document: {}", uri.as_str());
+            return;
+        };
+
+        self.on_change(TextDocumentChange {
+            text: &text,
+            uri: uri.clone(),
+            version,
+        })
+        .await;
+        self.diagnostics_history.mark_saved(&uri);
+        debug!("file saved!");
+    }
+
+    /// Called when a document is closed in the client.
+    ///
+    /// This notification is sent from the client to the server when a document is closed.
+    /// The server removes the document from its internal state to free resources and
+    /// clears its diagnostics. Workspace and stdlib files are reanalyzed from disk
+    /// instead, unless the `keepWorkspaceDiagnostics` setting is disabled, since the
+    /// closed buffer may have differed from the file.
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = normalize_uri(&params.text_document.uri).into_owned();
+        self.debouncer.forget(uri.as_str());
+        self.open_documents.remove(&uri);
+        self.documents.remove(&uri);
+        self.analysis_queue.close(&uri);
+        self.diagnostics_history.remove(&uri);
+        self.virtual_documents.remove(&ast_uri(uri.as_str()));
+
+        let keep = self.settings_for(&uri).keep_workspace_diagnostics
+            && (self.is_in_workspace(&uri) || self.stdlib.contains(&uri));
+        let text = match uri_to_file_path(&uri) {
+            Some(path) if keep => tokio::fs::read_to_string(&path).await.ok(),
+            _ => None,
+        };
+        if let Some(text) = text {
+            self.on_change(TextDocumentChange {
+                uri,
+                text: &text,
+                version: None,
+            })
+            .await;
+        } else {
+            self.symbol_index.remove(&uri);
+            self.clear_diagnostics(uri).await;
+        }
+        debug!("file closed!");
+    }
+
+    /// Go to the definition of the symbol at the given position.
+    ///
+    /// This request is sent from the client to the server to get the location
+    /// of the definition of the symbol at the given cursor position.
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        debug!(
+            "Goto definition request for {} at line {}, col {}",
+            uri, position.line, position.character
+        );
+
+        self.check_position(uri, position)?;
+        let definition = self.get_definition(&params);
+
+        if definition.is_some() {
+            debug!(
+                "Found definition for symbol at line {}, col {}",
+                position.line, position.character
+            );
+        } else {
+            debug!(
+                "No definition found for symbol at line {}, col {}",
+                position.line, position.character
+            );
+        }
+
+        Ok(definition)
+    }
+
+    /// Describe the symbol at the given position.
+    ///
+    /// The hover shows the declaration of the symbol and its doc comment.
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = &params.text_document_position_params;
+        self.check_position(&position.text_document.uri, position.position)?;
+        if !self
+            .settings_for(&position.text_document.uri)
+            .enabled(Feature::Hover)
+        {
+            return Ok(None);
+        }
+        Ok(self.get_hover(&params))
+    }
+
+    /// Show the signature of the function called around the given position.
+    ///
+    /// The active parameter is recomputed from the text on every request, including
+    /// the ones retriggered by typing `,` or `)`.
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let position = &params.text_document_position_params;
+        self.check_position(&position.text_document.uri, position.position)?;
+        if !self
+            .settings_for(&position.text_document.uri)
+            .enabled(Feature::SignatureHelp)
+        {
+            return Ok(None);
+        }
+        let Some(doc) = self.documents.get_snapshot(&position.text_document.uri) else {
+            return Ok(None);
+        };
+        Ok(TextPos::new(&doc.rope, self.encoding(), Bounds::Clamp)
+            .offset(position.position)
+            .and_then(|offset| signature_help(&doc, offset)))
+    }
+
+    /// Search the symbols defined in the workspace.
+    ///
+    /// The symbols come from the symbol index, which also holds the files that indexing
+    /// skipped because their text hadn't changed since the last session.
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<WorkspaceSymbolResponse>> {
+        let found = self.symbol_index.search(&params.query);
+        let stored = found
+            .iter()
+            .filter_map(|(uri, _)| {
+                let doc = self.documents.get_snapshot(uri)?;
+                Some((uri.clone(), doc.rope.clone()))
+            })
+            .collect();
+        let encoding = self.encoding();
+        let symbols = run_cancellable(move |token| {
+            Self::get_workspace_symbols(found, stored, encoding, token)
+        })
+        .await
+        .unwrap_or_default();
+        debug!(
+            "Found {} workspace symbols for {:?}",
+            symbols.len(),
+            params.query
+        );
+        Ok(Some(WorkspaceSymbolResponse::Flat(symbols)))
+    }
+
+    /// Find all references to the symbol at the given position.
+    ///
+    /// This request is sent from the client to the server to get all locations
+    /// where the symbol at the given cursor position is referenced.
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let include_declaration = params.context.include_declaration;
+        debug!(
+            "References request for {} at line {}, col {} (include_declaration: {})",
+            uri, position.line, position.character, include_declaration
+        );
+        self.check_position(&uri, position)?;
+
+        let Some(doc) = self
+            .documents
+            .get_snapshot(&uri)
+            .map(DocSnapshot::into_shared)
+        else {
+            debug!("No stored content for document: {uri}");
+            return Ok(None);
+        };
+        let analyzer = Arc::clone(&self.analyzer);
+        let encoding = self.encoding();
+        let references = run_cancellable(move |token| {
+            Self::get_references(
+                &analyzer,
+                &doc,
+                encoding,
+                &uri,
+                position,
+                include_declaration,
+                token,
+            )
+        })
+        .await;
+
+        if let Some(refs) = &references {
+            debug!(
+                "Found {} references for symbol at line {}, col {}",
+                refs.len(),
+                position.line,
+                position.character
+            );
+        } else {
+            debug!(
+                "No references found for symbol at line {}, col {}",
+                position.line, position.character
+            );
+        }
+
+        Ok(references)
+    }
+
+    /// Provide semantic tokens for the entire document.
+    ///
+    /// This request is sent from the client to the server to get semantic tokens,
+    /// which are used for syntax highlighting based on semantic understanding.
+    /// Documents larger than [`FULL_SEMANTIC_TOKENS_LIMIT`] get no tokens, leaving
+    /// their highlighting to range requests.
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let uri = params.text_document.uri;
+        if !self.settings_for(&uri).enabled(Feature::SemanticTokens) {
+            return Ok(None);
+        }
+        let Some(doc) = self
+            .documents
+            .get_snapshot(&uri)
+            .map(DocSnapshot::into_shared)
+        else {
+            return Ok(None);
+        };
+        let size = doc.rope.len_bytes();
+        if size > FULL_SEMANTIC_TOKENS_LIMIT {
+            info!(
+                "Serving only range semantic tokens for {} ({size} bytes)",
+                uri.as_str()
+            );
+            return Ok(Some(
+                SemanticTokensResult::Tokens(SemanticTokens::default()),
+            ));
+        }
+        let analyzer = Arc::clone(&self.analyzer);
+        let grammar = self.grammar.get();
+        let encoding = self.encoding();
+        let semantic_tokens = run_cancellable(move |token| {
+            Self::build_semantic_tokens(&analyzer, &doc, &grammar, encoding, token)
+        })
+        .await;
+        if let Some(tokens) = semantic_tokens {
+            return Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+                result_id: None,
+                data: tokens,
+            })));
+        }
+        Ok(None)
+    }
+
+    /// Provide semantic tokens for a specific range in a document.
+    ///
+    /// This request is sent from the client to the server to get semantic tokens
+    /// for a specific range, which is used for incremental syntax highlighting.
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
+        let uri = params.text_document.uri;
+        let range = params.range;
+        self.check_range(&uri, range)?;
+        if !self.settings_for(&uri).enabled(Feature::SemanticTokens) {
+            return Ok(None);
+        }
+        let Some(doc) = self
+            .documents
+            .get_snapshot(&uri)
+            .map(DocSnapshot::into_shared)
+        else {
+            return Ok(None);
+        };
+        let analyzer = Arc::clone(&self.analyzer);
+        let grammar = self.grammar.get();
+        let encoding = self.encoding();
+        let semantic_tokens = run_cancellable(move |token| {
+            Self::build_semantic_tokens_range(&analyzer, &doc, &grammar, encoding, range, token)
+        })
+        .await;
+        Ok(semantic_tokens.map(|data| {
+            SemanticTokensRangeResult::Tokens(SemanticTokens {
+                result_id: None,
+                data,
+            })
+        }))
+    }
+
+    /// Provide inlay hints for a document.
+    ///
+    /// This request is sent from the client to the server to get inlay hints,
+    /// which are additional information displayed inline with the code.
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let uri = params.text_document.uri;
+        self.check_range(&uri, params.range)?;
+        if !self.settings_for(&uri).enabled(Feature::InlayHints) {
+            return Ok(None);
+        }
+        Ok(self.build_inlay_hints(&uri, Some(params.range)))
+    }
+
+    /// Add the tooltip, and the location of a struct type, to an inlay hint the client
+    /// is about to show.
+    ///
+    /// The hint carries where its symbol is declared: the variable of a type hint, the
+    /// function of a return type hint or the parameter of a parameter hint.
+    async fn inlay_hint_resolve(&self, mut hint: InlayHint) -> Result<InlayHint> {
+        let Some(data) = hint
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<ResolveData>(data).ok())
+        else {
+            return Ok(hint);
+        };
+        let Some(doc) = self.documents.get_snapshot(&data.uri) else {
+            return Ok(hint);
+        };
+        let Some(symbol_id) = data.symbol(&doc) else {
+            return Ok(hint);
+        };
+        if hint.tooltip.is_none() {
+            hint.tooltip = symbol_documentation(&doc, symbol_id).map(|value| {
+                InlayHintTooltip::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                })
+            });
+        }
+        if let InlayHintLabel::LabelParts(parts) = &mut hint.label
+            && let Some(part) = parts.last_mut()
+            && part.location.is_none()
+        {
+            part.location = self.struct_type_location(&data.uri, &doc, symbol_id);
+        }
+        Ok(hint)
+    }
+
+    /// Provide code completion items at a specific position in a document.
+    ///
+    /// This request is sent from the client to the server to get completion items
+    /// at a given cursor position. The server analyzes the context and provides
+    /// relevant suggestions such as variables, functions, and fields.
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let position = &params.text_document_position;
+        self.check_position(&position.text_document.uri, position.position)?;
+        if !self
+            .settings_for(&position.text_document.uri)
+            .enabled(Feature::Completion)
+        {
+            return Ok(None);
+        }
+        Ok(self.get_completion(params))
+    }
+
+    /// Add the documentation of a completion item the client is about to show.
+    ///
+    /// Items are listed without documentation to keep the list small; the item carries
+    /// where its symbol is declared, and the declaration and its comment are added here.
+    async fn completion_resolve(&self, item: CompletionItem) -> Result<CompletionItem> {
+        let Some(data) = item
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<ResolveData>(data).ok())
+        else {
+            return Ok(item);
+        };
+        Ok(match self.documents.get_snapshot(&data.uri) {
+            Some(doc) => resolve_item(&doc, &data, item),
+            None => item,
+        })
+    }
+
+    /// Rename the symbol at the given position.
+    ///
+    /// This request is sent from the client to the server to rename the symbol
+    /// at the given cursor position and all its references.
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let new_name = params.new_name;
+        debug!(
+            "Rename request for {} at line {}, col {} to '{}'",
+            uri, position.line, position.character, new_name
+        );
+        self.check_position(&uri, position)?;
+        // The progress round trip leaves time for further edits, so the edit is
+        // computed against the version the rename was requested for
+        let version = self.documents.latest_version(&uri);
+
+        let progress = self
+            .begin_progress(
+                params.work_done_progress_params.work_done_token,
+                format!("Renaming to `{new_name}`"),
+            )
+            .await;
+        let workspace_edit = self.get_rename_edit(&uri, version, position, &new_name);
+        progress.end(None).await;
+        let workspace_edit = workspace_edit?;
+
+        if let Some(edit) = &workspace_edit {
+            debug!("Created workspace edit for rename operation");
+            self.journal_edit(format!("Rename to `{new_name}`"), edit);
+            if self.is_parameter_at(&uri, position) {
+                self.pending_hint_refresh
+                    .insert(normalize_uri(&uri).into_owned());
+            }
+        } else {
+            debug!("Could not create workspace edit for rename operation");
+        }
+
+        Ok(workspace_edit)
+    }
+
+    /// Format the entire document.
+    ///
+    /// This request is sent from the client to the server to format the entire document
+    /// according to the language's formatting rules.
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        if !self
+            .settings_for(&params.text_document.uri)
+            .enabled(Feature::Formatting)
+        {
+            return Ok(None);
+        }
+        Ok(self.format_text(&params.text_document.uri))
+    }
+
+    /// Provide code actions for a range of a document.
+    ///
+    /// Offers a quick fix for every diagnostic in the request context that suggests a
+    /// replacement for an unresolved name, and ones creating the undefined functions
+    /// and struct fields used in the requested range.
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        self.check_range(&uri, params.range)?;
+        if !self.settings_for(&uri).enabled(Feature::CodeActions) {
+            return Ok(None);
+        }
+        let mut actions = params
+            .context
+            .diagnostics
+            .iter()
+            .filter_map(|diagnostic| {
+                let suggestion = Suggestion::from_diagnostic(diagnostic)?;
+                Some(CodeActionOrCommand::CodeAction(
+                    suggestion.code_action(&uri, diagnostic.clone()),
+                ))
+            })
+            .collect::<Vec<_>>();
+        actions.extend(
+            self.insertion_actions(&uri, params.range, &params.context.diagnostics)
+                .into_iter()
+                .map(CodeActionOrCommand::CodeAction),
+        );
+        if wants_kind(params.context.only.as_deref(), EXTRACT_FUNCTION_KIND)
+            && let Some(action) = self.extract_function_action(&uri, params.range)
+        {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+        if wants_kind(params.context.only.as_deref(), EXTRACT_VARIABLE_KIND) {
+            actions.extend(
+                self.extract_variable_actions(&uri, params.range)
+                    .into_iter()
+                    .map(CodeActionOrCommand::CodeAction),
+            );
+        }
+        if wants_kind(
+            params.context.only.as_deref(),
+            CodeActionKind::REFACTOR_INLINE.as_str(),
+        ) && let Some(action) = self.inline_variable_action(&uri, params.range.start)
+        {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+        if wants_kind(
+            params.context.only.as_deref(),
+            CodeActionKind::REFACTOR_REWRITE.as_str(),
+        ) && let Some(action) = self.type_annotation_action(&uri, params.range.start)
+        {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+        if wants_kind(params.context.only.as_deref(), GENERATE_CONSTRUCTOR_KIND)
+            && let Some(action) = self.struct_constructor_action(&uri, params.range.start)
+        {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+        if wants_kind(params.context.only.as_deref(), FIX_ALL_KIND)
+            && let Some((edit, _)) = self.fix_all_edit(&uri)
+        {
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: "Fix all auto-fixable problems".to_string(),
+                kind: Some(CodeActionKind::from(FIX_ALL_KIND)),
+                edit: Some(edit),
+                ..Default::default()
+            }));
+        }
+        Ok((!actions.is_empty()).then_some(actions))
+    }
+
+    /// Called when the client's configuration changes.
+    ///
+    /// The settings are fetched again with `workspace/configuration`, or read from the
+    /// notification if the client doesn't support the request, and replace the current
+    /// ones.
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        debug!("configuration changed!");
+        let settings = match self.fetch_settings().await {
+            Some(settings) => settings,
+            None => ScopedSettings::global(self.initial_settings().merged(&params.settings)),
+        };
+        self.apply_settings(settings).await;
+    }
+
+    /// Called when workspace folders are added to or removed from the client.
+    ///
+    /// Source files in added folders are indexed, while documents belonging to removed
+    /// folders are evicted and their diagnostics cleared. Documents open in the client
+    /// are kept until they are closed.
+    ///
+    /// The settings are fetched again, as every folder may have its own.
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        debug!(
+            "workspace folders changed: {} added, {} removed",
+            params.event.added.len(),
+            params.event.removed.len()
+        );
+
+        for folder in params.event.removed {
+            self.workspace_folders
+                .remove(normalize_uri(&folder.uri).as_ref());
+            let prefix = format!(
+                "{}/",
+                normalize_uri(&folder.uri).as_str().trim_end_matches('/')
+            );
+            for uri in self.documents.uris() {
+                if !uri.as_str().starts_with(&prefix)
+                    || self.open_documents.contains(&uri)
+                    || self.is_in_workspace(&uri)
+                    || self.stdlib.contains(&uri)
+                {
+                    continue;
+                }
+                self.documents.remove(&uri);
+                self.analysis_queue.close(&uri);
+                self.clear_diagnostics(uri).await;
+            }
+            self.symbol_index.retain(|uri| {
+                !uri.as_str().starts_with(&prefix)
+                    || self.open_documents.contains(uri)
+                    || self.is_in_workspace(uri)
+            });
+        }
+
+        let mut added = Vec::new();
+        for folder in params.event.added {
+            let Some(root) = uri_to_file_path(&folder.uri) else {
+                debug!(
+                    "Ignoring non-file workspace folder: {}",
+                    folder.uri.as_str()
+                );
+                continue;
+            };
+            self.workspace_folders
+                .insert(normalize_uri(&folder.uri).into_owned(), root.clone());
+            added.push(root);
+        }
+
+        // Added folders are indexed with their own settings
+        if let Some(settings) = self.fetch_settings().await {
+            self.apply_settings(settings).await;
+        }
+        for root in added {
+            self.index_workspace_folder(root).await;
+        }
+    }
+
+    /// Called after files or folders were renamed in the client.
+    ///
+    /// Documents stored under the old URIs (or below a renamed folder) are moved to
+    /// their new URIs, and their diagnostics are republished under the new URI. L has
+    /// no import statements, so a rename never requires edits in other files.
+    async fn did_rename_files(&self, params: RenameFilesParams) {
+        for file in params.files {
+            let (Ok(old_uri), Ok(new_uri)) =
+                (Uri::from_str(&file.old_uri), Uri::from_str(&file.new_uri))
+            else {
+                debug!("Ignoring rename of invalid URI: {}", file.old_uri);
+                continue;
+            };
+            let old_uri = normalize_uri(&old_uri).into_owned();
+            let new_uri = normalize_uri(&new_uri).into_owned();
+            let old_prefix = format!("{}/", old_uri.as_str().trim_end_matches('/'));
+            let new_prefix = format!("{}/", new_uri.as_str().trim_end_matches('/'));
+            for uri in self.documents.uris() {
+                let renamed = if uri == old_uri {
+                    new_uri.clone()
+                } else if let Some(rest) = uri.as_str().strip_prefix(&old_prefix) {
+                    let Ok(renamed) = Uri::from_str(&format!("{new_prefix}{rest}")) else {
+                        continue;
+                    };
+                    renamed
+                } else {
+                    continue;
+                };
+                self.rename_document(uri, renamed).await;
+            }
+        }
+    }
+
+    /// Called when files watched by the client change on disk.
+    ///
+    /// This notification is sent when files are created, changed or deleted outside the
+    /// editor (e.g. by a git checkout or code generation). The server re-reads changed
+    /// files, drops deleted ones and republishes their diagnostics. Documents open in the
+    /// client are skipped, since their buffer content is authoritative.
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        debug!("{} watched files have changed", params.changes.len());
+
+        let mut projects_changed = false;
+        for change in params.changes {
+            let uri = normalize_uri(&change.uri).into_owned();
+            if uri.as_str().rsplit('/').next() == Some(PROJECT_FILE) {
+                projects_changed = true;
+                continue;
+            }
+            if self.open_documents.contains(&uri) {
+                debug!("Ignoring disk change for open document: {uri}");
+                continue;
+            }
+
+            match change.typ {
+                FileChangeType::CREATED | FileChangeType::CHANGED => {
+                    let Some(path) = uri_to_file_path(&change.uri) else {
+                        debug!("Ignoring change for non-file URI: {uri}");
+                        continue;
+                    };
+                    match tokio::fs::read_to_string(&path).await {
+                        Ok(text) => {
+                            self.on_change(TextDocumentChange {
+                                uri,
+                                text: &text,
+                                version: None,
+                            })
+                            .await;
+                        }
+                        Err(err) => {
+                            debug!("Failed to read {}: {err}", path.display());
+                        }
+                    }
+                }
+                FileChangeType::DELETED => {
+                    self.documents.remove(&uri);
+                    self.analysis_queue.close(&uri);
+                    self.symbol_index.remove(&uri);
+                    debug!("Removed deleted file: {uri}");
+                    self.clear_diagnostics(uri).await;
+                }
+                _ => debug!("Unknown file change type for: {uri}"),
+            }
+        }
+
+        if projects_changed {
+            debug!("Project configuration changed, analyzing the documents again");
+            self.projects.clear();
+            self.restart_analysis().await;
+            self.refresh_inlay_hints().await;
+        }
+    }
+
+    /// Execute a command advertised by the server.
+    ///
+    /// This request is sent from the client to the server to run one of the commands
+    /// listed in the server capabilities, with its arguments.
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        debug!("Executing command: {}", params.command);
+        let command = Command::parse(&params.command, params.arguments)?;
+        self.run_command(command).await
+    }
+}
+
+impl Backend {
+    /// Create the backend of a connection to a client, analyzing documents with
+    /// `l_lang`.
+    ///
+    /// The backend records when the client asks to shut down in `lifecycle`, and the
+    /// compilations it runs in `metrics`.
+    pub fn new(
+        client: Client,
+        strict_protocol: bool,
+        lifecycle: Arc<Lifecycle>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let analyzer = Arc::new(LLang);
+        Self {
+            client,
+            analysis_queue: AnalysisQueue::new(Arc::clone(&analyzer), Arc::clone(&metrics)),
+            analyzer,
+            client_capabilities: OnceLock::new(),
+            position_encoding: OnceLock::new(),
+            workspace_folders: DashMap::new(),
+            stdlib: Stdlib::default(),
+            grammar: GrammarTable::default(),
+            documents: DocumentStore::default(),
+            diagnostics_history: DiagnosticsHistory::default(),
+            initial_settings: OnceLock::new(),
+            settings: ArcSwap::from_pointee(ScopedSettings::default()),
+            registered_features: DashSet::new(),
+            projects: Projects::default(),
+            enabled_analyses: EnabledAnalyses::default(),
+            memory_budget: MemoryBudget::default(),
+            symbol_index: SymbolIndex::default(),
+            completion_stats: CompletionStats::default(),
+            metrics,
+            virtual_documents: VirtualDocuments::default(),
+            open_documents: DashSet::new(),
+            pending_hint_refresh: DashSet::new(),
+            debouncer: Debouncer::default(),
+            refactor_journal: RefactorJournal::default(),
+            outgoing: OutgoingRequests::default(),
+            upgrades: Upgrades::default(),
+            strict_protocol,
+            lifecycle,
+        }
+    }
+
+    /// Build the LSP service of a backend, serving the custom requests of the server
+    /// besides the standard ones.
+    pub fn service(init: impl FnOnce(Client) -> Self) -> (LspService<Self>, ClientSocket) {
+        LspService::build(init)
+            .custom_method(RUN_ANALYSIS_METHOD, Self::run_analysis)
+            .custom_method(VIRTUAL_DOCUMENT_METHOD, Self::virtual_document)
+            .custom_method(SEMANTIC_INFO_METHOD, Self::semantic_info)
+            .custom_method(SCOPES_METHOD, Self::scopes)
+            .custom_method(DOCUMENT_TEXT_METHOD, Self::document_text)
+            .custom_method(SERVER_STATUS_METHOD, Self::server_status)
+            .finish()
+    }
+
+    /// Check if the server is shutting down.
+    ///
+    /// The flag is set as soon as the `shutdown` request arrives. This is used to avoid
+    /// unnecessary work during shutdown.
+    fn is_shutting_down(&self) -> bool {
+        self.lifecycle.is_shut_down()
+    }
+
+    /// The encoding positions exchanged with the client are counted in.
+    ///
+    /// Before initialization, UTF-16 is assumed, like the protocol does.
+    fn encoding(&self) -> Encoding {
+        self.position_encoding
+            .get()
+            .copied()
+            .unwrap_or(Encoding::Utf16)
+    }
+
+    /// Register a watcher for L source files with the client.
+    ///
+    /// This lets the server decide which files it is notified about through
+    /// `workspace/didChangeWatchedFiles`, instead of relying on the client to watch
+    /// the right files. Skipped if the client doesn't support dynamic registration.
+    async fn register_file_watchers(&self) {
+        let dynamic_registration = self
+            .client_capabilities
+            .get()
+            .and_then(|caps| caps.workspace.as_ref())
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched_files| watched_files.dynamic_registration)
+            .unwrap_or(false);
+        if !dynamic_registration {
+            debug!("Client doesn't support dynamic file watcher registration");
+            return;
+        }
+
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: [WATCHED_FILES_GLOB, PROJECT_FILE_GLOB]
+                .map(|glob| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(glob.to_string()),
+                    kind: None,
+                })
+                .to_vec(),
+        };
+        let registration = Registration {
+            id: WATCHED_FILES_REGISTRATION_ID.to_string(),
+            method: DidChangeWatchedFiles::METHOD.to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+
+        match self
+            .outgoing
+            .track(self.client.register_capability(vec![registration]))
+            .await
+        {
+            Some(Ok(())) => {
+                debug!("Registered file watcher for {WATCHED_FILES_GLOB} and {PROJECT_FILE_GLOB}");
+            }
+            Some(Err(err)) => debug!("Failed to register file watcher: {err}"),
+            None => {}
+        }
+    }
+
+    /// Register the dynamically registered features the settings turn on, and
+    /// unregister those they turn off.
+    async fn update_feature_registrations(&self) {
+        let settings = self.settings();
+        let mut registrations = Vec::new();
+        let mut unregistrations = Vec::new();
+        for feature in DYNAMIC_FEATURES {
+            if !registers_dynamically(self.client_capabilities.get(), feature) {
+                continue;
+            }
+            let registered = self.registered_features.contains(&feature);
+            if settings.enabled(feature) && !registered {
+                if let Some(registration) = feature_registration(feature) {
+                    registrations.push((feature, registration));
+                }
+            } else if !settings.enabled(feature)
+                && registered
+                && let (Some(id), Some(method)) =
+                    (registration_id(feature), registration_method(feature))
+            {
+                let unregistration = Unregistration {
+                    id: id.to_string(),
+                    method: method.to_string(),
+                };
+                unregistrations.push((feature, unregistration));
+            }
+        }
+
+        if !registrations.is_empty() {
+            let (features, registrations): (Vec<_>, Vec<_>) = registrations.into_iter().unzip();
+            match self
+                .outgoing
+                .track(self.client.register_capability(registrations))
+                .await
+            {
+                Some(Ok(())) => {
+                    debug!("Registered {features:?}");
+                    for feature in features {
+                        self.registered_features.insert(feature);
+                    }
+                }
+                Some(Err(err)) => debug!("Failed to register {features:?}: {err}"),
+                None => {}
+            }
+        }
+        if !unregistrations.is_empty() {
+            let (features, unregistrations): (Vec<_>, Vec<_>) = unregistrations.into_iter().unzip();
+            match self
+                .outgoing
+                .track(self.client.unregister_capability(unregistrations))
+                .await
+            {
+                Some(Ok(())) => {
+                    debug!("Unregistered {features:?}");
+                    for feature in features {
+                        self.registered_features.remove(&feature);
+                    }
+                }
+                Some(Err(err)) => debug!("Failed to unregister {features:?}: {err}"),
+                None => {}
+            }
+        }
+    }
+
+    /// Check if a stored document is kept regardless of the memory budget: open
+    /// documents, and stdlib files, which completion reads from.
+    fn is_pinned(&self, uri: &Uri) -> bool {
+        self.open_documents.contains(uri) || self.stdlib.contains(uri)
+    }
+
+    /// Evict the documents of closed files the memory budget has no room for.
+    ///
+    /// Their diagnostics stay published and their symbols stay in the symbol index.
+    fn enforce_memory_budget(&self) {
+        let evicted = self
+            .memory_budget
+            .enforce(&self.documents, |uri| self.is_pinned(uri));
+        for uri in evicted {
+            debug!("Evicted the document of closed file {uri}");
+            self.analysis_queue.close(&uri);
+        }
+    }
+
+    /// Check if a document URI lies within one of the workspace folders.
+    fn is_in_workspace(&self, uri: &Uri) -> bool {
+        self.workspace_folders.iter().any(|folder| {
+            uri.as_str()
+                .strip_prefix(folder.key().as_str().trim_end_matches('/'))
+                .is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    /// Index the sources of a workspace folder.
+    ///
+    /// If the folder is a project whose `l.toml` lists source roots, only those are
+    /// indexed.
+    async fn index_workspace_folder(&self, root: PathBuf) {
+        let dirs = match self
+            .projects
+            .for_file(&root.join(PROJECT_FILE))
+            .filter(|project| project.root == root)
+        {
+            Some(project) => project.source_dirs(),
+            None => vec![root],
+        };
+        for dir in dirs {
+            self.index_directory(dir, true).await;
+        }
+    }
+
+    /// Compile every L source file below `root` that isn't open in the client.
+    ///
+    /// This method is used to index workspace folders and the stdlib, so that their
+    /// files are analyzed and their diagnostics published without being opened.
+    /// With `reuse_index`, files the symbol index recorded without diagnostics for the
+    /// same text aren't compiled again. Returns the URIs of all source files found, including
+    /// open ones.
+    async fn index_directory(&self, root: PathBuf, reuse_index: bool) -> Vec<Uri> {
+        debug!("Indexing workspace folder: {}", root.display());
+        let root_display = root.display().to_string();
+        let files = tokio::task::spawn_blocking(move || collect_source_files(&root))
+            .await
+            .unwrap_or_default();
+
+        let mut progress = self
+            .begin_progress(None, format!("Indexing {root_display}"))
+            .await;
+        let total = files.len();
+        let mut indexed = Vec::with_capacity(total);
+        for (done, path) in files.into_iter().enumerate() {
+            if self.is_shutting_down() {
+                break;
+            }
+            progress
+                .report(done, total, Some(format!("{done}/{total} files")))
+                .await;
+            let Some(uri) = file_path_to_uri(&path) else {
+                continue;
+            };
+            let uri = normalize_uri(&uri).into_owned();
+            indexed.push(uri.clone());
+            if self.open_documents.contains(&uri) {
+                continue;
+            }
+            match tokio::fs::read_to_string(&path).await {
+                Ok(text)
+                    if reuse_index
+                        && self.symbol_index.is_current(&uri, &Rope::from_str(&text)) =>
+                {
+                    debug!("Reusing the indexed symbols of {uri}");
+                }
+                Ok(text) => {
+                    self.on_change(TextDocumentChange {
+                        uri,
+                        text: &text,
+                        version: None,
+                    })
+                    .await;
+                }
+                Err(err) => debug!("Failed to read {}: {err}", path.display()),
+            }
+        }
+        progress
+            .end(Some(format!("Indexed {} files", indexed.len())))
+            .await;
+        indexed
+    }
+
+    /// Start reporting the progress of a long-running operation.
+    ///
+    /// Uses the client-provided `token` if any, and falls back to logging for clients
+    /// without work done progress support.
+    async fn begin_progress(
+        &self,
+        token: Option<ProgressToken>,
+        title: impl Into<String>,
+    ) -> ProgressReporter<'_> {
+        let supported = self.client_support().work_done_progress;
+        ProgressReporter::begin(&self.client, &self.outgoing, supported, token, title).await
+    }
+
+    /// Reject a position outside a document, in strict protocol mode.
+    ///
+    /// Outside strict mode, and for documents the server doesn't know, every position
+    /// is accepted.
+    fn check_position(&self, uri: &Uri, position: Position) -> Result<()> {
+        if !self.strict_protocol {
+            return Ok(());
+        }
+        let Some(doc) = self.documents.get_snapshot(uri) else {
+            return Ok(());
+        };
+        validate_position(position, &doc.rope, self.encoding())
+            .map_err(|err| Error::invalid_params(format!("{uri}: {err}")))
+    }
+
+    /// Reject a range outside a document or ending before it starts, in strict
+    /// protocol mode.
+    fn check_range(&self, uri: &Uri, range: Range) -> Result<()> {
+        if !self.strict_protocol {
+            return Ok(());
+        }
+        let Some(doc) = self.documents.get_snapshot(uri) else {
+            return Ok(());
+        };
+        validate_range(range, &doc.rope, self.encoding())
+            .map_err(|err| Error::invalid_params(format!("{uri}: {err}")))
+    }
+
+    /// Run the startup self-check of the embedded compiler, reporting any failure.
+    async fn check_compiler(&self) {
+        let problems = tokio::task::spawn_blocking(check_compiler)
+            .await
+            .unwrap_or_else(|err| vec![format!("the self-check failed: {err}")]);
+        if problems.is_empty() {
+            debug!("Compiler self-check passed");
+            return;
+        }
+        let message = format!(
+            "The embedded L compiler doesn't behave as this server expects, so language \
+             features may silently return nothing: {}",
+            problems.join("; ")
+        );
+        self.outgoing
+            .track(self.client.show_message(MessageType::ERROR, message))
+            .await;
+    }
+
+    /// Show the errors of the `l.toml` files that couldn't be loaded, whose documents
+    /// fall back to the editor settings.
+    async fn report_project_errors(&self) {
+        for err in self.projects.take_errors() {
+            let message = format!("Ignoring the project configuration: {err}");
+            self.outgoing
+                .track(self.client.show_message(MessageType::WARNING, message))
+                .await;
+        }
+    }
+
+    /// The current settings of the server as a whole.
+    fn settings(&self) -> Arc<Settings> {
+        self.settings.load().global.clone()
+    }
+
+    /// The current settings of a document: those of its workspace folder if it has
+    /// any, overridden by the `l.toml` of its project.
+    fn settings_for(&self, uri: &Uri) -> Arc<Settings> {
+        let settings = self.settings.load().for_document(uri);
+        match uri_to_file_path(uri) {
+            Some(path) => self.projects.settings_for(&path, settings),
+            None => settings,
+        }
+    }
+
+    /// The settings sent in `initializationOptions`, or the defaults.
+    fn initial_settings(&self) -> Settings {
+        self.initial_settings.get().cloned().unwrap_or_default()
+    }
+
+    /// Fetch the settings of the server's section with `workspace/configuration`, once
+    /// unscoped and once for every workspace folder, merged over the initial settings.
+    ///
+    /// Returns `None` if the client doesn't support the request or it failed.
+    async fn fetch_settings(&self) -> Option<ScopedSettings> {
+        if !self.client_support().configuration {
+            return None;
+        }
+        let folders = self
+            .workspace_folders
+            .iter()
+            .map(|folder| folder.key().clone())
+            .collect::<Vec<_>>();
+        let items = std::iter::once(None)
+            .chain(folders.iter().cloned().map(Some))
+            .map(|scope_uri| ConfigurationItem {
+                scope_uri,
+                section: Some(SETTINGS_SECTION.to_string()),
+            })
+            .collect();
+        let values = match self
+            .outgoing
+            .track(self.client.configuration(items))
+            .await?
+        {
+            Ok(values) => values,
+            Err(err) => {
+                debug!("Failed to fetch the settings: {err}");
+                return None;
+            }
+        };
+        let initial = self.initial_settings();
+        let mut values = values.iter();
+        let global = initial.merged(values.next().unwrap_or(&Value::Null));
+        let folders = folders
+            .into_iter()
+            .zip(values)
+            .map(|(folder, value)| (folder, Arc::new(initial.merged(value))))
+            .collect();
+        Some(ScopedSettings {
+            global: Arc::new(global),
+            folders,
+        })
+    }
+
+    /// Replace the settings, updating what depends on the ones that changed.
+    ///
+    /// A changed stdlib directory or grammar file is picked up immediately by reloading
+    /// the stdlib or grammar, and changed severities are published by analyzing the
+    /// documents again. The stdlib, grammar, debounce delay and registered features
+    /// follow the global settings.
+    async fn apply_settings(&self, settings: ScopedSettings) {
+        let settings = Arc::new(settings);
+        let previous = self.settings.swap(Arc::clone(&settings));
+        let global = &settings.global;
+        self.debouncer.set_delay(global.debounce_delay);
+        self.memory_budget.set_budget(global.memory_budget);
+        self.enforce_memory_budget();
+        self.update_feature_registrations().await;
+        if settings.differ(&previous, Settings::hints_differ) {
+            self.refresh_inlay_hints().await;
+        }
+        if settings.differ(&previous, |settings, previous| {
+            settings.enabled(Feature::SemanticTokens) != previous.enabled(Feature::SemanticTokens)
+        }) {
+            self.refresh_semantic_tokens().await;
+        }
+        if self.grammar.set_path(global.grammar_path.clone()) {
+            self.reload_grammar().await;
+        }
+        if self.stdlib.set_root(global.stdlib_path.clone()) {
+            self.reload_stdlib().await;
+        }
+        if settings.differ(&previous, |settings, previous| {
+            settings.severity_overrides != previous.severity_overrides
+        }) {
+            self.restart_analysis().await;
+        }
+    }
+
+    /// Handle the `l/documentText` request.
+    ///
+    /// Returns the text the stored analysis of a document was computed from, with its
+    /// version and hash, or `None` if the document isn't known.
+    async fn document_text(&self, params: DocumentTextParams) -> Result<Option<DocumentText>> {
+        let uri = normalize_uri(&params.text_document.uri).into_owned();
+        let Some(doc) = self.documents.get_snapshot(&uri) else {
+            return Ok(None);
+        };
+        Ok(Some(DocumentText {
+            version: doc.version,
+            latest_version: self.documents.latest_version(&uri),
+            text: doc.rope.to_string(),
+            content_hash: content_hash(&doc.rope),
+            uri,
+        }))
+    }
+
+    /// Handle the `l/status` request.
+    ///
+    /// Returns the memory used, the number of stored documents and the timing of the
+    /// messages handled and compilations run since the server started.
+    async fn server_status(&self) -> Result<ServerStatus> {
+        let memory = self.memory_budget.stats(
+            &self.documents,
+            |uri| self.is_pinned(uri),
+            self.symbol_index.len(),
+        );
+        Ok(self.metrics.status(self.documents.len(), memory))
+    }
+
+    /// Return the content of a virtual document.
+    ///
+    /// This custom request is sent by the client's content provider for the virtual
+    /// document schemes, e.g. when opening a document shown by `l.showAst`.
+    async fn virtual_document(&self, params: VirtualDocumentParams) -> Result<Option<String>> {
+        Ok(self.virtual_documents.get(params.uri.as_str()))
+    }
+
+    /// Pretty-print the AST of a document into a virtual `l-ast:` document and open it.
+    ///
+    /// Returns the URI of the virtual document, or the AST itself if the client can't
+    /// open documents through `window/showDocument`.
+    async fn show_ast(&self, uri: &Uri) -> Result<Option<Value>> {
+        let ast = self
+            .documents
+            .get_snapshot(uri)
+            .map(|doc| format!("{:#?}", doc.analysis.program.file()))
+            .ok_or_else(|| Error::invalid_params("document not found"))?;
+
+        let show_document = self
+            .client_capabilities
+            .get()
+            .and_then(|caps| caps.window.as_ref())
+            .and_then(|window| window.show_document.as_ref())
+            .is_some_and(|show_document| show_document.support);
+        if !show_document {
+            debug!("Client doesn't support window/showDocument");
+            return Ok(Some(Value::String(ast)));
+        }
+
+        let virtual_uri = ast_uri(uri.as_str());
+        self.virtual_documents.insert(virtual_uri.clone(), ast);
+        let params = ShowDocumentParams {
+            uri: Uri::from_str(&virtual_uri).map_err(|_| Error::internal_error())?,
+            external: Some(false),
+            take_focus: Some(true),
+            selection: None,
+        };
+        match self.outgoing.track(self.client.show_document(params)).await {
+            Some(Ok(true)) => {}
+            Some(Ok(false)) => debug!("Client failed to show {virtual_uri}"),
+            Some(Err(err)) => return Err(err),
+            None => return Err(Error::request_cancelled()),
+        }
+        Ok(Some(Value::String(virtual_uri)))
+    }
+
+    /// Run a parsed `workspace/executeCommand` request.
+    async fn run_command(&self, command: Command) -> Result<Option<Value>> {
+        match command {
+            Command::RestartAnalysis => {
+                let count = self.restart_analysis().await;
+                Ok(Some(serde_json::json!({ "documents": count })))
+            }
+            Command::FormatWorkspace => {
+                let applied = self.format_workspace().await?;
+                Ok(Some(serde_json::json!({ "applied": applied })))
+            }
+            Command::PreviewFormat(args) => {
+                let (text, formatted_text) = self
+                    .formatted_text(&args.uri)
+                    .ok_or_else(|| Error::invalid_params("document not found"))?;
+                let diff =
+                    unified_diff(args.uri.as_str(), args.uri.as_str(), &text, &formatted_text);
+                Ok(Some(Value::String(diff)))
+            }
+            Command::ShowAst(args) => self.show_ast(&args.uri).await,
+            Command::DiffDiagnostics(args) => {
+                let diff = self
+                    .diagnostics_history
+                    .diff(normalize_uri(&args.uri).as_ref())
+                    .ok_or_else(|| Error::invalid_params("document not found"))?;
+                Ok(Some(
+                    serde_json::to_value(diff).map_err(|_| Error::internal_error())?,
+                ))
+            }
+            Command::GotoFirstError(args) => {
+                let uri = args.uri.as_ref().map(|uri| normalize_uri(uri).into_owned());
+                let location = self.diagnostics_history.first_error(uri.as_ref());
+                Ok(Some(
+                    serde_json::to_value(location).map_err(|_| Error::internal_error())?,
+                ))
+            }
+            Command::ShowSemanticInfo(params) => {
+                let info = self.get_semantic_info(&params);
+                Ok(Some(
+                    serde_json::to_value(info).map_err(|_| Error::internal_error())?,
+                ))
+            }
+            Command::SetAnalysisEnabled(args) => {
+                let changed = match self.enabled_analyses.set(args.pass, args.enabled) {
+                    Ok(changed) => changed,
+                    Err(err) => {
+                        self.outgoing
+                            .track(self.client.show_message(
+                                MessageType::WARNING,
+                                format!("The analysis setting won't persist: {err:#}"),
+                            ))
+                            .await;
+                        true
+                    }
+                };
+                if changed {
+                    self.restart_analysis().await;
+                }
+                let enabled = self
+                    .enabled_analyses
+                    .enabled()
+                    .into_iter()
+                    .map(AnalysisPass::name)
+                    .collect::<Vec<_>>();
+                Ok(Some(serde_json::json!({ "enabled": enabled })))
+            }
+            Command::FixAll(args) => {
+                let Some((edit, fixes)) = self.fix_all_edit(&args.uri) else {
+                    return Ok(Some(serde_json::json!({ "fixes": 0, "applied": true })));
+                };
+                let journal_entry = self.journal_entry("Fix all".to_string(), &edit);
+                let applied = self.apply_edit(edit).await?;
+                if applied {
+                    self.refactor_journal.record(journal_entry);
+                }
+                Ok(Some(
+                    serde_json::json!({ "fixes": fixes, "applied": applied }),
+                ))
+            }
+            Command::WhatsNew => Ok(Some(
+                serde_json::to_value(self.upgrades.last_upgrade())
+                    .map_err(|_| Error::internal_error())?,
+            )),
+            Command::CompletionStats => Ok(Some(
+                serde_json::to_value(self.completion_stats.report())
+                    .map_err(|_| Error::internal_error())?,
+            )),
+            Command::ServerStatus => Ok(Some(
+                serde_json::to_value(self.server_status().await?)
+                    .map_err(|_| Error::internal_error())?,
+            )),
+            Command::BrowseExamples => Ok(Some(
+                serde_json::to_value(EXAMPLES).map_err(|_| Error::internal_error())?,
+            )),
+            Command::OpenExample(args) => self.open_example(args).await,
+            Command::UndoLastRefactoring => self.undo_last_refactoring().await,
+            Command::ReloadStdlib => {
+                let loaded = self.reload_stdlib().await;
+                Ok(Some(serde_json::json!({ "loaded": loaded })))
+            }
+            Command::ReloadGrammar => {
+                let reloaded = self.reload_grammar().await;
+                Ok(Some(serde_json::json!({ "reloaded": reloaded })))
+            }
+        }
+    }
+
+    /// Record a refactoring in the journal, so that it can be undone.
+    fn journal_edit(&self, label: String, edit: &WorkspaceEdit) {
+        self.refactor_journal
+            .record(self.journal_entry(label, edit));
+    }
+
+    /// Capture the text of every document touched by an edit, before and after it.
+    ///
+    /// Documents that aren't stored, or that the edit doesn't apply to cleanly, are
+    /// left out.
+    fn journal_entry(&self, label: String, edit: &WorkspaceEdit) -> JournalEntry {
+        let mut documents: Vec<JournalDocument> = Vec::new();
+        let mut skipped = Vec::new();
+        for (uri, edits) in text_edits(edit) {
+            if skipped.contains(&uri) {
+                continue;
+            }
+            let applied = match documents.iter_mut().find(|doc| doc.uri == uri) {
+                // Later text document edits of a document apply to the text of the
+                // earlier ones
+                Some(doc) => {
+                    let rope = Rope::from_str(&doc.after);
+                    apply_text_edits(&rope, self.encoding(), &edits)
+                        .map(|after| doc.after = after)
+                        .is_some()
+                }
+                None => self
+                    .documents
+                    .get_snapshot(&uri)
+                    .and_then(|doc| {
+                        let after = apply_text_edits(&doc.rope, self.encoding(), &edits)?;
+                        documents.push(JournalDocument {
+                            uri: uri.clone(),
+                            before: doc.rope.to_string(),
+                            after,
+                        });
+                        Some(())
+                    })
+                    .is_some(),
+            };
+            if !applied {
+                documents.retain(|doc| doc.uri != uri);
+                skipped.push(uri);
+            }
+        }
+        JournalEntry { label, documents }
+    }
+
+    /// Create a file from a bundled example through `workspace/applyEdit`.
+    ///
+    /// Returns the URI of the created file and whether the client applied the edit.
+    async fn open_example(&self, args: OpenExampleArgs) -> Result<Option<Value>> {
+        let example = find_example(&args.name)
+            .ok_or_else(|| Error::invalid_params(format!("unknown example: {}", args.name)))?;
+        let create_support = self
+            .client_capabilities
+            .get()
+            .and_then(|caps| caps.workspace.as_ref())
+            .and_then(|workspace| workspace.workspace_edit.as_ref())
+            .and_then(|workspace_edit| workspace_edit.resource_operations.as_ref())
+            .is_some_and(|operations| operations.contains(&ResourceOperationKind::Create));
+        if !create_support {
+            return Err(Error::invalid_params(
+                "the client can't create files through workspace edits",
+            ));
+        }
+
+        let uri = match args.target {
+            Some(target) => target,
+            None => self
+                .example_target(example.name)
+                .ok_or_else(Error::internal_error)?,
+        };
+        let mut builder = WorkspaceEditBuilder::new();
+        builder
+            .operation(ResourceOp::Create(CreateFile {
+                uri: uri.clone(),
+                options: None,
+                annotation_id: None,
+            }))
+            .insert(&uri, Position::default(), example.content);
+        let applied = self.apply_edit(builder.build()?).await?;
+        Ok(Some(serde_json::json!({ "uri": uri, "applied": applied })))
+    }
+
+    /// Pick the URI of the file an example is created as.
+    ///
+    /// The file goes into the first workspace folder, with a number appended to its
+    /// name if a file of that name exists. Without workspace folders an untitled
+    /// document is used.
+    fn example_target(&self, name: &str) -> Option<Uri> {
+        let root = self
+            .workspace_folders
+            .iter()
+            .map(|folder| folder.value().clone())
+            .min();
+        let Some(root) = root else {
+            return Uri::from_str(&format!("untitled:{name}.l")).ok();
+        };
+        let path = (1..)
+            .map(|n| match n {
+                1 => root.join(format!("{name}.l")),
+                n => root.join(format!("{name}-{n}.l")),
+            })
+            .find(|path| !path.exists())?;
+        file_path_to_uri(&path)
+    }
+
+    /// Revert the most recent refactoring through `workspace/applyEdit`.
+    ///
+    /// Fails if any affected document changed since the refactoring was applied.
+    async fn undo_last_refactoring(&self) -> Result<Option<Value>> {
+        let Some(entry) = self.refactor_journal.last() else {
+            return Ok(Some(serde_json::json!({ "undone": null })));
+        };
+
+        let annotate = self.client_support().change_annotations;
+        let annotation = format!("Undo \"{}\"", entry.label);
+        let mut builder = WorkspaceEditBuilder::new();
+        if annotate {
+            builder.annotation(
+                &annotation,
+                ChangeAnnotation {
+                    label: annotation.clone(),
+                    needs_confirmation: None,
+                    description: None,
+                },
+            );
+        }
+        for document in &entry.documents {
+            let current = self
+                .documents
+                .get_snapshot(&document.uri)
+                .map(|doc| doc.rope.to_string());
+            let edits = current
+                .and_then(|current| document.inverse_edits(&current, self.encoding()))
+                .ok_or_else(|| {
+                    Error::invalid_params(format!(
+                        "{} changed since \"{}\"",
+                        document.uri, entry.label
+                    ))
+                })?;
+            if annotate {
+                for edit in edits {
+                    builder.replace_annotated(
+                        &document.uri,
+                        edit.range,
+                        edit.new_text,
+                        &annotation,
+                    );
+                }
+            } else {
+                builder.extend(&document.uri, edits);
+            }
+        }
+
+        if !self.apply_edit(builder.build()?).await? {
+            return Err(Error::invalid_params(format!(
+                "client did not apply the undo of \"{}\"",
+                entry.label
+            )));
+        }
+        self.refactor_journal.pop();
+        debug!("Undid refactoring: {}", entry.label);
+        Ok(Some(serde_json::json!({ "undone": entry.label })))
+    }
+
+    /// Ask the client to apply a workspace edit, returning whether it was applied.
+    async fn apply_edit(&self, edit: WorkspaceEdit) -> Result<bool> {
+        match self.outgoing.track(self.client.apply_edit(edit)).await {
+            Some(Ok(response)) => {
+                if let Some(reason) = &response.failure_reason {
+                    debug!("Client failed to apply edit: {reason}");
+                }
+                Ok(response.applied)
+            }
+            Some(Err(err)) => Err(err),
+            None => Err(Error::request_cancelled()),
+        }
+    }
+
+    /// Reparse the stdlib definitions from the configured stdlib directory.
+    ///
+    /// Files that were loaded by a previous reload but no longer exist (or belong to a
+    /// previously configured directory) are evicted. Returns the number of stdlib files
+    /// loaded.
+    async fn reload_stdlib(&self) -> usize {
+        let loaded = match self.stdlib.root() {
+            Some(root) => self.index_directory(root, false).await,
+            None => Vec::new(),
+        };
+        let count = loaded.len();
+
+        for uri in self.stdlib.replace_loaded(loaded) {
+            if self.open_documents.contains(&uri) || self.is_in_workspace(&uri) {
+                continue;
+            }
+            self.documents.remove(&uri);
+            self.analysis_queue.close(&uri);
+            self.symbol_index.remove(&uri);
+            self.clear_diagnostics(uri).await;
+        }
+
+        debug!("Loaded {count} stdlib files");
+        count
+    }
+
+    /// Move a stored document from one URI to another.
+    ///
+    /// Documents open in the client are moved as-is, since the client resynchronizes
+    /// them after a rename. Other documents are reanalyzed under their new URI so their
+    /// diagnostics are published there.
+    async fn rename_document(&self, old_uri: Uri, new_uri: Uri) {
+        let Some(document) = self.documents.remove(&old_uri) else {
+            return;
+        };
+        debug!("Renaming document {old_uri} to {new_uri}");
+        self.diagnostics_history.rename(&old_uri, new_uri.clone());
+        self.analysis_queue.close(&old_uri);
+        self.symbol_index.rename(&old_uri, &new_uri);
+
+        if self.open_documents.remove(&old_uri).is_some() {
+            self.open_documents.insert(new_uri.clone());
+            self.documents.insert(&new_uri, document);
+        } else {
+            let text = document.rope.to_string();
+            self.on_change(TextDocumentChange {
+                uri: new_uri,
+                text: &text,
+                version: document.version,
+            })
+            .await;
+        }
+
+        self.clear_diagnostics(old_uri).await;
+    }
+
+    /// Reread the grammar data file, returning whether it was loaded successfully.
+    ///
+    /// On failure the previous grammar stays in use and the user is notified.
+    async fn reload_grammar(&self) -> bool {
+        match self.grammar.reload() {
+            Ok(()) => {
+                debug!("Reloaded grammar");
+                true
+            }
+            Err(err) => {
+                self.outgoing
+                    .track(
+                        self.client
+                            .show_message(MessageType::WARNING, format!("{err:#}")),
+                    )
+                    .await;
+                false
+            }
+        }
+    }
+
+    /// Get a snapshot of the version of a document a request was made for.
+    ///
+    /// `version` is the latest version the client announced when the request arrived.
+    /// If a newer version was compiled since, the request is answered against the
+    /// matching entry of the document history, or rejected with `ContentModified` if
+    /// that version was dropped from the history. If `version` isn't compiled yet, the
+    /// stored document is used, like for any other request.
+    fn snapshot_at_version(&self, uri: &Uri, version: Option<i32>) -> Result<Option<DocSnapshot>> {
+        let Some(doc) = self.documents.get_snapshot(uri) else {
+            return Ok(None);
+        };
+        let (Some(version), Some(stored)) = (version, doc.version) else {
+            return Ok(Some(doc));
+        };
+        if stored <= version {
+            return Ok(Some(doc));
+        }
+        drop(doc);
+        debug!("Answering request for version {version} of {uri}, now at version {stored}");
+        match self.documents.get_version(uri, version) {
+            Some(doc) => Ok(Some(doc)),
+            None => Err(Error {
+                message: format!(
+                    "{uri} changed from version {version} to {stored} while the request was pending"
+                )
+                .into(),
+                ..Error::content_modified()
+            }),
+        }
+    }
+
+    /// The newer protocol features supported by the client.
+    fn client_support(&self) -> ClientSupport {
+        ClientSupport::from_capabilities(self.client_capabilities.get())
+    }
+}
+
+/// Documents the language features of the server apply to.
+fn l_document_selector() -> DocumentSelector {
+    vec![DocumentFilter {
+        language: Some("l".to_string()),
+        scheme: Some("file".to_string()),
+        pattern: None,
+    }]
+}
+
+/// Build the registration of a dynamically registered feature.
+fn feature_registration(feature: Feature) -> Option<Registration> {
+    let register_options = match feature {
+        Feature::InlayHints => serde_json::to_value(InlayHintRegistrationOptions {
+            inlay_hint_options: inlay_hint_options(),
+            text_document_registration_options: TextDocumentRegistrationOptions {
+                document_selector: Some(l_document_selector()),
+            },
+            static_registration_options: StaticRegistrationOptions::default(),
+        }),
+        Feature::SemanticTokens => serde_json::to_value(semantic_tokens_options()),
+        _ => return None,
+    };
+    Some(Registration {
+        id: registration_id(feature)?.to_string(),
+        method: registration_method(feature)?.to_string(),
+        register_options: register_options.ok(),
+    })
+}
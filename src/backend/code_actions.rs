@@ -0,0 +1,273 @@
+//! Code actions: quick fixes, refactorings and generated code.
+
+use tower_lsp_server::ls_types::{
+    CodeAction, CodeActionKind, Diagnostic, Position, Range, Uri, WorkspaceEdit,
+};
+
+use super::Backend;
+use crate::document_store::normalize_uri;
+use crate::extract_function::{EXTRACT_FUNCTION_KIND, extract_function};
+use crate::extract_variable::{EXTRACT_VARIABLE_KIND, extract_variable};
+use crate::function_stub::function_stubs;
+use crate::inline_variable::inline_variable;
+use crate::missing_field::missing_fields;
+use crate::struct_constructor::{GENERATE_CONSTRUCTOR_KIND, struct_constructor};
+use crate::suggestions::suggest_names;
+use crate::symbol_at::pick_symbol_at;
+use crate::text_pos::{Bounds, TextPos};
+use crate::type_annotation::missing_annotation;
+use crate::workspace_edit::WorkspaceEditBuilder;
+
+impl Backend {
+    /// Create the edit applying every suggested fix of a document at once.
+    ///
+    /// Returns the edit and the number of fixes, or `None` if there is nothing to fix.
+    pub(super) fn fix_all_edit(&self, uri: &Uri) -> Option<(WorkspaceEdit, usize)> {
+        let doc = self.documents.get_snapshot(uri)?;
+        let uri = normalize_uri(uri);
+        let mut builder = WorkspaceEditBuilder::new();
+        let mut fixes = 0;
+        for (span, name) in suggest_names(&doc) {
+            if let Some(range) =
+                TextPos::new(&doc.rope, self.encoding(), Bounds::Strict).range(span)
+            {
+                builder.replace(&uri, range, name);
+                fixes += 1;
+            }
+        }
+        if fixes == 0 {
+            return None;
+        }
+        Some((builder.build().ok()?, fixes))
+    }
+
+    /// Create the quick fixes generating the undefined functions called in a range and
+    /// the missing struct fields used in it.
+    ///
+    /// Each action is linked to the diagnostics of the request context covering the
+    /// call or field use.
+    pub(super) fn insertion_actions(
+        &self,
+        uri: &Uri,
+        range: Range,
+        diagnostics: &[Diagnostic],
+    ) -> Vec<CodeAction> {
+        let Some(doc) = self.documents.get_snapshot(uri) else {
+            return Vec::new();
+        };
+        let rope = &doc.rope;
+        let Some(span) = TextPos::new(rope, self.encoding(), Bounds::Clamp).offsets(range) else {
+            return Vec::new();
+        };
+        let stubs = function_stubs(&doc, &span).into_iter().map(|stub| {
+            let title = format!("Create function `{}`", stub.name);
+            (title, stub.call.start, stub.insert_at, stub.text)
+        });
+        let fields = missing_fields(&doc, &span).into_iter().map(|field| {
+            let title = format!("Add field `{}` to `{}`", field.name, field.struct_name);
+            (title, field.usage.start, field.insert_at, field.text)
+        });
+        stubs
+            .chain(fields)
+            .filter_map(|(title, trigger, insert_at, new_text)| {
+                let trigger =
+                    TextPos::new(rope, self.encoding(), Bounds::Strict).position(trigger)?;
+                let insert_at =
+                    TextPos::new(rope, self.encoding(), Bounds::Strict).position(insert_at)?;
+                let diagnostics = diagnostics
+                    .iter()
+                    .filter(|diagnostic| {
+                        diagnostic.range.start <= trigger && trigger <= diagnostic.range.end
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let mut edit = WorkspaceEditBuilder::new();
+                edit.insert(uri, insert_at, new_text);
+                Some(CodeAction {
+                    title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: (!diagnostics.is_empty()).then_some(diagnostics),
+                    edit: Some(edit.build().ok()?),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    /// Create the code action extracting the statements in a range into a function.
+    ///
+    /// Returns `None` for empty ranges and ranges that can't be extracted.
+    pub(super) fn extract_function_action(&self, uri: &Uri, range: Range) -> Option<CodeAction> {
+        if range.start == range.end {
+            return None;
+        }
+        let doc = self.documents.get_snapshot(uri)?;
+        let rope = &doc.rope;
+        let span = TextPos::new(rope, self.encoding(), Bounds::Clamp).offsets(range)?;
+        let extracted = extract_function(&doc, &span)?;
+        let insert_at =
+            TextPos::new(rope, self.encoding(), Bounds::Strict).position(extracted.insert_at)?;
+        let mut edit = WorkspaceEditBuilder::new();
+        edit.replace(
+            uri,
+            TextPos::new(rope, self.encoding(), Bounds::Strict)
+                .range(extracted.selection.clone())?,
+            extracted.call,
+        )
+        .insert(uri, insert_at, extracted.text);
+        Some(CodeAction {
+            title: format!("Extract into function `{}`", extracted.name),
+            kind: Some(CodeActionKind::from(EXTRACT_FUNCTION_KIND)),
+            edit: Some(edit.build().ok()?),
+            ..Default::default()
+        })
+    }
+
+    /// Create the code actions binding the expression in a range to a variable.
+    ///
+    /// If the expression occurs again later in its block, a second action replaces the
+    /// duplicates too. Returns nothing for empty ranges and ranges that can't be
+    /// extracted.
+    pub(super) fn extract_variable_actions(&self, uri: &Uri, range: Range) -> Vec<CodeAction> {
+        if range.start == range.end {
+            return Vec::new();
+        }
+        let Some(doc) = self.documents.get_snapshot(uri) else {
+            return Vec::new();
+        };
+        let rope = &doc.rope;
+        let Some(span) = TextPos::new(rope, self.encoding(), Bounds::Clamp).offsets(range) else {
+            return Vec::new();
+        };
+        let Some(extracted) = extract_variable(&doc, &span) else {
+            return Vec::new();
+        };
+
+        let action = |title: String, occurrences: &[std::ops::Range<usize>]| {
+            let insert_at = TextPos::new(rope, self.encoding(), Bounds::Strict)
+                .position(extracted.insert_at)?;
+            let mut edit = WorkspaceEditBuilder::new();
+            edit.insert(uri, insert_at, extracted.declaration.clone());
+            for occurrence in occurrences {
+                edit.replace(
+                    uri,
+                    TextPos::new(rope, self.encoding(), Bounds::Strict)
+                        .range(occurrence.clone())?,
+                    extracted.name.clone(),
+                );
+            }
+            Some(CodeAction {
+                title,
+                kind: Some(CodeActionKind::from(EXTRACT_VARIABLE_KIND)),
+                edit: Some(edit.build().ok()?),
+                ..Default::default()
+            })
+        };
+        let mut actions = Vec::new();
+        actions.extend(action(
+            format!("Extract into variable `{}`", extracted.name),
+            std::slice::from_ref(&extracted.selection),
+        ));
+        if !extracted.duplicates.is_empty() {
+            let occurrences = std::iter::once(extracted.selection.clone())
+                .chain(extracted.duplicates.iter().cloned())
+                .collect::<Vec<_>>();
+            actions.extend(action(
+                format!(
+                    "Extract all {} occurrences into variable `{}`",
+                    occurrences.len(),
+                    extracted.name
+                ),
+                &occurrences,
+            ));
+        }
+        actions
+    }
+
+    /// Create the code action inlining the variable at a position.
+    pub(super) fn inline_variable_action(
+        &self,
+        uri: &Uri,
+        position: Position,
+    ) -> Option<CodeAction> {
+        let doc = self.documents.get_snapshot(uri)?;
+        let rope = &doc.rope;
+        let inlined = inline_variable(
+            &doc,
+            TextPos::new(rope, self.encoding(), Bounds::Clamp).offset(position)?,
+        )?;
+        let mut edit = WorkspaceEditBuilder::new();
+        edit.delete(
+            uri,
+            TextPos::new(rope, self.encoding(), Bounds::Strict).range(inlined.binding.clone())?,
+        );
+        for (reference, new_text) in inlined.replacements {
+            edit.replace(
+                uri,
+                TextPos::new(rope, self.encoding(), Bounds::Strict).range(reference)?,
+                new_text,
+            );
+        }
+        Some(CodeAction {
+            title: format!("Inline variable `{}`", inlined.name),
+            kind: Some(CodeActionKind::REFACTOR_INLINE),
+            edit: Some(edit.build().ok()?),
+            ..Default::default()
+        })
+    }
+
+    /// Create the code action annotating the variable at a position with its type.
+    pub(super) fn type_annotation_action(
+        &self,
+        uri: &Uri,
+        position: Position,
+    ) -> Option<CodeAction> {
+        let doc = self.documents.get_snapshot(uri)?;
+        let rope = &doc.rope;
+        let offset = TextPos::new(rope, self.encoding(), Bounds::Clamp).offset(position)?;
+        let symbol_id = pick_symbol_at(&doc.analysis, offset)?.symbol_id;
+        let annotation = missing_annotation(&doc, symbol_id)?;
+        let insert_at =
+            TextPos::new(rope, self.encoding(), Bounds::Strict).position(annotation.insert_at)?;
+        let name = doc.symbol_name(symbol_id)?;
+        let mut edit = WorkspaceEditBuilder::new();
+        edit.insert(uri, insert_at, annotation.text.clone());
+        Some(CodeAction {
+            title: format!(
+                "Add type annotation `{}` to `{}`",
+                annotation.text.trim_start_matches(": "),
+                name
+            ),
+            kind: Some(CodeActionKind::REFACTOR_REWRITE),
+            edit: Some(edit.build().ok()?),
+            ..Default::default()
+        })
+    }
+
+    /// Create the code action generating a constructor for the struct at a position.
+    pub(super) fn struct_constructor_action(
+        &self,
+        uri: &Uri,
+        position: Position,
+    ) -> Option<CodeAction> {
+        let doc = self.documents.get_snapshot(uri)?;
+        let rope = &doc.rope;
+        let constructor = struct_constructor(
+            &doc,
+            TextPos::new(rope, self.encoding(), Bounds::Clamp).offset(position)?,
+        )?;
+        let insert_at =
+            TextPos::new(rope, self.encoding(), Bounds::Strict).position(constructor.insert_at)?;
+        let mut edit = WorkspaceEditBuilder::new();
+        edit.insert(uri, insert_at, constructor.text);
+        Some(CodeAction {
+            title: format!(
+                "Generate constructor `{}` for `{}`",
+                constructor.name, constructor.struct_name
+            ),
+            kind: Some(CodeActionKind::from(GENERATE_CONSTRUCTOR_KIND)),
+            edit: Some(edit.build().ok()?),
+            ..Default::default()
+        })
+    }
+}
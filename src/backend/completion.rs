@@ -0,0 +1,94 @@
+//! Completion of the symbol being typed.
+
+use l_lang::{AstNode, find_node_at_offset};
+use tower_lsp_server::ls_types::{CompletionList, CompletionParams, CompletionResponse, Range};
+
+use super::Backend;
+use crate::completion::{
+    CompletionContext, CompletionSite, complete, expected_type, field_access_struct,
+};
+use crate::text_pos::{Bounds, TextPos};
+
+impl Backend {
+    /// Get the completion items for a given position.
+    ///
+    /// The context at the position, with the word typed before it, is handed to the
+    /// enabled completion providers, whose items are filtered by that word and merged
+    /// by [`complete`]. The list is marked incomplete if it was truncated, so the
+    /// client asks again as the user keeps typing.
+    pub(super) fn get_completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let text_doc_position = params.text_document_position;
+        let uri = text_doc_position.text_document.uri;
+        let position = text_doc_position.position;
+        let doc = self.documents.get_snapshot(&uri)?;
+        let rope = &doc.rope;
+        let offset = TextPos::new(rope, self.encoding(), Bounds::Clamp).offset(position)?;
+
+        let site = match find_node_at_offset(
+            doc.analysis.program.file(),
+            u32::try_from(offset).expect("offset out of range"),
+        ) {
+            Some(AstNode::ExprField(field_expr)) => {
+                let object = field_expr.object.as_ref()?.span();
+                let start = object.start as usize;
+                CompletionSite::FieldAccess {
+                    struct_id: field_access_struct(field_expr, &doc.analysis),
+                    receiver: rope.get_byte_slice(start..object.end as usize)?.to_string(),
+                    replace: Range {
+                        start: TextPos::new(rope, self.encoding(), Bounds::Strict)
+                            .position(start)?,
+                        end: position,
+                    },
+                }
+            }
+            _ => CompletionSite::Expression,
+        };
+
+        // The word being typed, which the items are filtered by
+        let prefix = rope
+            .chars_at(rope.byte_to_char(offset))
+            .reversed()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect::<String>();
+        let before_word = rope.byte_slice(..offset - prefix.len()).to_string();
+
+        let stdlib = self
+            .documents
+            .uris()
+            .into_iter()
+            .filter(|stdlib_uri| self.stdlib.contains(stdlib_uri) && *stdlib_uri != uri)
+            .filter_map(|stdlib_uri| {
+                let name = stdlib_uri.as_str().rsplit('/').next()?.to_string();
+                let document = self.documents.get_snapshot(&stdlib_uri)?;
+                Some((name, stdlib_uri, document))
+            })
+            .collect::<Vec<_>>();
+        let grammar = self.grammar.get();
+        let context = CompletionContext {
+            uri: &uri,
+            document: &doc,
+            site,
+            prefix,
+            expected_type: expected_type(&before_word),
+            grammar: &grammar,
+            stdlib: stdlib
+                .iter()
+                .map(|(name, stdlib_uri, document)| (name.clone(), stdlib_uri, &**document))
+                .collect(),
+            snippets: self.client_support().snippets,
+        };
+        let settings = self.settings_for(&uri);
+        let (items, is_incomplete) = complete(
+            &context,
+            &settings.disabled_completion_providers,
+            &self.completion_stats,
+        );
+        Some(CompletionResponse::List(CompletionList {
+            is_incomplete,
+            items,
+        }))
+    }
+}
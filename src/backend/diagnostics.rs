@@ -0,0 +1,253 @@
+//! Analysis of documents as they change, and the diagnostics published for them.
+
+use tower_lsp_server::jsonrpc::{Error, Result};
+use tower_lsp_server::ls_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag, Uri};
+use tracing::debug;
+
+use super::Backend;
+use crate::analysis_passes::{
+    AnalysisPass, AnalysisScope, DocumentAnalysis, RunAnalysisParams, RunAnalysisResult,
+};
+use crate::analyzer::LanguageAnalyzer;
+use crate::compat::{fit_diagnostics, hint_diagnostics};
+use crate::compile_diagnostics::compile_diagnostics;
+use crate::diagnostic_codes::{DIAGNOSTIC_SOURCE, DiagnosticCode, override_severities};
+use crate::settings::Feature;
+use crate::text_pos::{Bounds, TextPos};
+
+/// Size in bytes above which the analysis of a single file reports its progress.
+const LARGE_FILE_THRESHOLD: usize = 256 * 1024;
+
+/// Represents a change to a text document.
+///
+/// This struct contains the URI of the document and the new text content.
+pub(super) struct TextDocumentChange<'a> {
+    /// The URI of the document
+    pub(super) uri: Uri,
+    /// The new text content of the document
+    pub(super) text: &'a str,
+    /// The client-side version of the document, if known
+    pub(super) version: Option<i32>,
+}
+
+impl Backend {
+    /// Run a single analysis pass on demand.
+    ///
+    /// This custom request runs one of the opt-in passes that are too expensive to
+    /// run on every change, on either one document or every stored document.
+    pub(super) async fn run_analysis(
+        &self,
+        params: RunAnalysisParams,
+    ) -> Result<RunAnalysisResult> {
+        debug!(
+            "Running analysis pass {} on {:?}",
+            params.pass.name(),
+            params.scope
+        );
+        let uris = match params.scope {
+            AnalysisScope::File => {
+                let document = params.text_document.ok_or_else(|| {
+                    Error::invalid_params("textDocument is required for the file scope")
+                })?;
+                vec![document.uri]
+            }
+            AnalysisScope::Workspace => self.documents.uris(),
+        };
+
+        let documents = uris
+            .iter()
+            .filter_map(|uri| self.analyze_document(params.pass, uri))
+            .collect();
+        Ok(RunAnalysisResult {
+            pass: params.pass,
+            documents,
+        })
+    }
+
+    /// Run an analysis pass on a stored document and convert its findings to diagnostics.
+    fn analyze_document(&self, pass: AnalysisPass, uri: &Uri) -> Option<DocumentAnalysis> {
+        let doc = self.documents.get_snapshot(uri)?;
+        let tags = (pass == AnalysisPass::DeadCode).then(|| vec![DiagnosticTag::UNNECESSARY]);
+        let code = DiagnosticCode::Analysis(pass);
+        let diagnostics = pass
+            .run(&doc)
+            .into_iter()
+            .filter_map(|finding| {
+                Some(Diagnostic {
+                    range: TextPos::new(&doc.rope, self.encoding(), Bounds::Strict)
+                        .range(finding.span)?,
+                    severity: Some(DiagnosticSeverity::INFORMATION),
+                    code: Some(code.code()),
+                    code_description: code.description(),
+                    source: Some(DIAGNOSTIC_SOURCE.to_string()),
+                    message: finding.message,
+                    related_information: None,
+                    tags: tags.clone(),
+                    data: None,
+                })
+            })
+            .collect();
+        Some(DocumentAnalysis {
+            uri: uri.clone(),
+            diagnostics,
+        })
+    }
+
+    /// Reanalyze every stored document and republish its diagnostics.
+    ///
+    /// Returns the number of documents analyzed.
+    pub(super) async fn restart_analysis(&self) -> usize {
+        let uris = self.documents.uris();
+        for uri in &uris {
+            let Some((text, version)) = self
+                .documents
+                .get_snapshot(uri)
+                .map(|doc| (doc.rope.to_string(), doc.version))
+            else {
+                continue;
+            };
+            self.on_change(TextDocumentChange {
+                uri: uri.clone(),
+                text: &text,
+                version,
+            })
+            .await;
+        }
+        uris.len()
+    }
+
+    /// Clear the diagnostics the client shows for a document.
+    pub(super) async fn clear_diagnostics(&self, uri: Uri) {
+        if self.is_shutting_down() {
+            return;
+        }
+        self.outgoing
+            .track(self.client.publish_diagnostics(uri, vec![], None))
+            .await;
+    }
+
+    /// Handle a document change event.
+    ///
+    /// This method is called when a document is opened, changed, or saved.
+    /// It compiles the document on its worker and publishes diagnostics, unless a
+    /// later change superseded this one in the meantime.
+    pub(super) async fn on_change(&self, item: TextDocumentChange<'_>) {
+        debug!("Processing document change for: {}", item.uri);
+        if self.documents.is_stale(&item.uri, item.version) {
+            debug!(
+                "Skipping outdated version {:?} of {}",
+                item.version, item.uri
+            );
+            return;
+        }
+
+        let progress = if item.text.len() >= LARGE_FILE_THRESHOLD {
+            Some(
+                self.begin_progress(None, format!("Analyzing {}", item.uri))
+                    .await,
+            )
+        } else {
+            None
+        };
+        let document = self
+            .analysis_queue
+            .analyze(&item.uri, item.text, item.version)
+            .await;
+        if let Some(progress) = progress {
+            progress.end(None).await;
+        }
+        let Some(document) = document else {
+            debug!(
+                "Skipping superseded version {:?} of {}",
+                item.version, item.uri
+            );
+            return;
+        };
+        debug!(
+            "Compilation completed with {} diagnostics and {} semantic errors",
+            document.analysis.diagnostics.len(),
+            document.analysis.semantic.errors.len()
+        );
+        let mut diagnostics = compile_diagnostics(&item.uri, &document, self.encoding());
+
+        debug!("Processed {} total diagnostics", diagnostics.len());
+        let symbols = self.analyzer.symbols(&document.analysis);
+        let rope = document.rope.clone();
+        // A newer version may have been compiled while this one was
+        if !self.documents.insert_if_current(&item.uri, document) {
+            debug!(
+                "Dropping stale analysis of {} (version {:?})",
+                item.uri, item.version
+            );
+            return;
+        }
+        self.diagnostics_history
+            .record(&item.uri, diagnostics.clone());
+        if !self.is_pinned(&item.uri) {
+            self.enforce_memory_budget();
+        }
+
+        for pass in self.enabled_analyses.enabled() {
+            if let Some(analysis) = self.analyze_document(pass, &item.uri) {
+                diagnostics.extend(analysis.diagnostics);
+            }
+        }
+
+        let support = self.client_support();
+        let settings = self.settings_for(&item.uri);
+        self.report_project_errors().await;
+        if !support.inlay_hints && settings.enabled(Feature::InlayHints) {
+            // Clients without inlay hints get them as hint-severity diagnostics
+            let hints = self.build_inlay_hints(&item.uri, None).unwrap_or_default();
+            diagnostics.extend(hint_diagnostics(hints));
+        }
+        override_severities(&mut diagnostics, &settings.severity_overrides);
+        fit_diagnostics(&mut diagnostics, support);
+        // Only files the client sees nothing for may be skipped when reindexing, so
+        // the findings of analysis passes and hint fallbacks count as well
+        self.symbol_index
+            .record(&item.uri, &rope, symbols, diagnostics.is_empty());
+
+        // Check if the server is shutting down
+        if self.is_shutting_down() {
+            debug!("Skipping diagnostics publish - server is shutting down");
+            return;
+        }
+        // The client typed on while the diagnostics were built; the change of the newer
+        // version publishes its own, and clients that ignore the version of diagnostics
+        // would show these on the newer text until then
+        if self.documents.is_stale(&item.uri, item.version) {
+            debug!(
+                "Skipping diagnostics of outdated version {:?} of {}",
+                item.version, item.uri
+            );
+            return;
+        }
+
+        debug!(
+            "Publishing {} diagnostics for document: {}",
+            diagnostics.len(),
+            item.uri
+        );
+
+        // Double-check server status before publishing diagnostics
+        if self.is_shutting_down() {
+            debug!("Skipping diagnostics publish - server is shutting down");
+        } else if self
+            .outgoing
+            .track(self.client.publish_diagnostics(
+                item.uri.clone(),
+                diagnostics,
+                item.version.filter(|_| support.diagnostic_versions),
+            ))
+            .await
+            .is_some()
+        {
+            debug!("Diagnostics published successfully");
+        }
+
+        if !support.semantic_tokens && settings.enabled(Feature::SemanticTokens) {
+            self.publish_decorations(&item.uri).await;
+        }
+    }
+}
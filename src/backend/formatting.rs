@@ -0,0 +1,92 @@
+//! Formatting of documents, one at a time or across the workspace.
+
+use ropey::Rope;
+use tower_lsp_server::jsonrpc::Result;
+use tower_lsp_server::ls_types::{TextEdit, Uri};
+
+use super::Backend;
+use crate::analyzer::LanguageAnalyzer;
+use crate::text_diff::text_edits;
+use crate::text_pos::{Bounds, Encoding, TextPos};
+use crate::workspace_edit::WorkspaceEditBuilder;
+
+impl Backend {
+    /// Format every stored document through `workspace/applyEdit`.
+    ///
+    /// Returns whether the client applied the edit.
+    pub(super) async fn format_workspace(&self) -> Result<bool> {
+        let mut builder = WorkspaceEditBuilder::new();
+        for uri in self.documents.uris() {
+            let Some(edits) = self.format_text(&uri) else {
+                continue;
+            };
+            if edits.is_empty() {
+                continue;
+            }
+            builder.extend(&uri, edits);
+        }
+
+        if builder.is_empty() {
+            return Ok(true);
+        }
+        let edit = builder.build()?;
+        let journal_entry = self.journal_entry("Format workspace".to_string(), &edit);
+        let applied = self.apply_edit(edit).await?;
+        if applied {
+            self.refactor_journal.record(journal_entry);
+        }
+        Ok(applied)
+    }
+
+    /// Format the text of a document.
+    ///
+    /// This method uses the formatter of the analyzer to format the entire document
+    /// and returns the minimal text edits needed to apply the formatting.
+    pub(super) fn format_text(&self, uri: &Uri) -> Option<Vec<TextEdit>> {
+        let (text, formatted_text) = self.formatted_text(uri)?;
+        Some(text_edits(&text, &formatted_text, self.encoding()))
+    }
+
+    /// Run the formatter on a document, returning its current and formatted text.
+    pub(super) fn formatted_text(&self, uri: &Uri) -> Option<(String, String)> {
+        let doc = self.documents.get_snapshot(uri)?;
+        let text = doc.rope.to_string();
+        let formatted_text =
+            self.analyzer
+                .format(&text, &doc.analysis, self.settings_for(uri).format_width);
+        Some((text, formatted_text))
+    }
+}
+
+/// Apply text edits to a document's text, returning the resulting text.
+///
+/// Returns `None` if an edit range lies outside the document or edits overlap.
+pub(super) fn apply_text_edits(
+    rope: &Rope,
+    encoding: Encoding,
+    edits: &[TextEdit],
+) -> Option<String> {
+    let mut ranges = edits
+        .iter()
+        .map(|edit| {
+            let pos = TextPos::new(rope, encoding, Bounds::Strict);
+            let start = rope.byte_to_char(pos.offset(edit.range.start)?);
+            let end = rope.byte_to_char(pos.offset(edit.range.end)?);
+            (start <= end).then_some((start..end, edit.new_text.as_str()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    ranges.sort_by_key(|(range, _)| range.start);
+    if ranges
+        .windows(2)
+        .any(|pair| pair[0].0.end > pair[1].0.start)
+    {
+        return None;
+    }
+
+    let mut result = rope.clone();
+    for (range, new_text) in ranges.into_iter().rev() {
+        result.remove(range.clone());
+        result.insert(range.start, new_text);
+    }
+    Some(result.to_string())
+}
@@ -0,0 +1,175 @@
+//! Inlay hints showing the types of variables and the names of parameters.
+
+use l_lang::Type;
+use tower_lsp_server::ls_types::{
+    InlayHint, InlayHintKind, InlayHintLabel, InlayHintLabelPart, InlayHintOptions, Position,
+    Range, TextEdit, Uri, WorkDoneProgressOptions,
+};
+use tracing::debug;
+
+use super::Backend;
+use crate::parameter_hints::parameter_hints;
+use crate::symbol_docs::ResolveData;
+use crate::text_pos::{Bounds, TextPos};
+use crate::type_annotation::{missing_annotation, missing_return_type};
+
+impl Backend {
+    /// Ask the client to re-request inlay hints, if it supports refreshing them.
+    ///
+    /// Clients re-request the hints of an edited document on their own, but keep the
+    /// hints they cached for other visible documents until asked to refresh them.
+    pub(super) async fn refresh_inlay_hints(&self) {
+        let refresh_support = self
+            .client_capabilities
+            .get()
+            .and_then(|caps| caps.workspace.as_ref())
+            .and_then(|workspace| workspace.inlay_hint.as_ref())
+            .and_then(|inlay_hint| inlay_hint.refresh_support)
+            .unwrap_or(false);
+        if !refresh_support {
+            return;
+        }
+        match self.outgoing.track(self.client.inlay_hint_refresh()).await {
+            Some(Ok(())) => debug!("Requested inlay hint refresh"),
+            Some(Err(err)) => debug!("Failed to refresh inlay hints: {err}"),
+            None => {}
+        }
+    }
+
+    /// Build inlay hints for a document.
+    ///
+    /// This method analyzes the semantic information of a document and creates
+    /// inlay hints for variable types, inferred return types and the parameter names
+    /// of call arguments, each unless turned off by the settings.
+    ///
+    /// With a range, only the hints positioned in it are built. Hints carry a
+    /// [`ResolveData`] of their symbol, and [`Self::inlay_hint_resolve`] adds their
+    /// tooltip; the location of a struct type is left to it too if the client resolves
+    /// locations.
+    pub(super) fn build_inlay_hints(
+        &self,
+        uri: &Uri,
+        range: Option<Range>,
+    ) -> Option<Vec<InlayHint>> {
+        let doc = self.documents.get_snapshot(uri)?;
+        let semantic_result = &doc.analysis;
+        let rope = &doc.rope;
+        let bindings = &semantic_result.semantic.bindings;
+        let span = match range {
+            Some(range) => TextPos::new(rope, self.encoding(), Bounds::Clamp).offsets(range)?,
+            None => 0..rope.len_bytes(),
+        };
+        // Hints at the end of the range are still shown in it
+        let in_range = |offset: usize| span.start <= offset && offset <= span.end;
+        let data = |symbol_id| serde_json::to_value(ResolveData::new(uri, &doc, symbol_id)).ok();
+        let support = self.client_support();
+        let settings = self.settings_for(uri);
+        let mut hints = bindings
+            .iter_enumerated()
+            .filter_map(|(symbol_id, type_info)| {
+                if !settings.variable_type_hints
+                    || semantic_result.semantic.get_symbol_kind(symbol_id)
+                        != l_lang::SymbolKind::Variable
+                {
+                    return None;
+                }
+                // Get the symbol definition span (not the binding span)
+                let symbol_span = semantic_result.semantic.symbol_spans.get(symbol_id)?;
+                if !in_range(symbol_span.end as usize) {
+                    return None;
+                }
+                let end = TextPos::new(rope, self.encoding(), Bounds::Strict)
+                    .position(symbol_span.end as usize)?;
+                let inlay_hint_parts = match type_info.ty {
+                    Type::Struct(_) if support.inlay_hint_label_parts => {
+                        let mut parts = vec![];
+                        parts.push(InlayHintLabelPart {
+                            value: ": ".to_string(),
+                            ..Default::default()
+                        });
+                        let location = if support.inlay_hint_locations {
+                            None
+                        } else {
+                            self.struct_type_location(uri, &doc, symbol_id)
+                        };
+                        parts.push(InlayHintLabelPart {
+                            value: doc.type_label(symbol_id)?.to_string(),
+                            location,
+                            ..Default::default()
+                        });
+                        InlayHintLabel::LabelParts(parts)
+                    }
+                    _ => InlayHintLabel::String(format!(": {}", doc.type_label(symbol_id)?)),
+                };
+                // Accepting the hint writes it into the source as an annotation
+                let text_edits = missing_annotation(&doc, symbol_id).map(|annotation| {
+                    vec![TextEdit {
+                        range: Range::new(end, end),
+                        new_text: annotation.text,
+                    }]
+                });
+                Some(InlayHint {
+                    position: Position::new(end.line, end.character),
+                    label: inlay_hint_parts,
+                    kind: Some(InlayHintKind::TYPE),
+                    text_edits,
+                    tooltip: None,
+                    padding_left: Some(true),
+                    padding_right: Some(false),
+                    data: data(symbol_id),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if settings.return_type_hints {
+            hints.extend(bindings.iter_enumerated().filter_map(|(symbol_id, _)| {
+                let annotation = missing_return_type(&doc, symbol_id)
+                    .filter(|annotation| in_range(annotation.insert_at))?;
+                let position = TextPos::new(rope, self.encoding(), Bounds::Strict)
+                    .position(annotation.insert_at)?;
+                Some(InlayHint {
+                    position,
+                    label: InlayHintLabel::String(annotation.text.trim_start().to_string()),
+                    kind: Some(InlayHintKind::TYPE),
+                    // Accepting the hint writes it into the source as an annotation
+                    text_edits: Some(vec![TextEdit {
+                        range: Range::new(position, position),
+                        new_text: annotation.text,
+                    }]),
+                    tooltip: None,
+                    padding_left: Some(true),
+                    padding_right: Some(false),
+                    data: data(symbol_id),
+                })
+            }));
+        }
+
+        hints.extend(
+            parameter_hints(&doc, settings.hide_matching_parameter_hints)
+                .into_iter()
+                .filter(|hint| in_range(hint.offset))
+                .filter_map(|hint| {
+                    Some(InlayHint {
+                        position: TextPos::new(rope, self.encoding(), Bounds::Strict)
+                            .position(hint.offset)?,
+                        label: InlayHintLabel::String(format!("{}:", hint.name)),
+                        kind: Some(InlayHintKind::PARAMETER),
+                        text_edits: None,
+                        tooltip: None,
+                        padding_left: Some(false),
+                        padding_right: Some(true),
+                        data: data(hint.parameter),
+                    })
+                }),
+        );
+        Some(hints)
+    }
+}
+
+/// Options of inlay hints, announced or registered.
+pub(super) fn inlay_hint_options() -> InlayHintOptions {
+    InlayHintOptions {
+        work_done_progress_options: WorkDoneProgressOptions::default(),
+        resolve_provider: Some(true),
+    }
+}
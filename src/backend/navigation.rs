@@ -0,0 +1,325 @@
+//! Navigation of the symbols of a document: definition, hover, references, rename,
+//! and the semantic info and scopes served through custom requests.
+
+use std::collections::HashMap;
+
+use l_lang::{SymbolId, SymbolKind, Type};
+use ropey::Rope;
+use tower_lsp_server::jsonrpc::{Error, Result};
+use tower_lsp_server::ls_types::{
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams, Location,
+    MarkupContent, MarkupKind, Position, Range, SymbolInformation, TextDocumentPositionParams, Uri,
+    WorkspaceEdit,
+};
+use tracing::debug;
+
+use super::Backend;
+use crate::analyzer::{LLang, LanguageAnalyzer};
+use crate::cancellation::CancellationToken;
+use crate::document_store::Document;
+use crate::scopes::{Scope, ScopeSpan, ScopeSymbol, ScopesParams, scope_tree};
+use crate::semantic_info::{SemanticInfo, symbol_kind_name};
+use crate::symbol_at::pick_symbol_at;
+use crate::symbol_docs::symbol_documentation;
+use crate::symbol_index::IndexedSymbol;
+use crate::text_pos::{Bounds, Encoding, TextPos};
+use crate::uri_to_file_path;
+use crate::workspace_edit::WorkspaceEditBuilder;
+
+impl Backend {
+    /// Check if the symbol at a position is a function parameter.
+    pub(super) fn is_parameter_at(&self, uri: &Uri, position: Position) -> bool {
+        let Some(doc) = self.documents.get_snapshot(uri) else {
+            return false;
+        };
+        TextPos::new(&doc.rope, self.encoding(), Bounds::Clamp)
+            .offset(position)
+            .and_then(|offset| pick_symbol_at(&doc.analysis, offset))
+            .is_some_and(|symbol| {
+                doc.analysis.semantic.get_symbol_kind(symbol.symbol_id) == SymbolKind::Parameter
+            })
+    }
+
+    /// Return the resolved semantic info of the symbol at a position.
+    ///
+    /// This custom request exposes the symbol id, kind, type, span and references the
+    /// semantic analysis computed, to debug the other language features.
+    pub(super) async fn semantic_info(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<SemanticInfo>> {
+        self.check_position(&params.text_document.uri, params.position)?;
+        Ok(self.get_semantic_info(&params))
+    }
+
+    /// Handle the `l/scopes` request.
+    ///
+    /// Returns the scope tree of a document, limited to the scopes overlapping the
+    /// requested range, or `None` if the document isn't known.
+    pub(super) async fn scopes(&self, params: ScopesParams) -> Result<Option<Scope>> {
+        if let Some(range) = params.range {
+            self.check_range(&params.text_document.uri, range)?;
+        }
+        let Some(doc) = self.documents.get_snapshot(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        let mut tree = scope_tree(&doc);
+        if let Some(range) = params.range {
+            let Some(span) =
+                TextPos::new(&doc.rope, self.encoding(), Bounds::Strict).offsets(range)
+            else {
+                return Err(Error::invalid_params("range is outside the document"));
+            };
+            tree.retain_overlapping(&span);
+        }
+        Ok(scope_to_lsp(&doc, self.encoding(), tree))
+    }
+
+    /// Resolve the symbol at a position and collect its semantic info.
+    ///
+    /// Both the definition of a symbol and references to it resolve to the symbol.
+    pub(super) fn get_semantic_info(
+        &self,
+        params: &TextDocumentPositionParams,
+    ) -> Option<SemanticInfo> {
+        let doc = self.documents.get_snapshot(&params.text_document.uri)?;
+        let rope = &doc.rope;
+        let semantic = &doc.analysis.semantic;
+        let offset = TextPos::new(rope, self.encoding(), Bounds::Clamp).offset(params.position)?;
+
+        let symbol_id = pick_symbol_at(&doc.analysis, offset)?.symbol_id;
+
+        let symbol_span = semantic.get_symbol_span(symbol_id);
+        let span = symbol_span.start as usize..symbol_span.end as usize;
+        let references = semantic
+            .get_symbol_references(symbol_id)
+            .iter()
+            .filter_map(|ref_id| {
+                let span = semantic.reference_spans.get(*ref_id)?;
+                let start = TextPos::new(rope, self.encoding(), Bounds::Strict)
+                    .position(span.start as usize)?;
+                let end = TextPos::new(rope, self.encoding(), Bounds::Strict)
+                    .position(span.end as usize)?;
+                Some(Range::new(start, end))
+            })
+            .collect();
+
+        Some(SemanticInfo {
+            symbol_id: symbol_id.index(),
+            name: doc.symbol_name(symbol_id)?.to_string(),
+            kind: symbol_kind_name(semantic.get_symbol_kind(symbol_id)),
+            ty: doc.type_label(symbol_id).map(str::to_string),
+            span: TextPos::new(rope, self.encoding(), Bounds::Strict).range(span.clone())?,
+            references,
+        })
+    }
+
+    /// Locate the declaration of the struct a variable has the type of.
+    pub(super) fn struct_type_location(
+        &self,
+        uri: &Uri,
+        doc: &Document,
+        symbol_id: SymbolId,
+    ) -> Option<Location> {
+        let semantic = &doc.analysis.semantic;
+        let Type::Struct(id) = semantic.get_symbol_type(symbol_id)?.ty else {
+            return None;
+        };
+        let span = semantic.get_symbol_span(id);
+        let start = TextPos::new(&doc.rope, self.encoding(), Bounds::Strict)
+            .position(span.start as usize)?;
+        let end =
+            TextPos::new(&doc.rope, self.encoding(), Bounds::Strict).position(span.end as usize)?;
+        Some(Location::new(uri.clone(), Range::new(start, end)))
+    }
+
+    /// Get the definition location for a symbol at a given position.
+    ///
+    /// This method finds the symbol referenced or defined at the given position and
+    /// returns the location of its definition.
+    pub(super) fn get_definition(
+        &self,
+        params: &GotoDefinitionParams,
+    ) -> Option<GotoDefinitionResponse> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let doc = self.documents.get_snapshot(uri)?;
+        let rope = &doc.rope;
+        let offset = TextPos::new(rope, self.encoding(), Bounds::Clamp).offset(position)?;
+        let symbol_id = pick_symbol_at(&doc.analysis, offset)?.symbol_id;
+
+        let symbol_span = doc.analysis.semantic.get_symbol_span(symbol_id);
+        let start = TextPos::new(rope, self.encoding(), Bounds::Strict)
+            .position(symbol_span.start as usize)?;
+        let end = TextPos::new(rope, self.encoding(), Bounds::Strict)
+            .position(symbol_span.end as usize)?;
+        let location = Location::new(uri.clone(), Range::new(start, end));
+        Some(GotoDefinitionResponse::Scalar(location))
+    }
+
+    /// Describe the symbol at a given position, for a hover.
+    pub(super) fn get_hover(&self, params: &HoverParams) -> Option<Hover> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let doc = self.documents.get_snapshot(uri)?;
+        let offset = TextPos::new(&doc.rope, self.encoding(), Bounds::Clamp).offset(position)?;
+        let symbol = pick_symbol_at(&doc.analysis, offset)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: symbol_documentation(&doc, symbol.symbol_id)?,
+            }),
+            range: TextPos::new(&doc.rope, self.encoding(), Bounds::Strict).range(symbol.span),
+        })
+    }
+
+    /// Get all references to a symbol at a given position.
+    ///
+    /// This method finds the symbol at the given position and returns
+    /// all locations where this symbol is referenced. Returns `None` once `token`
+    /// is cancelled.
+    pub(super) fn get_references(
+        analyzer: &LLang,
+        doc: &Document,
+        encoding: Encoding,
+        uri: &Uri,
+        position: Position,
+        include_declaration: bool,
+        token: &CancellationToken,
+    ) -> Option<Vec<Location>> {
+        let rope = &doc.rope;
+        let offset = TextPos::new(rope, encoding, Bounds::Clamp).offset(position)?;
+        let found = analyzer.references(&doc.analysis, offset)?;
+
+        let mut spans = Vec::new();
+        if include_declaration {
+            // Include the symbol definition itself
+            spans.push(found.definition);
+        }
+        spans.extend(found.references);
+
+        let mut references = Vec::new();
+        for span in spans {
+            if token.is_cancelled() {
+                debug!("References request cancelled");
+                return None;
+            }
+            let range = TextPos::new(rope, encoding, Bounds::Strict).range(span)?;
+            references.push(Location::new(uri.clone(), range));
+        }
+        Some(references)
+    }
+
+    /// Locate the indexed symbols `found` in the workspace, given the ropes of the
+    /// stored documents among their files.
+    ///
+    /// Files the symbol index knows without storing them are read from disk. The token
+    /// is checked before each file, returning `None` once it is cancelled.
+    pub(super) fn get_workspace_symbols(
+        found: Vec<(Uri, IndexedSymbol)>,
+        mut stored: HashMap<Uri, Rope>,
+        encoding: Encoding,
+        token: &CancellationToken,
+    ) -> Option<Vec<SymbolInformation>> {
+        let mut ropes: HashMap<Uri, Option<Rope>> = HashMap::new();
+        let mut symbols = Vec::new();
+        for (uri, symbol) in found {
+            if !ropes.contains_key(&uri) {
+                if token.is_cancelled() {
+                    debug!("Workspace symbol search cancelled");
+                    return None;
+                }
+                let rope = stored.remove(&uri).or_else(|| {
+                    let text = std::fs::read_to_string(uri_to_file_path(&uri)?).ok()?;
+                    Some(Rope::from_str(&text))
+                });
+                ropes.insert(uri.clone(), rope);
+            }
+            let Some(Some(rope)) = ropes.get(&uri) else {
+                continue;
+            };
+            let Some(range) =
+                TextPos::new(rope, encoding, Bounds::Strict).range(symbol.span.clone())
+            else {
+                continue;
+            };
+            #[allow(deprecated)]
+            symbols.push(SymbolInformation {
+                kind: symbol.kind(),
+                name: symbol.name,
+                tags: None,
+                deprecated: None,
+                location: Location::new(uri, range),
+                container_name: None,
+            });
+        }
+        Some(symbols)
+    }
+
+    /// Create a workspace edit for renaming a symbol.
+    ///
+    /// This method finds all references to the symbol at the given position
+    /// and creates a workspace edit that replaces them with the new name. The
+    /// references are looked up in `version` of the document, see
+    /// [`Self::snapshot_at_version`].
+    pub(super) fn get_rename_edit(
+        &self,
+        uri: &Uri,
+        version: Option<i32>,
+        position: Position,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>> {
+        let Some(doc) = self.snapshot_at_version(uri, version)? else {
+            return Ok(None);
+        };
+        let Some(all_reference) = Self::get_references(
+            &self.analyzer,
+            &doc,
+            self.encoding(),
+            uri,
+            position,
+            true,
+            &CancellationToken::new(),
+        ) else {
+            return Ok(None);
+        };
+
+        let mut builder = WorkspaceEditBuilder::new();
+        for item in all_reference {
+            builder.replace(uri, item.range, new_name);
+        }
+        Ok(Some(builder.build()?))
+    }
+}
+
+/// Convert a scope with byte spans into its `l/scopes` representation.
+fn scope_to_lsp(doc: &Document, encoding: Encoding, scope: ScopeSpan) -> Option<Scope> {
+    let range = |span: std::ops::Range<usize>| {
+        TextPos::new(&doc.rope, encoding, Bounds::Strict).range(span)
+    };
+    let semantic = &doc.analysis.semantic;
+    let symbols = scope
+        .symbols
+        .into_iter()
+        .filter_map(|symbol_id| {
+            let span = semantic.get_symbol_span(symbol_id);
+            let span = span.start as usize..span.end as usize;
+            Some(ScopeSymbol {
+                name: doc.symbol_name(symbol_id)?.to_string(),
+                kind: symbol_kind_name(semantic.get_symbol_kind(symbol_id)),
+                range: range(span)?,
+            })
+        })
+        .collect();
+    Some(Scope {
+        kind: scope.kind,
+        range: range(scope.span)?,
+        symbols,
+        children: scope
+            .children
+            .into_iter()
+            .filter_map(|child| scope_to_lsp(doc, encoding, child))
+            .collect(),
+    })
+}
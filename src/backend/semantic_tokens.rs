@@ -0,0 +1,196 @@
+//! Semantic tokens, and the decorations replacing them for clients without support.
+
+use tower_lsp_server::ls_types::{
+    Range, SemanticToken, SemanticTokenType, SemanticTokensFullOptions, SemanticTokensLegend,
+    SemanticTokensOptions, SemanticTokensRegistrationOptions, StaticRegistrationOptions,
+    TextDocumentRegistrationOptions, Uri, WorkDoneProgressOptions,
+};
+use tracing::debug;
+
+use super::{Backend, l_document_selector};
+use crate::analyzer::{LLang, LanguageAnalyzer};
+use crate::cancellation::CancellationToken;
+use crate::compat::{PublishDecorations, PublishDecorationsParams, decorations_from_tokens};
+use crate::document_store::Document;
+use crate::grammar::Grammar;
+use crate::lexical_tokens::{LexicalKind, lexical_tokens};
+use crate::semantic_tokens::encode_tokens;
+use crate::text_pos::{Bounds, Encoding, TextPos};
+
+/// Semantic token types in the order of the legend announced to the client.
+const LEGEND_TYPE: &[SemanticTokenType] = &[
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::STRUCT,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::STRING,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::OPERATOR,
+];
+
+/// Size in bytes above which only range semantic tokens are served.
+///
+/// The tokens of a whole generated file can reach megabytes, which freezes some clients;
+/// highlighting the viewport through range requests keeps them responsive.
+pub(super) const FULL_SEMANTIC_TOKENS_LIMIT: usize = 1024 * 1024;
+
+impl Backend {
+    /// Ask the client to re-request semantic tokens, if it supports refreshing them.
+    pub(super) async fn refresh_semantic_tokens(&self) {
+        let refresh_support = self
+            .client_capabilities
+            .get()
+            .and_then(|caps| caps.workspace.as_ref())
+            .and_then(|workspace| workspace.semantic_tokens.as_ref())
+            .and_then(|semantic_tokens| semantic_tokens.refresh_support)
+            .unwrap_or(false);
+        if !refresh_support {
+            return;
+        }
+        match self
+            .outgoing
+            .track(self.client.semantic_tokens_refresh())
+            .await
+        {
+            Some(Ok(())) => debug!("Requested semantic tokens refresh"),
+            Some(Err(err)) => debug!("Failed to refresh semantic tokens: {err}"),
+            None => {}
+        }
+    }
+
+    /// Send the semantic tokens of a document as `l/publishDecorations`.
+    ///
+    /// This is the fallback for clients that don't request semantic tokens.
+    pub(super) async fn publish_decorations(&self, uri: &Uri) {
+        let tokens = self
+            .documents
+            .get_snapshot(uri)
+            .and_then(|doc| {
+                Self::build_semantic_tokens(
+                    &self.analyzer,
+                    &doc,
+                    &self.grammar.get(),
+                    self.encoding(),
+                    &CancellationToken::new(),
+                )
+            })
+            .unwrap_or_default();
+        let params = PublishDecorationsParams {
+            uri: uri.clone(),
+            decorations: decorations_from_tokens(&tokens, LEGEND_TYPE),
+        };
+        self.outgoing
+            .track(self.client.send_notification::<PublishDecorations>(params))
+            .await;
+    }
+
+    /// Collect the spans of the semantic tokens of a document, unsorted.
+    ///
+    /// Token type indices correspond to `LEGEND_TYPE` order: 0: FUNCTION, 1: VARIABLE,
+    /// 2: PARAMETER, 3: STRUCT, 4: PROPERTY (field) for symbols and their references,
+    /// taken from `analyzer`, then the lexical tokens of the text, with keywords taken
+    /// from `grammar`. The spans are cached per version, so the keywords of a reloaded
+    /// grammar show up once the document changes. Returns `None` once `token` is
+    /// cancelled.
+    fn collect_token_spans(
+        analyzer: &LLang,
+        doc: &Document,
+        grammar: &Grammar,
+        token: &CancellationToken,
+    ) -> Option<Vec<(usize, usize, u32)>> {
+        // Symbol definitions and references
+        let mut incomplete_tokens = analyzer.tokens(&doc.analysis); // (start, length, token_type)
+
+        // Add keywords, literals, comments and operators, which have no symbols
+        if token.is_cancelled() {
+            debug!("Semantic tokens request cancelled");
+            return None;
+        }
+        let text = doc.rope.to_string();
+        for (span, kind) in lexical_tokens(&text, &grammar.keywords) {
+            incomplete_tokens.push((
+                span.start,
+                span.len(),
+                Self::lexical_kind_to_token_type(kind),
+            ));
+        }
+        Some(incomplete_tokens)
+    }
+
+    /// Build semantic tokens for an entire document.
+    ///
+    /// The token spans are cached in the document, so later requests for the same
+    /// version only redo the delta encoding.
+    pub(super) fn build_semantic_tokens(
+        analyzer: &LLang,
+        doc: &Document,
+        grammar: &Grammar,
+        encoding: Encoding,
+        token: &CancellationToken,
+    ) -> Option<Vec<SemanticToken>> {
+        let spans =
+            doc.token_spans(|doc| Self::collect_token_spans(analyzer, doc, grammar, token))?;
+        Some(encode_tokens(
+            spans,
+            &doc.rope,
+            encoding,
+            0..doc.rope.len_bytes(),
+        ))
+    }
+
+    /// Build semantic tokens for a specific range in a document.
+    ///
+    /// Tokens intersecting the range are included, taken from the token spans cached in
+    /// the document, with the lines of multi-line tokens outside of the range left out.
+    pub(super) fn build_semantic_tokens_range(
+        analyzer: &LLang,
+        doc: &Document,
+        grammar: &Grammar,
+        encoding: Encoding,
+        range: Range,
+        token: &CancellationToken,
+    ) -> Option<Vec<SemanticToken>> {
+        // Convert range to byte offsets
+        let span = TextPos::new(&doc.rope, encoding, Bounds::Clamp).offsets(range)?;
+
+        let spans =
+            doc.token_spans(|doc| Self::collect_token_spans(analyzer, doc, grammar, token))?;
+        Some(encode_tokens(spans, &doc.rope, encoding, span))
+    }
+
+    /// Convert `LexicalKind` to semantic token type.
+    ///
+    /// Token type indices correspond to `LEGEND_TYPE` order:
+    /// 5: KEYWORD, 6: NUMBER, 7: STRING, 8: COMMENT, 9: OPERATOR
+    const fn lexical_kind_to_token_type(kind: LexicalKind) -> u32 {
+        match kind {
+            LexicalKind::Keyword => 5,
+            LexicalKind::Number => 6,
+            LexicalKind::String => 7,
+            LexicalKind::Comment => 8,
+            LexicalKind::Operator => 9,
+        }
+    }
+}
+
+/// Options of semantic tokens, announced or registered.
+pub(super) fn semantic_tokens_options() -> SemanticTokensRegistrationOptions {
+    SemanticTokensRegistrationOptions {
+        text_document_registration_options: TextDocumentRegistrationOptions {
+            document_selector: Some(l_document_selector()),
+        },
+        semantic_tokens_options: SemanticTokensOptions {
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+            legend: SemanticTokensLegend {
+                token_types: LEGEND_TYPE.to_vec(),
+                token_modifiers: vec![],
+            },
+            range: Some(true),
+            full: Some(SemanticTokensFullOptions::Bool(true)),
+        },
+        static_registration_options: StaticRegistrationOptions::default(),
+    }
+}
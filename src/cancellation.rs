@@ -0,0 +1,75 @@
+//! Cooperative cancellation of long-running request handlers.
+//!
+//! When the client sends `$/cancelRequest`, the handler future is dropped at its next
+//! await point. Work that runs synchronously never reaches one, so expensive handlers
+//! run it on a blocking task through [`run_cancellable`] instead and poll the
+//! [`CancellationToken`], which is cancelled as soon as the handler future is dropped.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing::debug;
+
+/// Shared flag telling a piece of work that its result is no longer needed.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    /// Set once the work is cancelled
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the work observing this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Check if the work observing this token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Check if two tokens are clones of each other.
+    pub fn is_same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+/// Cancels a token when dropped.
+#[derive(Debug)]
+struct CancelOnDrop {
+    /// The token to cancel
+    token: CancellationToken,
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+/// Run synchronous work on a blocking task, cancelling it if the caller is dropped.
+///
+/// `work` should poll the token regularly and return `None` once it is cancelled.
+/// Also returns `None` if the blocking task panicked.
+pub async fn run_cancellable<T, F>(work: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&CancellationToken) -> Option<T> + Send + 'static,
+{
+    let token = CancellationToken::new();
+    let _guard = CancelOnDrop {
+        token: token.clone(),
+    };
+    match tokio::task::spawn_blocking(move || work(&token)).await {
+        Ok(result) => result,
+        Err(err) => {
+            debug!("Cancellable work failed: {err}");
+            None
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! The `check` subcommand, reporting the diagnostics of files without an editor.
+//!
+//! Files are compiled and converted to diagnostics exactly as the server does for
+//! documents it analyzes, with the lint levels of their `l.toml` applied, so CI sees
+//! what editors show. The exit code is 1 if any diagnostic is an error, and 2 if a
+//! path can't be read.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use anyhow::{Context, bail};
+use l_lang::compile;
+use ropey::Rope;
+use serde::Serialize;
+use tower_lsp_server::ls_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+use crate::cli::{CheckArgs, OutputFormat};
+use crate::compile_diagnostics::compile_diagnostics;
+use crate::diagnostic_codes::override_severities;
+use crate::document_store::Document;
+use crate::project_config::{PROJECT_FILE, Projects};
+use crate::text_pos::Encoding;
+use crate::{collect_source_files, file_path_to_uri, uri_to_file_path};
+
+/// The diagnostics of one file, as printed with `--format json`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileReport {
+    /// The checked file
+    path: PathBuf,
+    /// Its diagnostics, in LSP form
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Check the files the arguments name, print their diagnostics and return the exit code.
+pub fn run(args: &CheckArgs) -> ExitCode {
+    let projects = Projects::default();
+    let mut unreadable = false;
+    let mut reports = Vec::new();
+    for path in args
+        .paths
+        .iter()
+        .flat_map(|path| source_files(path, &projects))
+    {
+        match path.and_then(|path| check_file(&path, &projects)) {
+            Ok(report) => reports.push(report),
+            Err(err) => {
+                eprintln!("error: {err:#}");
+                unreadable = true;
+            }
+        }
+    }
+
+    for err in projects.take_errors() {
+        eprintln!("warning: ignoring the project configuration: {err}");
+    }
+
+    match args.format {
+        OutputFormat::Human => print_human(&reports),
+        OutputFormat::Json => match serde_json::to_string_pretty(&reports) {
+            Ok(json) => println!("{json}"),
+            Err(err) => {
+                eprintln!("error: {err}");
+                unreadable = true;
+            }
+        },
+    }
+
+    let has_errors = reports
+        .iter()
+        .flat_map(|report| &report.diagnostics)
+        .any(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR));
+    if unreadable {
+        ExitCode::from(2)
+    } else if has_errors {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// The L source files a path names: the file itself, the files below a directory, or
+/// the files matching a glob pattern.
+///
+/// A directory holding an `l.toml` with source roots only contributes those.
+pub fn source_files(path: &str, projects: &Projects) -> Vec<anyhow::Result<PathBuf>> {
+    let as_path = Path::new(path);
+    if as_path.is_file() {
+        return vec![Ok(as_path.to_path_buf())];
+    }
+    if as_path.is_dir() {
+        let dirs = match projects
+            .for_file(&as_path.join(PROJECT_FILE))
+            .filter(|project| project.root == as_path)
+        {
+            Some(project) => project.source_dirs(),
+            None => vec![as_path.to_path_buf()],
+        };
+        return dirs
+            .iter()
+            .flat_map(|dir| collect_source_files(dir))
+            .map(Ok)
+            .collect();
+    }
+    let matches = match glob::glob(path) {
+        Ok(matches) => matches,
+        Err(err) => return vec![Err(err).with_context(|| format!("invalid pattern {path}"))],
+    };
+    let files = matches
+        .map(|entry| entry.context("failed to read a match of the pattern"))
+        .filter(|entry| entry.as_ref().map_or(true, |path| path.is_file()))
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        let err = anyhow::anyhow!("no such file, directory or match: {path}");
+        return vec![Err(err)];
+    }
+    files
+}
+
+/// Compile a file and compute its diagnostics.
+fn check_file(path: &Path, projects: &Projects) -> anyhow::Result<FileReport> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let absolute = std::path::absolute(path)?;
+    let Some(uri) = file_path_to_uri(&absolute) else {
+        bail!("{} isn't a valid file path", path.display());
+    };
+    let document = Document::new(Rope::from_str(&text), compile(&text), None);
+    let mut diagnostics = compile_diagnostics(&uri, &document, Encoding::Utf32);
+    let settings = projects.settings_for(&absolute, Arc::default());
+    override_severities(&mut diagnostics, &settings.severity_overrides);
+    Ok(FileReport {
+        path: path.to_path_buf(),
+        diagnostics,
+    })
+}
+
+/// Print diagnostics as `path:line:column: severity[code]: message` lines, followed by
+/// a summary.
+fn print_human(reports: &[FileReport]) {
+    let mut errors = 0;
+    let mut warnings = 0;
+    for report in reports {
+        for diagnostic in &report.diagnostics {
+            let severity = diagnostic.severity.unwrap_or(DiagnosticSeverity::ERROR);
+            if severity == DiagnosticSeverity::ERROR {
+                errors += 1;
+            } else if severity == DiagnosticSeverity::WARNING {
+                warnings += 1;
+            }
+            let code = match &diagnostic.code {
+                Some(NumberOrString::String(code)) => format!("[{code}]"),
+                Some(NumberOrString::Number(code)) => format!("[{code}]"),
+                None => String::new(),
+            };
+            let start = diagnostic.range.start;
+            println!(
+                "{}:{}:{}: {}{code}: {}",
+                report.path.display(),
+                start.line + 1,
+                start.character + 1,
+                severity_name(severity),
+                diagnostic.message
+            );
+            for related in diagnostic.related_information.iter().flatten() {
+                let path = uri_to_file_path(&related.location.uri).unwrap_or_default();
+                let start = related.location.range.start;
+                println!(
+                    "  {}:{}:{}: {}",
+                    path.display(),
+                    start.line + 1,
+                    start.character + 1,
+                    related.message
+                );
+            }
+        }
+    }
+    println!(
+        "{} files checked: {errors} errors, {warnings} warnings",
+        reports.len()
+    );
+}
+
+/// The name a severity is printed with.
+fn severity_name(severity: DiagnosticSeverity) -> &'static str {
+    match severity {
+        DiagnosticSeverity::WARNING => "warning",
+        DiagnosticSeverity::INFORMATION => "info",
+        DiagnosticSeverity::HINT => "hint",
+        _ => "error",
+    }
+}
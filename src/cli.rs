@@ -0,0 +1,264 @@
+//! Command line interface of the server binary.
+//!
+//! `l-language-server serve` runs the server, over stdio unless told to listen for a
+//! connection. Running the binary without a subcommand does the same, so editors
+//! launching it bare keep working. Logging is configured with `--log-level`,
+//! `--log-file` and `--log-max-size` instead of `RUST_LOG`, which is still read when
+//! they aren't given, see [`crate::logging`].
+//! `l-language-server check` prints the diagnostics of files instead, see
+//! [`crate::check`], `l-language-server format` formats them, see [`crate::format`], and
+//! `l-language-server index` exports an LSIF dump of them, see [`crate::lsif`].
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use tracing_subscriber::filter::LevelFilter;
+
+use crate::transport::Transport;
+
+/// Language server for the L language.
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    /// What to run, `serve` if omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Options of `serve` when no subcommand is given
+    #[command(flatten)]
+    pub serve: ServeArgs,
+    /// Options of the log
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+/// A subcommand of the binary.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the language server
+    Serve(ServeArgs),
+    /// Print the diagnostics of L files, failing if any is an error
+    Check(CheckArgs),
+    /// Format L files in place
+    Format(FormatArgs),
+    /// Export the definitions, references and hovers of a workspace as an LSIF dump
+    Index(IndexArgs),
+}
+
+/// Options of the `serve` subcommand.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Args)]
+pub struct ServeArgs {
+    /// Accept a single TCP connection on this address instead of using stdio
+    #[arg(long, value_name = "ADDR", group = "address")]
+    pub listen: Option<SocketAddr>,
+    /// Accept a single TCP connection on this port of 127.0.0.1 instead of using stdio
+    #[arg(long, group = "address")]
+    pub port: Option<u16>,
+    /// Speak WebSocket on the connection, for browser-based editors
+    #[arg(long, requires = "address")]
+    pub websocket: bool,
+    /// Reject messages with unknown fields or invalid positions
+    #[arg(long)]
+    pub strict_protocol: bool,
+}
+
+/// Options of the `check` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct CheckArgs {
+    /// Files, directories or glob patterns to check
+    #[arg(required = true, value_name = "PATH")]
+    pub paths: Vec<String>,
+    /// How diagnostics are printed
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+    pub format: OutputFormat,
+}
+
+/// Options of the `format` subcommand.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Args)]
+pub struct FormatArgs {
+    /// Files, directories or glob patterns to format
+    #[arg(
+        required_unless_present = "stdin",
+        conflicts_with = "stdin",
+        value_name = "PATH"
+    )]
+    pub paths: Vec<String>,
+    /// Only list unformatted files, failing if there are any
+    #[arg(long)]
+    pub check: bool,
+    /// Format standard input to standard output
+    #[arg(long)]
+    pub stdin: bool,
+}
+
+/// Options of the `index` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct IndexArgs {
+    /// Root of the workspace to index
+    #[arg(default_value = ".", value_name = "ROOT")]
+    pub root: PathBuf,
+    /// File the dump is written to
+    #[arg(long, short, default_value = "dump.lsif", value_name = "PATH")]
+    pub output: PathBuf,
+}
+
+/// How the `check` subcommand prints diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One `path:line:column: severity[code]: message` line per diagnostic
+    Human,
+    /// The diagnostics of each file as LSP JSON
+    Json,
+}
+
+/// Options of the log, written to stderr by default.
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct LogArgs {
+    /// Most verbose level logged: off, error, warn, info, debug or trace
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<LevelFilter>,
+    /// Write the log to this file instead of stderr
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// Size in MiB past which the log file is rotated
+    #[arg(long, global = true, default_value_t = 10, value_name = "MIB")]
+    pub log_max_size: u64,
+}
+
+impl ServeArgs {
+    /// The connection the server is served over.
+    pub fn transport(&self) -> Transport {
+        let address = self.listen.or_else(|| {
+            self.port
+                .map(|port| SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+        });
+        match (address, self.websocket) {
+            (None, _) => Transport::Stdio,
+            (Some(address), false) => Transport::Tcp(address),
+            (Some(address), true) => Transport::WebSocket(address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Option<Cli> {
+        Cli::try_parse_from(["l-language-server"].iter().chain(args)).ok()
+    }
+
+    fn serve_args(args: &[&str]) -> Option<ServeArgs> {
+        match parse(args)? {
+            Cli {
+                command: None,
+                serve,
+                ..
+            } => Some(serve),
+            Cli {
+                command: Some(Command::Serve(serve)),
+                ..
+            } => Some(serve),
+            Cli { .. } => None,
+        }
+    }
+
+    #[test]
+    fn serve_options_are_parsed_with_or_without_subcommand() {
+        let strict = ServeArgs {
+            strict_protocol: true,
+            ..ServeArgs::default()
+        };
+        assert_eq!(serve_args(&[]), Some(ServeArgs::default()));
+        assert_eq!(serve_args(&["--strict-protocol"]), Some(strict.clone()));
+        assert_eq!(serve_args(&["serve", "--strict-protocol"]), Some(strict));
+        assert_eq!(serve_args(&["--port", "1", "--listen", "0.0.0.0:1"]), None);
+        assert_eq!(serve_args(&["--port", "x"]), None);
+
+        let transport = |args: &[&str]| serve_args(args).map(|args| args.transport());
+        assert_eq!(transport(&["serve"]), Some(Transport::Stdio));
+        assert_eq!(
+            transport(&["serve", "--listen", "0.0.0.0:9257"]),
+            Some(Transport::Tcp(SocketAddr::from(([0, 0, 0, 0], 9257))))
+        );
+        assert_eq!(
+            transport(&["--websocket", "--port=9257"]),
+            Some(Transport::WebSocket(SocketAddr::from((
+                Ipv4Addr::LOCALHOST,
+                9257
+            ))))
+        );
+        assert_eq!(transport(&["--websocket"]), None);
+    }
+
+    #[test]
+    fn check_takes_paths_and_a_format() {
+        let check = |args: &[&str]| match parse(args)?.command? {
+            Command::Check(check) => Some(check),
+            Command::Serve(_) | Command::Format(_) | Command::Index(_) => None,
+        };
+        assert_eq!(
+            check(&["check", "src", "**/*.l", "--format", "json"]),
+            Some(CheckArgs {
+                paths: vec!["src".to_string(), "**/*.l".to_string()],
+                format: OutputFormat::Json,
+            })
+        );
+        assert_eq!(
+            check(&["check", "a.l"]).map(|check| check.format),
+            Some(OutputFormat::Human)
+        );
+        assert_eq!(check(&["check"]), None);
+        assert_eq!(check(&["check", "a.l", "--port", "1"]), None);
+    }
+
+    #[test]
+    fn format_takes_paths_or_stdin() {
+        let format = |args: &[&str]| match parse(args)?.command? {
+            Command::Format(format) => Some(format),
+            Command::Serve(_) | Command::Check(_) | Command::Index(_) => None,
+        };
+        assert_eq!(
+            format(&["format", "src", "--check"]),
+            Some(FormatArgs {
+                paths: vec!["src".to_string()],
+                check: true,
+                stdin: false,
+            })
+        );
+        assert_eq!(
+            format(&["format", "--stdin"]).map(|format| format.stdin),
+            Some(true)
+        );
+        assert_eq!(format(&["format"]), None);
+        assert_eq!(format(&["format", "--stdin", "a.l"]), None);
+    }
+
+    #[test]
+    fn index_defaults_to_the_current_directory() {
+        let index = |args: &[&str]| match parse(args)?.command? {
+            Command::Index(index) => Some(index),
+            Command::Serve(_) | Command::Check(_) | Command::Format(_) => None,
+        };
+        assert_eq!(
+            index(&["index"]),
+            Some(IndexArgs {
+                root: PathBuf::from("."),
+                output: PathBuf::from("dump.lsif"),
+            })
+        );
+        assert_eq!(
+            index(&["index", "app", "--output", "app.lsif"]).map(|index| index.output),
+            Some(PathBuf::from("app.lsif"))
+        );
+    }
+
+    #[test]
+    fn log_options_are_global() {
+        let cli = Cli::try_parse_from(["l-language-server", "serve", "--log-level", "trace"]);
+        assert_eq!(
+            cli.ok().and_then(|cli| cli.log.log_level),
+            Some(LevelFilter::TRACE)
+        );
+    }
+}
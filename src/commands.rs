@@ -0,0 +1,159 @@
+//! Registry of the commands served through `workspace/executeCommand`.
+//!
+//! Every command the server advertises is listed in [`Command::NAMES`] and parsed into
+//! a typed [`Command`] together with its deserialized arguments; `Backend` then
+//! dispatches on the parsed command.
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tower_lsp_server::jsonrpc::{Error, Result};
+use tower_lsp_server::ls_types::{TextDocumentPositionParams, Uri};
+
+use crate::completion::COMPLETION_STATS_COMMAND;
+use crate::diagnostics_history::{
+    DIFF_DIAGNOSTICS_COMMAND, GOTO_FIRST_ERROR_COMMAND, GotoFirstErrorArgs,
+};
+use crate::enabled_analyses::{SET_ANALYSIS_ENABLED_COMMAND, SetAnalysisEnabledArgs};
+use crate::examples::{BROWSE_EXAMPLES_COMMAND, OPEN_EXAMPLE_COMMAND, OpenExampleArgs};
+use crate::grammar::RELOAD_GRAMMAR_COMMAND;
+use crate::refactor_journal::UNDO_LAST_REFACTORING_COMMAND;
+use crate::semantic_info::SHOW_SEMANTIC_INFO_COMMAND;
+use crate::server_status::SERVER_STATUS_COMMAND;
+use crate::stdlib::RELOAD_STDLIB_COMMAND;
+use crate::suggestions::FIX_ALL_COMMAND;
+use crate::text_diff::PREVIEW_FORMAT_COMMAND;
+use crate::whats_new::WHATS_NEW_COMMAND;
+
+/// Name of the command that reanalyzes every stored document.
+pub const RESTART_ANALYSIS_COMMAND: &str = "l.restartAnalysis";
+
+/// Name of the command that formats every stored document.
+pub const FORMAT_WORKSPACE_COMMAND: &str = "l.formatWorkspace";
+
+/// Name of the command that pretty-prints the AST of a document.
+pub const SHOW_AST_COMMAND: &str = "l.showAst";
+
+/// A parsed `workspace/executeCommand` request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Reanalyze every stored document and republish its diagnostics
+    RestartAnalysis,
+    /// Format every stored document through `workspace/applyEdit`
+    FormatWorkspace,
+    /// Return a unified diff of what formatting a document would change
+    PreviewFormat(DocumentArgs),
+    /// Pretty-print the AST of a document into a virtual document and open it
+    ShowAst(DocumentArgs),
+    /// Compare the diagnostics of a document with those at its last save
+    DiffDiagnostics(DocumentArgs),
+    /// Return the location of the first error in a document or the workspace
+    GotoFirstError(GotoFirstErrorArgs),
+    /// Apply every suggested fix of a document through `workspace/applyEdit`
+    FixAll(DocumentArgs),
+    /// Return the resolved semantic info of the symbol at a position
+    ShowSemanticInfo(TextDocumentPositionParams),
+    /// Enable or disable an analysis pass for the workspace
+    SetAnalysisEnabled(SetAnalysisEnabledArgs),
+    /// List the bundled example programs
+    BrowseExamples,
+    /// Create a file from a bundled example program
+    OpenExample(OpenExampleArgs),
+    /// Revert the most recent rename or workspace formatting
+    UndoLastRefactoring,
+    /// Reparse the stdlib definitions
+    ReloadStdlib,
+    /// Reread the grammar data file
+    ReloadGrammar,
+    /// List the capabilities, commands and settings added by the last upgrade
+    WhatsNew,
+    /// Return the timing statistics of the completion providers
+    CompletionStats,
+    /// Return the memory, documents and timing statistics of the server
+    ServerStatus,
+}
+
+/// Arguments of commands operating on a single document.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DocumentArgs {
+    /// The document to operate on
+    pub uri: Uri,
+}
+
+impl Command {
+    /// Names of all commands, as advertised in the server capabilities.
+    pub const NAMES: &[&str] = &[
+        RESTART_ANALYSIS_COMMAND,
+        FORMAT_WORKSPACE_COMMAND,
+        PREVIEW_FORMAT_COMMAND,
+        SHOW_AST_COMMAND,
+        DIFF_DIAGNOSTICS_COMMAND,
+        GOTO_FIRST_ERROR_COMMAND,
+        FIX_ALL_COMMAND,
+        SHOW_SEMANTIC_INFO_COMMAND,
+        SET_ANALYSIS_ENABLED_COMMAND,
+        BROWSE_EXAMPLES_COMMAND,
+        OPEN_EXAMPLE_COMMAND,
+        UNDO_LAST_REFACTORING_COMMAND,
+        RELOAD_STDLIB_COMMAND,
+        RELOAD_GRAMMAR_COMMAND,
+        WHATS_NEW_COMMAND,
+        COMPLETION_STATS_COMMAND,
+        SERVER_STATUS_COMMAND,
+    ];
+
+    /// Parse a command name and its arguments.
+    ///
+    /// Unknown commands and malformed arguments are reported as invalid params.
+    pub fn parse(name: &str, arguments: Vec<Value>) -> Result<Self> {
+        match name {
+            RESTART_ANALYSIS_COMMAND => Ok(Self::RestartAnalysis),
+            FORMAT_WORKSPACE_COMMAND => Ok(Self::FormatWorkspace),
+            PREVIEW_FORMAT_COMMAND => Ok(Self::PreviewFormat(first_argument(name, arguments)?)),
+            SHOW_AST_COMMAND => Ok(Self::ShowAst(first_argument(name, arguments)?)),
+            DIFF_DIAGNOSTICS_COMMAND => Ok(Self::DiffDiagnostics(first_argument(name, arguments)?)),
+            GOTO_FIRST_ERROR_COMMAND => {
+                Ok(Self::GotoFirstError(optional_argument(name, arguments)?))
+            }
+            FIX_ALL_COMMAND => Ok(Self::FixAll(first_argument(name, arguments)?)),
+            SHOW_SEMANTIC_INFO_COMMAND => {
+                Ok(Self::ShowSemanticInfo(first_argument(name, arguments)?))
+            }
+            SET_ANALYSIS_ENABLED_COMMAND => {
+                Ok(Self::SetAnalysisEnabled(first_argument(name, arguments)?))
+            }
+            BROWSE_EXAMPLES_COMMAND => Ok(Self::BrowseExamples),
+            OPEN_EXAMPLE_COMMAND => Ok(Self::OpenExample(first_argument(name, arguments)?)),
+            UNDO_LAST_REFACTORING_COMMAND => Ok(Self::UndoLastRefactoring),
+            RELOAD_STDLIB_COMMAND => Ok(Self::ReloadStdlib),
+            RELOAD_GRAMMAR_COMMAND => Ok(Self::ReloadGrammar),
+            WHATS_NEW_COMMAND => Ok(Self::WhatsNew),
+            COMPLETION_STATS_COMMAND => Ok(Self::CompletionStats),
+            SERVER_STATUS_COMMAND => Ok(Self::ServerStatus),
+            _ => Err(Error::invalid_params(format!("unknown command: {name}"))),
+        }
+    }
+}
+
+/// Deserialize the first argument of a command, falling back to the default if
+/// there is none.
+fn optional_argument<T: DeserializeOwned + Default>(
+    name: &str,
+    arguments: Vec<Value>,
+) -> Result<T> {
+    if arguments.is_empty() {
+        Ok(T::default())
+    } else {
+        first_argument(name, arguments)
+    }
+}
+
+/// Deserialize the first argument of a command.
+fn first_argument<T: DeserializeOwned>(name: &str, arguments: Vec<Value>) -> Result<T> {
+    let argument = arguments
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::invalid_params(format!("{name}: missing argument")))?;
+    serde_json::from_value(argument)
+        .map_err(|err| Error::invalid_params(format!("{name}: invalid argument: {err}")))
+}
@@ -0,0 +1,236 @@
+//! Compatibility layer for clients predating parts of the protocol.
+//!
+//! Older clients (many Vim and Emacs LSP plugins target LSP 3.15) lack semantic tokens,
+//! inlay hints or work done progress. Instead of dropping that information, the server
+//! routes it through channels those clients do support:
+//!
+//! - inlay hints are published as hint-severity diagnostics,
+//! - semantic tokens are sent as decorations through the custom
+//!   `l/publishDecorations` notification,
+//! - progress is reported through `window/logMessage`,
+//! - inlay hints are labelled with plain strings instead of parts,
+//! - related information of diagnostics is appended to their message, and tags
+//!   unknown to the client are dropped.
+
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::ls_types::notification::Notification;
+use tower_lsp_server::ls_types::{
+    ClientCapabilities, Diagnostic, DiagnosticSeverity, InlayHint, InlayHintLabel, NumberOrString,
+    Position, PublishDiagnosticsClientCapabilities, Range, SemanticToken, SemanticTokenType, Uri,
+};
+
+use crate::diagnostic_codes::DIAGNOSTIC_SOURCE;
+
+/// Which of the newer protocol features a client supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientSupport {
+    /// The client shows `$/progress` notifications
+    pub work_done_progress: bool,
+    /// The client requests semantic tokens
+    pub semantic_tokens: bool,
+    /// The client requests inlay hints
+    pub inlay_hints: bool,
+    /// The client shows inlay hint labels made of parts
+    pub inlay_hint_label_parts: bool,
+    /// The client resolves the locations of inlay hint label parts
+    pub inlay_hint_locations: bool,
+    /// The client expands snippets in completion items
+    pub snippets: bool,
+    /// The client accepts workspace edits as document changes with change annotations
+    pub change_annotations: bool,
+    /// The client answers `workspace/configuration` requests
+    pub configuration: bool,
+    /// The client shows the related information of diagnostics
+    pub related_information: bool,
+    /// The client checks the document version of published diagnostics
+    pub diagnostic_versions: bool,
+    /// The client knows the `unnecessary` and `deprecated` diagnostic tags
+    pub diagnostic_tags: bool,
+}
+
+impl ClientSupport {
+    /// Detect the supported features from the capabilities announced by the client.
+    ///
+    /// Before the client announced its capabilities every feature is assumed to be
+    /// supported.
+    pub fn from_capabilities(capabilities: Option<&ClientCapabilities>) -> Self {
+        let Some(capabilities) = capabilities else {
+            return Self {
+                work_done_progress: true,
+                semantic_tokens: true,
+                inlay_hints: true,
+                inlay_hint_label_parts: true,
+                inlay_hint_locations: true,
+                snippets: true,
+                change_annotations: true,
+                configuration: true,
+                related_information: true,
+                diagnostic_versions: true,
+                diagnostic_tags: true,
+            };
+        };
+        let text_document = capabilities.text_document.as_ref();
+        let inlay_hint_resolved = text_document
+            .and_then(|text_document| text_document.inlay_hint.as_ref())
+            .and_then(|inlay_hint| inlay_hint.resolve_support.as_ref())
+            .map(|resolve| resolve.properties.as_slice())
+            .unwrap_or_default();
+        let publish_diagnostics =
+            text_document.and_then(|text_document| text_document.publish_diagnostics.as_ref());
+        let diagnostics_support = |supported: fn(&PublishDiagnosticsClientCapabilities) -> bool| {
+            publish_diagnostics.is_some_and(supported)
+        };
+        Self {
+            work_done_progress: capabilities
+                .window
+                .as_ref()
+                .and_then(|window| window.work_done_progress)
+                .unwrap_or(false),
+            semantic_tokens: text_document
+                .and_then(|text_document| text_document.semantic_tokens.as_ref())
+                .is_some(),
+            inlay_hints: text_document
+                .and_then(|text_document| text_document.inlay_hint.as_ref())
+                .is_some(),
+            // The protocol has no capability for label parts; clients resolving their
+            // properties show them
+            inlay_hint_label_parts: inlay_hint_resolved
+                .iter()
+                .any(|property| property.starts_with("label.")),
+            inlay_hint_locations: inlay_hint_resolved
+                .iter()
+                .any(|property| property == "label.location"),
+            snippets: text_document
+                .and_then(|text_document| text_document.completion.as_ref())
+                .and_then(|completion| completion.completion_item.as_ref())
+                .and_then(|item| item.snippet_support)
+                .unwrap_or(false),
+            change_annotations: capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.workspace_edit.as_ref())
+                .is_some_and(|workspace_edit| {
+                    workspace_edit.document_changes == Some(true)
+                        && workspace_edit.change_annotation_support.is_some()
+                }),
+            configuration: capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.configuration)
+                .unwrap_or(false),
+            related_information: diagnostics_support(|publish| {
+                publish.related_information == Some(true)
+            }),
+            diagnostic_versions: diagnostics_support(|publish| {
+                publish.version_support == Some(true)
+            }),
+            diagnostic_tags: diagnostics_support(|publish| publish.tag_support.is_some()),
+        }
+    }
+}
+
+/// Adapt diagnostics about to be published to what the client supports.
+///
+/// Related information the client can't show is appended to the message, one line per
+/// entry, and tags are dropped for clients that don't know them.
+pub fn fit_diagnostics(diagnostics: &mut [Diagnostic], support: ClientSupport) {
+    for diagnostic in diagnostics {
+        if !support.related_information
+            && let Some(related) = diagnostic.related_information.take()
+        {
+            for information in related {
+                let position = information.location.range.start;
+                diagnostic.message.push_str(&format!(
+                    "\n{}:{}: {}",
+                    position.line + 1,
+                    position.character + 1,
+                    information.message
+                ));
+            }
+        }
+        if !support.diagnostic_tags {
+            diagnostic.tags = None;
+        }
+    }
+}
+
+/// A highlighted range, the fallback for a semantic token.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Decoration {
+    /// The highlighted range
+    pub range: Range,
+    /// The semantic token type of the range, e.g. `function`
+    pub kind: String,
+}
+
+/// Parameters of the `l/publishDecorations` notification.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PublishDecorationsParams {
+    /// The decorated document
+    pub uri: Uri,
+    /// All decorations of the document, replacing previously published ones
+    pub decorations: Vec<Decoration>,
+}
+
+/// Custom notification carrying decorations to clients without semantic tokens.
+#[derive(Debug)]
+pub enum PublishDecorations {}
+
+impl Notification for PublishDecorations {
+    type Params = PublishDecorationsParams;
+    const METHOD: &'static str = "l/publishDecorations";
+}
+
+/// Convert delta-encoded semantic tokens to decorations with absolute ranges.
+pub fn decorations_from_tokens(
+    tokens: &[SemanticToken],
+    token_types: &[SemanticTokenType],
+) -> Vec<Decoration> {
+    let mut line = 0;
+    let mut start = 0;
+    tokens
+        .iter()
+        .filter_map(|token| {
+            if token.delta_line == 0 {
+                start += token.delta_start;
+            } else {
+                line += token.delta_line;
+                start = token.delta_start;
+            }
+            let kind = token_types.get(token.token_type as usize)?;
+            Some(Decoration {
+                range: Range::new(
+                    Position::new(line, start),
+                    Position::new(line, start + token.length),
+                ),
+                kind: kind.as_str().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Convert inlay hints to hint-severity diagnostics at the hint position.
+pub fn hint_diagnostics(hints: Vec<InlayHint>) -> Vec<Diagnostic> {
+    hints
+        .into_iter()
+        .map(|hint| {
+            let label = match hint.label {
+                InlayHintLabel::String(label) => label,
+                InlayHintLabel::LabelParts(parts) => {
+                    parts.into_iter().map(|part| part.value).collect()
+                }
+            };
+            Diagnostic {
+                range: Range::new(hint.position, hint.position),
+                severity: Some(DiagnosticSeverity::HINT),
+                code: Some(NumberOrString::String("inlayHint".to_string())),
+                code_description: None,
+                source: Some(DIAGNOSTIC_SOURCE.to_string()),
+                message: label.trim_start_matches(": ").to_string(),
+                related_information: None,
+                tags: None,
+                data: None,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,288 @@
+//! Diagnostics of the errors the compiler reports for a document.
+//!
+//! Shared by the server, which publishes them for every analyzed document, and the
+//! `check` subcommand, which prints them.
+
+use std::collections::HashSet;
+
+use codespan_reporting::diagnostic::LabelStyle;
+use l_lang::{CompileResult, SymbolId, SymbolKind};
+use ropey::Rope;
+use tower_lsp_server::ls_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, Range, Uri,
+};
+
+use crate::diagnostic_codes::{DIAGNOSTIC_SOURCE, DiagnosticCode, lsp_severity};
+use crate::document_store::Document;
+use crate::suggestions::{Suggestion, suggest_names, unresolved_references};
+use crate::text_pos::{Bounds, Encoding, TextPos};
+
+/// Convert the syntax and semantic errors of a compiled document to diagnostics.
+///
+/// Each compiler diagnostic becomes one LSP diagnostic at its primary label, with its
+/// other labels as related information. Semantic errors get a suggestion for a
+/// misspelled name they cover, which the quick fix reads back from the diagnostic data.
+pub fn compile_diagnostics(uri: &Uri, document: &Document, encoding: Encoding) -> Vec<Diagnostic> {
+    let (rope, analysis) = (&document.rope, &document.analysis);
+    let suggestions = suggest_names(document);
+    let unresolved = unresolved_references(document);
+
+    let mut diagnostics = analysis
+        .diagnostics
+        .iter()
+        .filter_map(|d| {
+            // The first primary label locates the diagnostic. The other labels point
+            // at related code, such as an unclosed delimiter, and become its related
+            // information; without primary labels, the first secondary one locates it
+            let position = d
+                .labels
+                .iter()
+                .position(|label| label.style == LabelStyle::Primary)
+                .unwrap_or(0);
+            let label = d.labels.get(position)?;
+            let related_information = d
+                .labels
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != position)
+                .filter_map(|(_, label)| {
+                    let range =
+                        TextPos::new(rope, encoding, Bounds::Strict).range(label.range.clone())?;
+                    let message = if label.message.is_empty() {
+                        d.message.clone()
+                    } else {
+                        label.message.clone()
+                    };
+                    Some(DiagnosticRelatedInformation {
+                        location: Location::new(uri.clone(), range),
+                        message,
+                    })
+                })
+                .collect::<Vec<_>>();
+            Some(Diagnostic {
+                range: TextPos::new(rope, encoding, Bounds::Strict).range(label.range.clone())?,
+                severity: Some(lsp_severity(d.severity)),
+                code: Some(DiagnosticCode::compiler(d.severity).code()),
+                code_description: DiagnosticCode::compiler(d.severity).description(),
+                source: Some(DIAGNOSTIC_SOURCE.to_string()),
+                message: d.message.clone(),
+                related_information: (!related_information.is_empty())
+                    .then_some(related_information),
+                tags: None,
+                data: None,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    analysis.semantic.errors.iter().for_each(|sem_err| {
+        let span = sem_err.span;
+        let start = TextPos::new(rope, encoding, Bounds::Strict).position(span.start as usize);
+        let end = TextPos::new(rope, encoding, Bounds::Strict).position(span.end as usize);
+        if let (Some(start), Some(end)) = (start, end) {
+            let span = span.start as usize..span.end as usize;
+            let redefined = redefined_symbols(rope, analysis, &span);
+            let related_information =
+                semantic_related_information(uri, rope, encoding, analysis, &redefined, &span);
+            let code = if !redefined.is_empty() {
+                DiagnosticCode::DuplicateDefinition
+            } else if unresolved
+                .iter()
+                .any(|ref_span| span.start <= ref_span.start && ref_span.end <= span.end)
+            {
+                DiagnosticCode::UndefinedName
+            } else {
+                DiagnosticCode::Semantic
+            };
+            // Suggest a name for the first unresolved reference the error covers
+            let suggestion = suggestions
+                .iter()
+                .find(|(ref_span, _)| span.start <= ref_span.start && ref_span.end <= span.end)
+                .and_then(|(ref_span, name)| {
+                    Some(Suggestion {
+                        range: TextPos::new(rope, encoding, Bounds::Strict)
+                            .range(ref_span.clone())?,
+                        name: name.clone(),
+                    })
+                });
+            let message = match &suggestion {
+                Some(suggestion) => {
+                    format!("{}; did you mean `{}`?", sem_err.message, suggestion.name)
+                }
+                None => sem_err.message.clone(),
+            };
+            let diag = Diagnostic {
+                range: Range::new(start, end),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(code.code()),
+                code_description: code.description(),
+                source: Some(DIAGNOSTIC_SOURCE.to_string()),
+                message,
+                related_information: (!related_information.is_empty())
+                    .then_some(related_information),
+                tags: None,
+                data: suggestion.as_ref().and_then(Suggestion::to_data),
+            };
+            diagnostics.push(diag);
+        }
+    });
+    dedup_diagnostics(&mut diagnostics);
+    diagnostics
+}
+
+/// Remove the diagnostics with the same range and message as an earlier one.
+///
+/// The parser may report an error once per recovery attempt at the same place, which
+/// clients would show stacked on the same squiggle.
+fn dedup_diagnostics(diagnostics: &mut Vec<Diagnostic>) {
+    let mut seen = HashSet::with_capacity(diagnostics.len());
+    diagnostics.retain(|diagnostic| seen.insert((diagnostic.range, diagnostic.message.clone())));
+}
+
+/// Find the functions and structs defined within a semantic error span whose name was
+/// already defined, with the original definition of each.
+///
+/// Functions and structs share one namespace, so either kind counts as the original.
+fn redefined_symbols(
+    rope: &Rope,
+    analysis: &CompileResult,
+    span: &std::ops::Range<usize>,
+) -> Vec<(String, SymbolId)> {
+    let semantic = &analysis.semantic;
+    let name_of = |symbol_id: SymbolId| symbol_name(rope, analysis, symbol_id);
+    let is_global = |symbol_id: SymbolId| {
+        matches!(
+            semantic.get_symbol_kind(symbol_id),
+            SymbolKind::Function | SymbolKind::Struct
+        )
+    };
+    semantic
+        .symbol_spans
+        .iter_enumerated()
+        .filter(|(symbol_id, symbol_span)| {
+            within(span, symbol_span.start, symbol_span.end) && is_global(*symbol_id)
+        })
+        .filter_map(|(symbol_id, symbol_span)| {
+            let name = name_of(symbol_id)?;
+            let original = semantic
+                .symbol_spans
+                .iter_enumerated()
+                .filter(|(other, other_span)| {
+                    other_span.start < symbol_span.start
+                        && is_global(*other)
+                        && name_of(*other).as_deref() == Some(name.as_str())
+                })
+                .map(|(other, _)| other)
+                .next()?;
+            Some((name, original))
+        })
+        .collect()
+}
+
+/// Collect the locations related to a semantic error from the symbols around its span.
+///
+/// The original definitions of the `redefined` symbols are reported, see
+/// [`redefined_symbols`]. Symbols referenced inside the error span are reported with
+/// their definition and type, e.g. the operands of a type mismatch.
+fn semantic_related_information(
+    uri: &Uri,
+    rope: &Rope,
+    encoding: Encoding,
+    analysis: &CompileResult,
+    redefined: &[(String, SymbolId)],
+    span: &std::ops::Range<usize>,
+) -> Vec<DiagnosticRelatedInformation> {
+    let semantic = &analysis.semantic;
+    let name_of = |symbol_id: SymbolId| symbol_name(rope, analysis, symbol_id);
+    let location_of = |symbol_id: SymbolId| {
+        let span = semantic.get_symbol_span(symbol_id);
+        let start = TextPos::new(rope, encoding, Bounds::Strict).position(span.start as usize)?;
+        let end = TextPos::new(rope, encoding, Bounds::Strict).position(span.end as usize)?;
+        Some(Location::new(uri.clone(), Range::new(start, end)))
+    };
+
+    let mut related = Vec::new();
+    for (name, original) in redefined {
+        if let Some(location) = location_of(*original) {
+            related.push(DiagnosticRelatedInformation {
+                location,
+                message: format!("`{name}` is first defined here"),
+            });
+        }
+    }
+
+    let mut referenced: Vec<SymbolId> = Vec::new();
+    for (ref_id, ref_span) in semantic.reference_spans.iter().enumerate() {
+        if !within(span, ref_span.start, ref_span.end) {
+            continue;
+        }
+        let Some(symbol_id) = semantic.references.get(ref_id).copied().flatten() else {
+            continue;
+        };
+        if referenced.contains(&symbol_id) {
+            continue;
+        }
+        referenced.push(symbol_id);
+        let (Some(name), Some(location)) = (name_of(symbol_id), location_of(symbol_id)) else {
+            continue;
+        };
+        let message = match semantic.get_symbol_type(symbol_id) {
+            Some(type_info) => format!(
+                "`{name}` is defined here with type `{}`",
+                type_info.ty.format_literal_type(semantic)
+            ),
+            None => format!("`{name}` is defined here"),
+        };
+        related.push(DiagnosticRelatedInformation { location, message });
+    }
+    related
+}
+
+/// The name of a symbol, as written at its definition.
+fn symbol_name(rope: &Rope, analysis: &CompileResult, symbol_id: SymbolId) -> Option<String> {
+    let span = analysis.semantic.get_symbol_span(symbol_id);
+    rope.get_byte_slice(span.start as usize..span.end as usize)
+        .map(|name| name.to_string())
+}
+
+/// Check if a non-empty span of the compiler lies within an error span.
+fn within(error: &std::ops::Range<usize>, start: u32, end: u32) -> bool {
+    error.start <= start as usize && end as usize <= error.end && start < end
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp_server::ls_types::Position;
+
+    use super::*;
+
+    fn diagnostic(line: u32, message: &str) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 4)),
+            message: message.to_string(),
+            ..Diagnostic::default()
+        }
+    }
+
+    #[test]
+    fn identical_diagnostics_are_reported_once() {
+        let mut diagnostics = vec![
+            diagnostic(0, "expected `;`"),
+            diagnostic(1, "expected `;`"),
+            diagnostic(0, "expected `;`"),
+            diagnostic(0, "unknown name"),
+        ];
+        dedup_diagnostics(&mut diagnostics);
+        let kept = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kept,
+            [
+                (0, "expected `;`"),
+                (1, "expected `;`"),
+                (0, "unknown name")
+            ]
+        );
+    }
+}
@@ -0,0 +1,950 @@
+//! Completion items, collected from independent providers.
+//!
+//! Each [`CompletionProvider`] looks at the [`CompletionContext`] of a request and
+//! returns scored items. The items of all enabled providers are merged centrally: the
+//! best scoring item of each label is kept, items not matching the word typed before
+//! the cursor are dropped, and the rest is ordered by how well they match, then by
+//! score, and truncated to [`MAX_COMPLETION_ITEMS`]. A truncated list is marked
+//! incomplete, so the client asks again with a longer word instead of filtering a list
+//! missing the items it needs. Providers can be disabled with the
+//! `disabledCompletionProviders` setting.
+//!
+//! Every provider gets [`PROVIDER_BUDGET`] per request. Providers walking many symbols
+//! give up once it is spent, and the items of any provider that took longer are
+//! dropped, so a single slow provider doesn't hold up the whole list. Overruns are
+//! logged and counted in the [`CompletionStats`] returned by `l.completionStats`.
+//!
+//! Clients expanding snippets get functions completed as calls, with a placeholder
+//! for every parameter to tab through. Items of symbols are accepted by typing the
+//! character that usually follows them, see [`commit_characters`], and in the value of
+//! a `let` with a type annotation the struct of that type is preselected.
+//!
+//! Items of symbols are sent without documentation; they carry a [`ResolveData`]
+//! pointing at their declaration, and [`resolve_item`] adds the declaration and its
+//! comment once the client shows the item.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use tracing::warn;
+
+use l_lang::{CompileResult, SymbolId, SymbolKind, Type};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp_server::ls_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Documentation, InsertTextFormat,
+    MarkupContent, MarkupKind, Range, TextEdit, Uri,
+};
+
+use crate::document_store::Document;
+use crate::grammar::Grammar;
+use crate::lexer::tokenize;
+use crate::scopes::{ScopeSpan, scope_tree};
+use crate::settings::section;
+use crate::symbol_docs::{ResolveData, declaration_text, symbol_documentation};
+
+/// Name of the command returning the timing statistics of the completion providers.
+pub const COMPLETION_STATS_COMMAND: &str = "l.completionStats";
+
+/// Maximum number of items returned for a completion request.
+pub const MAX_COMPLETION_ITEMS: usize = 200;
+
+/// Time a provider may take for a single request before its items are dropped.
+pub const PROVIDER_BUDGET: Duration = Duration::from_millis(20);
+
+/// Scores of the kinds of items, higher ones are listed first.
+const FIELD_SCORE: u32 = 100;
+const VARIABLE_SCORE: u32 = 90;
+const FUNCTION_SCORE: u32 = 80;
+const STRUCT_SCORE: u32 = 70;
+const POSTFIX_SCORE: u32 = 60;
+const SNIPPET_SCORE: u32 = 50;
+const KEYWORD_SCORE: u32 = 40;
+const BUILTIN_TYPE_SCORE: u32 = 35;
+const IMPORT_SCORE: u32 = 30;
+
+/// A source of completion items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CompletionProvider {
+    /// Variables, functions and structs of the document
+    ScopeSymbols,
+    /// Fields of the struct a field access is made on
+    StructFields,
+    /// Keywords and builtin types of the grammar
+    Keywords,
+    /// Templates of declarations and statements
+    Snippets,
+    /// Functions and structs defined by the stdlib
+    Imports,
+    /// Templates wrapping the expression before a `.`
+    Postfix,
+}
+
+impl CompletionProvider {
+    /// All providers, in the order their items are collected.
+    pub const ALL: [Self; 6] = [
+        Self::ScopeSymbols,
+        Self::StructFields,
+        Self::Keywords,
+        Self::Snippets,
+        Self::Imports,
+        Self::Postfix,
+    ];
+
+    /// The name of the provider, as used in settings and statistics.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ScopeSymbols => "scopeSymbols",
+            Self::StructFields => "structFields",
+            Self::Keywords => "keywords",
+            Self::Snippets => "snippets",
+            Self::Imports => "imports",
+            Self::Postfix => "postfix",
+        }
+    }
+
+    /// Collect the items of the provider for a request.
+    ///
+    /// Returns `None` if the provider gave up because its budget was spent.
+    pub fn provide(
+        self,
+        context: &CompletionContext<'_>,
+        budget: &Budget,
+    ) -> Option<Vec<ScoredItem>> {
+        match self {
+            Self::ScopeSymbols => scope_symbols(context, budget),
+            Self::StructFields => Some(struct_fields(context)),
+            Self::Keywords => Some(keywords(context)),
+            Self::Snippets => Some(snippets(context)),
+            Self::Imports => imports(context, budget),
+            Self::Postfix => Some(postfix(context)),
+        }
+    }
+}
+
+/// Where completion was requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionSite {
+    /// After the `.` of a field access
+    FieldAccess {
+        /// The struct the field is accessed on, if the receiver has a known struct type
+        struct_id: Option<SymbolId>,
+        /// Source text of the receiver, the expression before the `.`
+        receiver: String,
+        /// Range from the start of the receiver to the cursor, replaced by postfix
+        /// templates
+        replace: Range,
+    },
+    /// Anywhere else
+    Expression,
+}
+
+/// Everything providers know about a completion request.
+#[derive(Debug)]
+pub struct CompletionContext<'a> {
+    /// URI of the document completion was requested in
+    pub uri: &'a Uri,
+    /// The document completion was requested in
+    pub document: &'a Document,
+    /// Where in the document completion was requested
+    pub site: CompletionSite,
+    /// The part of the word before the cursor, which items are filtered by
+    pub prefix: String,
+    /// Name of the type the completed expression should have, see [`expected_type`]
+    pub expected_type: Option<String>,
+    /// Keywords and builtin types of the language
+    pub grammar: &'a Grammar,
+    /// Loaded stdlib documents other than the completed one, with the name shown for
+    /// each and their URI
+    pub stdlib: Vec<(String, &'a Uri, &'a Document)>,
+    /// Whether the client expands snippets
+    pub snippets: bool,
+}
+
+/// A completion item with the score it is ordered by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredItem {
+    /// Relevance of the item, higher is better
+    pub score: u32,
+    /// The item
+    pub item: CompletionItem,
+}
+
+/// The time left to a provider for a request.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    /// When the provider's time is up
+    deadline: Instant,
+}
+
+impl Budget {
+    /// Start a budget of the given length.
+    pub fn start(length: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + length,
+        }
+    }
+
+    /// Check if the budget is spent.
+    pub fn is_spent(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+}
+
+/// Collect the items of all enabled providers, merged and truncated.
+///
+/// Providers exceeding their budget are skipped and recorded in `stats`. Returns the
+/// items and whether some were dropped.
+pub fn complete(
+    context: &CompletionContext<'_>,
+    disabled: &[CompletionProvider],
+    stats: &CompletionStats,
+) -> (Vec<CompletionItem>, bool) {
+    let mut items = Vec::new();
+    for provider in CompletionProvider::ALL {
+        if disabled.contains(&provider) {
+            continue;
+        }
+        let started = Instant::now();
+        let budget = Budget::start(PROVIDER_BUDGET);
+        let provided = provider
+            .provide(context, &budget)
+            .filter(|_| !budget.is_spent());
+        let elapsed = started.elapsed();
+        stats.record(provider, elapsed, provided.is_none());
+        match provided {
+            Some(provided) => items.extend(provided),
+            None => warn!(
+                "Skipped completion provider {} after {elapsed:?}, over its budget of \
+                 {PROVIDER_BUDGET:?}",
+                provider.name()
+            ),
+        }
+    }
+    let (mut items, truncated) = merge(items, &context.prefix, MAX_COMPLETION_ITEMS);
+    if let Some(expected) = &context.expected_type
+        && let Some(item) = items
+            .iter_mut()
+            .find(|item| item.kind == Some(CompletionItemKind::STRUCT) && item.label == *expected)
+    {
+        item.preselect = Some(true);
+    }
+    (items, truncated)
+}
+
+/// Find the type the expression ending a text should have.
+///
+/// Only the value of a `let` with a type annotation, as in `let p: Point = `, has a
+/// known type.
+pub fn expected_type(text: &str) -> Option<String> {
+    let tokens = tokenize(text);
+    match tokens.as_slice() {
+        [.., colon, ty, equals]
+            if colon.text == ":"
+                && equals.text == "="
+                && ty.text.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+        {
+            Some(ty.text.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// The characters accepting an item of a kind when typed, as they usually follow it.
+///
+/// Functions completed as call snippets get none, as their parentheses are inserted
+/// already.
+pub fn commit_characters(kind: CompletionItemKind) -> Option<Vec<String>> {
+    let characters: &[&str] = match kind {
+        CompletionItemKind::VARIABLE | CompletionItemKind::FIELD => &[".", ";", ",", ")"],
+        CompletionItemKind::FUNCTION => &["("],
+        CompletionItemKind::STRUCT => &["{"],
+        _ => return None,
+    };
+    Some(characters.iter().map(|c| c.to_string()).collect())
+}
+
+/// Timing of the runs of a provider.
+#[derive(Debug, Clone, Copy, Default)]
+struct ProviderTimes {
+    /// Number of requests the provider ran for
+    runs: u64,
+    /// Number of runs whose items were dropped for exceeding the budget
+    skipped: u64,
+    /// Total time spent in the provider
+    total: Duration,
+    /// Longest run
+    max: Duration,
+}
+
+/// Timing of the completion providers since the server started.
+#[derive(Debug, Default)]
+pub struct CompletionStats {
+    /// Timing of each provider that ran
+    times: DashMap<CompletionProvider, ProviderTimes>,
+}
+
+impl CompletionStats {
+    /// Record a run of a provider.
+    pub fn record(&self, provider: CompletionProvider, elapsed: Duration, skipped: bool) {
+        let mut times = self.times.entry(provider).or_default();
+        times.runs += 1;
+        times.skipped += u64::from(skipped);
+        times.total += elapsed;
+        times.max = times.max.max(elapsed);
+    }
+
+    /// Summarize the timing of every provider, the result of `l.completionStats`.
+    pub fn report(&self) -> Vec<ProviderStats> {
+        CompletionProvider::ALL
+            .into_iter()
+            .map(|provider| {
+                let times = self
+                    .times
+                    .get(&provider)
+                    .map(|times| *times)
+                    .unwrap_or_default();
+                let average = u32::try_from(times.runs)
+                    .ok()
+                    .filter(|runs| *runs > 0)
+                    .map_or(Duration::ZERO, |runs| times.total / runs);
+                ProviderStats {
+                    provider,
+                    runs: times.runs,
+                    skipped: times.skipped,
+                    average_ms: average.as_secs_f64() * 1000.0,
+                    max_ms: times.max.as_secs_f64() * 1000.0,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Timing statistics of a provider, as returned by `l.completionStats`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderStats {
+    /// The provider
+    pub provider: CompletionProvider,
+    /// Number of requests the provider ran for
+    pub runs: u64,
+    /// Number of runs whose items were dropped for exceeding the budget
+    pub skipped: u64,
+    /// Average time of a run, in milliseconds
+    pub average_ms: f64,
+    /// Longest run, in milliseconds
+    pub max_ms: f64,
+}
+
+/// Keep the best scoring item of each label matching `prefix`, ordered by how well it
+/// matches, then by score and label.
+///
+/// Items are matched on their `filter_text`, or their label, with [`fuzzy_match`]. The
+/// order is stored in the `sort_text` of the items, so clients keep it. Returns the
+/// first `limit` items and whether some were dropped.
+pub fn merge(items: Vec<ScoredItem>, prefix: &str, limit: usize) -> (Vec<CompletionItem>, bool) {
+    let mut best = HashMap::<String, ScoredItem>::new();
+    for item in items {
+        match best.get(&item.item.label) {
+            Some(kept) if kept.score >= item.score => {}
+            _ => {
+                best.insert(item.item.label.clone(), item);
+            }
+        }
+    }
+    let mut items = best
+        .into_values()
+        .filter_map(|scored| {
+            let text = scored
+                .item
+                .filter_text
+                .as_ref()
+                .unwrap_or(&scored.item.label);
+            Some((fuzzy_match(prefix, text)?, scored))
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|(a_match, a), (b_match, b)| {
+        b_match
+            .cmp(a_match)
+            .then_with(|| b.score.cmp(&a.score))
+            .then_with(|| a.item.label.cmp(&b.item.label))
+    });
+    let truncated = items.len() > limit;
+    let items = items
+        .into_iter()
+        .take(limit)
+        .enumerate()
+        .map(|(rank, (_, scored))| CompletionItem {
+            sort_text: Some(format!("{rank:04}")),
+            ..scored.item
+        })
+        .collect();
+    (items, truncated)
+}
+
+/// Rate how well a text matches a typed word, or `None` if it doesn't.
+///
+/// The characters of the word must appear in the text in order, ignoring case. Text
+/// starting with the word is rated highest, exact case first; other matches are rated
+/// by how many characters fall at the start of a word of the text, after `_` or on a
+/// lowercase to uppercase change, or right after the previous matched character. The
+/// empty word matches everything equally.
+pub fn fuzzy_match(word: &str, text: &str) -> Option<u32> {
+    if word.is_empty() {
+        return Some(0);
+    }
+    if text.starts_with(word) {
+        return Some(u32::MAX);
+    }
+    if text.to_lowercase().starts_with(&word.to_lowercase()) {
+        return Some(u32::MAX - 1);
+    }
+    let text = text.chars().collect::<Vec<_>>();
+    let mut rating = 0u32;
+    let mut next = 0;
+    for wanted in word.chars() {
+        let found = next
+            + text[next..]
+                .iter()
+                .position(|c| c.to_lowercase().eq(wanted.to_lowercase()))?;
+        let word_start = found == 0
+            || text[found - 1] == '_'
+            || (text[found - 1].is_lowercase() && text[found].is_uppercase());
+        rating += 1 + 2 * u32::from(word_start) + u32::from(found > 0 && found == next);
+        next = found + 1;
+    }
+    Some(rating)
+}
+
+/// Find the struct a field access is made on, following the chain of accessed fields.
+pub fn field_access_struct(
+    field_expr: &l_lang::ExprField,
+    semantic_result: &CompileResult,
+) -> Option<SymbolId> {
+    let mut access_arr = vec![];
+    let mut cur = field_expr.object.as_ref()?;
+    loop {
+        match cur.as_ref() {
+            l_lang::Expr::Field(field_expr) => {
+                access_arr.push(field_expr.field.as_ref()?.name.clone());
+                cur = field_expr.object.as_ref()?;
+            }
+            l_lang::Expr::Name(_name_expr) => {
+                break;
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+    access_arr.reverse();
+
+    let object_span = field_expr.object.as_ref()?.span();
+    let reference_id = semantic_result
+        .semantic
+        .get_reference_at(object_span.start as usize)?;
+
+    // Check if reference_id is within bounds
+    if reference_id >= semantic_result.semantic.references.len() {
+        return None;
+    }
+
+    let symbol_id = semantic_result.semantic.references[reference_id]?;
+    let ty_info = semantic_result.semantic.get_symbol_type(symbol_id)?;
+    let Type::Struct(mut struct_id) = ty_info.ty else {
+        return None;
+    };
+
+    for field_name in access_arr {
+        let struct_def = semantic_result.semantic.structs.get(&struct_id)?;
+        let field = struct_def.fields.iter().find(|f| f.name == field_name)?;
+        let Type::Struct(next_struct_id) = field.ty else {
+            return None;
+        };
+        struct_id = next_struct_id;
+    }
+    Some(struct_id)
+}
+
+/// Read the providers disabled by a settings object.
+///
+/// Unknown provider names are ignored.
+pub fn disabled_completion_providers_from_settings(
+    settings: &Value,
+) -> Option<Vec<CompletionProvider>> {
+    let names = section(settings)
+        .get("disabledCompletionProviders")?
+        .as_array()?;
+    Some(
+        names
+            .iter()
+            .filter_map(|name| serde_json::from_value(name.clone()).ok())
+            .collect(),
+    )
+}
+
+/// Create an item inserting its label.
+fn item(label: &str, kind: CompletionItemKind, detail: Option<String>) -> CompletionItem {
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(kind),
+        detail,
+        insert_text: Some(label.to_string()),
+        commit_characters: commit_characters(kind),
+        ..Default::default()
+    }
+}
+
+/// Describe the named variables, functions and structs of a document.
+///
+/// With `snippets`, functions are completed as calls with parameter placeholders.
+/// Every item gets the [`ResolveData`] of its symbol in the document at `uri`.
+/// Returns the score and item of each symbol, or `None` once the budget is spent.
+fn symbol_items(
+    uri: &Uri,
+    document: &Document,
+    kinds: &[SymbolKind],
+    snippets: bool,
+    budget: &Budget,
+) -> Option<Vec<(u32, CompletionItem)>> {
+    let semantic = &document.analysis.semantic;
+    let scopes = (snippets && kinds.contains(&SymbolKind::Function)).then(|| scope_tree(document));
+    let mut items = Vec::new();
+    for (symbol_id, _) in semantic.bindings.iter_enumerated() {
+        if budget.is_spent() {
+            return None;
+        }
+        let Some(name) = document
+            .symbol_name(symbol_id)
+            .filter(|name| !name.is_empty())
+        else {
+            continue;
+        };
+        let kind = semantic.get_symbol_kind(symbol_id);
+        if !kinds.contains(&kind) {
+            continue;
+        }
+        let (score, mut item) = match kind {
+            SymbolKind::Variable => (
+                VARIABLE_SCORE,
+                item(
+                    name,
+                    CompletionItemKind::VARIABLE,
+                    document.type_label(symbol_id).map(|ty| format!(": {ty}")),
+                ),
+            ),
+            SymbolKind::Function => {
+                let mut item = item(name, CompletionItemKind::FUNCTION, None);
+                if let Some(scopes) = &scopes {
+                    item.insert_text = Some(call_snippet(document, scopes, symbol_id, name));
+                    item.insert_text_format = Some(InsertTextFormat::SNIPPET);
+                    item.commit_characters = None;
+                }
+                (FUNCTION_SCORE, item)
+            }
+            _ => (STRUCT_SCORE, item(name, CompletionItemKind::STRUCT, None)),
+        };
+        item.data = serde_json::to_value(ResolveData::new(uri, document, symbol_id)).ok();
+        items.push((score, item));
+    }
+    Some(items)
+}
+
+/// Add the documentation of the symbol an item was created for.
+///
+/// `document` is the document named by the item's [`ResolveData`]. Items whose
+/// symbol is no longer declared at the recorded offset, because the document changed
+/// since, are returned unchanged. Functions without detail get their signature.
+pub fn resolve_item(
+    document: &Document,
+    data: &ResolveData,
+    mut item: CompletionItem,
+) -> CompletionItem {
+    let semantic = &document.analysis.semantic;
+    let Some(symbol_id) = data
+        .symbol(document)
+        .filter(|symbol_id| document.symbol_name(*symbol_id) == Some(item.label.as_str()))
+    else {
+        return item;
+    };
+    if item.detail.is_none() && semantic.get_symbol_kind(symbol_id) == SymbolKind::Function {
+        item.detail = declaration_text(document, symbol_id);
+    }
+    item.documentation = symbol_documentation(document, symbol_id).map(|value| {
+        Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        })
+    });
+    item
+}
+
+/// Build the snippet calling a function, such as `add(${1:a}, ${2:b})$0`.
+fn call_snippet(document: &Document, scopes: &ScopeSpan, function: SymbolId, name: &str) -> String {
+    let parameters = scopes.parameters(document, function);
+    let placeholders = parameters
+        .iter()
+        .filter_map(|symbol| document.symbol_name(*symbol))
+        .enumerate()
+        .map(|(index, parameter)| format!("${{{}:{parameter}}}", index + 1))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{name}({placeholders})$0")
+}
+
+/// Variables, functions and structs of the document.
+fn scope_symbols(context: &CompletionContext<'_>, budget: &Budget) -> Option<Vec<ScoredItem>> {
+    if context.site != CompletionSite::Expression {
+        return Some(Vec::new());
+    }
+    let kinds = [
+        SymbolKind::Variable,
+        SymbolKind::Function,
+        SymbolKind::Struct,
+    ];
+    let items = symbol_items(
+        context.uri,
+        context.document,
+        &kinds,
+        context.snippets,
+        budget,
+    )?;
+    Some(
+        items
+            .into_iter()
+            .map(|(score, item)| ScoredItem { score, item })
+            .collect(),
+    )
+}
+
+/// Fields of the struct a field access is made on.
+fn struct_fields(context: &CompletionContext<'_>) -> Vec<ScoredItem> {
+    let CompletionSite::FieldAccess {
+        struct_id: Some(struct_id),
+        ..
+    } = &context.site
+    else {
+        return Vec::new();
+    };
+    let semantic = &context.document.analysis.semantic;
+    let Some(struct_def) = semantic.structs.get(struct_id) else {
+        return Vec::new();
+    };
+    struct_def
+        .fields
+        .iter()
+        .map(|field| ScoredItem {
+            score: FIELD_SCORE,
+            item: item(
+                &field.name,
+                CompletionItemKind::FIELD,
+                Some(format!(": {}", field.ty.format_literal_type(semantic))),
+            ),
+        })
+        .collect()
+}
+
+/// Keywords and builtin types of the grammar.
+fn keywords(context: &CompletionContext<'_>) -> Vec<ScoredItem> {
+    if context.site != CompletionSite::Expression {
+        return Vec::new();
+    }
+    let keywords = context.grammar.keywords.iter().map(|keyword| ScoredItem {
+        score: KEYWORD_SCORE,
+        item: CompletionItem {
+            label: keyword.clone(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..Default::default()
+        },
+    });
+    let builtin_types = context.grammar.builtin_types.iter().map(|ty| ScoredItem {
+        score: BUILTIN_TYPE_SCORE,
+        item: CompletionItem {
+            label: ty.clone(),
+            kind: Some(CompletionItemKind::STRUCT),
+            detail: Some("builtin type".to_string()),
+            ..Default::default()
+        },
+    });
+    keywords.chain(builtin_types).collect()
+}
+
+/// Create a snippet item.
+fn snippet(label: &str, detail: &str, body: &str) -> CompletionItem {
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(CompletionItemKind::SNIPPET),
+        detail: Some(detail.to_string()),
+        insert_text: Some(body.to_string()),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        ..Default::default()
+    }
+}
+
+/// Templates of declarations and statements, for clients that expand snippets.
+///
+/// The labels differ from the keywords, so both are listed.
+fn snippets(context: &CompletionContext<'_>) -> Vec<ScoredItem> {
+    if !context.snippets || context.site != CompletionSite::Expression {
+        return Vec::new();
+    }
+    [
+        snippet(
+            "fn …",
+            "function declaration",
+            "fn ${1:name}(${2}) {\n\t$0\n}",
+        ),
+        snippet(
+            "struct …",
+            "struct declaration",
+            "struct ${1:Name} {\n\t${2:field}: ${3:int},\n}",
+        ),
+        snippet("if …", "if statement", "if ${1:condition} {\n\t$0\n}"),
+        snippet(
+            "if … else …",
+            "if-else statement",
+            "if ${1:condition} {\n\t$2\n} else {\n\t$0\n}",
+        ),
+        snippet(
+            "let …",
+            "variable declaration",
+            "let ${1:name} = ${0:value};",
+        ),
+    ]
+    .into_iter()
+    .map(|item| ScoredItem {
+        score: SNIPPET_SCORE,
+        item,
+    })
+    .collect()
+}
+
+/// Functions and structs defined by the stdlib, usable in every document.
+fn imports(context: &CompletionContext<'_>, budget: &Budget) -> Option<Vec<ScoredItem>> {
+    if context.site != CompletionSite::Expression {
+        return Some(Vec::new());
+    }
+    let kinds = [SymbolKind::Function, SymbolKind::Struct];
+    let mut items = Vec::new();
+    for (name, uri, document) in &context.stdlib {
+        items.extend(
+            symbol_items(uri, document, &kinds, context.snippets, budget)?
+                .into_iter()
+                .map(|(_, item)| ScoredItem {
+                    score: IMPORT_SCORE,
+                    item: CompletionItem {
+                        detail: Some(format!("from {name}")),
+                        ..item
+                    },
+                }),
+        );
+    }
+    Some(items)
+}
+
+/// Templates wrapping the receiver of a field access, such as `x.if` for `if x {}`.
+fn postfix(context: &CompletionContext<'_>) -> Vec<ScoredItem> {
+    let CompletionSite::FieldAccess {
+        receiver, replace, ..
+    } = &context.site
+    else {
+        return Vec::new();
+    };
+    if !context.snippets {
+        return Vec::new();
+    }
+    [
+        ("if", "if x {}", format!("if {receiver} {{\n\t$0\n}}")),
+        (
+            "let",
+            "let name = x;",
+            format!("let ${{1:name}} = {receiver};"),
+        ),
+        ("return", "return x;", format!("return {receiver};")),
+    ]
+    .into_iter()
+    .map(|(label, detail, body)| ScoredItem {
+        score: POSTFIX_SCORE,
+        item: CompletionItem {
+            label: label.to_string(),
+            kind: Some(CompletionItemKind::SNIPPET),
+            detail: Some(detail.to_string()),
+            filter_text: Some(format!("{receiver}.{label}")),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: *replace,
+                new_text: body,
+            })),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            ..Default::default()
+        },
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn scored(score: u32, label: &str) -> ScoredItem {
+        ScoredItem {
+            score,
+            item: CompletionItem {
+                label: label.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|item| item.label.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_orders_by_score_then_label() {
+        let (items, truncated) = merge(
+            vec![scored(10, "b"), scored(20, "c"), scored(10, "a")],
+            "",
+            MAX_COMPLETION_ITEMS,
+        );
+        assert_eq!(labels(&items), ["c", "a", "b"]);
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn merge_keeps_best_item_of_each_label() {
+        let mut keyword = scored(40, "x");
+        keyword.item.kind = Some(CompletionItemKind::KEYWORD);
+        let mut variable = scored(90, "x");
+        variable.item.kind = Some(CompletionItemKind::VARIABLE);
+        let (items, _) = merge(vec![keyword, variable], "", MAX_COMPLETION_ITEMS);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].kind, Some(CompletionItemKind::VARIABLE));
+    }
+
+    #[test]
+    fn merge_truncates_and_reports_it() {
+        let (items, truncated) = merge(vec![scored(1, "a"), scored(3, "b"), scored(2, "c")], "", 2);
+        assert_eq!(labels(&items), ["b", "c"]);
+        assert!(truncated);
+    }
+
+    #[test]
+    fn merge_filters_and_ranks_by_the_typed_word() {
+        let (items, _) = merge(
+            vec![
+                scored(90, "total_count"),
+                scored(40, "return"),
+                scored(80, "to_string"),
+                scored(70, "Token"),
+                scored(90, "value"),
+            ],
+            "to",
+            MAX_COMPLETION_ITEMS,
+        );
+        assert_eq!(labels(&items), ["total_count", "to_string", "Token"]);
+
+        let (items, _) = merge(
+            vec![scored(10, "total_count"), scored(90, "attic")],
+            "tc",
+            MAX_COMPLETION_ITEMS,
+        );
+        assert_eq!(labels(&items), ["total_count", "attic"]);
+    }
+
+    #[test]
+    fn fuzzy_match_prefers_prefixes_and_word_starts() {
+        assert_eq!(fuzzy_match("", "anything"), Some(0));
+        assert_eq!(fuzzy_match("xyz", "xy"), None);
+        assert!(fuzzy_match("Po", "Point") > fuzzy_match("po", "Point"));
+        assert!(fuzzy_match("po", "Point") > fuzzy_match("pt", "Point"));
+        assert!(fuzzy_match("gx", "get_x") > fuzzy_match("gx", "gadgetx"));
+        assert!(fuzzy_match("nP", "newPoint") > fuzzy_match("nP", "nopoint"));
+    }
+
+    #[test]
+    fn expected_type_is_the_annotation_of_a_let() {
+        assert_eq!(
+            expected_type("fn f() {\n    let p: Point = "),
+            Some("Point".to_string())
+        );
+        assert_eq!(expected_type("let p = "), None);
+        assert_eq!(expected_type("let p: Point = q;\n"), None);
+    }
+
+    #[test]
+    fn commit_characters_depend_on_the_kind() {
+        assert_eq!(
+            commit_characters(CompletionItemKind::FUNCTION),
+            Some(vec!["(".to_string()])
+        );
+        assert_eq!(commit_characters(CompletionItemKind::KEYWORD), None);
+        let variable = item("x", CompletionItemKind::VARIABLE, None);
+        assert!(
+            variable
+                .commit_characters
+                .is_some_and(|characters| characters.contains(&".".to_string()))
+        );
+    }
+
+    #[test]
+    fn merge_stores_order_in_sort_text() {
+        let (items, _) = merge(
+            vec![scored(1, "a"), scored(2, "b")],
+            "",
+            MAX_COMPLETION_ITEMS,
+        );
+        let sort_texts = items
+            .iter()
+            .map(|item| item.sort_text.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(sort_texts, [Some("0000"), Some("0001")]);
+    }
+
+    #[test]
+    fn stats_count_runs_and_skips() {
+        let stats = CompletionStats::default();
+        stats.record(
+            CompletionProvider::Imports,
+            Duration::from_millis(10),
+            false,
+        );
+        stats.record(CompletionProvider::Imports, Duration::from_millis(30), true);
+        let report = stats.report();
+        let imports = report
+            .iter()
+            .find(|stats| stats.provider == CompletionProvider::Imports)
+            .expect("every provider is reported");
+        assert_eq!((imports.runs, imports.skipped), (2, 1));
+        assert!((imports.average_ms - 20.0).abs() < 1e-9);
+        assert!((imports.max_ms - 30.0).abs() < 1e-9);
+        assert_eq!(report.len(), CompletionProvider::ALL.len());
+    }
+
+    #[test]
+    fn spent_budget_is_reported() {
+        assert!(Budget::start(Duration::ZERO).is_spent());
+        assert!(!Budget::start(Duration::from_secs(60)).is_spent());
+    }
+
+    #[test]
+    fn disabled_providers_ignore_unknown_names() {
+        let settings = json!({
+            "l-language-server": { "disabledCompletionProviders": ["snippets", "nope", "postfix"] }
+        });
+        assert_eq!(
+            disabled_completion_providers_from_settings(&settings),
+            Some(vec![
+                CompletionProvider::Snippets,
+                CompletionProvider::Postfix
+            ])
+        );
+        assert_eq!(
+            disabled_completion_providers_from_settings(&json!({})),
+            None
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Per-document debouncing of recompilation on `textDocument/didChange`.
+//!
+//! Each change waits for the configured delay before the document is recompiled. A
+//! newer change to the same document cancels the pending one, so bursts of keystrokes
+//! only compile and publish diagnostics for the latest version.
+//!
+//! Documents that don't live on disk, such as untitled scratch buffers or buffers a
+//! REPL keeps appending to, may change continuously, so that plain debouncing either
+//! never compiles them or compiles them on every pause. Their delay is scaled with
+//! their change rate and size instead, and a compile is forced once the last one is
+//! older than [`MAX_VOLATILE_STALENESS`] or the scaled delay, so CPU usage stays
+//! bounded while diagnostics are still published eventually.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use serde_json::Value;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::cancellation::CancellationToken;
+use crate::settings::section;
+
+/// Delay used until the client configures one.
+pub const DEFAULT_DEBOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+/// Upper bound of the scaled delay of a volatile document.
+pub const MAX_VOLATILE_DELAY: Duration = Duration::from_secs(5);
+
+/// Age of the last compile of a volatile document after which a change is compiled
+/// without waiting for the document to settle, unless its scaled delay is longer.
+pub const MAX_VOLATILE_STALENESS: Duration = Duration::from_secs(2);
+
+/// Change rate, in changes per second, at which the delay of a volatile document is
+/// doubled.
+const CALM_CHANGE_RATE: f64 = 5.0;
+
+/// Document size, in bytes, at which the delay of a volatile document is doubled.
+const CALM_DOCUMENT_LEN: f64 = 64.0 * 1024.0;
+
+/// Weight of the latest change in the smoothed change rate.
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// Change history of a volatile document.
+#[derive(Debug, Clone, Copy)]
+struct Throttle {
+    /// When the document last changed
+    last_change: Instant,
+    /// When a change of the document was last let through to be compiled
+    last_compile: Instant,
+    /// Smoothed number of changes per second
+    rate: f64,
+}
+
+/// Pending recompilations, keyed by document URI.
+#[derive(Debug)]
+pub struct Debouncer {
+    /// Delay before a change is compiled, in milliseconds
+    delay_ms: AtomicU64,
+    /// Token and version of the latest pending change of each document
+    pending: DashMap<String, (CancellationToken, Option<i32>)>,
+    /// Change history of volatile documents
+    throttles: DashMap<String, Throttle>,
+}
+
+impl Default for Debouncer {
+    fn default() -> Self {
+        Self {
+            delay_ms: AtomicU64::new(
+                u64::try_from(DEFAULT_DEBOUNCE_DELAY.as_millis()).expect("delay out of range"),
+            ),
+            pending: DashMap::new(),
+            throttles: DashMap::new(),
+        }
+    }
+}
+
+impl Debouncer {
+    /// Set the delay before a change is compiled.
+    pub fn set_delay(&self, delay: Duration) {
+        let delay_ms = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX);
+        self.delay_ms.store(delay_ms, Ordering::Relaxed);
+    }
+
+    /// The delay before a change is compiled.
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(self.delay_ms.load(Ordering::Relaxed))
+    }
+
+    /// Wait out the delay for a change to a document.
+    ///
+    /// Returns `false` if a newer change to the same document, or a call to
+    /// [`Debouncer::cancel`], superseded this one in the meantime.
+    pub async fn wait(&self, uri: &str, version: Option<i32>) -> bool {
+        self.wait_for(uri, version, self.delay()).await
+    }
+
+    /// Wait out the throttled delay for a change to a volatile document of `len` bytes.
+    ///
+    /// The delay grows with the change rate and size of the document, up to
+    /// [`MAX_VOLATILE_DELAY`], but never extends past the point where the last compile
+    /// becomes too old. Returns `false` if the change was superseded, like
+    /// [`Debouncer::wait`].
+    pub async fn wait_volatile(&self, uri: &str, version: Option<i32>, len: usize) -> bool {
+        let now = Instant::now();
+        let throttle = *self
+            .throttles
+            .entry(uri.to_string())
+            .and_modify(|throttle| {
+                let elapsed = now
+                    .duration_since(throttle.last_change)
+                    .as_secs_f64()
+                    .max(0.001);
+                throttle.rate += RATE_SMOOTHING * (1.0 / elapsed - throttle.rate);
+                throttle.last_change = now;
+            })
+            .or_insert(Throttle {
+                last_change: now,
+                last_compile: now,
+                rate: 0.0,
+            });
+
+        let factor =
+            (1.0 + throttle.rate / CALM_CHANGE_RATE) * (1.0 + len as f64 / CALM_DOCUMENT_LEN);
+        let delay = self.delay().mul_f64(factor).min(MAX_VOLATILE_DELAY);
+        let deadline = (now + delay).min(throttle.last_compile + MAX_VOLATILE_STALENESS.max(delay));
+        debug!(
+            "Throttling {uri} by {:?} ({:.1} changes/s, {len} bytes)",
+            deadline.saturating_duration_since(now),
+            throttle.rate
+        );
+        if !self
+            .wait_for(uri, version, deadline.saturating_duration_since(now))
+            .await
+        {
+            return false;
+        }
+        if let Some(mut throttle) = self.throttles.get_mut(uri) {
+            throttle.last_compile = Instant::now();
+        }
+        true
+    }
+
+    /// Wait for `delay`, unless a newer change to the document comes in.
+    async fn wait_for(&self, uri: &str, version: Option<i32>, delay: Duration) -> bool {
+        let token = CancellationToken::new();
+        if let Some((previous, _)) = self
+            .pending
+            .insert(uri.to_string(), (token.clone(), version))
+        {
+            previous.cancel();
+        }
+
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        if token.is_cancelled() {
+            return false;
+        }
+        self.pending
+            .remove_if(uri, |_, (pending, _)| pending.is_same(&token));
+        true
+    }
+
+    /// Cancel the pending change of a document, e.g. because it was closed or its
+    /// latest content was compiled right away.
+    ///
+    /// Returns the version of the cancelled change, if one was pending.
+    pub fn cancel(&self, uri: &str) -> Option<Option<i32>> {
+        let (_, (token, version)) = self.pending.remove(uri)?;
+        token.cancel();
+        Some(version)
+    }
+
+    /// Drop the change history of a document, e.g. because it was closed.
+    pub fn forget(&self, uri: &str) {
+        self.cancel(uri);
+        self.throttles.remove(uri);
+    }
+}
+
+/// Read the debounce delay from a settings object.
+pub fn debounce_delay_from_settings(settings: &Value) -> Option<Duration> {
+    section(settings)
+        .get("debounceMs")
+        .and_then(Value::as_u64)
+        .map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn newer_changes_supersede_pending_ones() {
+        let debouncer = Debouncer::default();
+        let start = Instant::now();
+        let (first, second, other) = tokio::join!(
+            debouncer.wait("file:///a.l", Some(1)),
+            debouncer.wait("file:///a.l", Some(2)),
+            debouncer.wait("file:///b.l", Some(1)),
+        );
+        assert!(!first);
+        assert!(second);
+        assert!(other);
+        assert_eq!(start.elapsed(), DEFAULT_DEBOUNCE_DELAY);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelling_flushes_the_pending_change() {
+        let debouncer = Arc::new(Debouncer::default());
+        let pending = tokio::spawn({
+            let debouncer = debouncer.clone();
+            async move { debouncer.wait("file:///a.l", Some(3)).await }
+        });
+        tokio::task::yield_now().await;
+        // A save compiles the latest text right away, for the version that was waiting
+        assert_eq!(debouncer.cancel("file:///a.l"), Some(Some(3)));
+        assert!(!pending.await.expect("the wait completes"));
+        assert_eq!(debouncer.cancel("file:///a.l"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn continuously_changing_volatile_documents_are_compiled_periodically() {
+        let debouncer = Arc::new(Debouncer::default());
+        let start = Instant::now();
+        let mut changes = Vec::new();
+        // Changes come in faster than the debounce delay, so none would settle
+        for version in 0..40 {
+            changes.push(tokio::spawn({
+                let debouncer = debouncer.clone();
+                async move {
+                    debouncer
+                        .wait_volatile("untitled:Untitled-1", Some(version), 1024)
+                        .await
+                        .then(Instant::now)
+                }
+            }));
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let mut compiled = Vec::new();
+        for change in changes {
+            compiled.extend(change.await.expect("the wait completes"));
+        }
+        let first = compiled.first().expect("a change is compiled");
+        assert!(first.duration_since(start) <= MAX_VOLATILE_STALENESS + Duration::from_millis(100));
+        assert!(compiled.len() >= 2, "{} compiles", compiled.len());
+    }
+}
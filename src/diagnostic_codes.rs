@@ -0,0 +1,162 @@
+//! Stable codes of the diagnostics published by the server.
+//!
+//! Every diagnostic carries a code identifying the kind of issue, independent of its
+//! message, and a link to the documentation of that code in `docs/diagnostics.md`.
+//! The `diagnosticSeverity` setting changes the severity published for a code, or
+//! turns its diagnostics off.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use codespan_reporting::diagnostic::Severity;
+use serde_json::Value;
+use tower_lsp_server::ls_types::{
+    CodeDescription, Diagnostic, DiagnosticSeverity, NumberOrString, Uri,
+};
+
+use crate::analysis_passes::AnalysisPass;
+use crate::settings::section;
+
+/// Value of the `source` field of every diagnostic published by the server.
+pub const DIAGNOSTIC_SOURCE: &str = "l";
+
+/// Severities replacing the published ones, by diagnostic code; `None` drops the
+/// diagnostics of the code.
+pub type SeverityOverrides = HashMap<String, Option<DiagnosticSeverity>>;
+
+/// Location of the documentation of all diagnostic codes.
+const DOCS_URL: &str =
+    "https://github.com/SmiteWindows/tower-lsp-boilerplate/blob/main/docs/diagnostics.md";
+
+/// The kind of issue a diagnostic reports.
+///
+/// The compiler reports semantic errors with a message only, so their kind is told
+/// from the symbols and references the error covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticCode {
+    /// The parser rejected the document
+    Syntax,
+    /// The semantic analysis rejected the document for another reason than below
+    Semantic,
+    /// A name doesn't resolve to a symbol in scope
+    UndefinedName,
+    /// A function or struct reuses the name of an earlier one
+    DuplicateDefinition,
+    /// The compiler warns about the document without rejecting it
+    CompilerWarning,
+    /// A finding of an opt-in analysis pass
+    Analysis(AnalysisPass),
+}
+
+impl DiagnosticCode {
+    /// The code as published, e.g. `L0001`.
+    ///
+    /// Analysis passes keep their name as code, as clients already filter on it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Syntax => "L0001",
+            Self::Semantic => "L0002",
+            Self::UndefinedName => "L0003",
+            Self::DuplicateDefinition => "L0004",
+            Self::CompilerWarning => "L0005",
+            Self::Analysis(pass) => pass.name(),
+        }
+    }
+
+    /// The code of a diagnostic of the compiler, which only errors reject the document.
+    pub fn compiler(severity: Severity) -> Self {
+        match severity {
+            Severity::Bug | Severity::Error => Self::Syntax,
+            Severity::Warning | Severity::Note | Severity::Help => Self::CompilerWarning,
+        }
+    }
+
+    /// The `code` field of a diagnostic with this code.
+    pub fn code(self) -> NumberOrString {
+        NumberOrString::String(self.as_str().to_string())
+    }
+
+    /// The `code_description` field of a diagnostic with this code, linking to its
+    /// documentation.
+    pub fn description(self) -> Option<CodeDescription> {
+        let anchor = self.as_str().to_ascii_lowercase();
+        let href = Uri::from_str(&format!("{DOCS_URL}#{anchor}")).ok()?;
+        Some(CodeDescription { href })
+    }
+}
+
+/// Map the severity of a compiler diagnostic to an LSP severity.
+///
+/// Notes and help messages are hints, as they don't describe a problem on their own.
+pub fn lsp_severity(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Bug | Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Note | Severity::Help => DiagnosticSeverity::HINT,
+    }
+}
+
+/// Apply the severity overrides of the settings to diagnostics about to be published.
+pub fn override_severities(diagnostics: &mut Vec<Diagnostic>, overrides: &SeverityOverrides) {
+    if overrides.is_empty() {
+        return;
+    }
+    diagnostics.retain_mut(|diagnostic| {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return true;
+        };
+        match overrides.get(code) {
+            Some(Some(severity)) => {
+                diagnostic.severity = Some(*severity);
+                true
+            }
+            Some(None) => false,
+            None => true,
+        }
+    });
+}
+
+/// Read the severity overrides from a settings object.
+///
+/// Each key is a diagnostic code, mapped to `error`, `warning`, `information`, `hint`
+/// or `off`. Entries with other values are ignored.
+pub fn severity_overrides_from_settings(settings: &Value) -> Option<SeverityOverrides> {
+    let overrides = section(settings).get("diagnosticSeverity")?.as_object()?;
+    Some(
+        overrides
+            .iter()
+            .filter_map(|(code, severity)| {
+                let severity = match severity.as_str()? {
+                    "error" => Some(DiagnosticSeverity::ERROR),
+                    "warning" => Some(DiagnosticSeverity::WARNING),
+                    "information" => Some(DiagnosticSeverity::INFORMATION),
+                    "hint" => Some(DiagnosticSeverity::HINT),
+                    "off" => None,
+                    _ => return None,
+                };
+                Some((code.clone(), severity))
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiler_diagnostics_are_coded_by_severity() {
+        assert_eq!(
+            DiagnosticCode::compiler(Severity::Error),
+            DiagnosticCode::Syntax
+        );
+        assert_eq!(
+            DiagnosticCode::compiler(Severity::Warning).as_str(),
+            "L0005"
+        );
+        let description = DiagnosticCode::UndefinedName
+            .description()
+            .expect("the documentation URL is valid");
+        assert!(description.href.as_str().ends_with("diagnostics.md#l0003"));
+    }
+}
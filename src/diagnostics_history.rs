@@ -0,0 +1,167 @@
+//! History of the diagnostics published for each document.
+//!
+//! Besides the diagnostics of the latest analysis, the diagnostics published for the
+//! last saved version of a document are kept so `l.diffDiagnostics` can report which
+//! issues an in-progress edit introduced and which it fixed. The latest diagnostics
+//! also back `l.gotoFirstError`.
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp_server::ls_types::{Diagnostic, DiagnosticSeverity, Location, Uri};
+
+use crate::settings::section;
+
+/// Name of the command that compares current diagnostics with those at the last save.
+pub const DIFF_DIAGNOSTICS_COMMAND: &str = "l.diffDiagnostics";
+
+/// Name of the command returning the location of the first error.
+pub const GOTO_FIRST_ERROR_COMMAND: &str = "l.gotoFirstError";
+
+/// Arguments of the `l.gotoFirstError` command.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct GotoFirstErrorArgs {
+    /// The document to search; the whole workspace if omitted
+    pub uri: Option<Uri>,
+}
+
+/// Diagnostics of a single document over time.
+#[derive(Debug, Clone, Default)]
+struct HistoryEntry {
+    /// Diagnostics of the latest analysis
+    current: Vec<Diagnostic>,
+    /// Diagnostics of the last saved version, if the document was saved
+    saved: Option<Vec<Diagnostic>>,
+}
+
+/// Difference between the current diagnostics of a document and those at its last save.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsDiff {
+    /// Whether the document was saved since it was opened
+    pub has_saved_version: bool,
+    /// Diagnostics present now but not at the last save
+    pub introduced: Vec<Diagnostic>,
+    /// Diagnostics present at the last save but not anymore
+    pub fixed: Vec<Diagnostic>,
+}
+
+/// Thread-safe map of document URIs to their diagnostics history.
+#[derive(Debug, Default)]
+pub struct DiagnosticsHistory {
+    /// Maps document URIs to their history
+    entries: DashMap<Uri, HistoryEntry>,
+}
+
+impl DiagnosticsHistory {
+    /// Record the diagnostics of the latest analysis of a document.
+    pub fn record(&self, uri: &Uri, diagnostics: Vec<Diagnostic>) {
+        self.entries.entry(uri.clone()).or_default().current = diagnostics;
+    }
+
+    /// Remember the latest diagnostics of a document as those of its saved version.
+    pub fn mark_saved(&self, uri: &Uri) {
+        if let Some(mut entry) = self.entries.get_mut(uri) {
+            entry.saved = Some(entry.current.clone());
+        }
+    }
+
+    /// Move the history of a document to a new URI.
+    pub fn rename(&self, old_uri: &Uri, new_uri: Uri) {
+        if let Some((_, entry)) = self.entries.remove(old_uri) {
+            self.entries.insert(new_uri, entry);
+        }
+    }
+
+    /// Forget the history of a document.
+    pub fn remove(&self, uri: &Uri) {
+        self.entries.remove(uri);
+    }
+
+    /// Forget the history of all documents.
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+
+    /// The location of the first error among the latest diagnostics.
+    ///
+    /// Only searches the given document if any, otherwise every recorded document,
+    /// ordered by URI and then by position.
+    pub fn first_error(&self, uri: Option<&Uri>) -> Option<Location> {
+        self.entries
+            .iter()
+            .filter(|entry| uri.is_none_or(|uri| entry.key() == uri))
+            .filter_map(|entry| {
+                entry
+                    .current
+                    .iter()
+                    .filter(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR))
+                    .map(|diagnostic| diagnostic.range)
+                    .min_by_key(|range| (range.start.line, range.start.character))
+                    .map(|range| Location::new(entry.key().clone(), range))
+            })
+            .min_by(|left, right| {
+                (
+                    left.uri.as_str(),
+                    left.range.start.line,
+                    left.range.start.character,
+                )
+                    .cmp(&(
+                        right.uri.as_str(),
+                        right.range.start.line,
+                        right.range.start.character,
+                    ))
+            })
+    }
+
+    /// Compare the current diagnostics of a document with those at its last save.
+    ///
+    /// Diagnostics are matched by message and severity only, so issues that merely
+    /// moved because of edits elsewhere in the document are neither introduced nor
+    /// fixed. Returns `None` if nothing was recorded for the document.
+    pub fn diff(&self, uri: &Uri) -> Option<DiagnosticsDiff> {
+        let entry = self.entries.get(uri)?;
+        let Some(saved) = &entry.saved else {
+            return Some(DiagnosticsDiff {
+                has_saved_version: false,
+                introduced: Vec::new(),
+                fixed: Vec::new(),
+            });
+        };
+
+        Some(DiagnosticsDiff {
+            has_saved_version: true,
+            introduced: unmatched(&entry.current, saved),
+            fixed: unmatched(saved, &entry.current),
+        })
+    }
+}
+
+/// Diagnostics of `left` without a counterpart in `right`.
+///
+/// Each diagnostic of `right` is matched at most once, so duplicated issues are counted.
+fn unmatched(left: &[Diagnostic], right: &[Diagnostic]) -> Vec<Diagnostic> {
+    let mut remaining = right.iter().collect::<Vec<_>>();
+    left.iter()
+        .filter(|diagnostic| {
+            let matched = remaining.iter().position(|other| {
+                other.message == diagnostic.message && other.severity == diagnostic.severity
+            });
+            match matched {
+                Some(index) => {
+                    remaining.swap_remove(index);
+                    false
+                }
+                None => true,
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+/// Read whether closed workspace files keep their diagnostics from a settings object.
+pub fn keep_workspace_diagnostics_from_settings(settings: &Value) -> Option<bool> {
+    section(settings)
+        .get("keepWorkspaceDiagnostics")
+        .and_then(Value::as_bool)
+}
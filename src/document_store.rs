@@ -0,0 +1,430 @@
+//! Storage for open and indexed documents.
+//!
+//! Each document's text, analysis result and version are stored together
+//! in a single [`Document`] entry, so handlers always observe a consistent view of a
+//! document through one [`DocumentStore::get_snapshot`] lookup instead of separate
+//! lookups into parallel maps that may be updated in between.
+//!
+//! The store also tracks the latest version the client announced for each document,
+//! so that a slow compile of an older version can't replace the analysis of a newer
+//! one, see [`DocumentStore::insert_if_current`].
+//!
+//! Replaced versions of a document are kept in a short history, so that a request
+//! computed after the user typed on can still be answered against the version it was
+//! made for, see [`DocumentStore::get_version`].
+//!
+//! Entries are reference-counted, so a snapshot doesn't lock the map and can be moved
+//! to a blocking task for expensive work. Handlers still copy what they need out of a
+//! snapshot before awaiting, or hand the document off with
+//! [`DocSnapshot::into_shared`], so that they never work on an outdated document for
+//! longer than necessary. Debug builds time how long each snapshot is held, see
+//! [`crate::lock_audit`].
+//!
+//! Every lookup and insertion marks the entry as used, so that the documents of closed
+//! files can be evicted least recently used first, see [`crate::memory_budget`].
+//!
+//! Clients tend to request semantic tokens, inlay hints and completions together
+//! right after an edit. Artifacts those requests share, such as the sorted token
+//! spans and the names, type labels and doc comments of symbols, are computed on first use and
+//! cached in the [`Document`] entry, so they are computed once per version and dropped
+//! together with it.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use l_lang::{CompileResult, SymbolId};
+use ropey::Rope;
+use tower_lsp_server::ls_types::Uri;
+
+use crate::semantic_tokens::remove_overlaps;
+use crate::symbol_docs::doc_comments;
+
+#[cfg(debug_assertions)]
+use crate::lock_audit::{HoldTimer, LockAudit};
+
+/// A single document together with its analysis.
+#[derive(Debug)]
+pub struct Document {
+    /// The text content of the document
+    pub rope: Rope,
+    /// The semantic analysis result for `rope`
+    pub analysis: Arc<CompileResult>,
+    /// The client-side version of the document, if it is known
+    pub version: Option<i32>,
+    /// Sorted `(start, length, token type)` spans of all semantic tokens, once computed
+    token_spans: OnceLock<Vec<(usize, usize, u32)>>,
+    /// Name of each symbol, indexed by symbol id, once computed
+    symbol_names: OnceLock<Vec<Option<String>>>,
+    /// Formatted type of each symbol with a binding, indexed by symbol id, once computed
+    type_labels: OnceLock<Vec<String>>,
+    /// Doc comment of each symbol, indexed by symbol id, once computed
+    doc_comments: OnceLock<Vec<Option<String>>>,
+    /// Tick of the store clock at which the entry was last looked up or stored
+    last_used: AtomicU64,
+}
+
+impl Document {
+    /// Create a document entry from its text and analysis result.
+    pub fn new(rope: Rope, analysis: impl Into<Arc<CompileResult>>, version: Option<i32>) -> Self {
+        Self {
+            rope,
+            analysis: analysis.into(),
+            version,
+            token_spans: OnceLock::new(),
+            symbol_names: OnceLock::new(),
+            type_labels: OnceLock::new(),
+            doc_comments: OnceLock::new(),
+            last_used: AtomicU64::new(0),
+        }
+    }
+
+    /// The sorted, disjoint spans of all semantic tokens, computed with `compute` on
+    /// first use.
+    ///
+    /// Of overlapping spans, the one starting first is kept, or the one computed first
+    /// if they start together, see [`remove_overlaps`].
+    ///
+    /// If `compute` returns `None`, e.g. because the request was cancelled, nothing is
+    /// cached and the next caller computes the spans again.
+    pub fn token_spans(
+        &self,
+        compute: impl FnOnce(&Self) -> Option<Vec<(usize, usize, u32)>>,
+    ) -> Option<&[(usize, usize, u32)]> {
+        if let Some(spans) = self.token_spans.get() {
+            return Some(spans);
+        }
+        let mut spans = compute(self)?;
+        spans.sort_by_key(|(start, _, _)| *start);
+        remove_overlaps(&mut spans);
+        Some(self.token_spans.get_or_init(|| spans))
+    }
+
+    /// The name of a symbol, as written at its definition.
+    pub fn symbol_name(&self, symbol_id: SymbolId) -> Option<&str> {
+        self.symbol_names
+            .get_or_init(|| {
+                self.analysis
+                    .semantic
+                    .symbol_spans
+                    .iter()
+                    .map(|span| {
+                        let span = span.start as usize..span.end as usize;
+                        self.rope.get_byte_slice(span).map(|name| name.to_string())
+                    })
+                    .collect()
+            })
+            .get(symbol_id.index())?
+            .as_deref()
+    }
+
+    /// The formatted type of a symbol, if it has a binding.
+    pub fn type_label(&self, symbol_id: SymbolId) -> Option<&str> {
+        let semantic = &self.analysis.semantic;
+        self.type_labels
+            .get_or_init(|| {
+                semantic
+                    .bindings
+                    .iter()
+                    .map(|type_info| type_info.ty.format_literal_type(semantic))
+                    .collect()
+            })
+            .get(symbol_id.index())
+            .map(String::as_str)
+    }
+
+    /// The `///` comment documenting a symbol, without its comment markers.
+    pub fn doc_comment(&self, symbol_id: SymbolId) -> Option<&str> {
+        self.doc_comments
+            .get_or_init(|| doc_comments(self))
+            .get(symbol_id.index())?
+            .as_deref()
+    }
+}
+
+/// Number of replaced versions kept for each document.
+pub const VERSION_HISTORY_LEN: usize = 8;
+
+/// A read-only view of a [`Document`] in the store.
+///
+/// The snapshot shares the entry with the store without locking it, so it stays valid
+/// after the document is replaced.
+#[derive(Debug)]
+pub struct DocSnapshot {
+    /// The shared document entry
+    document: Arc<Document>,
+    /// Reports snapshots that are held for too long
+    #[cfg(debug_assertions)]
+    hold: HoldTimer,
+}
+
+impl DocSnapshot {
+    /// Take the shared document out of the snapshot.
+    ///
+    /// Use this to hand the document to another task on purpose; the returned document
+    /// isn't covered by the lock audit.
+    pub fn into_shared(self) -> Arc<Document> {
+        #[cfg(debug_assertions)]
+        self.hold.disarm();
+        self.document
+    }
+}
+
+impl Deref for DocSnapshot {
+    type Target = Document;
+
+    fn deref(&self) -> &Self::Target {
+        &self.document
+    }
+}
+
+/// Thread-safe map of document URIs to their [`Document`] entries.
+///
+/// Every method normalizes the URIs it is given with [`normalize_uri`], so a file
+/// reached through differently encoded URIs maps to a single entry.
+#[derive(Debug, Default)]
+pub struct DocumentStore {
+    /// Maps document URIs to their entries
+    documents: DashMap<Uri, Arc<Document>>,
+    /// Latest version announced by the client for each document, possibly not yet
+    /// compiled
+    latest_versions: DashMap<Uri, i32>,
+    /// Versioned entries replaced by newer ones, oldest first, at most
+    /// [`VERSION_HISTORY_LEN`] per document
+    history: DashMap<Uri, VecDeque<Arc<Document>>>,
+    /// Ticks on every use of an entry, ordering the entries by last use
+    clock: AtomicU64,
+    /// Where snapshots held for too long are reported
+    #[cfg(debug_assertions)]
+    audit: Arc<LockAudit>,
+}
+
+impl DocumentStore {
+    /// Get a consistent snapshot of the document with the given URI.
+    pub fn get_snapshot(&self, uri: &Uri) -> Option<DocSnapshot> {
+        let uri = normalize_uri(uri);
+        self.documents.get(uri.as_ref()).map(|entry| {
+            self.touch(entry.value());
+            DocSnapshot {
+                document: Arc::clone(entry.value()),
+                #[cfg(debug_assertions)]
+                hold: HoldTimer::start(Arc::clone(&self.audit), uri.as_str()),
+            }
+        })
+    }
+
+    /// Get a snapshot of a specific version of a document.
+    ///
+    /// Returns the stored document if it has the version, or else the matching entry
+    /// of its history. Returns `None` if the version was never compiled or has been
+    /// dropped from the history.
+    pub fn get_version(&self, uri: &Uri, version: i32) -> Option<DocSnapshot> {
+        let uri = normalize_uri(uri);
+        let current = self
+            .documents
+            .get(uri.as_ref())
+            .map(|entry| Arc::clone(entry.value()))
+            .filter(|document| document.version == Some(version));
+        let document = current.or_else(|| {
+            self.history
+                .get(uri.as_ref())?
+                .iter()
+                .find(|document| document.version == Some(version))
+                .cloned()
+        })?;
+        self.touch(&document);
+        Some(DocSnapshot {
+            document,
+            #[cfg(debug_assertions)]
+            hold: HoldTimer::start(Arc::clone(&self.audit), uri.as_str()),
+        })
+    }
+
+    /// The latest version of a document announced by the client, if it is open.
+    pub fn latest_version(&self, uri: &Uri) -> Option<i32> {
+        self.latest_versions
+            .get(normalize_uri(uri).as_ref())
+            .map(|latest| *latest)
+    }
+
+    /// Mark an entry as used now.
+    fn touch(&self, document: &Document) {
+        let now = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+        document.last_used.store(now, Ordering::Relaxed);
+    }
+
+    /// Move a replaced entry into the history of its document.
+    ///
+    /// Unversioned entries are dropped, as no request can refer to them.
+    fn remember(&self, uri: Uri, replaced: Arc<Document>) {
+        if replaced.version.is_none() {
+            return;
+        }
+        let mut history = self.history.entry(uri).or_default();
+        if history.len() == VERSION_HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(replaced);
+    }
+
+    /// Where snapshots held for too long are reported.
+    #[cfg(debug_assertions)]
+    pub fn lock_audit(&self) -> &LockAudit {
+        &self.audit
+    }
+
+    /// Insert or replace the document with the given URI.
+    pub fn insert(&self, uri: &Uri, document: impl Into<Arc<Document>>) {
+        let uri = normalize_uri(uri).into_owned();
+        let document = document.into();
+        self.touch(&document);
+        if let Some(replaced) = self.documents.insert(uri.clone(), document) {
+            self.remember(uri, replaced);
+        }
+    }
+
+    /// Record a version of a document announced by the client, before it is compiled.
+    pub fn note_version(&self, uri: &Uri, version: i32) {
+        self.latest_versions
+            .entry(normalize_uri(uri).into_owned())
+            .and_modify(|latest| *latest = (*latest).max(version))
+            .or_insert(version);
+    }
+
+    /// Check if a version of a document is older than the latest announced one.
+    ///
+    /// Unversioned content, such as a file read from disk, is never stale.
+    pub fn is_stale(&self, uri: &Uri, version: Option<i32>) -> bool {
+        version.is_some_and(|version| {
+            self.latest_versions
+                .get(normalize_uri(uri).as_ref())
+                .is_some_and(|latest| *latest > version)
+        })
+    }
+
+    /// Insert or replace the document with the given URI, unless its version is older
+    /// than the stored one or the latest announced one.
+    ///
+    /// Returns whether the document was stored.
+    pub fn insert_if_current(&self, uri: &Uri, document: impl Into<Arc<Document>>) -> bool {
+        let document = document.into();
+        if self.is_stale(uri, document.version) {
+            return false;
+        }
+        let uri = normalize_uri(uri).into_owned();
+        self.touch(&document);
+        match self.documents.entry(uri.clone()) {
+            Entry::Occupied(mut entry) => {
+                if let (Some(stored), Some(version)) = (entry.get().version, document.version)
+                    && stored > version
+                {
+                    return false;
+                }
+                let replaced = entry.insert(document);
+                drop(entry);
+                self.remember(uri, replaced);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(document);
+            }
+        }
+        true
+    }
+
+    /// Remove the document with the given URI, returning it if it was present.
+    pub fn remove(&self, uri: &Uri) -> Option<Arc<Document>> {
+        let uri = normalize_uri(uri);
+        self.latest_versions.remove(uri.as_ref());
+        self.history.remove(uri.as_ref());
+        self.documents
+            .remove(uri.as_ref())
+            .map(|(_, document)| document)
+    }
+
+    /// Normalized URIs of all documents in the store.
+    pub fn uris(&self) -> Vec<Uri> {
+        self.documents
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// The stored documents, least recently used first.
+    pub fn by_last_use(&self) -> Vec<(Uri, Arc<Document>)> {
+        let mut documents = self
+            .documents
+            .iter()
+            .map(|entry| (entry.key().clone(), Arc::clone(entry.value())))
+            .collect::<Vec<_>>();
+        documents.sort_by_key(|(_, document)| document.last_used.load(Ordering::Relaxed));
+        documents
+    }
+
+    /// Number of documents in the store.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Remove all documents from the store.
+    pub fn clear(&self) {
+        self.latest_versions.clear();
+        self.history.clear();
+        self.documents.clear();
+    }
+}
+
+/// Normalize the spelling of a `file:` URI.
+///
+/// Clients and the workspace scan may encode the same path differently, e.g.
+/// `file:///C%3A/src/a.l` and `file:///c:/src/a.l`. Percent escapes of characters that
+/// don't need escaping are decoded, remaining escapes use uppercase hex digits, and
+/// Windows drive letters are lowercased and followed by a plain colon. Other URIs are
+/// returned unchanged.
+pub fn normalize_uri(uri: &Uri) -> Cow<'_, Uri> {
+    let Some(rest) = uri.as_str().strip_prefix("file://") else {
+        return Cow::Borrowed(uri);
+    };
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+
+    let mut normalized_path = String::with_capacity(path.len());
+    let mut chars = path.char_indices();
+    while let Some((index, c)) = chars.next() {
+        let escaped = path
+            .get(index + 1..index + 3)
+            .filter(|hex| c == '%' && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                    normalized_path.push(char::from(byte));
+                } else {
+                    normalized_path.push_str(&format!("%{byte:02X}"));
+                }
+                chars.nth(1);
+            }
+            None => normalized_path.push(c),
+        }
+    }
+
+    // `/C:/...` or `/C%3A/...`
+    let bytes = normalized_path.as_bytes();
+    if bytes.len() >= 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() {
+        let drive = char::from(bytes[1].to_ascii_lowercase());
+        if let Some(rest) = normalized_path[2..]
+            .strip_prefix(':')
+            .or_else(|| normalized_path[2..].strip_prefix("%3A"))
+        {
+            normalized_path = format!("/{drive}:{rest}");
+        }
+    }
+
+    let normalized = format!("file://{authority}{normalized_path}");
+    if normalized == uri.as_str() {
+        return Cow::Borrowed(uri);
+    }
+    normalized.parse().map_or(Cow::Borrowed(uri), Cow::Owned)
+}
@@ -0,0 +1,51 @@
+//! The server's view of a document, served through the `l/documentText` request.
+//!
+//! Client extensions and debugging tools compare the returned text, or just its hash,
+//! with the client buffer to find out whether the server missed or misapplied a change.
+//! The text is the one the stored analysis was computed from; changes still waiting
+//! for the debouncer show up as a newer `latestVersion`.
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::ls_types::{TextDocumentIdentifier, Uri};
+
+/// Name of the custom request returning the text of a document as the server sees it.
+pub const DOCUMENT_TEXT_METHOD: &str = "l/documentText";
+
+/// Parameters of the `l/documentText` request.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentTextParams {
+    /// The document to return the text of
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// Result of the `l/documentText` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentText {
+    /// The document, with the URI normalized as the server stores it
+    pub uri: Uri,
+    /// Version of the returned text, if the document is open in the client
+    pub version: Option<i32>,
+    /// Latest version announced by the client, newer than `version` while a change
+    /// waits to be analyzed
+    pub latest_version: Option<i32>,
+    /// The text of the document
+    pub text: String,
+    /// Hash of the text, see [`content_hash`]
+    pub content_hash: String,
+}
+
+/// Hash the text of a document for cheap comparisons with the client buffer.
+///
+/// The hash is the 64-bit FNV-1a hash of the UTF-8 bytes of the text, formatted as 16
+/// lowercase hexadecimal digits, so clients can compute it themselves.
+pub fn content_hash(rope: &Rope) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in rope.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
@@ -0,0 +1,173 @@
+//! Opt-in analysis passes enabled for a workspace, remembered across sessions.
+//!
+//! Passes enabled with `l.setAnalysisEnabled` run on every change in addition to the
+//! compiler diagnostics. The set of enabled passes is stored in the cache directory,
+//! in a file keyed by the workspace folders, and loaded again when the server starts
+//! on the same workspace.
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::analysis_passes::AnalysisPass;
+use crate::settings::path_setting;
+
+/// Name of the command that enables or disables an analysis pass for the workspace.
+pub const SET_ANALYSIS_ENABLED_COMMAND: &str = "l.setAnalysisEnabled";
+
+/// Name of the file the enabled passes are stored in.
+const STATE_FILE: &str = "analyses.json";
+
+/// Arguments of the `l.setAnalysisEnabled` command.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetAnalysisEnabledArgs {
+    /// The pass to enable or disable
+    pub pass: AnalysisPass,
+    /// Whether the pass runs on every change
+    pub enabled: bool,
+}
+
+/// Contents of the state file of a workspace.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedAnalyses {
+    /// Workspace folders the state belongs to, for people inspecting the cache
+    roots: Vec<PathBuf>,
+    /// Enabled passes
+    enabled: Vec<AnalysisPass>,
+}
+
+/// The analysis passes enabled for the current workspace.
+#[derive(Debug, Default)]
+pub struct EnabledAnalyses {
+    /// Workspace folders and state file, once the workspace is known
+    workspace: RwLock<Option<(Vec<PathBuf>, PathBuf)>>,
+    /// Passes run on every change
+    enabled: RwLock<Vec<AnalysisPass>>,
+}
+
+impl EnabledAnalyses {
+    /// The enabled passes.
+    pub fn enabled(&self) -> Vec<AnalysisPass> {
+        self.enabled
+            .read()
+            .expect("enabled analyses lock poisoned")
+            .clone()
+    }
+
+    /// Load the passes enabled in a previous session on the same workspace folders.
+    ///
+    /// Without workspace folders nothing is loaded and later changes aren't persisted.
+    pub fn load(&self, cache_dir: &Path, roots: &[PathBuf]) -> anyhow::Result<()> {
+        if roots.is_empty() {
+            return Ok(());
+        }
+        let mut roots = roots.to_vec();
+        roots.sort();
+        let path = cache_dir
+            .join("workspaces")
+            .join(workspace_key(&roots))
+            .join(STATE_FILE);
+        *self
+            .workspace
+            .write()
+            .expect("enabled analyses lock poisoned") = Some((roots, path.clone()));
+
+        if !path.exists() {
+            return Ok(());
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let state: PersistedAnalyses = serde_json::from_str(&text)
+            .with_context(|| format!("invalid analysis state file {}", path.display()))?;
+        *self
+            .enabled
+            .write()
+            .expect("enabled analyses lock poisoned") = state.enabled;
+        Ok(())
+    }
+
+    /// Enable or disable a pass and persist the change, returning whether it changed.
+    ///
+    /// The change takes effect even if persisting it fails.
+    pub fn set(&self, pass: AnalysisPass, enabled: bool) -> anyhow::Result<bool> {
+        let passes = {
+            let mut passes = self
+                .enabled
+                .write()
+                .expect("enabled analyses lock poisoned");
+            if passes.contains(&pass) == enabled {
+                return Ok(false);
+            }
+            if enabled {
+                passes.push(pass);
+            } else {
+                passes.retain(|enabled| *enabled != pass);
+            }
+            passes.clone()
+        };
+
+        let workspace = self
+            .workspace
+            .read()
+            .expect("enabled analyses lock poisoned")
+            .clone();
+        let Some((roots, path)) = workspace else {
+            return Ok(true);
+        };
+        let state = PersistedAnalyses {
+            roots,
+            enabled: passes,
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let text = serde_json::to_string_pretty(&state)?;
+        std::fs::write(&path, text)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(true)
+    }
+}
+
+/// Derive a stable directory name from the workspace folders.
+///
+/// Uses FNV-1a, whose output, unlike that of the std hashers, is fixed across
+/// compiler versions.
+pub fn workspace_key(roots: &[PathBuf]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for root in roots {
+        for byte in root.to_string_lossy().bytes().chain([0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+/// Read the cache directory from a settings object, falling back to the platform's
+/// user cache directory.
+pub fn cache_dir_from_settings(settings: Option<&Value>) -> Option<PathBuf> {
+    settings
+        .and_then(|settings| path_setting(settings, "cacheDirectory"))
+        .or_else(default_cache_dir)
+}
+
+/// The platform's user cache directory for the server.
+fn default_cache_dir() -> Option<PathBuf> {
+    let non_empty = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        non_empty("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        non_empty("HOME").map(|home| PathBuf::from(home).join("Library").join("Caches"))
+    } else {
+        non_empty("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| non_empty("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+    base.map(|base| base.join("l-language-server"))
+}
@@ -0,0 +1,59 @@
+//! Example programs bundled with the server.
+//!
+//! `l.browseExamples` lists them and `l.openExample` creates one as a new file through
+//! `workspace/applyEdit`, giving newcomers runnable L code to start from.
+
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::ls_types::Uri;
+
+/// Name of the command listing the bundled examples.
+pub const BROWSE_EXAMPLES_COMMAND: &str = "l.browseExamples";
+
+/// Name of the command creating a file from a bundled example.
+pub const OPEN_EXAMPLE_COMMAND: &str = "l.openExample";
+
+/// A bundled example program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Example {
+    /// Identifier of the example, also used as its file name
+    pub name: &'static str,
+    /// One-line summary of what the example shows
+    pub description: &'static str,
+    /// Source code of the example
+    pub content: &'static str,
+}
+
+/// All bundled examples.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        name: "functions",
+        description: "Functions, parameters and return values",
+        content: include_str!("../examples/functions.l"),
+    },
+    Example {
+        name: "conditionals",
+        description: "Branching with if and else",
+        content: include_str!("../examples/conditionals.l"),
+    },
+    Example {
+        name: "structs",
+        description: "Struct definitions, literals and field access",
+        content: include_str!("../examples/test.l"),
+    },
+];
+
+/// Look up a bundled example by name.
+pub fn find_example(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
+
+/// Arguments of the `l.openExample` command.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenExampleArgs {
+    /// Name of the example to create
+    pub name: String,
+    /// URI of the file to create; defaults to a file in the first workspace folder,
+    /// or an untitled document without workspace folders
+    pub target: Option<Uri>,
+}
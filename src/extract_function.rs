@@ -0,0 +1,310 @@
+//! Refactoring moving selected statements into a new function.
+//!
+//! The selection must consist of whole statements of a single function body. Variables
+//! used in the selection but declared before it become the parameters of the new
+//! function, in the order they are first used. A variable declared or assigned in the
+//! selection and used after it is returned by the new function and bound by the call
+//! that replaces the selection; as a function returns a single value, selections with
+//! more than one such variable, or containing a `return`, can't be extracted.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use l_lang::{SymbolId, SymbolKind};
+
+use crate::document_store::Document;
+use crate::function_stub::FALLBACK_TYPE;
+use crate::lexer::{Token, tokenize};
+use crate::scopes::{ScopeKind, scope_tree};
+
+/// Kind of the code action extracting a function.
+pub const EXTRACT_FUNCTION_KIND: &str = "refactor.extract.function";
+
+/// Name given to the new function, numbered if it is taken.
+const FUNCTION_NAME: &str = "extracted";
+
+/// A function extracted from a selection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedFunction {
+    /// Name of the new function
+    pub name: String,
+    /// Byte range of the extracted statements, replaced by the call
+    pub selection: Range<usize>,
+    /// Text of the statement calling the new function
+    pub call: String,
+    /// Byte offset the new function is inserted at
+    pub insert_at: usize,
+    /// Text of the new function, including the blank line separating it from the code
+    /// before
+    pub text: String,
+}
+
+/// How the value returned by the new function is bound at the call.
+enum Output {
+    /// Nothing is returned
+    None,
+    /// A variable declared in the selection is declared by the call
+    Declared(SymbolId),
+    /// A variable declared before the selection is assigned by the call
+    Assigned(SymbolId),
+}
+
+/// Extract the statements in a byte range of a document into a new function.
+///
+/// Whitespace and comments around the statements may be part of the range. Returns
+/// `None` if the range doesn't cover whole statements of a function body, or if the
+/// statements can't be moved into a function.
+pub fn extract_function(document: &Document, range: &Range<usize>) -> Option<ExtractedFunction> {
+    let text = document.rope.to_string();
+    let tokens = tokenize(&text);
+    let first = tokens
+        .iter()
+        .position(|token| token.span.start >= range.start)?;
+    let last = tokens
+        .iter()
+        .rposition(|token| token.span.end <= range.end)?;
+    if first > last {
+        return None;
+    }
+    let selected = &tokens[first..=last];
+    let selection = selected[0].span.start..selected[selected.len() - 1].span.end;
+    if !is_statement_list(&tokens[..first], selected) {
+        return None;
+    }
+
+    // The function scope starts at the parameter list, its body at the first brace
+    let tree = scope_tree(document);
+    let function = tree
+        .children
+        .iter()
+        .find(|scope| scope.kind == ScopeKind::Function && scope.span.contains(&selection.start))?;
+    let body_start = tokens
+        .iter()
+        .find(|token| token.text == "{" && token.span.start >= function.span.start)?
+        .span
+        .end;
+    if selection.start < body_start || selection.end >= function.span.end {
+        return None;
+    }
+
+    let semantic = &document.analysis.semantic;
+    let mut inputs = Vec::new();
+    let mut assigned = HashSet::new();
+    let mut outputs = Vec::new();
+    for (ref_id, span) in semantic.reference_spans.iter_enumerated() {
+        let Some(symbol) = semantic.references.get(ref_id).copied().flatten() else {
+            continue;
+        };
+        if !matches!(
+            semantic.get_symbol_kind(symbol),
+            SymbolKind::Variable | SymbolKind::Parameter
+        ) {
+            continue;
+        }
+        let (start, end) = (span.start as usize, span.end as usize);
+        let declared_inside =
+            selection.contains(&(semantic.get_symbol_span(symbol).start as usize));
+        if selection.contains(&start) {
+            if !declared_inside && !inputs.contains(&symbol) {
+                inputs.push(symbol);
+            }
+            if is_assigned(&tokens, end) {
+                assigned.insert(symbol);
+            }
+        } else if start >= selection.end
+            && end <= function.span.end
+            && (declared_inside || assigned.contains(&symbol))
+            && !outputs.contains(&symbol)
+        {
+            outputs.push(symbol);
+        }
+    }
+    let output = match outputs[..] {
+        [] => Output::None,
+        [symbol] if inputs.contains(&symbol) => Output::Assigned(symbol),
+        [symbol] => Output::Declared(symbol),
+        _ => return None,
+    };
+
+    let name = unused_name(document, FUNCTION_NAME);
+    let type_of = |symbol| document.type_label(symbol).unwrap_or(FALLBACK_TYPE);
+    let params = inputs
+        .iter()
+        .map(|symbol| {
+            Some(format!(
+                "{}: {}",
+                document.symbol_name(*symbol)?,
+                type_of(*symbol)
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?
+        .join(", ");
+    let arguments = inputs
+        .iter()
+        .map(|symbol| document.symbol_name(*symbol))
+        .collect::<Option<Vec<_>>>()?
+        .join(", ");
+    let body = reindent(&text, &selection);
+    let (signature, call) = match output {
+        Output::None => (
+            format!("fn {name}({params}) {{\n{body}\n}}"),
+            format!("{name}({arguments});"),
+        ),
+        Output::Declared(symbol) | Output::Assigned(symbol) => {
+            let variable = document.symbol_name(symbol)?;
+            let binding = if matches!(output, Output::Declared(_)) {
+                "let "
+            } else {
+                ""
+            };
+            (
+                format!(
+                    "fn {name}({params}) -> {} {{\n{body}\n    return {variable};\n}}",
+                    type_of(symbol)
+                ),
+                format!("{binding}{variable} = {name}({arguments});"),
+            )
+        }
+    };
+    Some(ExtractedFunction {
+        name,
+        selection,
+        call,
+        insert_at: function.span.end,
+        text: format!("\n\n{signature}"),
+    })
+}
+
+/// Check that the selected tokens form a sequence of whole statements.
+///
+/// The selection must start where a statement can start, end with a `;` or `}`, keep
+/// its braces balanced and not return from the function.
+fn is_statement_list(before: &[Token<'_>], selected: &[Token<'_>]) -> bool {
+    let starts_statement = before
+        .last()
+        .is_some_and(|token| matches!(token.text, "{" | "}" | ";"));
+    let ends_statement = selected
+        .last()
+        .is_some_and(|token| matches!(token.text, "}" | ";"));
+    let mut depth = 0usize;
+    for token in selected {
+        match token.text {
+            "{" => depth += 1,
+            "}" => match depth.checked_sub(1) {
+                Some(outer) => depth = outer,
+                None => return false,
+            },
+            "return" => return false,
+            _ => {}
+        }
+    }
+    starts_statement && ends_statement && depth == 0
+}
+
+/// Check if the variable referenced by the token ending at `end` is assigned to.
+fn is_assigned(tokens: &[Token<'_>], end: usize) -> bool {
+    let Some(index) = tokens.iter().position(|token| token.span.start >= end) else {
+        return false;
+    };
+    tokens[index].text == "="
+        && tokens.get(index + 1).is_none_or(|next| next.text != "=")
+        && (index == 0 || tokens[index - 1].span.end == end)
+}
+
+/// Pick a name no symbol of the document has, numbering `base` if it is taken.
+pub fn unused_name(document: &Document, base: &str) -> String {
+    let semantic = &document.analysis.semantic;
+    let taken = semantic
+        .bindings
+        .iter_enumerated()
+        .filter_map(|(symbol, _)| document.symbol_name(symbol))
+        .collect::<HashSet<_>>();
+    std::iter::once(base.to_string())
+        .chain((2..).map(|index| format!("{base}{index}")))
+        .find(|name| !taken.contains(name.as_str()))
+        .expect("the candidate names are endless")
+}
+
+/// Indent the selected lines as the body of a top-level function.
+///
+/// The indentation of the line the selection starts on is replaced by four spaces.
+fn reindent(text: &str, selection: &Range<usize>) -> String {
+    let line_start = text[..selection.start]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+    let indent = &text[line_start..selection.start];
+    let indent = if indent.trim().is_empty() { indent } else { "" };
+    text[selection.clone()]
+        .lines()
+        .map(|line| {
+            let line = line.strip_prefix(indent).unwrap_or(line);
+            if line.trim().is_empty() {
+                String::new()
+            } else {
+                format!("    {line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use l_lang::compile;
+    use ropey::Rope;
+
+    use super::*;
+
+    /// Extract the first occurrence of `selected` in `text`, returning the edited text.
+    fn extract(text: &str, selected: &str) -> Option<String> {
+        let start = text.find(selected).expect("the selection is in the text");
+        let document = Document::new(Rope::from_str(text), compile(text), None);
+        let extracted = extract_function(&document, &(start..start + selected.len()))?;
+        let mut edited = text.to_string();
+        edited.insert_str(extracted.insert_at, &extracted.text);
+        edited.replace_range(extracted.selection, &extracted.call);
+        Some(edited)
+    }
+
+    #[test]
+    fn statements_without_outputs_become_a_call() {
+        let text = "fn show(value: int) {}\n\nfn main() {\n    let x = 1;\n    show(x);\n    return x;\n}\n";
+        assert_eq!(
+            extract(text, "show(x);").as_deref(),
+            Some(
+                "fn show(value: int) {}\n\nfn main() {\n    let x = 1;\n    extracted(x);\n    return x;\n}\n\nfn extracted(x: int) {\n    show(x);\n}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn variable_declared_in_the_selection_is_returned() {
+        let text = "fn main() {\n    let a = 1;\n    let b = a + 2;\n    return b;\n}\n";
+        assert_eq!(
+            extract(text, "let b = a + 2;").as_deref(),
+            Some(
+                "fn main() {\n    let a = 1;\n    let b = extracted(a);\n    return b;\n}\n\nfn extracted(a: int) -> int {\n    let b = a + 2;\n    return b;\n}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn variable_assigned_in_the_selection_and_used_after_it_is_assigned_by_the_call() {
+        let text =
+            "fn main() {\n    let total = 1;\n    total = total + 2;\n    return total;\n}\n";
+        assert_eq!(
+            extract(text, "total = total + 2;").as_deref(),
+            Some(
+                "fn main() {\n    let total = 1;\n    total = extracted(total);\n    return total;\n}\n\nfn extracted(total: int) -> int {\n    total = total + 2;\n    return total;\n}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn selections_cutting_a_statement_are_not_extracted() {
+        let text = "fn main() {\n    let a = 1;\n    let b = a + 2;\n    return b;\n}\n";
+        assert_eq!(extract(text, "let b = a"), None);
+        assert_eq!(extract(text, "a + 2;"), None);
+        assert_eq!(extract(text, "let b = a + 2;\n    return b;"), None);
+    }
+}
@@ -0,0 +1,327 @@
+//! Refactoring binding a selected expression to a new variable.
+//!
+//! The `let` binding is inserted above the statement containing the expression, on a
+//! line of its own with the statement's indentation, and the expression is replaced
+//! with the variable. Identical expressions later in the same block can be replaced
+//! too; they are compared by their tokens, so a duplicate reading a variable that was
+//! reassigned in between is replaced as well and should be checked by hand. The type
+//! annotation is omitted if the type of the expression isn't known.
+
+use std::ops::Range;
+
+use crate::document_store::Document;
+use crate::extract_function::unused_name;
+use crate::function_stub::{Expression, describe_expression};
+use crate::lexer::{Token, tokenize};
+use crate::scopes::{ScopeKind, scope_tree};
+
+/// Kind of the code action extracting a variable.
+pub const EXTRACT_VARIABLE_KIND: &str = "refactor.extract.variable";
+
+/// Name given to the new variable, numbered if it is taken.
+const VARIABLE_NAME: &str = "value";
+
+/// A variable extracted from a selected expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedVariable {
+    /// Name of the new variable
+    pub name: String,
+    /// Byte offset the declaration is inserted at, the start of the statement
+    pub insert_at: usize,
+    /// Text of the declaration, including the line break and indentation separating it
+    /// from the statement
+    pub declaration: String,
+    /// Byte range of the selected expression
+    pub selection: Range<usize>,
+    /// Byte ranges of identical expressions later in the same block
+    pub duplicates: Vec<Range<usize>>,
+}
+
+/// Extract the expression in a byte range of a document into a variable.
+///
+/// Whitespace around the expression may be part of the range. Returns `None` if the
+/// range doesn't cover a whole expression inside a function body.
+pub fn extract_variable(document: &Document, range: &Range<usize>) -> Option<ExtractedVariable> {
+    let text = document.rope.to_string();
+    let selected = text.get(range.clone())?;
+    let start = range.start + (selected.len() - selected.trim_start().len());
+    let selection = start..start + selected.trim().len();
+    if selection.is_empty() {
+        return None;
+    }
+    let tokens = tokenize(&text);
+    let (first, last) = expression_tokens(&text, &tokens, &selection)?;
+
+    // The function scope starts at the parameter list, its body at the first brace
+    let tree = scope_tree(document);
+    let function = tree
+        .children
+        .iter()
+        .find(|scope| scope.kind == ScopeKind::Function && scope.span.contains(&selection.start))?;
+    let body_start = tokens
+        .iter()
+        .find(|token| token.text == "{" && token.span.start >= function.span.start)?
+        .span
+        .start;
+    let statement = statement_start(&tokens, first)?;
+    if tokens[statement - 1].span.start < body_start || tokens[statement].text == "else" {
+        return None;
+    }
+
+    // Duplicates are only searched for expressions delimited by tokens, as string
+    // literals at either end aren't part of the compared tokens
+    let token_aligned = first < last
+        && tokens[first].span.start == selection.start
+        && tokens[last - 1].span.end == selection.end;
+    let pattern = token_texts(&text, &tokens[first..last]);
+    let block_end = if token_aligned {
+        block_end(&tokens, last)
+    } else {
+        last
+    };
+    let duplicates = (last..block_end)
+        .filter_map(|start| {
+            let end = start + (last - first);
+            let candidate = tokens.get(start..end)?;
+            let span = candidate.first()?.span.start..candidate.last()?.span.end;
+            (token_texts(&text, candidate) == pattern
+                && expression_tokens(&text, &tokens, &span) == Some((start, end)))
+            .then_some(span)
+        })
+        .fold(Vec::<Range<usize>>::new(), |mut duplicates, span| {
+            if duplicates.last().is_none_or(|last| last.end <= span.start) {
+                duplicates.push(span);
+            }
+            duplicates
+        });
+
+    let expression = Expression {
+        text: &text[selection.clone()],
+        tokens: &tokens[first..last],
+    };
+    let ty = infer_type(document, &expression);
+    let name = unused_name(document, VARIABLE_NAME);
+    let statement_at = tokens[statement].span.start;
+    let line_start = text[..statement_at]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+    let indent = &text[line_start..statement_at];
+    let indent = if indent.trim().is_empty() { indent } else { "" };
+    let annotation = ty.map(|ty| format!(": {ty}")).unwrap_or_default();
+    Some(ExtractedVariable {
+        declaration: format!("let {name}{annotation} = {};\n{indent}", expression.text),
+        name,
+        insert_at: statement_at,
+        selection,
+        duplicates,
+    })
+}
+
+/// Find the tokens of the expression spanning exactly a byte range.
+///
+/// Returns the index of the first token and the index after the last one, or `None`
+/// if the range cuts a token or string literal, isn't balanced, or isn't an
+/// expression that can be read into a variable, such as a name being declared or
+/// assigned.
+fn expression_tokens(
+    text: &str,
+    tokens: &[Token<'_>],
+    selection: &Range<usize>,
+) -> Option<(usize, usize)> {
+    if tokens
+        .iter()
+        .any(|token| token.span.contains(&selection.start) && token.span.start != selection.start)
+        || tokens
+            .iter()
+            .any(|token| token.span.contains(&selection.end) && token.span.start != selection.end)
+        || !text[selection.clone()]
+            .matches('"')
+            .count()
+            .is_multiple_of(2)
+    {
+        return None;
+    }
+    let first = tokens
+        .iter()
+        .position(|token| token.span.start >= selection.start)
+        .unwrap_or(tokens.len());
+    let last = tokens
+        .iter()
+        .position(|token| token.span.start >= selection.end)
+        .unwrap_or(tokens.len());
+    let inner = &tokens[first..last];
+    if inner.is_empty() && !text[selection.clone()].starts_with('"') {
+        return None;
+    }
+
+    let mut depth = 0usize;
+    for (index, token) in inner.iter().enumerate() {
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth = depth.checked_sub(1)?,
+            ";" => return None,
+            "=" if depth == 0 => {
+                let next = inner.get(index + 1).map(|token| token.text);
+                let previous = index.checked_sub(1).map(|index| inner[index].text);
+                let comparison =
+                    next == Some("=") || matches!(previous, Some("=" | "!" | "<" | ">"));
+                if !comparison {
+                    return None;
+                }
+            }
+            "let" | "fn" | "struct" | "return" => return None,
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+
+    let before = first.checked_sub(1).map(|index| tokens[index].text);
+    let after = tokens.get(last).map(|token| token.text);
+    let after_next = tokens.get(last + 1).map(|token| token.text);
+    let declared = matches!(before, Some("." | "let" | "fn" | "struct"));
+    let assigned = after == Some("=") && after_next != Some("=");
+    let called = after == Some("(") && inner.len() == 1;
+    let labelled = after == Some(":");
+    (!declared && !assigned && !called && !labelled).then_some((first, last))
+}
+
+/// Find the first token of the statement containing a token.
+///
+/// The statement starts after the closest `;` or brace outside of parentheses. Returns
+/// `None` if that is the end of the file, outside of any function body.
+fn statement_start(tokens: &[Token<'_>], index: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for start in (0..index).rev() {
+        match tokens[start].text {
+            ")" | "]" => depth += 1,
+            "(" | "[" => depth = depth.saturating_sub(1),
+            ";" | "{" | "}" if depth == 0 => return Some(start + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find the index of the brace closing the block containing a token.
+fn block_end(tokens: &[Token<'_>], index: usize) -> usize {
+    let mut depth = 0usize;
+    for (end, token) in tokens.iter().enumerate().skip(index) {
+        match token.text {
+            "{" => depth += 1,
+            "}" if depth == 0 => return end,
+            "}" => depth -= 1,
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// The source text of a sequence of tokens, with string literals in between.
+fn token_texts<'a>(text: &'a str, tokens: &[Token<'_>]) -> Vec<&'a str> {
+    tokens
+        .windows(2)
+        .flat_map(|pair| {
+            let between = text[pair[0].span.end..pair[1].span.start].trim();
+            [&text[pair[0].span.clone()], between]
+        })
+        .chain(tokens.last().map(|token| &text[token.span.clone()]))
+        .filter(|text| !text.is_empty())
+        .collect()
+}
+
+/// Infer the type of an expression, as far as it is known.
+///
+/// Comparisons and logical operators yield `bool`; anything else is left to
+/// [`describe_expression`].
+fn infer_type(document: &Document, expression: &Expression<'_>) -> Option<String> {
+    let mut depth = 0usize;
+    let mut boolean = false;
+    for (index, token) in expression.tokens.iter().enumerate() {
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            "&&" | "||" | "<" | ">" if depth == 0 => boolean = true,
+            "=" | "!" if depth == 0 => {
+                let next = expression.tokens.get(index + 1).map(|token| token.text);
+                boolean |= token.text == "=" || next == Some("=") || index == 0;
+            }
+            _ => {}
+        }
+    }
+    if boolean {
+        return Some("bool".to_string());
+    }
+    describe_expression(document, expression).1
+}
+
+#[cfg(test)]
+mod tests {
+    use l_lang::compile;
+    use ropey::Rope;
+
+    use super::*;
+
+    /// Extract the first occurrence of `selected` in `text`, returning the edited text
+    /// with the selection, and the duplicates if `all`, replaced by the variable.
+    fn extract(text: &str, selected: &str, all: bool) -> Option<String> {
+        let start = text.find(selected).expect("the selection is in the text");
+        let document = Document::new(Rope::from_str(text), compile(text), None);
+        let extracted = extract_variable(&document, &(start..start + selected.len()))?;
+        let mut edited = text.to_string();
+        let duplicates = if all { &extracted.duplicates[..] } else { &[] };
+        for range in duplicates.iter().rev() {
+            edited.replace_range(range.clone(), &extracted.name);
+        }
+        edited.replace_range(extracted.selection, &extracted.name);
+        edited.insert_str(extracted.insert_at, &extracted.declaration);
+        Some(edited)
+    }
+
+    #[test]
+    fn expression_is_bound_above_its_statement() {
+        let text = "fn main() {\n    let a = 1;\n    return a * 2 + 1;\n}\n";
+        assert_eq!(
+            extract(text, " a * 2 ", false).as_deref(),
+            Some("fn main() {\n    let a = 1;\n    let value = a * 2;\n    return value + 1;\n}\n")
+        );
+    }
+
+    #[test]
+    fn comparisons_are_annotated_as_bool() {
+        let text = "fn main() {\n    let a = 1;\n    let big = a > 2;\n    return a;\n}\n";
+        assert_eq!(
+            extract(text, "a > 2", false).as_deref(),
+            Some(
+                "fn main() {\n    let a = 1;\n    let value: bool = a > 2;\n    let big = value;\n    return a;\n}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn identical_expressions_later_in_the_block_can_be_replaced_too() {
+        let text = "fn main() {\n    let a = 1;\n    let b = a * 2;\n    return a * 2 + b;\n}\n";
+        assert_eq!(
+            extract(text, "a * 2", true).as_deref(),
+            Some(
+                "fn main() {\n    let a = 1;\n    let value = a * 2;\n    let b = value;\n    return value + b;\n}\n"
+            )
+        );
+        assert_eq!(
+            extract(text, "a * 2", false).as_deref(),
+            Some(
+                "fn main() {\n    let a = 1;\n    let value = a * 2;\n    let b = value;\n    return a * 2 + b;\n}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn selections_that_are_not_whole_expressions_are_not_extracted() {
+        let text = "fn main() {\n    let a = 1;\n    return a * 2 + 1;\n}\n";
+        assert_eq!(extract(text, "let a = 1", false), None);
+        assert_eq!(extract(text, "= 1", false), None);
+        assert_eq!(extract(text, "a * 2 + 1;", false), None);
+        assert_eq!(extract(text, "et a", false), None);
+    }
+}
@@ -0,0 +1,51 @@
+//! Dynamic registration of the language features settings turn on and off.
+//!
+//! A feature announced in the server capabilities stays active until the server
+//! restarts, so clients keep asking for inlay hints or semantic tokens the
+//! `disabledFeatures` setting turned off, and keep showing what they cached. Clients
+//! supporting dynamic registration of these features get them registered with
+//! `client/registerCapability` once initialized instead, unregistered with
+//! `client/unregisterCapability` when the setting turns them off and registered again
+//! when it turns them back on. Other clients get the static capabilities, and the
+//! handlers answer nothing while a feature is off.
+
+use tower_lsp_server::ls_types::ClientCapabilities;
+
+use crate::settings::Feature;
+
+/// Features registered dynamically with clients supporting it.
+pub const DYNAMIC_FEATURES: [Feature; 2] = [Feature::InlayHints, Feature::SemanticTokens];
+
+/// The method a dynamically registered feature is registered for.
+pub fn registration_method(feature: Feature) -> Option<&'static str> {
+    match feature {
+        Feature::InlayHints => Some("textDocument/inlayHint"),
+        Feature::SemanticTokens => Some("textDocument/semanticTokens"),
+        _ => None,
+    }
+}
+
+/// The id of the registration of a dynamically registered feature.
+pub fn registration_id(feature: Feature) -> Option<&'static str> {
+    match feature {
+        Feature::InlayHints => Some("l-inlay-hints"),
+        Feature::SemanticTokens => Some("l-semantic-tokens"),
+        _ => None,
+    }
+}
+
+/// Check if a client registers a feature dynamically instead of through the server
+/// capabilities.
+pub fn registers_dynamically(capabilities: Option<&ClientCapabilities>, feature: Feature) -> bool {
+    let text_document = capabilities.and_then(|capabilities| capabilities.text_document.as_ref());
+    let dynamic_registration = match feature {
+        Feature::InlayHints => text_document
+            .and_then(|text_document| text_document.inlay_hint.as_ref())
+            .and_then(|inlay_hint| inlay_hint.dynamic_registration),
+        Feature::SemanticTokens => text_document
+            .and_then(|text_document| text_document.semantic_tokens.as_ref())
+            .and_then(|semantic_tokens| semantic_tokens.dynamic_registration),
+        _ => None,
+    };
+    dynamic_registration.unwrap_or(false)
+}
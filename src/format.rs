@@ -0,0 +1,114 @@
+//! Formatting of L source, and the `format` subcommand running it on files.
+//!
+//! The subcommand formats files the way the server formats documents, with the width
+//! of their `l.toml` or the default. With `--check` it only lists the files that
+//! aren't formatted and fails if there are any, for CI. With `--stdin` it formats
+//! standard input to standard output, with the width of the project of the current
+//! directory. Files with syntax errors are left untouched.
+
+use std::io::Read;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use anyhow::{Context, bail};
+use l_lang::{CompileResult, Formatter, compile};
+
+use crate::check::source_files;
+use crate::cli::FormatArgs;
+use crate::project_config::{PROJECT_FILE, Projects};
+
+/// Format L source with its analysis.
+pub fn format_source(text: &str, analysis: &CompileResult, width: usize) -> String {
+    Formatter::new(width).format(analysis.program.file(), text)
+}
+
+/// Format the files or standard input the arguments name, and return the exit code.
+///
+/// The exit code is 1 if `--check` found unformatted code, and 2 if a file couldn't be
+/// read, parsed or written.
+pub fn run(args: &FormatArgs) -> ExitCode {
+    let projects = Projects::default();
+    let result = if args.stdin {
+        format_stdin(args.check, &projects)
+    } else {
+        let mut result = Ok(true);
+        for path in args
+            .paths
+            .iter()
+            .flat_map(|path| source_files(path, &projects))
+        {
+            match path.and_then(|path| format_file(&path, args.check, &projects)) {
+                Ok(formatted) => result = result.map(|all| all && formatted),
+                Err(err) => {
+                    eprintln!("error: {err:#}");
+                    result = Err(());
+                }
+            }
+        }
+        result
+    };
+    for err in projects.take_errors() {
+        eprintln!("warning: ignoring the project configuration: {err}");
+    }
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(()) => ExitCode::from(2),
+    }
+}
+
+/// Format a file in place, or only report it if it isn't formatted with `check`.
+///
+/// Returns whether the file was already formatted.
+fn format_file(path: &Path, check: bool, projects: &Projects) -> anyhow::Result<bool> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let absolute = std::path::absolute(path)?;
+    let width = projects
+        .settings_for(&absolute, Arc::default())
+        .format_width;
+    let formatted =
+        format_checked(&text, width).with_context(|| format!("can't format {}", path.display()))?;
+    if formatted == text {
+        return Ok(true);
+    }
+    if check {
+        println!("{} is not formatted", path.display());
+    } else {
+        std::fs::write(path, formatted)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(false)
+}
+
+/// Format standard input to standard output, or only compare them with `check`.
+///
+/// Returns whether the input was already formatted.
+fn format_stdin(check: bool, projects: &Projects) -> Result<bool, ()> {
+    let format = || -> anyhow::Result<bool> {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .context("failed to read standard input")?;
+        let directory = std::env::current_dir()?;
+        let width = projects
+            .settings_for(&directory.join(PROJECT_FILE), Arc::default())
+            .format_width;
+        let formatted = format_checked(&text, width).context("can't format standard input")?;
+        if !check {
+            print!("{formatted}");
+        }
+        Ok(formatted == text)
+    };
+    format().map_err(|err| eprintln!("error: {err:#}"))
+}
+
+/// Format L source, refusing to if it has syntax errors the formatter could mangle.
+fn format_checked(text: &str, width: usize) -> anyhow::Result<String> {
+    let analysis = compile(text);
+    if !analysis.diagnostics.is_empty() {
+        bail!("{} syntax errors", analysis.diagnostics.len());
+    }
+    Ok(format_source(text, &analysis, width))
+}
@@ -0,0 +1,276 @@
+//! Quick fix generating a stub for a called function that doesn't exist.
+//!
+//! A call to an undefined function is an unresolved reference followed by an argument
+//! list. The stub is inserted after the function containing the call, with one
+//! parameter per argument. Parameter names and types are taken from the arguments
+//! where the semantic analysis knows them: a variable or field passes its name and
+//! type, a literal or struct literal its type. Other arguments get a numbered name and
+//! the `int` type, to be fixed by hand.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use l_lang::{SymbolId, SymbolKind};
+
+use crate::document_store::Document;
+use crate::lexer::{Token, tokenize};
+use crate::scopes::{ScopeKind, scope_tree};
+
+/// Type given to parameters whose argument type isn't known.
+pub const FALLBACK_TYPE: &str = "int";
+
+/// A function to generate for a call to an undefined function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionStub {
+    /// Name of the called function
+    pub name: String,
+    /// Byte range of the function name at the call
+    pub call: Range<usize>,
+    /// Byte offset the stub is inserted at
+    pub insert_at: usize,
+    /// Text of the stub, including the blank line separating it from the code before
+    pub text: String,
+}
+
+/// Find the calls to undefined functions overlapping a byte range of a document.
+///
+/// Each called name gets a single stub, generated from its first call in the range.
+pub fn function_stubs(document: &Document, range: &Range<usize>) -> Vec<FunctionStub> {
+    let semantic = &document.analysis.semantic;
+    let unresolved = semantic
+        .reference_spans
+        .iter_enumerated()
+        .filter(|(ref_id, span)| {
+            semantic
+                .references
+                .get(*ref_id)
+                .copied()
+                .flatten()
+                .is_none()
+                && span.start as usize <= range.end
+                && range.start <= span.end as usize
+        })
+        .map(|(_, span)| span.start as usize..span.end as usize)
+        .collect::<Vec<_>>();
+    if unresolved.is_empty() {
+        return Vec::new();
+    }
+
+    let text = document.rope.to_string();
+    let tokens = tokenize(&text);
+    let tree = scope_tree(document);
+    let mut seen = HashSet::new();
+    unresolved
+        .into_iter()
+        .filter_map(|call| {
+            let index = tokens.iter().position(|token| token.span == call)?;
+            let arguments = delimited_list(&text, &tokens[index + 1..], "(", ")")?;
+            let name = tokens[index].text;
+            if !seen.insert(name) {
+                return None;
+            }
+            let params = parameters(document, &arguments);
+            let insert_at = tree
+                .children
+                .iter()
+                .find(|scope| scope.kind == ScopeKind::Function && scope.span.contains(&call.start))
+                .map_or(text.len(), |scope| scope.span.end);
+            Some(FunctionStub {
+                name: name.to_string(),
+                call,
+                insert_at,
+                text: format!("\n\nfn {name}({params}) {{\n}}"),
+            })
+        })
+        .collect()
+}
+
+/// An expression of a comma-separated list, such as a call argument.
+#[derive(Debug)]
+pub struct Expression<'a> {
+    /// Source text of the expression, trimmed
+    pub text: &'a str,
+    /// Tokens of the expression; string literals have none
+    pub tokens: &'a [Token<'a>],
+}
+
+/// Split the comma-separated list opened by the first token into its items.
+///
+/// Returns `None` if the first token isn't `open`, or if the list isn't closed.
+pub fn delimited_list<'a>(
+    text: &'a str,
+    tokens: &'a [Token<'a>],
+    open: &str,
+    close: &str,
+) -> Option<Vec<Expression<'a>>> {
+    if tokens.first()?.text != open {
+        return None;
+    }
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    // Index of the first token and byte offset of the current item
+    let mut start = (1, tokens[0].span.end);
+    for (index, token) in tokens.iter().enumerate().skip(1) {
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            item if depth == 0 && (item == "," || item == close) => {
+                let expression = Expression {
+                    text: text[start.1..token.span.start].trim(),
+                    tokens: &tokens[start.0..index],
+                };
+                if item == "," || !expression.text.is_empty() {
+                    items.push(expression);
+                }
+                if item == close {
+                    return Some(items);
+                }
+                start = (index + 1, token.span.end);
+            }
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Format the parameter list of a stub called with the given arguments.
+///
+/// Parameters whose name is unknown or already taken are named after their position.
+fn parameters(document: &Document, arguments: &[Expression<'_>]) -> String {
+    let mut names = HashSet::new();
+    arguments
+        .iter()
+        .enumerate()
+        .map(|(index, argument)| {
+            let (name, ty) = describe_expression(document, argument);
+            let name = match name {
+                Some(name) if names.insert(name.clone()) => name,
+                _ => format!("arg{index}"),
+            };
+            names.insert(name.clone());
+            format!(
+                "{name}: {}",
+                ty.unwrap_or_else(|| FALLBACK_TYPE.to_string())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Infer a name and the type of an expression, as far as they are known.
+///
+/// Only variables and field accesses have a name, the one of the variable or field.
+pub fn describe_expression(
+    document: &Document,
+    expression: &Expression<'_>,
+) -> (Option<String>, Option<String>) {
+    let semantic = &document.analysis.semantic;
+    let resolve = |token: &Token<'_>| resolve_token(document, token);
+
+    match expression.tokens {
+        [] if expression.text.starts_with('"') => (None, Some("string".to_string())),
+        [token] if token.text == "true" || token.text == "false" => {
+            (None, Some("bool".to_string()))
+        }
+        [token] if token.text.bytes().all(|byte| byte.is_ascii_digit()) => {
+            (None, Some("int".to_string()))
+        }
+        [first, open, ..] if open.text == "{" => {
+            let is_struct = resolve(first)
+                .is_some_and(|symbol| semantic.get_symbol_kind(symbol) == SymbolKind::Struct);
+            (None, is_struct.then(|| first.text.to_string()))
+        }
+        // A variable, or a chain of field accesses ending with the passed field
+        tokens
+            if tokens.len() % 2 == 1
+                && tokens
+                    .iter()
+                    .step_by(2)
+                    .all(|token| is_identifier(token.text))
+                && tokens
+                    .iter()
+                    .skip(1)
+                    .step_by(2)
+                    .all(|token| token.text == ".") =>
+        {
+            let last = tokens.last().expect("the chain has an odd length");
+            let ty = resolve(last)
+                .and_then(|symbol| document.type_label(symbol))
+                .map(str::to_string);
+            (Some(last.text.to_string()), ty)
+        }
+        _ => (None, None),
+    }
+}
+
+/// Resolve the symbol referenced by a token, if it is a resolved reference.
+pub fn resolve_token(document: &Document, token: &Token<'_>) -> Option<SymbolId> {
+    let semantic = &document.analysis.semantic;
+    let interval = semantic
+        .span_to_reference
+        .find(token.span.start, token.span.end)
+        .find(|interval| interval.start == token.span.start && interval.stop == token.span.end)?;
+    semantic.references.get(interval.val).copied().flatten()
+}
+
+/// Check if a token is an identifier.
+pub fn is_identifier(token: &str) -> bool {
+    token
+        .bytes()
+        .next()
+        .is_some_and(|byte| byte.is_ascii_alphabetic() || byte == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use l_lang::compile;
+    use ropey::Rope;
+
+    use super::*;
+
+    /// Generate the stubs for the calls in the whole of `text`, returning the edited
+    /// text.
+    fn with_stubs(text: &str) -> String {
+        let document = Document::new(Rope::from_str(text), compile(text), None);
+        let mut edited = text.to_string();
+        for stub in function_stubs(&document, &(0..text.len())).iter().rev() {
+            edited.insert_str(stub.insert_at, &stub.text);
+        }
+        edited
+    }
+
+    #[test]
+    fn stub_parameters_follow_the_arguments() {
+        let text =
+            "fn main() {\n    let a = 1;\n    let b = add(a, 2, a > 1, \"x\");\n    return b;\n}\n";
+        assert_eq!(
+            with_stubs(text),
+            "fn main() {\n    let a = 1;\n    let b = add(a, 2, a > 1, \"x\");\n    return b;\n}\n\nfn add(a: int, arg1: int, arg2: int, arg3: string) {\n}\n"
+        );
+    }
+
+    #[test]
+    fn each_undefined_function_gets_a_single_stub() {
+        let text = "fn main() {\n    show(1);\n    show(2);\n    return 0;\n}\n";
+        assert_eq!(
+            with_stubs(text),
+            "fn main() {\n    show(1);\n    show(2);\n    return 0;\n}\n\nfn show(arg0: int) {\n}\n"
+        );
+    }
+
+    #[test]
+    fn defined_functions_get_no_stub() {
+        let text = "fn one() -> int {\n    return 1;\n}\n\nfn main() {\n    return one();\n}\n";
+        assert_eq!(with_stubs(text), text);
+    }
+
+    #[test]
+    fn list_items_are_split_at_top_level_commas() {
+        let text = "(a, f(b, c), { d }, \"e, f\")";
+        let tokens = tokenize(text);
+        let items = delimited_list(text, &tokens, "(", ")").expect("the list is closed");
+        let texts = items.iter().map(|item| item.text).collect::<Vec<_>>();
+        assert_eq!(texts, ["a", "f(b, c)", "{ d }", "\"e, f\""]);
+        assert!(delimited_list(text, &tokens[..tokens.len() - 1], "(", ")").is_none());
+    }
+}
@@ -0,0 +1,94 @@
+//! Keyword table and other syntax-level data.
+//!
+//! The keywords, builtin types and completion trigger characters used by the
+//! syntax-level features are loaded from a JSON data file instead of being hardcoded,
+//! so the language can evolve without touching the server code. The file embedded in
+//! the binary is used by default and can be overridden with the `grammarPath` setting;
+//! the override is reread whenever the setting changes or `l.reloadGrammar` is run.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::settings::path_setting;
+
+/// The grammar data file embedded in the binary.
+const DEFAULT_GRAMMAR: &str = include_str!("grammar.json");
+
+/// Name of the command that rereads the grammar data file.
+pub const RELOAD_GRAMMAR_COMMAND: &str = "l.reloadGrammar";
+
+/// Syntax-level data describing the L language.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Grammar {
+    /// Reserved words of the language
+    pub keywords: Vec<String>,
+    /// Names of the types built into the language
+    pub builtin_types: Vec<String>,
+    /// Characters that trigger completion when typed
+    pub trigger_characters: Vec<String>,
+}
+
+impl Default for Grammar {
+    fn default() -> Self {
+        serde_json::from_str(DEFAULT_GRAMMAR).expect("embedded grammar is valid")
+    }
+}
+
+impl Grammar {
+    /// Load a grammar from a JSON data file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read grammar file {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("invalid grammar file {}", path.display()))
+    }
+}
+
+/// The grammar currently in use, together with the file it was loaded from.
+#[derive(Debug, Default)]
+pub struct GrammarTable {
+    /// Path of the overriding grammar file, if configured
+    path: RwLock<Option<PathBuf>>,
+    /// The grammar currently in use
+    current: RwLock<Arc<Grammar>>,
+}
+
+impl GrammarTable {
+    /// The grammar currently in use.
+    pub fn get(&self) -> Arc<Grammar> {
+        Arc::clone(&self.current.read().expect("grammar lock poisoned"))
+    }
+
+    /// Set the overriding grammar file, returning whether it changed.
+    pub fn set_path(&self, path: Option<PathBuf>) -> bool {
+        let mut current = self.path.write().expect("grammar lock poisoned");
+        if *current == path {
+            return false;
+        }
+        *current = path;
+        true
+    }
+
+    /// Reread the grammar from the configured file, or fall back to the embedded one.
+    ///
+    /// On error the previously loaded grammar stays in use.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let path = self.path.read().expect("grammar lock poisoned").clone();
+        let grammar = match path {
+            Some(path) => Grammar::load(&path)?,
+            None => Grammar::default(),
+        };
+        *self.current.write().expect("grammar lock poisoned") = Arc::new(grammar);
+        Ok(())
+    }
+}
+
+/// Read the overriding grammar file from a settings object.
+pub fn grammar_path_from_settings(settings: &Value) -> Option<PathBuf> {
+    path_setting(settings, "grammarPath")
+}
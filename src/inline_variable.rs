@@ -0,0 +1,302 @@
+//! Refactoring replacing a variable with its initializer.
+//!
+//! Every reference to the variable is replaced with the initializer of its `let`
+//! binding, parenthesized where the surrounding operators could bind to parts of it,
+//! and the binding is deleted. Inlining must not change what the program computes, so
+//! it is refused if the initializer calls a function, which could have side effects or
+//! be expensive to repeat, if the variable is ever reassigned, or if a variable the
+//! initializer reads is assigned or shadowed after the binding.
+
+use std::ops::Range;
+
+use l_lang::{SymbolId, SymbolKind};
+
+use crate::document_store::Document;
+use crate::function_stub::{is_identifier, resolve_token};
+use crate::lexer::{Token, tokenize};
+use crate::symbol_at::pick_symbol_at;
+
+/// A variable replaced with its initializer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlinedVariable {
+    /// Name of the variable
+    pub name: String,
+    /// Byte range of the binding to delete, including the line it is on if nothing
+    /// else is on it
+    pub binding: Range<usize>,
+    /// Byte ranges of the references and the text replacing each of them
+    pub replacements: Vec<(Range<usize>, String)>,
+}
+
+/// Inline the variable defined or referenced at a byte offset of a document.
+///
+/// Returns `None` if there is no variable at the offset, if its binding has no simple
+/// initializer, or if inlining it could change the behavior of the program.
+pub fn inline_variable(document: &Document, offset: usize) -> Option<InlinedVariable> {
+    let semantic = &document.analysis.semantic;
+    let symbol = pick_symbol_at(&document.analysis, offset)?.symbol_id;
+    if semantic.get_symbol_kind(symbol) != SymbolKind::Variable {
+        return None;
+    }
+    let text = document.rope.to_string();
+    let tokens = tokenize(&text);
+    let name_span = semantic.get_symbol_span(symbol);
+    let name = tokens
+        .iter()
+        .position(|token| token.span == (name_span.start as usize..name_span.end as usize))?;
+    if name == 0 || tokens[name - 1].text != "let" {
+        return None;
+    }
+
+    // The initializer follows the `=`, after an optional type annotation
+    let assign = name
+        + 1
+        + tokens[name + 1..]
+            .iter()
+            .take_while(|token| token.text != "=" && token.text != ";")
+            .count();
+    if tokens.get(assign)?.text != "=" {
+        return None;
+    }
+    let semicolon = assign
+        + 1
+        + tokens[assign + 1..]
+            .iter()
+            .scan(0usize, |depth, token| {
+                match token.text {
+                    "(" | "[" | "{" => *depth += 1,
+                    ")" | "]" | "}" => *depth = depth.saturating_sub(1),
+                    ";" if *depth == 0 => return None,
+                    _ => {}
+                }
+                Some(())
+            })
+            .count();
+    if tokens.get(semicolon)?.text != ";" {
+        return None;
+    }
+    // String literals have no tokens, so the initializer is taken from the text
+    let initializer = &tokens[assign + 1..semicolon];
+    let init_text = text[tokens[assign].span.end..tokens[semicolon].span.start].trim();
+    if init_text.is_empty() || calls_function(initializer) {
+        return None;
+    }
+    let binding_end = tokens[semicolon].span.end;
+
+    let references = references_to(document, symbol);
+    if references
+        .iter()
+        .any(|reference| is_assigned(&tokens, reference.end))
+    {
+        return None;
+    }
+    let scope_end = references
+        .last()
+        .map_or(binding_end, |reference| reference.end);
+    let read = initializer
+        .iter()
+        .filter(|token| is_identifier(token.text))
+        .filter_map(|token| resolve_token(document, token))
+        .collect::<Vec<_>>();
+    for symbol in &read {
+        let assigned = references_to(document, *symbol).iter().any(|reference| {
+            reference.start >= binding_end
+                && reference.start < scope_end
+                && is_assigned(&tokens, reference.end)
+        });
+        if assigned || is_shadowed(document, *symbol, binding_end..scope_end) {
+            return None;
+        }
+    }
+
+    // Literals, variables, field accesses and parenthesized expressions are never split
+    // by the operators around a reference
+    let simple = initializer.is_empty()
+        || initializer.len() == 1 && init_text == initializer[0].text
+        || init_text.starts_with('(') && matching_paren(&tokens, assign + 1) == Some(semicolon - 1)
+        || !init_text.contains('"') && is_field_chain(initializer);
+    let replacements = references
+        .iter()
+        .map(|reference| {
+            let index = tokens.iter().position(|token| token.span == *reference);
+            let standalone = index.is_some_and(|index| {
+                let before = index.checked_sub(1).map(|before| tokens[before].text);
+                let after = tokens.get(index + 1).map(|after| after.text);
+                let comparison = before == Some("=")
+                    && index >= 2
+                    && matches!(tokens[index - 2].text, "=" | "!" | "<" | ">");
+                matches!(before, Some("=" | "(" | "," | ":" | "{" | "return"))
+                    && matches!(after, Some(";" | ")" | "," | "}"))
+                    && !comparison
+            });
+            let replacement = if simple || standalone {
+                init_text.to_string()
+            } else {
+                format!("({init_text})")
+            };
+            (reference.clone(), replacement)
+        })
+        .collect();
+
+    Some(InlinedVariable {
+        name: document.symbol_name(symbol)?.to_string(),
+        binding: whole_lines(&text, tokens[name - 1].span.start..binding_end),
+        replacements,
+    })
+}
+
+/// Check if the tokens of an expression contain a call.
+fn calls_function(tokens: &[Token<'_>]) -> bool {
+    tokens
+        .windows(2)
+        .any(|pair| is_identifier(pair[0].text) && pair[1].text == "(")
+}
+
+/// Check if the tokens of an expression are a variable or a chain of field accesses.
+fn is_field_chain(tokens: &[Token<'_>]) -> bool {
+    tokens.len() % 2 == 1
+        && tokens
+            .iter()
+            .step_by(2)
+            .all(|token| is_identifier(token.text))
+        && tokens
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .all(|token| token.text == ".")
+}
+
+/// Find the index of the parenthesis closing the one at `open`.
+fn matching_paren(tokens: &[Token<'_>], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        match token.text {
+            "(" => depth += 1,
+            ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Collect the byte ranges of the references to a symbol, in document order.
+fn references_to(document: &Document, symbol: SymbolId) -> Vec<Range<usize>> {
+    let semantic = &document.analysis.semantic;
+    let mut references = semantic
+        .reference_spans
+        .iter_enumerated()
+        .filter(|(ref_id, _)| semantic.references.get(*ref_id).copied().flatten() == Some(symbol))
+        .map(|(_, span)| span.start as usize..span.end as usize)
+        .collect::<Vec<_>>();
+    references.sort_by_key(|reference| reference.start);
+    references
+}
+
+/// Check if the variable referenced by the token ending at `end`, or one of its fields,
+/// is assigned to.
+fn is_assigned(tokens: &[Token<'_>], end: usize) -> bool {
+    let Some(mut index) = tokens.iter().position(|token| token.span.start >= end) else {
+        return false;
+    };
+    while tokens[index].text == "."
+        && tokens
+            .get(index + 1)
+            .is_some_and(|field| is_identifier(field.text))
+    {
+        index += 2;
+        if index >= tokens.len() {
+            return false;
+        }
+    }
+    tokens[index].text == "=" && tokens.get(index + 1).is_none_or(|next| next.text != "=")
+}
+
+/// Check if another symbol with the name of `symbol` is declared in a byte range.
+fn is_shadowed(document: &Document, symbol: SymbolId, range: Range<usize>) -> bool {
+    let semantic = &document.analysis.semantic;
+    let Some(name) = document.symbol_name(symbol) else {
+        return true;
+    };
+    semantic.bindings.iter_enumerated().any(|(other, _)| {
+        other != symbol
+            && document.symbol_name(other) == Some(name)
+            && range.contains(&(semantic.get_symbol_span(other).start as usize))
+    })
+}
+
+/// Extend a byte range to the whole lines it is on, if nothing else is on them.
+fn whole_lines(text: &str, range: Range<usize>) -> Range<usize> {
+    let line_start = text[..range.start].rfind('\n').map_or(0, |index| index + 1);
+    let line_end = text[range.end..]
+        .find('\n')
+        .map_or(text.len(), |index| range.end + index + 1);
+    if text[line_start..range.start].trim().is_empty()
+        && text[range.end..line_end].trim().is_empty()
+    {
+        line_start..line_end
+    } else {
+        range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use l_lang::compile;
+    use ropey::Rope;
+
+    use super::*;
+
+    /// Inline the variable `name` declared in `text`, returning the edited text.
+    fn inline(text: &str, name: &str) -> Option<String> {
+        let offset = text
+            .find(&format!("let {name}"))
+            .expect("the variable is declared")
+            + "let ".len();
+        let document = Document::new(Rope::from_str(text), compile(text), None);
+        let inlined = inline_variable(&document, offset)?;
+        let mut edited = text.to_string();
+        for (range, replacement) in inlined.replacements.iter().rev() {
+            edited.replace_range(range.clone(), replacement);
+        }
+        edited.replace_range(inlined.binding, "");
+        Some(edited)
+    }
+
+    #[test]
+    fn references_are_replaced_and_the_binding_deleted() {
+        let text = "fn main() {\n    let a = 1;\n    let b = a + a;\n    return b;\n}\n";
+        assert_eq!(
+            inline(text, "a").as_deref(),
+            Some("fn main() {\n    let b = 1 + 1;\n    return b;\n}\n")
+        );
+        assert_eq!(
+            inline(text, "b").as_deref(),
+            Some("fn main() {\n    let a = 1;\n    return a + a;\n}\n")
+        );
+    }
+
+    #[test]
+    fn initializers_are_parenthesized_between_operators() {
+        let text = "fn main() {\n    let a = 1;\n    let b = a + 2;\n    return b * 3;\n}\n";
+        assert_eq!(
+            inline(text, "b").as_deref(),
+            Some("fn main() {\n    let a = 1;\n    return (a + 2) * 3;\n}\n")
+        );
+    }
+
+    #[test]
+    fn variables_whose_value_could_change_are_not_inlined() {
+        let called = "fn one() -> int {\n    return 1;\n}\n\nfn main() {\n    let a = one();\n    return a;\n}\n";
+        assert_eq!(inline(called, "a"), None);
+        let reassigned = "fn main() {\n    let a = 1;\n    a = 2;\n    return a;\n}\n";
+        assert_eq!(inline(reassigned, "a"), None);
+        let read_reassigned =
+            "fn main() {\n    let a = 1;\n    let b = a;\n    a = 2;\n    return b;\n}\n";
+        assert_eq!(inline(read_reassigned, "b"), None);
+    }
+}
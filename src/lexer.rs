@@ -0,0 +1,173 @@
+//! Lexer of L source text, for the features that read the text without the semantic
+//! analysis.
+//!
+//! [`lex`] splits a text into lexemes, and each feature keeps the ones it cares about:
+//! [`tokenize`] drops comments and string literals for the analysis passes and
+//! refactorings, while the highlighting of `lexical_tokens` classifies keywords,
+//! literals, comments and operators. Scanning the text in one place keeps them agreeing
+//! on where a string literal or a comment ends.
+
+use std::ops::Range;
+
+/// What a lexeme is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexemeKind {
+    /// A `//` comment, up to the end of its line
+    Comment,
+    /// A string literal, with its quotes
+    String,
+    /// A number literal
+    Number,
+    /// An identifier or keyword
+    Word,
+    /// An operator character, or `&&` and `||`
+    Operator,
+    /// Any other character, such as a brace or a comma
+    Punctuation,
+}
+
+/// A lexeme of L source text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lexeme<'a> {
+    /// What the lexeme is
+    pub kind: LexemeKind,
+    /// The lexeme text
+    pub text: &'a str,
+    /// Byte range of the lexeme
+    pub span: Range<usize>,
+}
+
+/// A token of L source text, as far as the textual passes care about.
+#[derive(Debug)]
+pub struct Token<'a> {
+    /// The token text
+    pub text: &'a str,
+    /// Byte range of the token
+    pub span: Range<usize>,
+}
+
+/// Characters operators are made of.
+const OPERATOR_CHARS: &str = "+-*/%=!<>&|^";
+
+/// Split source text into lexemes, in order, skipping whitespace.
+///
+/// Unterminated string literals end with the text.
+pub fn lex(text: &str) -> Vec<Lexeme<'_>> {
+    let bytes = text.as_bytes();
+    let is_word_byte = |byte: u8| byte.is_ascii_alphanumeric() || byte == b'_' || !byte.is_ascii();
+    let mut lexemes = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let start = index;
+        let byte = bytes[index];
+        let kind = match byte {
+            byte if byte.is_ascii_whitespace() => {
+                index += 1;
+                continue;
+            }
+            b'/' if bytes.get(index + 1) == Some(&b'/') => {
+                while index < bytes.len() && bytes[index] != b'\n' {
+                    index += 1;
+                }
+                // Keep a `\r` of the line terminator out of the comment
+                if bytes[index - 1] == b'\r' {
+                    index -= 1;
+                }
+                LexemeKind::Comment
+            }
+            b'"' => {
+                index += 1;
+                while index < bytes.len() && bytes[index] != b'"' {
+                    index += if bytes[index] == b'\\' { 2 } else { 1 };
+                }
+                index = (index + 1).min(bytes.len());
+                LexemeKind::String
+            }
+            b'0'..=b'9' => {
+                while index < bytes.len()
+                    && (bytes[index].is_ascii_alphanumeric()
+                        || bytes[index] == b'_'
+                        || (bytes[index] == b'.'
+                            && bytes.get(index + 1).is_some_and(u8::is_ascii_digit)))
+                {
+                    index += 1;
+                }
+                LexemeKind::Number
+            }
+            byte if is_word_byte(byte) => {
+                while index < bytes.len() && is_word_byte(bytes[index]) {
+                    index += 1;
+                }
+                LexemeKind::Word
+            }
+            b'&' | b'|' if bytes.get(index + 1) == Some(&byte) => {
+                index += 2;
+                LexemeKind::Operator
+            }
+            byte if OPERATOR_CHARS.as_bytes().contains(&byte) => {
+                index += 1;
+                LexemeKind::Operator
+            }
+            _ => {
+                index += 1;
+                LexemeKind::Punctuation
+            }
+        };
+        lexemes.push(Lexeme {
+            kind,
+            text: &text[start..index],
+            span: start..index,
+        });
+    }
+    lexemes
+}
+
+/// Split source text into identifiers, literals, braces and operators.
+///
+/// String literals and `//` comments are skipped; operators are one character each,
+/// except for `&&` and `||`.
+pub fn tokenize(text: &str) -> Vec<Token<'_>> {
+    lex(text)
+        .into_iter()
+        .filter(|lexeme| !matches!(lexeme.kind, LexemeKind::Comment | LexemeKind::String))
+        .map(|lexeme| Token {
+            text: lexeme.text,
+            span: lexeme.span,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(text: &str) -> Vec<&str> {
+        tokenize(text).into_iter().map(|token| token.text).collect()
+    }
+
+    #[test]
+    fn comments_and_strings_are_not_tokens() {
+        assert_eq!(
+            texts("let s = \"a \\\" // b\"; // note\nreturn s;"),
+            ["let", "s", "=", ";", "return", "s", ";"]
+        );
+        assert_eq!(texts("f(\"abc"), ["f", "("]);
+    }
+
+    #[test]
+    fn operators_are_split_except_logical_ones() {
+        assert_eq!(
+            texts("a==b&&c->d||!e"),
+            ["a", "=", "=", "b", "&&", "c", "-", ">", "d", "||", "!", "e"]
+        );
+        assert_eq!(texts("p.x + 1.5"), ["p", ".", "x", "+", "1.5"]);
+    }
+
+    #[test]
+    fn identifiers_may_contain_non_ascii_characters() {
+        assert_eq!(
+            texts("fn größe(höhe: int)"),
+            ["fn", "größe", "(", "höhe", ":", "int", ")"]
+        );
+    }
+}
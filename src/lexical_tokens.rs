@@ -0,0 +1,101 @@
+//! Tokens highlighted from the text alone.
+//!
+//! The semantic analysis only knows symbols and their references, so keywords,
+//! literals, comments and operators are found by scanning the text. They are
+//! highlighted even where the analysis has no symbols, such as in a file with syntax
+//! errors. Names aren't emitted: those the analysis resolves are highlighted as
+//! symbols, and the rest is left to the client's grammar.
+
+use std::ops::Range;
+
+use crate::lexer::{LexemeKind, lex};
+
+/// What a lexical token is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexicalKind {
+    /// A reserved word of the grammar
+    Keyword,
+    /// A number literal
+    Number,
+    /// A string literal, with its quotes
+    String,
+    /// A `//` comment, up to the end of its line
+    Comment,
+    /// A run of operator characters, such as `+` or `->`
+    Operator,
+}
+
+/// Find the keywords, literals, comments and operators of a text, in order.
+///
+/// Returns the byte range and kind of each token.
+pub fn lexical_tokens(text: &str, keywords: &[String]) -> Vec<(Range<usize>, LexicalKind)> {
+    let mut tokens: Vec<(Range<usize>, LexicalKind)> = Vec::new();
+    for lexeme in lex(text) {
+        let kind = match lexeme.kind {
+            LexemeKind::Comment => LexicalKind::Comment,
+            LexemeKind::String => LexicalKind::String,
+            LexemeKind::Number => LexicalKind::Number,
+            LexemeKind::Word if keywords.iter().any(|keyword| keyword == lexeme.text) => {
+                LexicalKind::Keyword
+            }
+            LexemeKind::Operator => {
+                // Adjacent operator characters are highlighted as one operator
+                if let Some((span, LexicalKind::Operator)) = tokens.last_mut()
+                    && span.end == lexeme.span.start
+                {
+                    span.end = lexeme.span.end;
+                    continue;
+                }
+                LexicalKind::Operator
+            }
+            LexemeKind::Word | LexemeKind::Punctuation => continue,
+        };
+        tokens.push((lexeme.span, kind));
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<(&str, LexicalKind)> {
+        let keywords = ["fn", "let", "return"].map(String::from);
+        lexical_tokens(text, &keywords)
+            .into_iter()
+            .map(|(span, kind)| (&text[span], kind))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_are_classified() {
+        assert_eq!(
+            kinds("fn f() -> int {\n    let s = \"a \\\" b\"; // note\r\n    return 1.5 + x2;\n}"),
+            [
+                ("fn", LexicalKind::Keyword),
+                ("->", LexicalKind::Operator),
+                ("let", LexicalKind::Keyword),
+                ("=", LexicalKind::Operator),
+                ("\"a \\\" b\"", LexicalKind::String),
+                ("// note", LexicalKind::Comment),
+                ("return", LexicalKind::Keyword),
+                ("1.5", LexicalKind::Number),
+                ("+", LexicalKind::Operator),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_literals_end_with_the_text() {
+        assert_eq!(kinds("\"abc"), [("\"abc", LexicalKind::String)]);
+        assert_eq!(
+            kinds("a.0 == 1//c"),
+            [
+                ("0", LexicalKind::Number),
+                ("==", LexicalKind::Operator),
+                ("1", LexicalKind::Number),
+                ("//c", LexicalKind::Comment),
+            ]
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! L Language Server Implementation
+//!
+//! This crate implements a Language Server Protocol (LSP) server for the L programming language.
+//! It provides features such as code completion, goto definition, references, rename,
+//! formatting, inlay hints, and semantic tokens.
+//!
+//! The server is built using the tower-lsp-server library and communicates with the client
+//! through JSON-RPC messages. [`Backend`] holds the state of a client connection and
+//! handles its messages, with each language feature in a module of its own, so it can be
+//! embedded in another server; [`run`] is the command line of the `l-language-server`
+//! binary. The compiler is reached through [`LLang`], the [`LanguageAnalyzer`] of `l_lang`.
+
+mod analysis_passes;
+mod analysis_queue;
+mod analyzer;
+mod backend;
+mod cancellation;
+mod check;
+pub mod cli;
+mod commands;
+mod compat;
+mod compile_diagnostics;
+mod completion;
+mod debounce;
+mod diagnostic_codes;
+mod diagnostics_history;
+mod document_store;
+mod document_text;
+mod enabled_analyses;
+mod examples;
+mod extract_function;
+mod extract_variable;
+mod feature_registration;
+mod format;
+mod function_stub;
+mod grammar;
+mod inline_variable;
+mod lexer;
+mod lexical_tokens;
+mod lifecycle;
+#[cfg(debug_assertions)]
+mod lock_audit;
+mod logging;
+mod lsif;
+mod memory_budget;
+mod missing_field;
+mod outgoing;
+mod panic_isolation;
+mod parameter_hints;
+mod progress;
+mod project_config;
+mod protocol_trace;
+mod refactor_journal;
+mod scopes;
+mod self_check;
+mod semantic_info;
+mod semantic_tokens;
+mod server_meta;
+mod server_status;
+mod settings;
+mod signature_help;
+mod stdlib;
+mod strict_protocol;
+mod struct_constructor;
+mod suggestions;
+mod symbol_at;
+mod symbol_docs;
+mod symbol_index;
+mod text_diff;
+mod text_pos;
+mod transport;
+mod type_annotation;
+mod virtual_documents;
+mod websocket;
+mod whats_new;
+mod workspace_edit;
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use tower_lsp_server::Server;
+use tower_lsp_server::ls_types::Uri;
+use tracing::debug;
+
+pub use crate::analyzer::{AnalyzedSymbol, LLang, LanguageAnalyzer, SymbolReferences};
+pub use crate::backend::Backend;
+use crate::cli::{Cli, Command as CliCommand, ServeArgs};
+pub use crate::lifecycle::Lifecycle;
+use crate::lifecycle::ShutdownGuard;
+use crate::logging::ClientLog;
+use crate::panic_isolation::{CatchPanics, install_panic_hook};
+use crate::protocol_trace::{ProtocolTrace, Traced};
+pub use crate::server_status::Metrics;
+use crate::strict_protocol::StrictProtocol;
+
+/// Run the command line: serve a client, or run one of the subcommands working on files.
+pub async fn run(cli: &Cli) -> ExitCode {
+    let client_log = match logging::init(&cli.log) {
+        Ok(client_log) => client_log,
+        Err(err) => {
+            eprintln!("Unable to open the log file: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match &cli.command {
+        None => serve(&cli.serve, client_log).await,
+        Some(CliCommand::Serve(args)) => serve(args, client_log).await,
+        Some(CliCommand::Check(args)) => check::run(args),
+        Some(CliCommand::Format(args)) => format::run(args),
+        Some(CliCommand::Index(args)) => lsif::run(args),
+    }
+}
+
+/// Serve a client over the transport the arguments select, until it disconnects or the
+/// process is interrupted.
+///
+/// This function sets up the server, handles signals for graceful shutdown,
+/// and starts the main event loop.
+async fn serve(args: &ServeArgs, client_log: Arc<ClientLog>) -> ExitCode {
+    debug!("Starting L Language Server");
+    install_panic_hook();
+    let strict_protocol = args.strict_protocol;
+    if strict_protocol {
+        debug!("Strict protocol mode enabled");
+    }
+
+    // Set up signal handling for graceful shutdown
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+
+    // Handle Ctrl+C signal
+    tokio::spawn(async move {
+        match tokio::signal::ctrl_c().await {
+            Ok(()) => {
+                debug!("Received shutdown signal (Ctrl+C)");
+                let _ = shutdown_tx.send(());
+            }
+            Err(err) => {
+                eprintln!("Unable to listen for shutdown signal: {err}");
+            }
+        }
+    });
+
+    let (input, output) = match args.transport().connect().await {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("Unable to open the connection: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    debug!("Creating LSP service");
+    let trace = Arc::new(ProtocolTrace::default());
+    let metrics = Arc::new(Metrics::default());
+    let lifecycle = Arc::new(Lifecycle::default());
+    let (service, socket) = Backend::service(|client| {
+        trace.set_client(client.clone());
+        client_log.set_client(client.clone());
+        Backend::new(
+            client,
+            strict_protocol,
+            Arc::clone(&lifecycle),
+            Arc::clone(&metrics),
+        )
+    });
+
+    debug!("Starting server with tokio::select! for graceful shutdown");
+    let service = Traced::new(
+        ShutdownGuard::new(
+            StrictProtocol::new(CatchPanics::new(service), strict_protocol),
+            Arc::clone(&lifecycle),
+        ),
+        trace,
+        metrics,
+    );
+    let server = Server::new(input, output, socket).serve(service);
+
+    tokio::select! {
+        () = server => {
+            debug!("Server completed normally");
+            lifecycle.exit_code()
+        }
+        _ = &mut shutdown_rx => {
+            debug!("Received shutdown signal, terminating server");
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// Recursively collect the L source files below a directory.
+///
+/// Hidden directories (such as `.git`) are skipped.
+fn collect_source_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            debug!("Failed to read directory: {}", dir.display());
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    pending.push(path);
+                }
+            } else if path.extension().is_some_and(|ext| ext == "l") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Convert a filesystem path to a `file://` URI.
+///
+/// This function percent-encodes every byte outside the unreserved URI characters.
+fn file_path_to_uri(path: &Path) -> Option<Uri> {
+    let path = path.to_str()?.replace('\\', "/");
+    let mut encoded = String::with_capacity(path.len() + 8);
+    // Windows paths (`C:/dir`) need a leading slash to become the URI path
+    if !path.starts_with('/') {
+        encoded.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    Uri::from_str(&format!("file://{encoded}")).ok()
+}
+
+/// Convert a `file://` URI to a filesystem path.
+///
+/// This function decodes percent-encoded characters and returns `None` for URIs
+/// with any other scheme (e.g. `untitled:`).
+fn uri_to_file_path(uri: &Uri) -> Option<PathBuf> {
+    let path = uri.as_str().strip_prefix("file://")?;
+    // Skip the authority component, which is empty for local files
+    let path = &path[path.find('/')?..];
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    let path = String::from_utf8(decoded).ok()?;
+
+    // Windows paths look like `/C:/dir/file.l`; drop the leading slash
+    if cfg!(windows) && path.as_bytes().get(2) == Some(&b':') {
+        return Some(PathBuf::from(&path[1..]));
+    }
+    Some(PathBuf::from(path))
+}
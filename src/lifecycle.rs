@@ -0,0 +1,149 @@
+//! The end of the server lifecycle: `shutdown` followed by `exit`.
+//!
+//! Once the client sent `shutdown`, the specification requires every further request
+//! to fail with `InvalidRequest` and the server to exit on the `exit` notification,
+//! with code 0, or with code 1 if `exit` came without `shutdown`. [`ShutdownGuard`]
+//! enforces this for every message before it reaches a handler, and the shared
+//! [`Lifecycle`] lets the handlers skip work and `main` pick the exit code.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+
+use tower_lsp_server::jsonrpc::{Error, Request, Response};
+use tower_service::Service;
+use tracing::debug;
+
+/// Whether the client asked the server to shut down.
+#[derive(Debug, Default)]
+pub struct Lifecycle {
+    /// Set when the `shutdown` request arrives
+    shut_down: AtomicBool,
+}
+
+impl Lifecycle {
+    /// Check if the client sent `shutdown`.
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down.load(Ordering::Acquire)
+    }
+
+    /// The exit code of the server once the connection ends: success only after
+    /// `shutdown`.
+    pub fn exit_code(&self) -> ExitCode {
+        if self.is_shut_down() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Service rejecting the messages sent after `shutdown`, except `exit`.
+///
+/// Requests are answered with `InvalidRequest` and notifications are dropped.
+#[derive(Debug)]
+pub struct ShutdownGuard<S> {
+    /// The wrapped service
+    inner: S,
+    /// Where the `shutdown` request is recorded
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl<S> ShutdownGuard<S> {
+    /// Wrap a service, guarding it against messages after `shutdown`.
+    pub const fn new(inner: S, lifecycle: Arc<Lifecycle>) -> Self {
+        Self { inner, lifecycle }
+    }
+}
+
+impl<S> Service<Request> for ShutdownGuard<S>
+where
+    S: Service<Request, Response = Option<Response>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Option<Response>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let method = request.method();
+        if self.lifecycle.is_shut_down() && method != "exit" {
+            let response = request.id().map(|id| {
+                let message = format!("{method}: the server is shutting down");
+                Response::from_error(
+                    id.clone(),
+                    Error {
+                        message: message.into(),
+                        ..Error::invalid_request()
+                    },
+                )
+            });
+            if response.is_none() {
+                debug!("Ignoring notification {method} after shutdown");
+            }
+            return Box::pin(std::future::ready(Ok(response)));
+        }
+        if method == "shutdown" {
+            self.lifecycle.shut_down.store(true, Ordering::Release);
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tower_lsp_server::jsonrpc::ErrorCode;
+
+    /// Service answering every request with `null`.
+    struct Answering;
+
+    impl Service<Request> for Answering {
+        type Response = Option<Response>;
+        type Error = ();
+        type Future = std::future::Ready<Result<Self::Response, ()>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request) -> Self::Future {
+            let response = request
+                .id()
+                .map(|id| Response::from_ok(id.clone(), Value::Null));
+            std::future::ready(Ok(response))
+        }
+    }
+
+    /// Send a request and return its result.
+    async fn send(service: &mut ShutdownGuard<Answering>, method: &'static str) -> Option<Error> {
+        let request = Request::build(method).id(1).finish();
+        let response = service.call(request).await.ok().flatten()?;
+        response.into_parts().1.err()
+    }
+
+    #[tokio::test]
+    async fn requests_after_shutdown_are_invalid() {
+        let lifecycle = Arc::new(Lifecycle::default());
+        let mut service = ShutdownGuard::new(Answering, Arc::clone(&lifecycle));
+        assert_eq!(send(&mut service, "textDocument/hover").await, None);
+        assert_eq!(lifecycle.exit_code(), ExitCode::FAILURE);
+
+        assert_eq!(send(&mut service, "shutdown").await, None);
+        assert!(lifecycle.is_shut_down());
+        for method in ["textDocument/hover", "shutdown"] {
+            let error = send(&mut service, method).await.map(|error| error.code);
+            assert_eq!(error, Some(ErrorCode::InvalidRequest));
+        }
+        assert_eq!(lifecycle.exit_code(), ExitCode::SUCCESS);
+    }
+}
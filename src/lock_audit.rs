@@ -0,0 +1,108 @@
+//! Debug-build instrumentation of how long handlers hold document snapshots.
+//!
+//! Every [`DocSnapshot`](crate::document_store::DocSnapshot) taken in a debug build
+//! records when and on which thread it was acquired. When it is dropped, holds longer
+//! than [`HOLD_THRESHOLD`] and holds that moved to another runtime thread (which only
+//! happens when the snapshot is kept across an `.await`) are logged as warnings and,
+//! if enabled with the `lockAuditMessages` setting, shown to the user. Release builds
+//! compile none of this.
+
+use std::sync::{Arc, RwLock};
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tower_lsp_server::Client;
+use tower_lsp_server::ls_types::MessageType;
+use tracing::warn;
+
+use crate::settings::section;
+
+/// How long a handler may hold a snapshot before a warning is reported.
+pub const HOLD_THRESHOLD: Duration = Duration::from_millis(50);
+
+/// Destination of the warnings reported by the snapshots of a document store.
+#[derive(Debug, Default)]
+pub struct LockAudit {
+    /// Client that warnings are shown to, if enabled
+    client: RwLock<Option<Client>>,
+}
+
+impl LockAudit {
+    /// Show warnings to the user through `client`, or only log them if `None`.
+    pub fn set_client(&self, client: Option<Client>) {
+        *self.client.write().expect("lock audit poisoned") = client;
+    }
+
+    /// Log a warning and show it to the user if enabled.
+    fn report(&self, message: String) {
+        warn!("{message}");
+        let client = self.client.read().expect("lock audit poisoned").clone();
+        if let (Some(client), Ok(runtime)) = (client, tokio::runtime::Handle::try_current()) {
+            runtime.spawn(async move {
+                client.show_message(MessageType::WARNING, message).await;
+            });
+        }
+    }
+}
+
+/// Records how long a single snapshot is held.
+#[derive(Debug)]
+pub struct HoldTimer {
+    /// Where violations are reported
+    audit: Arc<LockAudit>,
+    /// URI of the document the snapshot belongs to
+    uri: String,
+    /// When the snapshot was taken
+    acquired: Instant,
+    /// Thread the snapshot was taken on
+    thread: ThreadId,
+    /// Cleared once the snapshot is converted into a shared document on purpose
+    armed: bool,
+}
+
+impl HoldTimer {
+    /// Start timing a snapshot of the document with the given URI.
+    pub fn start(audit: Arc<LockAudit>, uri: &str) -> Self {
+        Self {
+            audit,
+            uri: uri.to_string(),
+            acquired: Instant::now(),
+            thread: std::thread::current().id(),
+            armed: true,
+        }
+    }
+
+    /// Stop timing without reporting, e.g. when the document is handed to a task.
+    pub fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for HoldTimer {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let held = self.acquired.elapsed();
+        if std::thread::current().id() != self.thread {
+            self.audit.report(format!(
+                "Snapshot of {} was held across an await ({held:?})",
+                self.uri
+            ));
+        } else if held > HOLD_THRESHOLD {
+            self.audit.report(format!(
+                "Snapshot of {} was held for {held:?} (threshold {HOLD_THRESHOLD:?})",
+                self.uri
+            ));
+        }
+    }
+}
+
+/// Read whether lock audit warnings are shown to the user from a settings object.
+pub fn lock_audit_messages_from_settings(settings: &Value) -> bool {
+    section(settings)
+        .get("lockAuditMessages")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
@@ -0,0 +1,209 @@
+//! The server log.
+//!
+//! Stdout carries JSON-RPC, so the log goes to stderr, which editors tend to hide or
+//! drop, or to a file given with `--log-file` that users can attach to bug reports.
+//! The file is appended to and rotated once it grows past `--log-max-size`, keeping a
+//! few older files next to it (`server.log.1`, `server.log.2`, ...).
+//!
+//! Warnings and errors are also mirrored to the client with `window/logMessage`,
+//! whatever the log level, so they show up in the editor's output panel.
+
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use tower_lsp_server::Client;
+use tower_lsp_server::ls_types::MessageType;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::cli::LogArgs;
+
+/// Number of rotated log files kept besides the current one.
+pub const LOG_BACKUPS: usize = 3;
+
+/// Set up the log, starting from the `RUST_LOG` environment variable.
+///
+/// Spans, such as the one of each LSP message, are printed when they close, with the
+/// time spent in them. Returns where to set the client warnings are mirrored to.
+pub fn init(args: &LogArgs) -> io::Result<Arc<ClientLog>> {
+    let filter = match args.log_level {
+        Some(level) => EnvFilter::default().add_directive(level.into()),
+        None => EnvFilter::from_default_env(),
+    };
+    let (writer, ansi) = match &args.log_file {
+        Some(path) => {
+            let file = RotatingFile::open(path.clone(), args.log_max_size * 1024 * 1024)?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (BoxMakeWriter::new(io::stderr), io::stderr().is_terminal()),
+    };
+    let client_log = Arc::new(ClientLog::default());
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(ansi)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_filter(filter),
+        )
+        .with(ClientLogLayer(Arc::clone(&client_log)).with_filter(LevelFilter::WARN))
+        .init();
+    Ok(client_log)
+}
+
+/// A log file rotated once it reaches a size.
+#[derive(Debug)]
+pub struct RotatingFile {
+    /// Path of the current file
+    path: PathBuf,
+    /// Size past which the file is rotated, in bytes
+    max_size: u64,
+    /// The current file
+    file: File,
+    /// Size of the current file
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Open a log file for appending.
+    pub fn open(path: PathBuf, max_size: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_size,
+            file,
+            size,
+        })
+    }
+
+    /// Move the current file to the first backup, shifting the older ones, and start a
+    /// new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..LOG_BACKUPS).rev() {
+            rename_existing(
+                &backup_path(&self.path, index),
+                &backup_path(&self.path, index + 1),
+            )?;
+        }
+        rename_existing(&self.path, &backup_path(&self.path, 1))?;
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Path of a rotated log file, `server.log.1` for `server.log`.
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut backup = OsString::from(path);
+    backup.push(format!(".{index}"));
+    PathBuf::from(backup)
+}
+
+/// Rename a file if it exists.
+fn rename_existing(from: &Path, to: &Path) -> io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// The client warnings and errors are mirrored to.
+#[derive(Debug, Default)]
+pub struct ClientLog {
+    /// The client, set once the LSP service is built
+    client: OnceLock<Client>,
+}
+
+impl ClientLog {
+    /// Set the client log messages are sent to.
+    pub fn set_client(&self, client: Client) {
+        let _ = self.client.set(client);
+    }
+}
+
+/// Layer sending the events it sees to the client as `window/logMessage`.
+#[derive(Debug)]
+struct ClientLogLayer(Arc<ClientLog>);
+
+impl<S: Subscriber> Layer<S> for ClientLogLayer {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let typ = match *event.metadata().level() {
+            Level::ERROR => MessageType::ERROR,
+            Level::WARN => MessageType::WARNING,
+            _ => return,
+        };
+        let (Some(client), Ok(runtime)) =
+            (self.0.client.get(), tokio::runtime::Handle::try_current())
+        else {
+            return;
+        };
+        let mut message = EventMessage::default();
+        event.record(&mut message);
+        let client = client.clone();
+        runtime.spawn(async move { client.log_message(typ, message.0).await });
+    }
+}
+
+/// The text of an event: its message followed by its other fields.
+#[derive(Debug, Default)]
+struct EventMessage(String);
+
+impl Visit for EventMessage {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let fields = std::mem::take(&mut self.0);
+            let _ = write!(self.0, "{value:?}{fields}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_files_are_rotated_past_their_size() {
+        let dir = std::env::temp_dir().join(format!("l-log-rotation-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("temporary directory");
+        let path = dir.join("server.log");
+        let mut file = RotatingFile::open(path.clone(), 8).expect("log file");
+        for line in ["first\n", "second\n", "third\n", "fourth\n", "fifth\n"] {
+            file.write_all(line.as_bytes()).expect("written");
+        }
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap_or_default();
+        assert_eq!(read(&path), "fifth\n");
+        assert_eq!(read(&backup_path(&path, 1)), "fourth\n");
+        assert_eq!(read(&backup_path(&path, 3)), "second\n");
+        assert!(!backup_path(&path, 4).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,308 @@
+//! The `index` subcommand, exporting code navigation as an LSIF dump.
+//!
+//! Code hosting platforms read LSIF (the Language Server Index Format) to offer go to
+//! definition, find references and hovers without running a language server. The dump
+//! is a graph written as one JSON vertex or edge per line: every symbol gets a result
+//! set holding its definition, references and hover, which the ranges of its
+//! declaration and references point to. L resolves names within a file, so each
+//! document is indexed on its own.
+//!
+//! See <https://microsoft.github.io/language-server-protocol/specifications/lsif/0.6.0/specification/>.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use anyhow::Context;
+use l_lang::{SymbolId, compile};
+use ropey::Rope;
+use serde_json::{Value, json};
+use tower_lsp_server::ls_types::{MarkupContent, MarkupKind, Range};
+
+use crate::check::source_files;
+use crate::cli::IndexArgs;
+use crate::document_store::Document;
+use crate::file_path_to_uri;
+use crate::project_config::Projects;
+use crate::server_meta::{SERVER_NAME, SERVER_VERSION};
+use crate::symbol_docs::symbol_documentation;
+use crate::text_pos::{Bounds, Encoding, TextPos};
+
+/// Version of LSIF the dump follows.
+const LSIF_VERSION: &str = "0.6.0";
+
+/// Index the workspace the arguments name into an LSIF dump, and return the exit code.
+///
+/// The exit code is 2 if a file couldn't be read or the dump couldn't be written.
+pub fn run(args: &IndexArgs) -> ExitCode {
+    match write_dump(args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(2),
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Write the dump, returning whether every source file could be indexed.
+fn write_dump(args: &IndexArgs) -> anyhow::Result<bool> {
+    let root = std::path::absolute(&args.root)?;
+    let root_uri = file_path_to_uri(&root).context("the root isn't a valid file path")?;
+    let file = File::create(&args.output)
+        .with_context(|| format!("failed to create {}", args.output.display()))?;
+    let mut dump = Dump::new(BufWriter::new(file));
+
+    dump.vertex(
+        "metaData",
+        json!({
+            "version": LSIF_VERSION,
+            "projectRoot": root_uri,
+            "positionEncoding": "utf-16",
+            "toolInfo": { "name": SERVER_NAME, "version": SERVER_VERSION },
+        }),
+    )?;
+    let project = dump.vertex("project", json!({ "kind": "l" }))?;
+    dump.event("begin", "project", project)?;
+
+    let projects = Projects::default();
+    let mut complete = true;
+    let mut documents = Vec::new();
+    for path in source_files(&root.to_string_lossy(), &projects) {
+        let indexed = path.and_then(|path| {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            dump.document(&path, &text)
+        });
+        match indexed {
+            Ok(document) => documents.push(document),
+            Err(err) => {
+                eprintln!("error: {err:#}");
+                complete = false;
+            }
+        }
+    }
+
+    if !documents.is_empty() {
+        dump.edge("contains", project, &documents, json!({}))?;
+    }
+    dump.event("end", "project", project)?;
+    dump.out.flush()?;
+    Ok(complete)
+}
+
+/// An LSIF dump being written.
+struct Dump<W> {
+    /// Where the dump is written
+    out: W,
+    /// Id of the last vertex or edge written
+    last_id: u64,
+}
+
+impl<W: Write> Dump<W> {
+    /// Start a dump.
+    fn new(out: W) -> Self {
+        Self { out, last_id: 0 }
+    }
+
+    /// Write an element with the next id, returning the id.
+    fn element(&mut self, kind: &str, label: &str, fields: Value) -> io::Result<u64> {
+        self.last_id += 1;
+        let mut element = json!({ "id": self.last_id, "type": kind, "label": label });
+        if let (Some(element), Value::Object(fields)) = (element.as_object_mut(), fields) {
+            element.extend(fields);
+        }
+        serde_json::to_writer(&mut self.out, &element)?;
+        self.out.write_all(b"\n")?;
+        Ok(self.last_id)
+    }
+
+    /// Write a vertex.
+    fn vertex(&mut self, label: &str, fields: Value) -> io::Result<u64> {
+        self.element("vertex", label, fields)
+    }
+
+    /// Write an edge from one vertex to others.
+    ///
+    /// One-to-one edges, such as `next` and the requests of a result set, take a
+    /// single vertex and write it as `inV`.
+    fn edge(&mut self, label: &str, out_v: u64, in_vs: &[u64], fields: Value) -> io::Result<u64> {
+        let mut edge = match in_vs {
+            [in_v] if label != "contains" && label != "item" => {
+                json!({ "outV": out_v, "inV": in_v })
+            }
+            _ => json!({ "outV": out_v, "inVs": in_vs }),
+        };
+        if let (Some(edge), Value::Object(fields)) = (edge.as_object_mut(), fields) {
+            edge.extend(fields);
+        }
+        self.element("edge", label, edge)
+    }
+
+    /// Write an event marking the start or end of the elements of a project or document.
+    fn event(&mut self, kind: &str, scope: &str, data: u64) -> io::Result<u64> {
+        self.vertex(
+            "$event",
+            json!({ "kind": kind, "scope": scope, "data": data }),
+        )
+    }
+
+    /// Write a source file with its symbols, returning the id of its document vertex.
+    fn document(&mut self, path: &Path, text: &str) -> anyhow::Result<u64> {
+        let absolute = std::path::absolute(path)?;
+        let uri = file_path_to_uri(&absolute)
+            .with_context(|| format!("{} isn't a valid file path", path.display()))?;
+        let document = Document::new(Rope::from_str(text), compile(text), None);
+        let vertex = self.vertex("document", json!({ "uri": uri, "languageId": "l" }))?;
+        self.event("begin", "document", vertex)?;
+        let ranges = self.symbols(vertex, &document)?;
+        if !ranges.is_empty() {
+            self.edge("contains", vertex, &ranges, json!({}))?;
+        }
+        self.event("end", "document", vertex)?;
+        Ok(vertex)
+    }
+
+    /// Write the result set of every symbol of a document, with the ranges of its
+    /// declaration and references, returning the ids of the ranges.
+    fn symbols(&mut self, document_id: u64, document: &Document) -> io::Result<Vec<u64>> {
+        let semantic = &document.analysis.semantic;
+        let to_range = |span: std::ops::Range<usize>| {
+            TextPos::new(&document.rope, Encoding::Utf16, Bounds::Strict).range(span)
+        };
+        let mut references: HashMap<SymbolId, Vec<Range>> = HashMap::new();
+        for (ref_id, span) in semantic.reference_spans.iter().enumerate() {
+            if let Some(symbol_id) = semantic.references.get(ref_id).copied().flatten()
+                && let Some(range) = to_range(span.start as usize..span.end as usize)
+            {
+                references.entry(symbol_id).or_default().push(range);
+            }
+        }
+        let mut ranges = Vec::new();
+        for (symbol_id, span) in semantic.symbol_spans.iter_enumerated() {
+            let Some(definition) = to_range(span.start as usize..span.end as usize) else {
+                continue;
+            };
+            let references = references.remove(&symbol_id).unwrap_or_default();
+            ranges.extend(self.symbol(
+                document_id,
+                document,
+                symbol_id,
+                definition,
+                &references,
+            )?);
+        }
+        Ok(ranges)
+    }
+
+    /// Write the result set of a symbol and its ranges, returning the ids of the ranges.
+    fn symbol(
+        &mut self,
+        document_id: u64,
+        document: &Document,
+        symbol_id: SymbolId,
+        definition: Range,
+        references: &[Range],
+    ) -> io::Result<Vec<u64>> {
+        let result_set = self.vertex("resultSet", json!({}))?;
+        let definition_range = self.range(definition, result_set)?;
+        let reference_ranges = references
+            .iter()
+            .map(|range| self.range(*range, result_set))
+            .collect::<io::Result<Vec<_>>>()?;
+        let in_document = json!({ "document": document_id });
+
+        let definition_result = self.vertex("definitionResult", json!({}))?;
+        self.edge(
+            "textDocument/definition",
+            result_set,
+            &[definition_result],
+            json!({}),
+        )?;
+        self.edge(
+            "item",
+            definition_result,
+            &[definition_range],
+            in_document.clone(),
+        )?;
+
+        let reference_result = self.vertex("referenceResult", json!({}))?;
+        self.edge(
+            "textDocument/references",
+            result_set,
+            &[reference_result],
+            json!({}),
+        )?;
+        self.edge(
+            "item",
+            reference_result,
+            &[definition_range],
+            json!({ "document": document_id, "property": "definitions" }),
+        )?;
+        if !reference_ranges.is_empty() {
+            self.edge(
+                "item",
+                reference_result,
+                &reference_ranges,
+                json!({ "document": document_id, "property": "references" }),
+            )?;
+        }
+
+        if let Some(markdown) = symbol_documentation(document, symbol_id) {
+            let contents = MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown,
+            };
+            let hover_result =
+                self.vertex("hoverResult", json!({ "result": { "contents": contents } }))?;
+            self.edge("textDocument/hover", result_set, &[hover_result], json!({}))?;
+        }
+
+        let mut ranges = vec![definition_range];
+        ranges.extend(reference_ranges);
+        Ok(ranges)
+    }
+
+    /// Write a range pointing to a result set, returning its id.
+    fn range(&mut self, range: Range, result_set: u64) -> io::Result<u64> {
+        let vertex = self.vertex("range", json!({ "start": range.start, "end": range.end }))?;
+        self.edge("next", vertex, &[result_set], json!({}))?;
+        Ok(vertex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_are_written_one_per_line() {
+        let mut dump = Dump::new(Vec::new());
+        let project = dump.vertex("project", json!({ "kind": "l" })).ok();
+        let document = dump.vertex("document", json!({ "languageId": "l" })).ok();
+        let (Some(project), Some(document)) = (project, document) else {
+            panic!("writing to a vector failed");
+        };
+        assert!(
+            dump.edge("contains", project, &[document], json!({}))
+                .is_ok()
+        );
+        assert!(dump.edge("next", document, &[project], json!({})).is_ok());
+        let lines = String::from_utf8(dump.out)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                json!({ "id": 1, "type": "vertex", "label": "project", "kind": "l" }),
+                json!({ "id": 2, "type": "vertex", "label": "document", "languageId": "l" }),
+                json!({ "id": 3, "type": "edge", "label": "contains", "outV": 1, "inVs": [2] }),
+                json!({ "id": 4, "type": "edge", "label": "next", "outV": 2, "inV": 1 }),
+            ]
+        );
+    }
+}
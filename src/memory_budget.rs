@@ -0,0 +1,189 @@
+//! Memory budget of the documents stored for files that aren't open.
+//!
+//! Indexing a workspace stores the text and analysis of every file, which adds up in
+//! large workspaces. Open documents and the stdlib, which completion reads from, are
+//! always kept; the documents of other files are evicted least recently used first once
+//! their estimated size exceeds the `memoryBudgetMb` setting. An evicted file keeps its
+//! published diagnostics and its entry in the symbol index, and is analyzed again when
+//! it is opened or changes on disk.
+//!
+//! The size of a document is a rough estimate from the length of its text and the
+//! number of symbols and references of its analysis, as the compiler doesn't report
+//! what its results take.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::Serialize;
+use serde_json::Value;
+use tower_lsp_server::ls_types::Uri;
+
+use crate::document_store::{Document, DocumentStore};
+use crate::settings::section;
+
+/// Budget used until the client configures one: 256 MiB.
+pub const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Estimated bytes of syntax tree per byte of source text.
+const SYNTAX_BYTES_PER_TEXT_BYTE: usize = 4;
+
+/// Estimated bytes of semantic model per symbol or reference.
+const SEMANTIC_BYTES_PER_ENTRY: usize = 96;
+
+/// Read the memory budget of closed documents, in bytes, from a settings object.
+pub fn memory_budget_from_settings(settings: &Value) -> Option<usize> {
+    section(settings)
+        .get("memoryBudgetMb")
+        .and_then(Value::as_u64)
+        .and_then(|megabytes| usize::try_from(megabytes).ok())
+        .map(|megabytes| megabytes.saturating_mul(1024 * 1024))
+}
+
+/// Rough estimate of the memory a stored document takes, in bytes.
+pub fn estimated_size(document: &Document) -> usize {
+    let semantic = &document.analysis.semantic;
+    let text = document.rope.len_bytes();
+    let entries = semantic.symbol_spans.len() + semantic.reference_spans.len();
+    text * (1 + SYNTAX_BYTES_PER_TEXT_BYTE) + entries * SEMANTIC_BYTES_PER_ENTRY
+}
+
+/// The memory budget of closed documents, and what it evicted.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    /// Size the closed documents may take, in bytes
+    budget: AtomicUsize,
+    /// Number of documents evicted since the server started
+    evicted: AtomicU64,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self {
+            budget: AtomicUsize::new(DEFAULT_MEMORY_BUDGET),
+            evicted: AtomicU64::new(0),
+        }
+    }
+}
+
+impl MemoryBudget {
+    /// Set the size the closed documents may take, in bytes.
+    pub fn set_budget(&self, budget: usize) {
+        self.budget.store(budget, Ordering::Relaxed);
+    }
+
+    /// Evict the least recently used documents not `pinned` until the others fit in
+    /// the budget, returning the URIs of the evicted documents.
+    pub fn enforce(&self, store: &DocumentStore, pinned: impl Fn(&Uri) -> bool) -> Vec<Uri> {
+        let budget = self.budget.load(Ordering::Relaxed);
+        let closed = store
+            .by_last_use()
+            .into_iter()
+            .filter(|(uri, _)| !pinned(uri))
+            .map(|(uri, document)| (uri, estimated_size(&document)))
+            .collect::<Vec<_>>();
+        let mut used = closed.iter().map(|(_, size)| size).sum::<usize>();
+        let mut evicted = Vec::new();
+        for (uri, size) in closed {
+            if used <= budget {
+                break;
+            }
+            store.remove(&uri);
+            used -= size;
+            evicted.push(uri);
+        }
+        self.evicted
+            .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+        evicted
+    }
+
+    /// Describe the stored documents, for the status of the server.
+    pub fn stats(
+        &self,
+        store: &DocumentStore,
+        pinned: impl Fn(&Uri) -> bool,
+        indexed_files: usize,
+    ) -> MemoryStats {
+        let mut stats = MemoryStats {
+            budget_bytes: self.budget.load(Ordering::Relaxed),
+            pinned_documents: 0,
+            closed_documents: 0,
+            closed_bytes: 0,
+            evicted: self.evicted.load(Ordering::Relaxed),
+            indexed_files,
+        };
+        for (uri, document) in store.by_last_use() {
+            if pinned(&uri) {
+                stats.pinned_documents += 1;
+            } else {
+                stats.closed_documents += 1;
+                stats.closed_bytes += estimated_size(&document);
+            }
+        }
+        stats
+    }
+}
+
+/// What the stored documents take, as returned by `l.serverStatus`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryStats {
+    /// Size the closed documents may take, in bytes
+    pub budget_bytes: usize,
+    /// Number of stored documents that are never evicted: the open ones and the stdlib
+    pub pinned_documents: usize,
+    /// Number of stored documents of closed files
+    pub closed_documents: usize,
+    /// Estimated size of the documents of closed files, in bytes
+    pub closed_bytes: usize,
+    /// Number of documents evicted since the server started
+    pub evicted: u64,
+    /// Number of files in the symbol index, including evicted ones
+    pub indexed_files: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use l_lang::compile;
+    use ropey::Rope;
+
+    use super::*;
+
+    fn store_file(store: &DocumentStore, uri: &Uri, text: &str) {
+        store.insert(
+            uri,
+            Document::new(Rope::from_str(text), compile(text), None),
+        );
+    }
+
+    #[test]
+    fn least_recently_used_closed_documents_are_evicted() {
+        let uri = |name: &str| {
+            format!("file:///{name}.l")
+                .parse::<Uri>()
+                .expect("valid URI")
+        };
+        let (open, a, b, c) = (uri("open"), uri("a"), uri("b"), uri("c"));
+        let text = "fn f() {}\n";
+        let store = DocumentStore::default();
+        for uri in [&open, &a, &b, &c] {
+            store_file(&store, uri, text);
+        }
+        // `a` is used after `b`, so `b` goes first
+        let _ = store.get_snapshot(&a);
+
+        let size = estimated_size(&store.get_snapshot(&c).expect("c is stored"));
+        let budget = MemoryBudget::default();
+        budget.set_budget(2 * size);
+        let pinned = |candidate: &Uri| *candidate == open;
+        assert_eq!(budget.enforce(&store, pinned), [b]);
+        assert!(store.get_snapshot(&open).is_some());
+        assert!(store.get_snapshot(&a).is_some());
+
+        budget.set_budget(0);
+        let evicted = budget.enforce(&store, pinned);
+        assert_eq!(evicted.len(), 2);
+        let stats = budget.stats(&store, pinned, 4);
+        assert_eq!(stats.pinned_documents, 1);
+        assert_eq!(stats.closed_documents, 0);
+        assert_eq!(stats.evicted, 3);
+    }
+}
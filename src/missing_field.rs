@@ -0,0 +1,268 @@
+//! Quick fix adding a field that doesn't exist to a struct definition.
+//!
+//! Two uses of a field are checked against the fields of the struct the semantic
+//! analysis resolved: accesses such as `p.z`, where `p` or the preceding fields have a
+//! struct type, and struct literals such as `Point { z: 1 }`. The field type is inferred
+//! from the value of the literal or the value assigned to the access, and defaults to
+//! `int` otherwise.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use l_lang::{SymbolId, SymbolKind, Type};
+
+use crate::document_store::Document;
+use crate::function_stub::{
+    Expression, FALLBACK_TYPE, delimited_list, describe_expression, is_identifier, resolve_token,
+};
+use crate::lexer::{Token, tokenize};
+use crate::scopes::{ScopeKind, scope_tree};
+
+/// A field to add to a struct for a use of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingField {
+    /// Name of the struct lacking the field
+    pub struct_name: String,
+    /// Name of the field
+    pub name: String,
+    /// Byte range of the field name at its use
+    pub usage: Range<usize>,
+    /// Byte offset the declaration is inserted at
+    pub insert_at: usize,
+    /// Text of the declaration, including the separator from the previous field
+    pub text: String,
+}
+
+/// Find the uses of missing struct fields overlapping a byte range of a document.
+///
+/// Each missing field gets a single fix, generated from its first use in the range.
+pub fn missing_fields(document: &Document, range: &Range<usize>) -> Vec<MissingField> {
+    let text = document.rope.to_string();
+    let tokens = tokenize(&text);
+    let mut uses = Vec::new();
+    for index in 0..tokens.len() {
+        let after_dot = index > 0 && tokens[index - 1].text == ".";
+        if !is_identifier(tokens[index].text) || after_dot {
+            continue;
+        }
+        match tokens.get(index + 1).map(|token| token.text) {
+            Some(".") => uses.extend(missing_in_access(document, &tokens, index)),
+            Some("{") if index == 0 || tokens[index - 1].text != "struct" => {
+                uses.extend(missing_in_literal(document, &text, &tokens, index));
+            }
+            _ => {}
+        }
+    }
+
+    let tree = scope_tree(document);
+    let semantic = &document.analysis.semantic;
+    let mut seen = HashSet::new();
+    uses.into_iter()
+        .filter(|field| field.usage.start <= range.end && range.start <= field.usage.end)
+        .filter(|field| seen.insert((field.struct_id, field.name)))
+        .filter_map(|field| {
+            let name_end = semantic.get_symbol_span(field.struct_id).end as usize;
+            let body = tree
+                .children
+                .iter()
+                .filter(|scope| scope.kind == ScopeKind::Struct && scope.span.start >= name_end)
+                .min_by_key(|scope| scope.span.start)?;
+            let closing = body.span.end.checked_sub(1)?;
+            let before = text[..closing].trim_end();
+            let separator = if before.ends_with([',', '{']) {
+                ""
+            } else {
+                ","
+            };
+            let line_break = if text[before.len()..closing].contains('\n') {
+                ""
+            } else {
+                "\n"
+            };
+            let ty = field.ty.unwrap_or_else(|| FALLBACK_TYPE.to_string());
+            Some(MissingField {
+                struct_name: document.symbol_name(field.struct_id)?.to_string(),
+                name: field.name.to_string(),
+                usage: field.usage,
+                insert_at: before.len(),
+                text: format!("{separator}\n    {}: {ty},{line_break}", field.name),
+            })
+        })
+        .collect()
+}
+
+/// A use of a field its struct doesn't have.
+struct FieldUse<'a> {
+    /// The struct lacking the field
+    struct_id: SymbolId,
+    /// Name of the field
+    name: &'a str,
+    /// Byte range of the field name
+    usage: Range<usize>,
+    /// Inferred type of the field, if known
+    ty: Option<String>,
+}
+
+/// Check the field access chain starting at a variable for a missing field.
+fn missing_in_access<'a>(
+    document: &Document,
+    tokens: &'a [Token<'a>],
+    start: usize,
+) -> Option<FieldUse<'a>> {
+    let semantic = &document.analysis.semantic;
+    let symbol = resolve_token(document, &tokens[start])?;
+    let Type::Struct(mut struct_id) = semantic.get_symbol_type(symbol)?.ty else {
+        return None;
+    };
+    let mut index = start + 1;
+    while tokens.get(index)?.text == "." {
+        let field = tokens
+            .get(index + 1)
+            .filter(|token| is_identifier(token.text))?;
+        let struct_def = semantic.structs.get(&struct_id)?;
+        match struct_def
+            .fields
+            .iter()
+            .find(|known| known.name == field.text)
+        {
+            Some(known) => {
+                let Type::Struct(next) = known.ty else {
+                    return None;
+                };
+                struct_id = next;
+            }
+            None => {
+                return Some(FieldUse {
+                    struct_id,
+                    name: field.text,
+                    usage: field.span.clone(),
+                    ty: assigned_type(document, &tokens[index + 2..]),
+                });
+            }
+        }
+        index += 2;
+    }
+    None
+}
+
+/// Infer the type of the value assigned by the tokens following a field access.
+fn assigned_type(document: &Document, tokens: &[Token<'_>]) -> Option<String> {
+    let [assign, rest @ ..] = tokens else {
+        return None;
+    };
+    if assign.text != "=" || rest.first().is_some_and(|token| token.text == "=") {
+        return None;
+    }
+    let end = rest
+        .iter()
+        .position(|token| token.text == ";")
+        .unwrap_or(rest.len());
+    let value = &rest[..end];
+    // String literals have no tokens, so only token-based values are inferred here
+    let (first, last) = (value.first()?, value.last()?);
+    let text = document
+        .rope
+        .get_byte_slice(first.span.start..last.span.end)?
+        .to_string();
+    describe_expression(
+        document,
+        &Expression {
+            text: &text,
+            tokens: value,
+        },
+    )
+    .1
+}
+
+/// Check the struct literal starting at a struct name for missing fields.
+fn missing_in_literal<'a>(
+    document: &Document,
+    text: &'a str,
+    tokens: &'a [Token<'a>],
+    start: usize,
+) -> Vec<FieldUse<'a>> {
+    let semantic = &document.analysis.semantic;
+    let Some(struct_id) = resolve_token(document, &tokens[start])
+        .filter(|symbol| semantic.get_symbol_kind(*symbol) == SymbolKind::Struct)
+    else {
+        return Vec::new();
+    };
+    let Some(struct_def) = semantic.structs.get(&struct_id) else {
+        return Vec::new();
+    };
+    let Some(items) = delimited_list(text, &tokens[start + 1..], "{", "}") else {
+        return Vec::new();
+    };
+    items
+        .into_iter()
+        .filter_map(|item| {
+            let [field, colon, value @ ..] = item.tokens else {
+                return None;
+            };
+            if colon.text != ":"
+                || struct_def
+                    .fields
+                    .iter()
+                    .any(|known| known.name == field.text)
+            {
+                return None;
+            }
+            let value_text = item.text.split_once(':')?.1.trim();
+            let (_, ty) = describe_expression(
+                document,
+                &Expression {
+                    text: value_text,
+                    tokens: value,
+                },
+            );
+            Some(FieldUse {
+                struct_id,
+                name: field.text,
+                usage: field.span.clone(),
+                ty,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use l_lang::compile;
+    use ropey::Rope;
+
+    use super::*;
+
+    /// Add the missing fields used in the whole of `text`, returning the edited text.
+    fn with_fields(text: &str) -> String {
+        let document = Document::new(Rope::from_str(text), compile(text), None);
+        let mut edited = text.to_string();
+        for field in missing_fields(&document, &(0..text.len())).iter().rev() {
+            edited.insert_str(field.insert_at, &field.text);
+        }
+        edited
+    }
+
+    #[test]
+    fn accessed_field_is_added_with_the_type_of_the_assigned_value() {
+        let text = "struct Point {\n    x: int,\n}\n\nfn main() {\n    let p = Point { x: 1 };\n    p.visible = true;\n    p.visible = false;\n    return p.x;\n}\n";
+        assert_eq!(
+            with_fields(text),
+            "struct Point {\n    x: int,\n    visible: bool,\n}\n\nfn main() {\n    let p = Point { x: 1 };\n    p.visible = true;\n    p.visible = false;\n    return p.x;\n}\n"
+        );
+    }
+
+    #[test]
+    fn literal_field_is_added_after_the_last_field() {
+        let text = "struct Point { x: int }\n\nfn main() {\n    let p = Point { x: 1, label: \"a\" };\n    return p.x;\n}\n";
+        assert_eq!(
+            with_fields(text),
+            "struct Point { x: int,\n    label: string,\n}\n\nfn main() {\n    let p = Point { x: 1, label: \"a\" };\n    return p.x;\n}\n"
+        );
+    }
+
+    #[test]
+    fn known_fields_need_no_fix() {
+        let text = "struct Point {\n    x: int,\n}\n\nfn main() {\n    let p = Point { x: 1 };\n    p.x = 2;\n    return p.x;\n}\n";
+        assert_eq!(with_fields(text), text);
+    }
+}
@@ -0,0 +1,177 @@
+//! Tracking of outgoing server-to-client traffic.
+//!
+//! Every request (`workspace/configuration`, `workspace/applyEdit`,
+//! `window/workDoneProgress/create`, ...) and notification the server sends to the
+//! client goes through [`OutgoingRequests::track`]. During shutdown the tracker stops
+//! accepting new traffic, gives in-flight messages a short grace period to settle and
+//! then cancels whatever is left, so editors closing a project don't receive responses
+//! for requests they already tore down.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tracing::debug;
+
+/// Registry of in-flight outgoing requests and notifications.
+#[derive(Debug, Default)]
+pub struct OutgoingRequests {
+    /// Number of messages currently awaiting completion
+    in_flight: AtomicUsize,
+    /// Set once shutdown has started; no new traffic is accepted afterwards
+    closed: AtomicBool,
+    /// Wakes every in-flight message when it has to be cancelled
+    cancel: Notify,
+    /// Signalled whenever the in-flight counter drops to zero
+    drained: Notify,
+}
+
+impl OutgoingRequests {
+    /// Run an outgoing request or notification while tracking it.
+    ///
+    /// Returns `None` without polling `message` if shutdown has already started, or if
+    /// the message was cancelled by [`OutgoingRequests::close`] before completing.
+    pub async fn track<F: Future>(&self, message: F) -> Option<F::Output> {
+        // Register for cancellation before checking the flag so a concurrent `close`
+        // can't slip in between the check and the `select!` below.
+        let cancelled = self.cancel.notified();
+        if self.is_closed() {
+            debug!("Dropping outgoing message - server is shutting down");
+            return None;
+        }
+
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        let _guard = InFlightGuard { tracker: self };
+
+        tokio::select! {
+            output = message => Some(output),
+            () = cancelled => {
+                debug!("Cancelled outgoing message during shutdown");
+                None
+            }
+        }
+    }
+
+    /// Check if the tracker has stopped accepting new traffic.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Number of messages that are still awaiting completion.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Stop accepting new traffic and settle in-flight messages.
+    ///
+    /// Messages that are already in flight get up to `grace` to complete; anything
+    /// still pending afterwards is cancelled.
+    pub async fn close(&self, grace: Duration) {
+        self.closed.store(true, Ordering::Release);
+
+        let pending = self.in_flight();
+        if pending == 0 {
+            return;
+        }
+        debug!("Waiting for {pending} outgoing messages before shutdown");
+
+        if tokio::time::timeout(grace, self.wait_drained())
+            .await
+            .is_err()
+        {
+            debug!(
+                "Cancelling {} outgoing messages still pending after {grace:?}",
+                self.in_flight()
+            );
+            self.cancel.notify_waiters();
+            self.wait_drained().await;
+        }
+    }
+
+    /// Wait until no messages are in flight.
+    async fn wait_drained(&self) {
+        loop {
+            let drained = self.drained.notified();
+            if self.in_flight() == 0 {
+                return;
+            }
+            drained.await;
+        }
+    }
+}
+
+/// Decrements the in-flight counter when a tracked message completes or is dropped.
+struct InFlightGuard<'a> {
+    /// The tracker that registered the message
+    tracker: &'a OutgoingRequests,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.tracker.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.tracker.drained.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::time::Instant;
+
+    use super::*;
+
+    const GRACE: Duration = Duration::from_secs(2);
+
+    #[tokio::test(start_paused = true)]
+    async fn responses_arriving_within_the_grace_period_are_returned() {
+        let outgoing = Arc::new(OutgoingRequests::default());
+        assert_eq!(outgoing.track(async { 1 }).await, Some(1));
+
+        let response = tokio::spawn({
+            let outgoing = outgoing.clone();
+            async move {
+                outgoing
+                    .track(async {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        2
+                    })
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(outgoing.in_flight(), 1);
+        let start = Instant::now();
+        outgoing.close(GRACE).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert_eq!(response.await.expect("the message completes"), Some(2));
+        assert_eq!(outgoing.in_flight(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn messages_pending_after_the_grace_period_are_cancelled() {
+        let outgoing = Arc::new(OutgoingRequests::default());
+        let response = tokio::spawn({
+            let outgoing = outgoing.clone();
+            async move { outgoing.track(std::future::pending::<()>()).await }
+        });
+        tokio::task::yield_now().await;
+        let start = Instant::now();
+        outgoing.close(GRACE).await;
+        assert_eq!(start.elapsed(), GRACE);
+        assert_eq!(response.await.expect("the message completes"), None);
+        assert_eq!(outgoing.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn messages_after_shutdown_are_dropped_unsent() {
+        let outgoing = OutgoingRequests::default();
+        outgoing.close(GRACE).await;
+        assert!(outgoing.is_closed());
+        let message = async { unreachable!("messages after shutdown aren't sent") };
+        assert_eq!(outgoing.track(message).await, None::<()>);
+        assert_eq!(outgoing.in_flight(), 0);
+    }
+}
@@ -0,0 +1,129 @@
+//! Isolation of panics to the message that caused them.
+//!
+//! A panic in a handler, or in `l_lang::compile` called by one, would otherwise unwind
+//! through the server loop and take every editor feature down with it. [`CatchPanics`]
+//! catches it at the boundary of each message instead: a request is answered with an
+//! `InternalError` carrying the panic message and a notification is dropped. The panic
+//! hook installed by [`install_panic_hook`] logs where it happened with a backtrace.
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::FutureExt;
+use tower_lsp_server::jsonrpc::{Error, Request, Response};
+use tower_service::Service;
+use tracing::error;
+
+/// Log panics with a backtrace instead of printing them to stderr.
+///
+/// The log mirrors errors to the client, so panics show up in the editor too.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let backtrace = Backtrace::force_capture();
+        error!("{info}\n{backtrace}");
+    }));
+}
+
+/// Service answering the messages whose handling panicked with an error, instead of
+/// letting the panic end the server.
+#[derive(Debug)]
+pub struct CatchPanics<S> {
+    /// The wrapped service
+    inner: S,
+}
+
+impl<S> CatchPanics<S> {
+    /// Wrap a service, catching the panics of its messages.
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S> Service<Request> for CatchPanics<S>
+where
+    S: Service<Request, Response = Option<Response>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Option<Response>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let method = request.method().to_string();
+        let id = request.id().cloned();
+        let response = AssertUnwindSafe(self.inner.call(request)).catch_unwind();
+        Box::pin(async move {
+            match response.await {
+                Ok(response) => response,
+                Err(payload) => {
+                    let message = format!("{method} panicked: {}", panic_message(&*payload));
+                    Ok(id.map(|id| {
+                        Response::from_error(
+                            id,
+                            Error {
+                                message: message.into(),
+                                ..Error::internal_error()
+                            },
+                        )
+                    }))
+                }
+            }
+        })
+    }
+}
+
+/// The message a panic was raised with.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp_server::jsonrpc::ErrorCode;
+
+    /// Service panicking on every message.
+    struct Panicking;
+
+    impl Service<Request> for Panicking {
+        type Response = Option<Response>;
+        type Error = ();
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, ()>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: Request) -> Self::Future {
+            Box::pin(async { panic!("compiler bug") })
+        }
+    }
+
+    #[tokio::test]
+    async fn panicking_requests_are_answered_with_an_internal_error() {
+        let mut service = CatchPanics::new(Panicking);
+        let request = Request::build("textDocument/hover").id(1).finish();
+        let response = service.call(request).await.ok().flatten();
+        let Some(Err(error)) = response.map(Response::into_parts).map(|(_, result)| result) else {
+            panic!("expected an error response");
+        };
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert_eq!(error.message, "textDocument/hover panicked: compiler bug");
+
+        let notification = Request::build("textDocument/didChange").finish();
+        assert!(matches!(service.call(notification).await, Ok(None)));
+    }
+}
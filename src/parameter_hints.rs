@@ -0,0 +1,149 @@
+//! Parameter name hints at the arguments of function calls.
+//!
+//! Every call of a function the analysis resolves gets a `name:` hint before each of
+//! its arguments, with the names of the function's parameters from its scope. The
+//! arguments are found in the text after the called name, split at the commas outside
+//! of nested parentheses, brackets and strings. An argument that is just a name equal
+//! to its parameter's is self-explanatory, so its hint is left out unless the
+//! `hideMatchingParameterHints` setting is disabled.
+
+use std::ops::Range;
+
+use l_lang::{SymbolId, SymbolKind};
+use serde_json::Value;
+
+use crate::document_store::Document;
+use crate::scopes::scope_tree;
+use crate::settings::section;
+
+/// A parameter name to show before an argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterHint {
+    /// Byte offset of the start of the argument
+    pub offset: usize,
+    /// The parameter the argument is passed to
+    pub parameter: SymbolId,
+    /// Name of the parameter
+    pub name: String,
+}
+
+/// Find the parameter names to show before the arguments of every call in a document.
+///
+/// With `hide_matching`, arguments spelling the name of their parameter get no hint.
+pub fn parameter_hints(document: &Document, hide_matching: bool) -> Vec<ParameterHint> {
+    let semantic = &document.analysis.semantic;
+    let text = document.rope.to_string();
+    let scopes = scope_tree(document);
+    let mut hints = Vec::new();
+    for (ref_id, span) in semantic.reference_spans.iter_enumerated() {
+        let Some(Some(function)) = semantic.references.get(ref_id) else {
+            continue;
+        };
+        if semantic.get_symbol_kind(*function) != SymbolKind::Function {
+            continue;
+        }
+        let Some(arguments) = call_arguments(&text, span.end as usize) else {
+            continue;
+        };
+        let parameters = scopes.parameters(document, *function);
+        for (argument, parameter) in arguments.into_iter().zip(parameters) {
+            let Some(name) = document.symbol_name(parameter) else {
+                continue;
+            };
+            if hide_matching && text[argument.clone()] == *name {
+                continue;
+            }
+            hints.push(ParameterHint {
+                offset: argument.start,
+                parameter,
+                name: name.to_string(),
+            });
+        }
+    }
+    hints
+}
+
+/// Find the byte ranges of the arguments of a call, without surrounding whitespace.
+///
+/// `callee_end` is the end of the called name, which must be followed by the opening
+/// parenthesis. Returns `None` if it isn't, or if the parentheses aren't closed before
+/// the end of the statement.
+pub fn call_arguments(text: &str, callee_end: usize) -> Option<Vec<Range<usize>>> {
+    let rest = text.get(callee_end..)?;
+    let open = callee_end + rest.len() - rest.trim_start().len();
+    if text.as_bytes().get(open) != Some(&b'(') {
+        return None;
+    }
+    let bytes = text.as_bytes();
+    let mut arguments = Vec::new();
+    let mut start = open + 1;
+    let mut depth = 0usize;
+    let mut index = start;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'"' => {
+                index += 1;
+                while index < bytes.len() && bytes[index] != b'"' {
+                    index += if bytes[index] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b';' => return None,
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' if depth > 0 => depth -= 1,
+            b',' | b')' if depth == 0 => {
+                let raw = &text[start..index];
+                let argument = raw.trim();
+                if !argument.is_empty() {
+                    let argument_start = start + raw.len() - raw.trim_start().len();
+                    arguments.push(argument_start..argument_start + argument.len());
+                }
+                if bytes[index] == b')' {
+                    return Some(arguments);
+                }
+                start = index + 1;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Read whether arguments named like their parameter get no hint from a settings
+/// object.
+pub fn hide_matching_parameter_hints_from_settings(settings: &Value) -> Option<bool> {
+    section(settings)
+        .get("hideMatchingParameterHints")
+        .and_then(Value::as_bool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(text: &str) -> Option<Vec<&str>> {
+        let callee_end = text.find(|c: char| !c.is_alphanumeric())?;
+        Some(
+            call_arguments(text, callee_end)?
+                .into_iter()
+                .map(|range| &text[range])
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn arguments_are_split_at_top_level_commas() {
+        assert_eq!(
+            arguments("add (1, mul(2, 3) ,  Point { x: 1, y: 2 }, \"a, b)\")"),
+            Some(vec!["1", "mul(2, 3)", "Point { x: 1, y: 2 }", "\"a, b)\""])
+        );
+        assert_eq!(arguments("main()"), Some(vec![]));
+    }
+
+    #[test]
+    fn names_without_a_closed_call_have_no_arguments() {
+        assert_eq!(arguments("add;"), None);
+        assert_eq!(arguments("add(1, 2"), None);
+        assert_eq!(arguments("add(1, 2;\nlet x = f(3);"), None);
+    }
+}
@@ -0,0 +1,176 @@
+//! Work done progress reporting for long-running operations.
+//!
+//! Workspace indexing, the analysis of large files and renames report their progress
+//! through `$/progress`, so clients show a spinner with a percentage instead of
+//! appearing frozen. Clients without work done progress support get the begin and
+//! end of each operation, and every quarter of its progress, as `window/logMessage`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tower_lsp_server::Client;
+use tower_lsp_server::ls_types::notification::Progress;
+use tower_lsp_server::ls_types::request::WorkDoneProgressCreate;
+use tower_lsp_server::ls_types::{
+    MessageType, NumberOrString, ProgressParams, ProgressParamsValue, ProgressToken,
+    WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+    WorkDoneProgressReport,
+};
+use tracing::debug;
+
+use crate::outgoing::OutgoingRequests;
+
+/// Counter used to generate unique server-initiated progress tokens.
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+/// Where the progress of an operation is reported.
+#[derive(Debug)]
+enum Channel {
+    /// `$/progress` notifications for the given token
+    Token(ProgressToken),
+    /// `window/logMessage` for clients without work done progress
+    Log,
+    /// Nowhere, e.g. because the client refused to create the token
+    Silent,
+}
+
+/// Reports the progress of a single long-running operation.
+#[derive(Debug)]
+pub struct ProgressReporter<'a> {
+    /// The LSP client connection
+    client: &'a Client,
+    /// Outgoing traffic tracker every message goes through
+    outgoing: &'a OutgoingRequests,
+    /// Where the progress is reported
+    channel: Channel,
+    /// Title of the operation
+    title: String,
+    /// The last reported percentage, to avoid sending duplicate reports
+    percentage: u32,
+}
+
+impl<'a> ProgressReporter<'a> {
+    /// Start reporting the progress of an operation.
+    ///
+    /// If `token` is provided by the client it is used as-is; otherwise a token is
+    /// created through `window/workDoneProgress/create` when `supported` is set, and
+    /// the progress is logged when it isn't.
+    pub async fn begin(
+        client: &'a Client,
+        outgoing: &'a OutgoingRequests,
+        supported: bool,
+        token: Option<ProgressToken>,
+        title: impl Into<String>,
+    ) -> Self {
+        let title = title.into();
+        let channel = match token {
+            Some(token) => Channel::Token(token),
+            None if supported => {
+                let token = NumberOrString::String(format!(
+                    "l-language-server/{}",
+                    NEXT_TOKEN.fetch_add(1, Ordering::Relaxed)
+                ));
+                let params = WorkDoneProgressCreateParams {
+                    token: token.clone(),
+                };
+                match outgoing
+                    .track(client.send_request::<WorkDoneProgressCreate>(params))
+                    .await
+                {
+                    Some(Ok(())) => Channel::Token(token),
+                    Some(Err(err)) => {
+                        debug!("Failed to create progress token: {err}");
+                        Channel::Silent
+                    }
+                    None => Channel::Silent,
+                }
+            }
+            None => Channel::Log,
+        };
+
+        let reporter = Self {
+            client,
+            outgoing,
+            channel,
+            title,
+            percentage: 0,
+        };
+        reporter
+            .send(
+                WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                    title: reporter.title.clone(),
+                    cancellable: Some(false),
+                    message: None,
+                    percentage: Some(0),
+                }),
+                format!("{}...", reporter.title),
+            )
+            .await;
+        reporter
+    }
+
+    /// Report that `done` out of `total` units of work are complete.
+    pub async fn report(&mut self, done: usize, total: usize, message: Option<String>) {
+        let percentage = u32::try_from(done.saturating_mul(100) / total.max(1))
+            .unwrap_or(100)
+            .min(100);
+        if percentage == self.percentage {
+            return;
+        }
+        let previous_quarter = self.percentage / 25;
+        self.percentage = percentage;
+
+        match self.channel {
+            Channel::Token(_) => {
+                self.send(
+                    WorkDoneProgress::Report(WorkDoneProgressReport {
+                        cancellable: Some(false),
+                        message,
+                        percentage: Some(percentage),
+                    }),
+                    String::new(),
+                )
+                .await;
+            }
+            // Only log every quarter so the log isn't flooded
+            Channel::Log if percentage / 25 != previous_quarter => {
+                self.send_log(format!("{}: {percentage}%", self.title))
+                    .await;
+            }
+            Channel::Log | Channel::Silent => {}
+        }
+    }
+
+    /// Finish reporting the progress of the operation.
+    pub async fn end(self, message: Option<String>) {
+        let log = match &message {
+            Some(message) => format!("{}: {message}", self.title),
+            None => format!("{}: done", self.title),
+        };
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd { message }), log)
+            .await;
+    }
+
+    /// Send a progress notification, or `log` for clients without progress support.
+    async fn send(&self, value: WorkDoneProgress, log: String) {
+        match &self.channel {
+            Channel::Token(token) => {
+                let params = ProgressParams {
+                    token: token.clone(),
+                    value: ProgressParamsValue::WorkDone(value),
+                };
+                self.outgoing
+                    .track(self.client.send_notification::<Progress>(params))
+                    .await;
+            }
+            Channel::Log => self.send_log(log).await,
+            Channel::Silent => {}
+        }
+    }
+
+    /// Log a progress message through `window/logMessage`.
+    async fn send_log(&self, message: String) {
+        self.outgoing
+            .track(self.client.log_message(MessageType::INFO, message))
+            .await;
+    }
+}
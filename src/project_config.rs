@@ -0,0 +1,285 @@
+//! Project configuration read from `l.toml` files.
+//!
+//! A project is the directory holding an `l.toml`, and contains every document below
+//! it up to the next `l.toml`. The file configures what is shared by everyone working
+//! on the project, and overrides the editor settings for its documents:
+//!
+//! ```toml
+//! # Directories with the project's sources, relative to this file
+//! source-roots = ["src"]
+//!
+//! [format]
+//! width = 100
+//!
+//! # Severity of the diagnostics with a code: error, warning, information, hint or off
+//! [lints]
+//! L0002 = "warning"
+//! deadCode = "off"
+//! ```
+//!
+//! The project of a document is found by walking up from its directory. Results are
+//! cached by directory, and the cache is cleared when an `l.toml` changes on disk. An
+//! `l.toml` that can't be loaded is skipped, and its error is kept for the server to
+//! show and the subcommands to print, see [`Projects::take_errors`].
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use dashmap::{DashMap, DashSet};
+use serde::Deserialize;
+use tower_lsp_server::ls_types::DiagnosticSeverity;
+use tracing::warn;
+
+use crate::settings::Settings;
+
+/// Name of the project configuration file.
+pub const PROJECT_FILE: &str = "l.toml";
+
+/// Glob pattern of the project configuration files, watched for changes.
+pub const PROJECT_FILE_GLOB: &str = "**/l.toml";
+
+/// The contents of an `l.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Directories with the project's sources, relative to the project root
+    pub source_roots: Vec<PathBuf>,
+    /// Options of the formatter
+    pub format: FormatConfig,
+    /// Severity of the diagnostics with a code
+    pub lints: HashMap<String, LintLevel>,
+}
+
+/// The `[format]` table of an `l.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FormatConfig {
+    /// Maximum line width of formatted code
+    pub width: Option<usize>,
+}
+
+/// The severity a lint is reported with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Reported as an error
+    Error,
+    /// Reported as a warning
+    Warning,
+    /// Reported as information
+    Information,
+    /// Reported as a hint
+    Hint,
+    /// Not reported
+    Off,
+}
+
+impl LintLevel {
+    /// The severity of diagnostics of this level, `None` if they aren't published.
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Self::Error => Some(DiagnosticSeverity::ERROR),
+            Self::Warning => Some(DiagnosticSeverity::WARNING),
+            Self::Information => Some(DiagnosticSeverity::INFORMATION),
+            Self::Hint => Some(DiagnosticSeverity::HINT),
+            Self::Off => None,
+        }
+    }
+}
+
+/// A directory configured by an `l.toml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    /// The directory holding the `l.toml`
+    pub root: PathBuf,
+    /// The parsed `l.toml`
+    pub config: ProjectConfig,
+}
+
+impl Project {
+    /// Load the project configured by an `l.toml`.
+    pub fn load(file: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(file)
+            .with_context(|| format!("failed to read {}", file.display()))?;
+        let config =
+            toml::from_str(&text).with_context(|| format!("failed to parse {}", file.display()))?;
+        let root = file.parent().unwrap_or(Path::new("")).to_path_buf();
+        Ok(Self { root, config })
+    }
+
+    /// The directories to index for the project's sources.
+    ///
+    /// Without source roots, the whole project is indexed.
+    pub fn source_dirs(&self) -> Vec<PathBuf> {
+        if self.config.source_roots.is_empty() {
+            return vec![self.root.clone()];
+        }
+        self.config
+            .source_roots
+            .iter()
+            .map(|dir| self.root.join(dir))
+            .collect()
+    }
+
+    /// Override editor settings with the project configuration.
+    pub fn apply(&self, settings: &Settings) -> Settings {
+        let mut settings = settings.clone();
+        if let Some(width) = self.config.format.width.filter(|width| *width > 0) {
+            settings.format_width = width;
+        }
+        for (code, level) in &self.config.lints {
+            settings
+                .severity_overrides
+                .insert(code.clone(), level.severity());
+        }
+        settings
+    }
+}
+
+/// Projects found for directories, loaded on first use.
+#[derive(Debug, Default)]
+pub struct Projects {
+    /// The project of each directory a document was looked up in, `None` outside of
+    /// any project
+    by_directory: DashMap<PathBuf, Option<Arc<Project>>>,
+    /// `l.toml` files that couldn't be loaded since the projects were last cleared
+    invalid: DashSet<PathBuf>,
+    /// Load errors not taken by [`Self::take_errors`] yet
+    errors: Mutex<Vec<String>>,
+}
+
+impl Projects {
+    /// Find the project a file belongs to.
+    ///
+    /// An `l.toml` that can't be read or parsed is skipped, so the file falls back to an
+    /// enclosing project or the editor settings, and its error is kept once.
+    pub fn for_file(&self, path: &Path) -> Option<Arc<Project>> {
+        let directory = path.parent()?;
+        if let Some(project) = self.by_directory.get(directory) {
+            return project.clone();
+        }
+        let project = directory
+            .ancestors()
+            .map(|ancestor| ancestor.join(PROJECT_FILE))
+            .filter(|file| file.is_file())
+            .find_map(|file| match Project::load(&file) {
+                Ok(project) => Some(Arc::new(project)),
+                Err(err) => {
+                    warn!("Ignoring the project configuration: {err:#}");
+                    if self.invalid.insert(file) {
+                        self.errors
+                            .lock()
+                            .expect("project errors lock poisoned")
+                            .push(format!("{err:#}"));
+                    }
+                    None
+                }
+            });
+        self.by_directory
+            .insert(directory.to_path_buf(), project.clone());
+        project
+    }
+
+    /// The settings of a file: `settings`, overridden by the `l.toml` of its project if
+    /// it has one.
+    ///
+    /// The server and the command line subcommands resolve settings through this, so
+    /// they agree on the formatter width and lint levels of a file.
+    pub fn settings_for(&self, path: &Path, settings: Arc<Settings>) -> Arc<Settings> {
+        match self.for_file(path) {
+            Some(project) => Arc::new(project.apply(&settings)),
+            None => settings,
+        }
+    }
+
+    /// Take the errors of the `l.toml` files that couldn't be loaded since the last
+    /// call.
+    pub fn take_errors(&self) -> Vec<String> {
+        std::mem::take(&mut *self.errors.lock().expect("project errors lock poisoned"))
+    }
+
+    /// Forget the projects found, e.g. because an `l.toml` changed.
+    pub fn clear(&self) {
+        self.by_directory.clear();
+        self.invalid.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_files_are_parsed() {
+        let config: ProjectConfig = toml::from_str(
+            "source-roots = [\"src\"]\n\n[format]\nwidth = 100\n\n[lints]\nL0002 = \"warning\"\ndeadCode = \"off\"\n",
+        )
+        .expect("valid project file");
+        assert_eq!(config.source_roots, [PathBuf::from("src")]);
+        assert_eq!(config.format.width, Some(100));
+        assert_eq!(config.lints.get("deadCode"), Some(&LintLevel::Off));
+        assert_eq!(
+            toml::from_str::<ProjectConfig>("").ok(),
+            Some(ProjectConfig::default())
+        );
+        assert!(toml::from_str::<ProjectConfig>("[lints]\nL0001 = \"loud\"").is_err());
+        assert!(toml::from_str::<ProjectConfig>("formatWidth = 100").is_err());
+    }
+
+    #[test]
+    fn sample_project_configuration_loads() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/sample-project/l.toml");
+        let project = Project::load(&file).expect("the sample project file is valid");
+        assert_eq!(project.config.source_roots, [PathBuf::from("src")]);
+        assert_eq!(project.config.format.width, Some(80));
+        assert_eq!(
+            project.config.lints.get("deadCode"),
+            Some(&LintLevel::Warning)
+        );
+    }
+
+    #[test]
+    fn invalid_project_files_are_reported_once() {
+        let root = std::env::temp_dir().join(format!("l-invalid-project-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).expect("the project is created");
+        std::fs::write(root.join(PROJECT_FILE), "[project]\nname = \"app\"\n")
+            .expect("the project file is written");
+
+        let projects = Projects::default();
+        assert!(projects.for_file(&root.join("src/main.l")).is_none());
+        assert!(projects.for_file(&root.join("main.l")).is_none());
+        let errors = projects.take_errors();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(errors[0].contains("failed to parse"), "{errors:?}");
+        assert!(projects.take_errors().is_empty());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn project_settings_override_editor_settings() {
+        let project = Project {
+            root: PathBuf::from("/work/app"),
+            config: ProjectConfig {
+                source_roots: vec![PathBuf::from("src"), PathBuf::from("lib")],
+                format: FormatConfig { width: Some(100) },
+                lints: HashMap::from([("L0002".to_string(), LintLevel::Hint)]),
+            },
+        };
+        let settings = project.apply(&Settings::default());
+        assert_eq!(settings.format_width, 100);
+        assert_eq!(
+            settings.severity_overrides.get("L0002"),
+            Some(&Some(DiagnosticSeverity::HINT))
+        );
+        assert_eq!(
+            project.source_dirs(),
+            [
+                PathBuf::from("/work/app/src"),
+                PathBuf::from("/work/app/lib")
+            ]
+        );
+    }
+}
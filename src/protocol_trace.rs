@@ -0,0 +1,248 @@
+//! Tracing of the LSP messages the server handles.
+//!
+//! Every message from the client is handled in a `message` span with its method, id
+//! and document, so whatever is logged while handling it is attributed to it, and the
+//! span is logged with the time it took when it closes.
+//!
+//! Clients trace the protocol on their side too: they pick a level with the `trace`
+//! field of `initialize` and change it with `$/setTrace`. At `messages` the server
+//! sends a `$/logTrace` for every message it handled, with how long it took, and at
+//! `verbose` adds the params of the message. Both are read by [`Traced`] as the
+//! messages go by, so the handlers don't need to know about them. It also records how
+//! long each message took in the server [`Metrics`].
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tower_lsp_server::Client;
+use tower_lsp_server::jsonrpc::{Id, Request, Response};
+use tower_lsp_server::ls_types::notification::LogTrace;
+use tower_lsp_server::ls_types::{LogTraceParams, TraceValue};
+use tower_service::Service;
+use tracing::{Instrument, debug_span};
+
+use crate::server_status::Metrics;
+
+/// The trace level the client asked for, and the client to send traces to.
+#[derive(Debug, Default)]
+pub struct ProtocolTrace {
+    /// The level, as encoded by [`encode_level`]
+    level: AtomicU8,
+    /// The client, set once the LSP service is built
+    client: OnceLock<Client>,
+}
+
+impl ProtocolTrace {
+    /// Set the client traces are sent to.
+    pub fn set_client(&self, client: Client) {
+        let _ = self.client.set(client);
+    }
+
+    /// The current trace level.
+    pub fn level(&self) -> TraceValue {
+        match self.level.load(Ordering::Relaxed) {
+            1 => TraceValue::Messages,
+            2 => TraceValue::Verbose,
+            _ => TraceValue::Off,
+        }
+    }
+
+    /// Change the trace level.
+    pub fn set_level(&self, level: TraceValue) {
+        self.level.store(encode_level(level), Ordering::Relaxed);
+    }
+
+    /// Read a trace level set by a message, from `initialize` or `$/setTrace`.
+    fn observe(&self, method: &str, params: Option<&Value>) {
+        let field = match method {
+            "initialize" => "trace",
+            "$/setTrace" => "value",
+            _ => return,
+        };
+        if let Some(level) = params
+            .and_then(|params| params.get(field))
+            .and_then(|level| serde_json::from_value(level.clone()).ok())
+        {
+            self.set_level(level);
+        }
+    }
+
+    /// Send a `$/logTrace` for a handled message, if the client traces messages.
+    async fn log_handled(&self, message: HandledMessage) {
+        let (Some(client), level) = (self.client.get(), self.level()) else {
+            return;
+        };
+        if level == TraceValue::Off {
+            return;
+        }
+        let params = LogTraceParams {
+            message: message.describe(),
+            verbose: message
+                .params
+                .filter(|_| level == TraceValue::Verbose)
+                .and_then(|params| serde_json::to_string_pretty(&params).ok()),
+        };
+        client.send_notification::<LogTrace>(params).await;
+    }
+}
+
+/// Encode a trace level for [`ProtocolTrace::level`].
+const fn encode_level(level: TraceValue) -> u8 {
+    match level {
+        TraceValue::Off => 0,
+        TraceValue::Messages => 1,
+        TraceValue::Verbose => 2,
+    }
+}
+
+/// What a `$/logTrace` reports about a handled message.
+#[derive(Debug)]
+struct HandledMessage {
+    /// Method of the message
+    method: String,
+    /// Id of the message, for requests
+    id: Option<Id>,
+    /// Params of the message, kept at the `verbose` level only
+    params: Option<Value>,
+    /// Time spent handling it
+    elapsed: Duration,
+}
+
+impl HandledMessage {
+    /// Describe the message like clients describe the ones they send.
+    fn describe(&self) -> String {
+        let elapsed = self.elapsed.as_millis();
+        match &self.id {
+            Some(Id::Number(id)) => {
+                format!("Handled request '{} - ({id})' in {elapsed}ms.", self.method)
+            }
+            Some(Id::String(id)) => {
+                format!("Handled request '{} - ({id})' in {elapsed}ms.", self.method)
+            }
+            Some(Id::Null) | None => {
+                format!("Handled notification '{}' in {elapsed}ms.", self.method)
+            }
+        }
+    }
+}
+
+/// Service handling every message in a span, reporting it to tracing clients and
+/// recording its latency.
+#[derive(Debug)]
+pub struct Traced<S> {
+    /// The wrapped service
+    inner: S,
+    /// The trace level and client
+    trace: Arc<ProtocolTrace>,
+    /// Where the latency of each message is recorded
+    metrics: Arc<Metrics>,
+}
+
+impl<S> Traced<S> {
+    /// Wrap a service, tracing its messages.
+    pub const fn new(inner: S, trace: Arc<ProtocolTrace>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            inner,
+            trace,
+            metrics,
+        }
+    }
+}
+
+impl<S> Service<Request> for Traced<S>
+where
+    S: Service<Request, Response = Option<Response>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Option<Response>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.trace.observe(request.method(), request.params());
+        let uri = request
+            .params()
+            .and_then(|params| params.get("textDocument"))
+            .and_then(|document| document.get("uri"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let span = debug_span!(
+            "message",
+            method = request.method(),
+            id = ?request.id(),
+            uri,
+        );
+        let mut handled = HandledMessage {
+            method: request.method().to_string(),
+            id: request.id().cloned(),
+            params: request
+                .params()
+                .filter(|_| self.trace.level() == TraceValue::Verbose)
+                .cloned(),
+            elapsed: Duration::ZERO,
+        };
+        let trace = Arc::clone(&self.trace);
+        let metrics = Arc::clone(&self.metrics);
+        let response = self.inner.call(request);
+        Box::pin(
+            async move {
+                let start = Instant::now();
+                let response = response.await;
+                handled.elapsed = start.elapsed();
+                metrics.record_request(&handled.method, handled.elapsed);
+                trace.log_handled(handled).await;
+                response
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn trace_level_follows_initialize_and_set_trace() {
+        let trace = ProtocolTrace::default();
+        assert_eq!(trace.level(), TraceValue::Off);
+        trace.observe("initialize", Some(&json!({ "trace": "messages" })));
+        assert_eq!(trace.level(), TraceValue::Messages);
+        trace.observe("$/setTrace", Some(&json!({ "value": "verbose" })));
+        assert_eq!(trace.level(), TraceValue::Verbose);
+        trace.observe("$/setTrace", Some(&json!({ "value": "loud" })));
+        trace.observe("textDocument/hover", Some(&json!({ "value": "off" })));
+        assert_eq!(trace.level(), TraceValue::Verbose);
+    }
+
+    #[test]
+    fn handled_messages_are_described_like_clients_do() {
+        let mut handled = HandledMessage {
+            method: "textDocument/hover".to_string(),
+            id: Some(Id::Number(3)),
+            params: None,
+            elapsed: Duration::from_millis(12),
+        };
+        assert_eq!(
+            handled.describe(),
+            "Handled request 'textDocument/hover - (3)' in 12ms."
+        );
+        handled.method = "textDocument/didChange".to_string();
+        handled.id = None;
+        assert_eq!(
+            handled.describe(),
+            "Handled notification 'textDocument/didChange' in 12ms."
+        );
+    }
+}
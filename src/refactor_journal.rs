@@ -0,0 +1,85 @@
+//! Journal of the refactorings the server handed to the client.
+//!
+//! Every workspace edit produced by a refactoring (renames, workspace formatting) is
+//! recorded together with the text of each affected document before and after the
+//! edit. `l.undoLastRefactoring` uses that to send the inverse edit as one
+//! `workspace/applyEdit`, for clients that can't undo multi-file edits atomically.
+
+use std::sync::Mutex;
+
+use tower_lsp_server::ls_types::{TextEdit, Uri};
+
+use crate::text_diff::text_edits;
+use crate::text_pos::Encoding;
+
+/// Name of the command that reverts the last recorded refactoring.
+pub const UNDO_LAST_REFACTORING_COMMAND: &str = "l.undoLastRefactoring";
+
+/// Number of refactorings kept in the journal.
+const JOURNAL_CAPACITY: usize = 20;
+
+/// A document affected by a refactoring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalDocument {
+    /// URI of the document
+    pub uri: Uri,
+    /// Text of the document before the refactoring
+    pub before: String,
+    /// Text of the document after the refactoring
+    pub after: String,
+}
+
+impl JournalDocument {
+    /// The edits reverting the refactoring, given the current text of the document,
+    /// with positions counted in `encoding`.
+    ///
+    /// Returns `None` if the document changed since the refactoring was applied, or
+    /// the refactoring was never applied.
+    pub fn inverse_edits(&self, current: &str, encoding: Encoding) -> Option<Vec<TextEdit>> {
+        (current == self.after).then(|| text_edits(&self.after, &self.before, encoding))
+    }
+}
+
+/// A recorded refactoring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// Human-readable description, e.g. "Rename to `total`"
+    pub label: String,
+    /// The affected documents
+    pub documents: Vec<JournalDocument>,
+}
+
+/// Bounded history of recorded refactorings, most recent last.
+#[derive(Debug, Default)]
+pub struct RefactorJournal {
+    /// Recorded refactorings
+    entries: Mutex<Vec<JournalEntry>>,
+}
+
+impl RefactorJournal {
+    /// Record a refactoring, dropping the oldest one if the journal is full.
+    pub fn record(&self, entry: JournalEntry) {
+        if entry.documents.is_empty() {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("journal lock poisoned");
+        if entries.len() == JOURNAL_CAPACITY {
+            entries.remove(0);
+        }
+        entries.push(entry);
+    }
+
+    /// The most recent refactoring.
+    pub fn last(&self) -> Option<JournalEntry> {
+        self.entries
+            .lock()
+            .expect("journal lock poisoned")
+            .last()
+            .cloned()
+    }
+
+    /// Remove the most recent refactoring, e.g. after it was undone.
+    pub fn pop(&self) -> Option<JournalEntry> {
+        self.entries.lock().expect("journal lock poisoned").pop()
+    }
+}
@@ -0,0 +1,191 @@
+//! Lexical scope tree of a document, served through the `l/scopes` request.
+//!
+//! Scopes are delimited by the braces of the document: the file is the root scope and
+//! every function, struct body and block opens a nested one. A function's scope starts
+//! at its parameter list, so that parameters belong to it while the function name
+//! belongs to the enclosing scope. Every symbol of the semantic analysis is attributed
+//! to the innermost scope containing its definition.
+
+use std::ops::Range;
+
+use l_lang::{SymbolId, SymbolKind};
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::ls_types::{self, TextDocumentIdentifier};
+
+use crate::document_store::Document;
+use crate::lexer::tokenize;
+
+/// Name of the custom request returning the scope tree of a document.
+pub const SCOPES_METHOD: &str = "l/scopes";
+
+/// Parameters of the `l/scopes` request.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopesParams {
+    /// The document to return the scopes of
+    pub text_document: TextDocumentIdentifier,
+    /// Only return scopes overlapping this range; the whole document if omitted
+    pub range: Option<ls_types::Range>,
+}
+
+/// What opened a scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScopeKind {
+    /// The top level of the document
+    File,
+    /// The parameters and body of a function
+    Function,
+    /// The fields of a struct
+    Struct,
+    /// Any other braced block
+    Block,
+}
+
+/// A scope of the `l/scopes` result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scope {
+    /// What opened the scope
+    pub kind: ScopeKind,
+    /// Range covered by the scope
+    pub range: ls_types::Range,
+    /// Symbols declared directly in the scope
+    pub symbols: Vec<ScopeSymbol>,
+    /// Scopes nested directly in the scope
+    pub children: Vec<Scope>,
+}
+
+/// A symbol declared in a [`Scope`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeSymbol {
+    /// Name of the symbol
+    pub name: String,
+    /// Kind of the symbol
+    pub kind: &'static str,
+    /// Range of the symbol definition
+    pub range: ls_types::Range,
+}
+
+/// A scope as byte spans into the document text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeSpan {
+    /// What opened the scope
+    pub kind: ScopeKind,
+    /// Byte range covered by the scope
+    pub span: Range<usize>,
+    /// Symbols declared directly in the scope
+    pub symbols: Vec<SymbolId>,
+    /// Scopes nested directly in the scope
+    pub children: Vec<ScopeSpan>,
+}
+
+impl ScopeSpan {
+    /// Create an empty scope.
+    fn new(kind: ScopeKind, start: usize) -> Self {
+        Self {
+            kind,
+            span: start..start,
+            symbols: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Attribute a symbol defined at an offset to the innermost scope containing it.
+    fn attribute(&mut self, symbol_id: SymbolId, offset: usize) {
+        match self
+            .children
+            .iter_mut()
+            .find(|child| child.span.contains(&offset))
+        {
+            Some(child) => child.attribute(symbol_id, offset),
+            None => self.symbols.push(symbol_id),
+        }
+    }
+
+    /// Find the parameters of a function declared directly in the scope, in order.
+    pub fn parameters(&self, document: &Document, function: SymbolId) -> Vec<SymbolId> {
+        let semantic = &document.analysis.semantic;
+        // The function scope starts at the parameter list, right after the name
+        let name_end = semantic.get_symbol_span(function).end as usize;
+        let mut parameters = self
+            .children
+            .iter()
+            .filter(|scope| scope.kind == ScopeKind::Function && scope.span.start >= name_end)
+            .min_by_key(|scope| scope.span.start)
+            .map(|scope| {
+                scope
+                    .symbols
+                    .iter()
+                    .copied()
+                    .filter(|symbol| semantic.get_symbol_kind(*symbol) == SymbolKind::Parameter)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        parameters.sort_by_key(|symbol| semantic.get_symbol_span(*symbol).start);
+        parameters
+    }
+
+    /// Drop the nested scopes that don't overlap a byte range.
+    pub fn retain_overlapping(&mut self, range: &Range<usize>) {
+        self.children
+            .retain(|child| child.span.start <= range.end && range.start <= child.span.end);
+        for child in &mut self.children {
+            child.retain_overlapping(range);
+        }
+    }
+}
+
+/// Compute the scope tree of a document.
+pub fn scope_tree(document: &Document) -> ScopeSpan {
+    let text = document.rope.to_string();
+    let mut stack = vec![ScopeSpan::new(ScopeKind::File, 0)];
+    // Kind and start of the scope opened by the next `{`, if it isn't a plain block
+    let mut pending: Option<(ScopeKind, Option<usize>)> = None;
+
+    for token in tokenize(&text) {
+        match token.text {
+            "fn" => pending = Some((ScopeKind::Function, None)),
+            "struct" => pending = Some((ScopeKind::Struct, None)),
+            "(" => {
+                if let Some((ScopeKind::Function, start @ None)) = &mut pending {
+                    *start = Some(token.span.start);
+                }
+            }
+            "{" => {
+                let (kind, start) = pending.take().unwrap_or((ScopeKind::Block, None));
+                stack.push(ScopeSpan::new(kind, start.unwrap_or(token.span.start)));
+            }
+            "}" if stack.len() > 1 => {
+                let mut scope = stack.pop().expect("stack is never empty");
+                scope.span.end = token.span.end;
+                stack
+                    .last_mut()
+                    .expect("the file scope is never popped")
+                    .children
+                    .push(scope);
+            }
+            ";" => pending = None,
+            _ => {}
+        }
+    }
+
+    // Close scopes left open by unbalanced braces at the end of the document
+    while stack.len() > 1 {
+        let mut scope = stack.pop().expect("stack is never empty");
+        scope.span.end = text.len();
+        stack
+            .last_mut()
+            .expect("the file scope is never popped")
+            .children
+            .push(scope);
+    }
+    let mut root = stack.pop().expect("stack is never empty");
+    root.span.end = text.len();
+
+    for (symbol_id, span) in document.analysis.semantic.symbol_spans.iter_enumerated() {
+        root.attribute(symbol_id, span.start as usize);
+    }
+    root
+}
@@ -0,0 +1,110 @@
+//! Startup check of the embedded L compiler.
+//!
+//! The server relies on invariants of the `l_lang` semantic analysis that its types
+//! don't express: a span for every symbol, the kind each declaration gets, resolved
+//! references and struct layouts. A compiler revision breaking one of them would make
+//! individual requests quietly come back empty, so a small program exercising all of
+//! them is compiled once at startup and any mismatch is reported to the user.
+
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+use l_lang::{CompileResult, SymbolKind, Type, compile};
+
+/// Program compiled by the check, covering every symbol kind the server handles.
+const PROGRAM: &str = "struct Point {
+    x: int,
+}
+
+fn get_x(p: Point) -> int {
+    let x = p.x;
+    return x;
+}
+";
+
+/// Declarations the check expects, with their kind.
+const DECLARATIONS: &[(&str, SymbolKind)] = &[
+    ("Point", SymbolKind::Struct),
+    ("get_x", SymbolKind::Function),
+    ("p", SymbolKind::Parameter),
+    ("x", SymbolKind::Variable),
+];
+
+/// Compile the check program and describe every broken invariant.
+///
+/// Returns no problems if the compiler behaves as the server expects.
+pub fn check_compiler() -> Vec<String> {
+    match catch_unwind(AssertUnwindSafe(|| compile(PROGRAM))) {
+        Ok(result) => check_result(&result),
+        Err(_) => vec!["compiling a valid program panicked".to_string()],
+    }
+}
+
+/// Check the analysis of the check program.
+fn check_result(result: &CompileResult) -> Vec<String> {
+    let semantic = &result.semantic;
+    let mut problems = Vec::new();
+    if !result.diagnostics.is_empty() || !semantic.errors.is_empty() {
+        problems.push(format!(
+            "a valid program got {} syntax and {} semantic errors",
+            result.diagnostics.len(),
+            semantic.errors.len()
+        ));
+    }
+    if semantic.symbol_spans.len() != semantic.bindings.len() {
+        problems.push(format!(
+            "{} symbols have {} spans",
+            semantic.bindings.len(),
+            semantic.symbol_spans.len()
+        ));
+        return problems;
+    }
+
+    let name_of = |span: l_lang::Span| PROGRAM.get(span.start as usize..span.end as usize);
+    for (name, kind) in DECLARATIONS {
+        let found = semantic.bindings.iter_enumerated().any(|(symbol, _)| {
+            name_of(semantic.get_symbol_span(symbol)) == Some(name)
+                && semantic.get_symbol_kind(symbol) == *kind
+        });
+        if !found {
+            problems.push(format!("`{name}` isn't declared as a {kind:?}"));
+        }
+    }
+    let variable = semantic.bindings.iter_enumerated().find(|(symbol, _)| {
+        semantic.get_symbol_kind(*symbol) == SymbolKind::Variable
+            && name_of(semantic.get_symbol_span(*symbol)) == Some("x")
+    });
+    if let Some((symbol, _)) = variable
+        && !semantic
+            .get_symbol_type(symbol)
+            .is_some_and(|type_info| matches!(type_info.ty, Type::Int))
+    {
+        problems.push("the type of `x` isn't inferred as int".to_string());
+    }
+
+    let unresolved = semantic
+        .references
+        .iter_enumerated()
+        .filter(|(_, symbol)| symbol.is_none())
+        .count();
+    if unresolved > 0 || semantic.references.len() != semantic.reference_spans.len() {
+        problems.push(format!(
+            "{unresolved} of {} references are unresolved",
+            semantic.references.len()
+        ));
+    }
+
+    let point_fields = semantic
+        .structs
+        .values()
+        .map(|def| {
+            def.fields
+                .iter()
+                .map(|field| (field.name.as_str(), matches!(field.ty, Type::Int)))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    if point_fields != [vec![("x", true)]] {
+        problems.push("the fields of `Point` aren't `x: int`".to_string());
+    }
+    problems
+}
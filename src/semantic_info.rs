@@ -0,0 +1,44 @@
+//! Resolved semantic information about the symbol at a position.
+//!
+//! Served through the `l/semanticInfo` request and the `l.showSemanticInfo` command,
+//! mainly to debug the analysis behind the other language features.
+
+use l_lang::SymbolKind;
+use serde::Serialize;
+use tower_lsp_server::ls_types::Range;
+
+/// Name of the custom request returning the semantic info at a position.
+pub const SEMANTIC_INFO_METHOD: &str = "l/semanticInfo";
+
+/// Name of the command returning the semantic info at a position.
+pub const SHOW_SEMANTIC_INFO_COMMAND: &str = "l.showSemanticInfo";
+
+/// Everything the semantic analysis knows about a symbol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticInfo {
+    /// Index of the symbol in the semantic analysis
+    pub symbol_id: usize,
+    /// Name of the symbol, as written at its definition
+    pub name: String,
+    /// Kind of the symbol
+    pub kind: &'static str,
+    /// Type of the symbol, if it is known
+    #[serde(rename = "type")]
+    pub ty: Option<String>,
+    /// Range of the symbol definition
+    pub span: Range,
+    /// Ranges of all references to the symbol
+    pub references: Vec<Range>,
+}
+
+/// Human-readable name of a symbol kind.
+pub const fn symbol_kind_name(kind: SymbolKind) -> &'static str {
+    match kind {
+        SymbolKind::Function => "function",
+        SymbolKind::Variable => "variable",
+        SymbolKind::Parameter => "parameter",
+        SymbolKind::Struct => "struct",
+        SymbolKind::Field => "field",
+    }
+}
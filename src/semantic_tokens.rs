@@ -0,0 +1,168 @@
+//! Encoding of semantic token spans for the client.
+//!
+//! The spans collected from the analysis and the text may overlap, such as a symbol
+//! definition also recorded as a reference, and may cover several lines, such as a
+//! string literal with a line break. The protocol forbids overlapping tokens, and
+//! clients without `multilineTokenSupport` can't display multi-line ones, so spans are
+//! made disjoint when they are cached, see [`remove_overlaps`], and [`encode_tokens`]
+//! splits every multi-line span into one token per line, without the line terminators.
+//!
+//! A range request returns every token intersecting the range, including those that
+//! start before it, so a long token reaching into the viewport stays highlighted.
+//! Columns and lengths are counted in the position encoding, like positions.
+
+use std::ops::Range;
+
+use ropey::Rope;
+use tower_lsp_server::ls_types::SemanticToken;
+
+use crate::text_pos::{Encoding, line_len};
+
+/// A `(start, length, token type)` span in bytes.
+pub type TokenSpan = (usize, usize, u32);
+
+/// Drop the spans overlapping an earlier one from spans sorted by start.
+///
+/// Of spans starting at the same offset, the first one is kept, so the sort must be
+/// stable for the order the spans were collected in to decide.
+pub fn remove_overlaps(spans: &mut Vec<TokenSpan>) {
+    let mut end = 0;
+    spans.retain(|(start, length, _)| {
+        if *start < end || *length == 0 {
+            return false;
+        }
+        end = start + length;
+        true
+    });
+}
+
+/// Delta-encode the tokens of disjoint spans, sorted by start, that intersect a byte
+/// range of the document.
+///
+/// Multi-line spans are split at line ends, and the parts outside of the range are
+/// left out. Spans reaching past the end of the document are cut at its end.
+pub fn encode_tokens(
+    spans: &[TokenSpan],
+    rope: &Rope,
+    encoding: Encoding,
+    range: Range<usize>,
+) -> Vec<SemanticToken> {
+    let len = rope.len_bytes();
+    // Disjoint spans sorted by start are sorted by end too
+    let first = spans.partition_point(|(start, length, _)| start + length <= range.start);
+    let range_start = rope.byte_to_char(range.start.min(len));
+    let range_end = rope.byte_to_char(range.end.min(len));
+
+    let mut tokens = Vec::new();
+    let (mut previous_line, mut previous_column) = (0, 0);
+    for &(start, length, token_type) in &spans[first..] {
+        if start >= range.end || start >= len {
+            break;
+        }
+        let mut char_start = rope.byte_to_char(start);
+        let char_end = rope.byte_to_char((start + length).min(len));
+        while char_start < char_end {
+            let line = rope.char_to_line(char_start);
+            let line_start = rope.line_to_char(line);
+            let part_end = char_end.min(line_start + line_len(rope, line));
+            if char_start < part_end && char_start < range_end && part_end > range_start {
+                let column = encoding.units(rope, char_start) - encoding.units(rope, line_start);
+                let to_u32 = |value: usize| u32::try_from(value).unwrap_or(u32::MAX);
+                tokens.push(SemanticToken {
+                    delta_line: to_u32(line - previous_line),
+                    delta_start: to_u32(if line == previous_line {
+                        column - previous_column
+                    } else {
+                        column
+                    }),
+                    length: to_u32(
+                        encoding.units(rope, part_end) - encoding.units(rope, char_start),
+                    ),
+                    token_type,
+                    token_modifiers_bitset: 0,
+                });
+                (previous_line, previous_column) = (line, column);
+            }
+            if line + 1 >= rope.len_lines() {
+                break;
+            }
+            char_start = rope.line_to_char(line + 1);
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode tokens to absolute `(line, column, length, token type)` tuples.
+    fn decode(tokens: &[SemanticToken]) -> Vec<(u32, u32, u32, u32)> {
+        let (mut line, mut column) = (0, 0);
+        tokens
+            .iter()
+            .map(|token| {
+                if token.delta_line > 0 {
+                    column = 0;
+                }
+                line += token.delta_line;
+                column += token.delta_start;
+                (line, column, token.length, token.token_type)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_and_empty_spans_are_removed() {
+        let mut spans = vec![(0, 3, 1), (0, 3, 2), (2, 4, 3), (5, 0, 4), (6, 2, 5)];
+        remove_overlaps(&mut spans);
+        assert_eq!(spans, [(0, 3, 1), (6, 2, 5)]);
+    }
+
+    #[test]
+    fn columns_and_lengths_count_characters() {
+        let rope = Rope::from_str("let é = \"ü\";");
+        let tokens = encode_tokens(
+            &[(4, 2, 1), (9, 4, 7)],
+            &rope,
+            Encoding::Utf32,
+            0..rope.len_bytes(),
+        );
+        assert_eq!(decode(&tokens), [(0, 4, 1, 1), (0, 8, 3, 7)]);
+    }
+
+    #[test]
+    fn utf16_columns_and_lengths_count_code_units() {
+        let rope = Rope::from_str("let 🎉 = \"🎉\";");
+        let tokens = encode_tokens(
+            &[(4, 4, 1), (11, 6, 7)],
+            &rope,
+            Encoding::Utf16,
+            0..rope.len_bytes(),
+        );
+        assert_eq!(decode(&tokens), [(0, 4, 2, 1), (0, 9, 4, 7)]);
+    }
+
+    #[test]
+    fn multi_line_spans_are_split_per_line() {
+        let rope = Rope::from_str("let s = \"a\r\n\nbc\";\nx");
+        let tokens = encode_tokens(
+            &[(8, 8, 7), (18, 1, 1)],
+            &rope,
+            Encoding::Utf32,
+            0..rope.len_bytes(),
+        );
+        assert_eq!(decode(&tokens), [(0, 8, 2, 7), (2, 0, 3, 7), (3, 0, 1, 1)]);
+    }
+
+    #[test]
+    fn ranges_include_tokens_starting_before_them() {
+        let rope = Rope::from_str("\"a\nb\nc\" x\ny");
+        let spans = [(0, 7, 7), (8, 1, 1), (10, 1, 1)];
+        let second_line = 3..5;
+        let tokens = encode_tokens(&spans, &rope, Encoding::Utf32, second_line);
+        assert_eq!(decode(&tokens), [(1, 0, 1, 7)]);
+        let tokens = encode_tokens(&spans, &rope, Encoding::Utf32, 5..9);
+        assert_eq!(decode(&tokens), [(2, 0, 2, 7), (2, 3, 1, 1)]);
+    }
+}
@@ -0,0 +1,118 @@
+//! What the server tells clients about itself in the `initialize` result.
+//!
+//! Clients show the name and version of the server, and bug reports quote them, so
+//! they come from the crate metadata of the build. The position encoding is agreed
+//! on with the client: the server prefers counting characters in Unicode scalar
+//! values, which is `utf-32`, and counts UTF-16 code units, which every client
+//! supports, when it isn't offered through `general.positionEncodings` or the older
+//! `offsetEncoding`.
+
+use tower_lsp_server::ls_types::{ClientCapabilities, PositionEncodingKind, ServerInfo};
+
+use crate::text_pos::Encoding;
+
+/// Name of the server, as reported to clients.
+pub const SERVER_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Version of the server, as reported to clients.
+pub const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The identity of the server and the position encoding agreed on with the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerMeta {
+    /// Name of the server
+    pub name: &'static str,
+    /// Version of the server
+    pub version: &'static str,
+    /// Encoding the character offsets of positions are counted in
+    pub position_encoding: PositionEncodingKind,
+}
+
+impl ServerMeta {
+    /// Describe the server to a client with the given capabilities.
+    pub fn negotiate(capabilities: Option<&ClientCapabilities>) -> Self {
+        let offers_utf32 = capabilities.is_some_and(|capabilities| {
+            let general = capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_ref())
+                .is_some_and(|encodings| encodings.contains(&PositionEncodingKind::UTF32));
+            let offset = capabilities
+                .offset_encoding
+                .as_ref()
+                .is_some_and(|encodings| {
+                    encodings
+                        .iter()
+                        .any(|encoding| encoding == PositionEncodingKind::UTF32.as_str())
+                });
+            general || offset
+        });
+        Self {
+            name: SERVER_NAME,
+            version: SERVER_VERSION,
+            position_encoding: if offers_utf32 {
+                PositionEncodingKind::UTF32
+            } else {
+                PositionEncodingKind::UTF16
+            },
+        }
+    }
+
+    /// The encoding positions exchanged with the client are counted in.
+    pub fn encoding(&self) -> Encoding {
+        if self.position_encoding == PositionEncodingKind::UTF32 {
+            Encoding::Utf32
+        } else {
+            Encoding::Utf16
+        }
+    }
+
+    /// The `serverInfo` of the `initialize` result.
+    pub fn server_info(&self) -> ServerInfo {
+        ServerInfo {
+            name: self.name.to_string(),
+            version: Some(self.version.to_string()),
+        }
+    }
+
+    /// The `offsetEncoding` of the `initialize` result, for clients predating
+    /// `positionEncoding`.
+    pub fn offset_encoding(&self) -> String {
+        self.position_encoding.as_str().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp_server::ls_types::GeneralClientCapabilities;
+
+    #[test]
+    fn utf32_is_used_when_the_client_offers_it() {
+        assert_eq!(
+            ServerMeta::negotiate(None).position_encoding,
+            PositionEncodingKind::UTF16
+        );
+        let mut capabilities = ClientCapabilities {
+            general: Some(GeneralClientCapabilities {
+                position_encodings: Some(vec![
+                    PositionEncodingKind::UTF8,
+                    PositionEncodingKind::UTF32,
+                ]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let meta = ServerMeta::negotiate(Some(&capabilities));
+        assert_eq!(meta.position_encoding, PositionEncodingKind::UTF32);
+        assert_eq!(meta.offset_encoding(), "utf-32");
+        assert_eq!(meta.encoding(), Encoding::Utf32);
+
+        capabilities.general = None;
+        capabilities.offset_encoding = Some(vec!["utf-8".to_string(), "utf-16".to_string()]);
+        let meta = ServerMeta::negotiate(Some(&capabilities));
+        assert_eq!(meta.position_encoding, PositionEncodingKind::UTF16);
+        assert_eq!(meta.encoding(), Encoding::Utf16);
+        assert_eq!(meta.server_info().version.as_deref(), Some(SERVER_VERSION));
+    }
+}
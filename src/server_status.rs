@@ -0,0 +1,195 @@
+//! Metrics of the running server, returned by `l.serverStatus` and `l/status`.
+//!
+//! Every message the client sends is counted and timed by method as it is handled, and
+//! every compilation of a document is timed, in the [`Metrics`] shared by the server.
+//! The status adds the memory the process uses and what the stored documents take, so
+//! users reporting a slow or bloated server can attach where the time and memory go.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::Serialize;
+
+use crate::memory_budget::MemoryStats;
+use crate::server_meta::SERVER_VERSION;
+
+/// Name of the command returning the status of the server.
+pub const SERVER_STATUS_COMMAND: &str = "l.serverStatus";
+
+/// Name of the custom request returning the status of the server.
+pub const SERVER_STATUS_METHOD: &str = "l/status";
+
+/// Timing of the runs of an operation.
+#[derive(Debug, Clone, Copy, Default)]
+struct Times {
+    /// Number of runs
+    count: u64,
+    /// Total time spent
+    total: Duration,
+    /// Longest run
+    max: Duration,
+}
+
+impl Times {
+    /// Summarize the runs of an operation.
+    fn stats(&self, name: &str) -> TimingStats {
+        let average = u32::try_from(self.count)
+            .ok()
+            .filter(|count| *count > 0)
+            .map_or(Duration::ZERO, |count| self.total / count);
+        TimingStats {
+            name: name.to_string(),
+            count: self.count,
+            average_ms: average.as_secs_f64() * 1000.0,
+            max_ms: self.max.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Counts and latencies of the messages handled and compilations run since the server
+/// started.
+#[derive(Debug)]
+pub struct Metrics {
+    /// When the server started
+    started: Instant,
+    /// Timing of the messages handled, by method
+    requests: DashMap<String, Times>,
+    /// Timing of the compilations of documents
+    compiles: Mutex<Times>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            requests: DashMap::new(),
+            compiles: Mutex::default(),
+        }
+    }
+}
+
+impl Metrics {
+    /// Record a handled message.
+    pub fn record_request(&self, method: &str, elapsed: Duration) {
+        let mut times = self.requests.entry(method.to_string()).or_default();
+        record(&mut times, elapsed);
+    }
+
+    /// Record a compilation of a document.
+    pub fn record_compile(&self, elapsed: Duration) {
+        let mut times = self.compiles.lock().expect("metrics lock poisoned");
+        record(&mut times, elapsed);
+    }
+
+    /// Describe the server, the result of `l.serverStatus` and `l/status`.
+    pub fn status(&self, documents: usize, memory: MemoryStats) -> ServerStatus {
+        let mut requests = self
+            .requests
+            .iter()
+            .map(|entry| entry.value().stats(entry.key()))
+            .collect::<Vec<_>>();
+        requests.sort_by(|a, b| a.name.cmp(&b.name));
+        let compiles = *self.compiles.lock().expect("metrics lock poisoned");
+        ServerStatus {
+            version: SERVER_VERSION,
+            uptime_ms: self.started.elapsed().as_millis(),
+            memory_bytes: resident_memory(),
+            documents,
+            memory,
+            requests,
+            compile: compiles.stats("compile"),
+        }
+    }
+}
+
+/// Add a run to the timing of an operation.
+fn record(times: &mut Times, elapsed: Duration) {
+    times.count += 1;
+    times.total += elapsed;
+    times.max = times.max.max(elapsed);
+}
+
+/// Timing statistics of an operation, as returned by `l.serverStatus`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingStats {
+    /// The method of the messages, or `compile`
+    pub name: String,
+    /// Number of runs
+    pub count: u64,
+    /// Average time of a run, in milliseconds
+    pub average_ms: f64,
+    /// Longest run, in milliseconds
+    pub max_ms: f64,
+}
+
+/// The status of the server, as returned by `l.serverStatus` and `l/status`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    /// Version of the server
+    pub version: &'static str,
+    /// Time since the server started, in milliseconds
+    pub uptime_ms: u128,
+    /// Resident memory of the process, where the platform tells
+    pub memory_bytes: Option<u64>,
+    /// Number of stored documents
+    pub documents: usize,
+    /// What the stored documents take, against the memory budget
+    pub memory: MemoryStats,
+    /// Timing of the messages handled, by method
+    pub requests: Vec<TimingStats>,
+    /// Timing of the compilations of documents
+    pub compile: TimingStats,
+}
+
+/// Resident memory of the process, read from `/proc` on Linux.
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_timed_by_method() {
+        let metrics = Metrics::default();
+        metrics.record_request("textDocument/hover", Duration::from_millis(2));
+        metrics.record_request("textDocument/hover", Duration::from_millis(4));
+        metrics.record_request("initialize", Duration::from_millis(1));
+        metrics.record_compile(Duration::from_millis(10));
+        let memory = MemoryStats {
+            budget_bytes: 1024,
+            pinned_documents: 1,
+            closed_documents: 2,
+            closed_bytes: 512,
+            evicted: 0,
+            indexed_files: 2,
+        };
+        let status = metrics.status(3, memory.clone());
+        assert_eq!(status.documents, 3);
+        assert_eq!(status.memory, memory);
+        let names = status
+            .requests
+            .iter()
+            .map(|stats| stats.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["initialize", "textDocument/hover"]);
+        let hover = &status.requests[1];
+        assert_eq!(hover.count, 2);
+        assert!((hover.average_ms - 3.0).abs() < 1e-9);
+        assert!((hover.max_ms - 4.0).abs() < 1e-9);
+        assert_eq!(status.compile.count, 1);
+    }
+}
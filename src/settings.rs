@@ -0,0 +1,367 @@
+//! Server settings sent by the client.
+//!
+//! Settings arrive either bare (`{ "stdlibPath": ... }`, as sent in
+//! `initializationOptions` or returned by `workspace/configuration`) or nested under
+//! the `l-language-server` section (as sent by `workspace/didChangeConfiguration`);
+//! both shapes are accepted. [`Settings::from_value`] reads all of them into one typed
+//! value, each key with the reader of the module it configures, and leaves missing or
+//! malformed keys at their default.
+//!
+//! Clients that can't answer `workspace/configuration`, such as simple editors and
+//! test harnesses, configure the server with `initializationOptions` alone. Those
+//! settings are the base the fetched ones are merged over, see [`Settings::merged`],
+//! so a client answering with an empty section keeps them.
+//!
+//! In multi-root workspaces each folder may have its own settings, fetched with the
+//! folder as scope. [`ScopedSettings`] keeps them next to the unscoped ones, and
+//! features working on a document use those of the folder containing it. Settings of
+//! the whole server, such as the stdlib path or the registered features, are read from
+//! the unscoped ones.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp_server::ls_types::Uri;
+
+use crate::completion::{CompletionProvider, disabled_completion_providers_from_settings};
+use crate::debounce::{DEFAULT_DEBOUNCE_DELAY, debounce_delay_from_settings};
+use crate::diagnostic_codes::{SeverityOverrides, severity_overrides_from_settings};
+use crate::diagnostics_history::keep_workspace_diagnostics_from_settings;
+use crate::document_store::normalize_uri;
+use crate::grammar::grammar_path_from_settings;
+use crate::memory_budget::{DEFAULT_MEMORY_BUDGET, memory_budget_from_settings};
+use crate::parameter_hints::hide_matching_parameter_hints_from_settings;
+use crate::stdlib::stdlib_path_from_settings;
+use crate::type_annotation::{return_type_hints_from_settings, variable_type_hints_from_settings};
+
+/// Name of the configuration section used by the client.
+pub const SETTINGS_SECTION: &str = "l-language-server";
+
+/// Keys of the settings the server reads, listed by `l.whatsNew` when new ones appear.
+pub const SETTING_KEYS: &[&str] = &[
+    "stdlibPath",
+    "grammarPath",
+    "debounceMs",
+    "memoryBudgetMb",
+    "keepWorkspaceDiagnostics",
+    "lockAuditMessages",
+    "cacheDirectory",
+    "disabledCompletionProviders",
+    "hideMatchingParameterHints",
+    "variableTypeHints",
+    "returnTypeHints",
+    "formatWidth",
+    "diagnosticSeverity",
+    "disabledFeatures",
+];
+
+/// Line width of the formatter when the settings don't set one.
+pub const DEFAULT_FORMAT_WIDTH: usize = 80;
+
+/// A language feature the `disabledFeatures` setting can turn off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Feature {
+    /// Completion items
+    Completion,
+    /// Documentation on hover
+    Hover,
+    /// Signature help in call arguments
+    SignatureHelp,
+    /// Inlay hints of types and parameter names
+    InlayHints,
+    /// Semantic tokens, and the decorations replacing them
+    SemanticTokens,
+    /// Document formatting
+    Formatting,
+    /// Quick fixes and refactorings
+    CodeActions,
+}
+
+/// All settings of the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Directory of the stdlib sources
+    pub stdlib_path: Option<PathBuf>,
+    /// Grammar file replacing the embedded one
+    pub grammar_path: Option<PathBuf>,
+    /// Line width of the formatter
+    pub format_width: usize,
+    /// Delay before a changed document is recompiled
+    pub debounce_delay: Duration,
+    /// Size the documents of closed files may take, in bytes
+    pub memory_budget: usize,
+    /// Whether workspace and stdlib files keep their diagnostics after being closed
+    pub keep_workspace_diagnostics: bool,
+    /// Whether arguments named like their parameter get no parameter name hint
+    pub hide_matching_parameter_hints: bool,
+    /// Whether variables get type hints
+    pub variable_type_hints: bool,
+    /// Whether functions without a return type annotation get return type hints
+    pub return_type_hints: bool,
+    /// Completion providers turned off
+    pub disabled_completion_providers: Vec<CompletionProvider>,
+    /// Severities replacing the published ones, by diagnostic code
+    pub severity_overrides: SeverityOverrides,
+    /// Language features turned off
+    pub disabled_features: Vec<Feature>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            stdlib_path: None,
+            grammar_path: None,
+            format_width: DEFAULT_FORMAT_WIDTH,
+            debounce_delay: DEFAULT_DEBOUNCE_DELAY,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            keep_workspace_diagnostics: true,
+            hide_matching_parameter_hints: true,
+            variable_type_hints: true,
+            return_type_hints: true,
+            disabled_completion_providers: Vec::new(),
+            severity_overrides: SeverityOverrides::new(),
+            disabled_features: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Read the settings from a settings object.
+    pub fn from_value(settings: &Value) -> Self {
+        Self::default().merged(settings)
+    }
+
+    /// Override these settings with the ones a settings object sets.
+    ///
+    /// Paths are unset by an empty string; other keys without a valid value keep the
+    /// current setting.
+    pub fn merged(&self, settings: &Value) -> Self {
+        let has = |key: &str| section(settings).get(key).is_some();
+        Self {
+            stdlib_path: if has("stdlibPath") {
+                stdlib_path_from_settings(settings)
+            } else {
+                self.stdlib_path.clone()
+            },
+            grammar_path: if has("grammarPath") {
+                grammar_path_from_settings(settings)
+            } else {
+                self.grammar_path.clone()
+            },
+            format_width: format_width_from_settings(settings).unwrap_or(self.format_width),
+            debounce_delay: debounce_delay_from_settings(settings).unwrap_or(self.debounce_delay),
+            memory_budget: memory_budget_from_settings(settings).unwrap_or(self.memory_budget),
+            keep_workspace_diagnostics: keep_workspace_diagnostics_from_settings(settings)
+                .unwrap_or(self.keep_workspace_diagnostics),
+            hide_matching_parameter_hints: hide_matching_parameter_hints_from_settings(settings)
+                .unwrap_or(self.hide_matching_parameter_hints),
+            variable_type_hints: variable_type_hints_from_settings(settings)
+                .unwrap_or(self.variable_type_hints),
+            return_type_hints: return_type_hints_from_settings(settings)
+                .unwrap_or(self.return_type_hints),
+            disabled_completion_providers: disabled_completion_providers_from_settings(settings)
+                .unwrap_or_else(|| self.disabled_completion_providers.clone()),
+            severity_overrides: severity_overrides_from_settings(settings)
+                .unwrap_or_else(|| self.severity_overrides.clone()),
+            disabled_features: disabled_features_from_settings(settings)
+                .unwrap_or_else(|| self.disabled_features.clone()),
+        }
+    }
+
+    /// Check if a language feature is turned on.
+    pub fn enabled(&self, feature: Feature) -> bool {
+        !self.disabled_features.contains(&feature)
+    }
+
+    /// Check if the inlay hints differ from those with other settings.
+    pub fn hints_differ(&self, other: &Self) -> bool {
+        self.hide_matching_parameter_hints != other.hide_matching_parameter_hints
+            || self.variable_type_hints != other.variable_type_hints
+            || self.return_type_hints != other.return_type_hints
+            || self.enabled(Feature::InlayHints) != other.enabled(Feature::InlayHints)
+    }
+}
+
+/// Get the server's section from a settings object.
+pub fn section(settings: &Value) -> &Value {
+    settings.get(SETTINGS_SECTION).unwrap_or(settings)
+}
+
+/// Read a path setting, treating an empty string as unset.
+pub fn path_setting(settings: &Value, key: &str) -> Option<PathBuf> {
+    section(settings)
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|path| !path.trim().is_empty())
+        .map(PathBuf::from)
+}
+
+/// The settings of the workspace and of each of its folders.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScopedSettings {
+    /// Settings outside of any workspace folder, and of the server as a whole
+    pub global: Arc<Settings>,
+    /// Settings of workspace folders, by normalized folder URI
+    pub folders: Vec<(Uri, Arc<Settings>)>,
+}
+
+impl ScopedSettings {
+    /// Settings without folder-specific ones.
+    pub fn global(settings: Settings) -> Self {
+        Self {
+            global: Arc::new(settings),
+            folders: Vec::new(),
+        }
+    }
+
+    /// The settings of the innermost workspace folder containing a document, or the
+    /// global ones.
+    pub fn for_document(&self, uri: &Uri) -> Arc<Settings> {
+        let uri = normalize_uri(uri);
+        self.folders
+            .iter()
+            .filter(|(folder, _)| {
+                uri.as_str()
+                    .strip_prefix(folder.as_str().trim_end_matches('/'))
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .max_by_key(|(folder, _)| folder.as_str().len())
+            .map_or(&self.global, |(_, settings)| settings)
+            .clone()
+    }
+
+    /// Iterate over the global settings and those of every folder.
+    pub fn all(&self) -> impl Iterator<Item = &Settings> {
+        std::iter::once(&*self.global).chain(self.folders.iter().map(|(_, settings)| &**settings))
+    }
+
+    /// Check if the settings of any scope differ from those of other scoped settings,
+    /// as compared by `differ`.
+    ///
+    /// Settings with other folders always differ.
+    pub fn differ(&self, other: &Self, differ: impl Fn(&Settings, &Settings) -> bool) -> bool {
+        let folders = |settings: &Self| {
+            settings
+                .folders
+                .iter()
+                .map(|(folder, _)| folder.clone())
+                .collect::<Vec<_>>()
+        };
+        folders(self) != folders(other)
+            || self
+                .all()
+                .zip(other.all())
+                .any(|(settings, other)| differ(settings, other))
+    }
+}
+
+/// Read the line width of the formatter from a settings object.
+pub fn format_width_from_settings(settings: &Value) -> Option<usize> {
+    section(settings)
+        .get("formatWidth")
+        .and_then(Value::as_u64)
+        .and_then(|width| usize::try_from(width).ok())
+        .filter(|width| *width > 0)
+}
+
+/// Read the language features turned off by a settings object.
+///
+/// Unknown feature names are ignored.
+pub fn disabled_features_from_settings(settings: &Value) -> Option<Vec<Feature>> {
+    let names = section(settings).get("disabledFeatures")?.as_array()?;
+    Some(
+        names
+            .iter()
+            .filter_map(|name| serde_json::from_value(name.clone()).ok())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tower_lsp_server::ls_types::DiagnosticSeverity;
+
+    use super::*;
+
+    #[test]
+    fn missing_and_malformed_keys_keep_their_default() {
+        assert_eq!(Settings::from_value(&Value::Null), Settings::default());
+        let settings = Settings::from_value(&json!({
+            "formatWidth": "wide",
+            "debounceMs": 50,
+            "variableTypeHints": false,
+        }));
+        assert_eq!(settings.format_width, DEFAULT_FORMAT_WIDTH);
+        assert_eq!(settings.debounce_delay, Duration::from_millis(50));
+        assert!(!settings.variable_type_hints);
+        assert!(settings.return_type_hints);
+    }
+
+    #[test]
+    fn nested_settings_are_read_from_the_section() {
+        let settings = Settings::from_value(&json!({
+            SETTINGS_SECTION: {
+                "formatWidth": 100,
+                "disabledFeatures": ["hover", "unknown", "inlayHints"],
+                "diagnosticSeverity": { "L0002": "warning", "deadCode": "off", "L0001": "loud" },
+            }
+        }));
+        assert_eq!(settings.format_width, 100);
+        assert_eq!(
+            settings.disabled_features,
+            [Feature::Hover, Feature::InlayHints]
+        );
+        assert!(!settings.enabled(Feature::Hover));
+        assert!(settings.enabled(Feature::Completion));
+        assert_eq!(
+            settings.severity_overrides.get("L0002"),
+            Some(&Some(DiagnosticSeverity::WARNING))
+        );
+        assert_eq!(settings.severity_overrides.get("deadCode"), Some(&None));
+        assert_eq!(settings.severity_overrides.get("L0001"), None);
+    }
+
+    #[test]
+    fn documents_use_the_settings_of_the_innermost_folder() {
+        let uri = |text: &str| text.parse::<Uri>().expect("valid URI");
+        let width = |width: usize| {
+            Arc::new(Settings {
+                format_width: width,
+                ..Settings::default()
+            })
+        };
+        let settings = ScopedSettings {
+            global: width(80),
+            folders: vec![
+                (uri("file:///work/app"), width(100)),
+                (uri("file:///work/app/vendor/"), width(120)),
+            ],
+        };
+        let width_of = |text: &str| settings.for_document(&uri(text)).format_width;
+        assert_eq!(width_of("file:///work/app/main.l"), 100);
+        assert_eq!(width_of("file:///work/app/vendor/lib.l"), 120);
+        assert_eq!(width_of("file:///work/application/main.l"), 80);
+        assert_eq!(width_of("untitled:Untitled-1"), 80);
+    }
+
+    #[test]
+    fn merged_settings_keep_the_keys_left_out() {
+        let initial = Settings::from_value(&json!({
+            "stdlibPath": "/opt/l/std",
+            "grammarPath": "/opt/l/grammar.json",
+            "formatWidth": 120,
+            "returnTypeHints": false,
+        }));
+        assert_eq!(initial.merged(&Value::Null), initial);
+        let merged = initial.merged(&json!({ "grammarPath": "", "formatWidth": 100 }));
+        assert_eq!(merged.stdlib_path, Some(PathBuf::from("/opt/l/std")));
+        assert_eq!(merged.grammar_path, None);
+        assert_eq!(merged.format_width, 100);
+        assert!(!merged.return_type_hints);
+    }
+}
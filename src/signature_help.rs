@@ -0,0 +1,210 @@
+//! Signature help for the call around the cursor.
+//!
+//! The call is found in the text rather than the syntax tree, as the code around the
+//! cursor is usually incomplete while it is typed. Walking back from the cursor through
+//! the rope, the first `(` that isn't closed before the cursor and follows a name opens
+//! the call, and the commas between it and the cursor, outside of nested parentheses,
+//! brackets and strings, give the active parameter. Both are recomputed on every
+//! request, so the highlighted parameter stays right while arguments are edited in the
+//! middle of a call. Typing `,` or `)` retriggers the help, so clients ask again as the
+//! cursor moves to the next argument or leaves the call.
+
+use l_lang::SymbolKind;
+use ropey::Rope;
+use tower_lsp_server::ls_types::{
+    Documentation, MarkupContent, MarkupKind, ParameterInformation, ParameterLabel, SignatureHelp,
+    SignatureInformation,
+};
+
+use crate::document_store::Document;
+use crate::symbol_at::{Occurrence, pick_symbol_at};
+use crate::symbol_docs::declaration_text;
+
+/// Characters triggering signature help.
+pub const TRIGGER_CHARACTERS: [&str; 1] = ["("];
+
+/// Characters asking for signature help again while it is shown.
+pub const RETRIGGER_CHARACTERS: [&str; 2] = [",", ")"];
+
+/// A call the cursor is in the arguments of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnclosingCall {
+    /// Byte offset of the end of the called name
+    pub callee_end: usize,
+    /// Index of the argument the cursor is in
+    pub active_parameter: u32,
+}
+
+/// Find the call whose arguments contain a byte offset.
+///
+/// Parentheses not preceded by a name group an expression and are skipped. The search
+/// stops at a brace or `;` outside of parentheses, which no argument list crosses.
+pub fn enclosing_call(rope: &Rope, offset: usize) -> Option<EnclosingCall> {
+    let mut char_index = rope.try_byte_to_char(offset).ok()?;
+    let mut depth = 0usize;
+    let mut commas = 0;
+    let mut in_string = false;
+    for c in rope.chars_at(char_index).reversed() {
+        char_index -= 1;
+        match c {
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            ')' | ']' => depth += 1,
+            '(' | '[' if depth > 0 => depth -= 1,
+            ',' if depth == 0 => commas += 1,
+            '{' | '}' | ';' if depth == 0 => return None,
+            '[' => commas = 0,
+            '(' => {
+                let name_end = char_index
+                    - rope
+                        .chars_at(char_index)
+                        .reversed()
+                        .take_while(|c| c.is_whitespace())
+                        .count();
+                let named = rope
+                    .chars_at(name_end)
+                    .reversed()
+                    .next()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_');
+                if named {
+                    return Some(EnclosingCall {
+                        callee_end: rope.char_to_byte(name_end),
+                        active_parameter: commas,
+                    });
+                }
+                commas = 0;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Describe the signature of the function called around a byte offset.
+pub fn signature_help(document: &Document, offset: usize) -> Option<SignatureHelp> {
+    let call = enclosing_call(&document.rope, offset)?;
+    // The parameter list of a declaration isn't a call
+    let callee = pick_symbol_at(&document.analysis, call.callee_end)
+        .filter(|callee| callee.occurrence == Occurrence::Reference)?;
+    let symbol_id = callee.symbol_id;
+    if document.analysis.semantic.get_symbol_kind(symbol_id) != SymbolKind::Function {
+        return None;
+    }
+    let label = declaration_text(document, symbol_id)?;
+    let parameters = parameter_offsets(&label)
+        .into_iter()
+        .map(|offsets| ParameterInformation {
+            label: ParameterLabel::LabelOffsets(offsets),
+            documentation: None,
+        })
+        .collect();
+    let documentation = document.doc_comment(symbol_id).map(|comment| {
+        Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: comment.to_string(),
+        })
+    });
+    Some(SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label,
+            documentation,
+            parameters: Some(parameters),
+            active_parameter: None,
+        }],
+        active_signature: Some(0),
+        active_parameter: Some(call.active_parameter),
+    })
+}
+
+/// Find the character offsets of the parameters in the signature of a function.
+///
+/// The parameters are the parts of the first parenthesized list, split at the commas
+/// outside of nested parentheses, without surrounding whitespace.
+fn parameter_offsets(signature: &str) -> Vec<[u32; 2]> {
+    let chars = signature.chars().collect::<Vec<_>>();
+    let Some(open) = chars.iter().position(|c| *c == '(') else {
+        return Vec::new();
+    };
+    let mut parameters = Vec::new();
+    let mut start = open + 1;
+    let mut depth = 0usize;
+    for (index, c) in chars.iter().enumerate().skip(open + 1) {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' if depth > 0 => depth -= 1,
+            ',' | ')' if depth == 0 => {
+                let part = &chars[start..index];
+                let leading = part.iter().take_while(|c| c.is_whitespace()).count();
+                let trailing = part.iter().rev().take_while(|c| c.is_whitespace()).count();
+                if leading < part.len() {
+                    let to_u32 = |offset: usize| u32::try_from(offset).unwrap_or(u32::MAX);
+                    parameters.push([to_u32(start + leading), to_u32(index - trailing)]);
+                }
+                if *c == ')' {
+                    break;
+                }
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parameters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call_at(text: &str) -> Option<EnclosingCall> {
+        let offset = text.find('|').expect("the cursor is marked");
+        let text = text.replace('|', "");
+        enclosing_call(&Rope::from_str(&text), offset)
+    }
+
+    #[test]
+    fn active_parameter_counts_the_commas_before_the_cursor() {
+        let text = "let x = add(1, |2);";
+        assert_eq!(
+            call_at(text),
+            Some(EnclosingCall {
+                callee_end: 11,
+                active_parameter: 1,
+            })
+        );
+        assert_eq!(call_at("add(|").map(|call| call.active_parameter), Some(0));
+        assert_eq!(
+            call_at("add(1, 2, |").map(|call| call.active_parameter),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn nested_calls_groups_and_strings_are_skipped() {
+        assert_eq!(
+            call_at("add(mul(1, 2), (3, 4), \"a, b\", |)").map(|call| call.active_parameter),
+            Some(3)
+        );
+        assert_eq!(
+            call_at("add(mul(1, |2))").map(|call| call.callee_end),
+            Some(7)
+        );
+        assert_eq!(
+            call_at("add((1, 2|").map(|call| (call.callee_end, call.active_parameter)),
+            Some((3, 0))
+        );
+    }
+
+    #[test]
+    fn no_call_outside_of_arguments() {
+        assert_eq!(call_at("add(1, 2);\nlet x = |"), None);
+        assert_eq!(call_at("fn f() {\n    |"), None);
+        assert_eq!(call_at("add(1, 2)|"), None);
+    }
+
+    #[test]
+    fn parameters_are_split_at_top_level_commas() {
+        let signature = "fn add(a: int, b: fn(int, int) -> int ) -> int";
+        assert_eq!(parameter_offsets(signature), [[7, 13], [15, 37]]);
+        assert!(parameter_offsets("fn main()").is_empty());
+    }
+}
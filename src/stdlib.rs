@@ -0,0 +1,70 @@
+//! Builtin and standard library definitions loaded from an external directory.
+//!
+//! Language developers iterating on the stdlib can point the server at a checkout of
+//! its sources through the `stdlibPath` setting and reload it with the
+//! `l.reloadStdlib` command, without rebuilding or restarting the server.
+
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use dashmap::DashSet;
+use serde_json::Value;
+use tower_lsp_server::ls_types::Uri;
+
+use crate::settings::path_setting;
+
+/// Name of the command that reparses the stdlib definitions.
+pub const RELOAD_STDLIB_COMMAND: &str = "l.reloadStdlib";
+
+/// Location and loaded files of the stdlib.
+#[derive(Debug, Default)]
+pub struct Stdlib {
+    /// Directory containing the stdlib sources, if configured
+    root: RwLock<Option<PathBuf>>,
+    /// URIs of the stdlib files loaded by the last reload
+    loaded: DashSet<Uri>,
+}
+
+impl Stdlib {
+    /// The configured stdlib directory.
+    pub fn root(&self) -> Option<PathBuf> {
+        self.root.read().expect("stdlib lock poisoned").clone()
+    }
+
+    /// Set the stdlib directory, returning whether it changed.
+    pub fn set_root(&self, root: Option<PathBuf>) -> bool {
+        let mut current = self.root.write().expect("stdlib lock poisoned");
+        if *current == root {
+            return false;
+        }
+        *current = root;
+        true
+    }
+
+    /// Check if a document URI belongs to the loaded stdlib.
+    pub fn contains(&self, uri: &Uri) -> bool {
+        self.loaded.contains(uri)
+    }
+
+    /// Replace the set of loaded files, returning the URIs that are no longer part of it.
+    pub fn replace_loaded(&self, uris: Vec<Uri>) -> Vec<Uri> {
+        let stale = self
+            .loaded
+            .iter()
+            .filter(|uri| !uris.contains(uri.key()))
+            .map(|uri| uri.key().clone())
+            .collect::<Vec<_>>();
+        self.loaded.clear();
+        for uri in uris {
+            self.loaded.insert(uri);
+        }
+        stale
+    }
+}
+
+/// Read the stdlib directory from a settings object.
+///
+/// An empty path means the stdlib isn't configured.
+pub fn stdlib_path_from_settings(settings: &Value) -> Option<PathBuf> {
+    path_setting(settings, "stdlibPath")
+}
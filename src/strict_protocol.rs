@@ -0,0 +1,209 @@
+//! Strict validation of incoming messages, enabled with `--strict-protocol`.
+//!
+//! By default the server is lenient: unknown fields are ignored, positions past the end
+//! of a line are clamped and positions past the end of a document make requests return
+//! nothing. That hides bugs in clients, so in strict mode the server rejects such
+//! requests with `InvalidParams` and a message describing the problem, to help client
+//! developers. Unknown fields are detected by [`StrictProtocol`], which wraps the LSP
+//! service, and positions are checked by the handlers against the document text.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use ropey::Rope;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tower_lsp_server::jsonrpc::{Error, Request, Response};
+use tower_lsp_server::ls_types::{
+    CodeActionParams, CompletionItem, CompletionParams, DidChangeTextDocumentParams,
+    DidChangeWatchedFilesParams, DidChangeWorkspaceFoldersParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DidSaveTextDocumentParams, DocumentFormattingParams,
+    ExecuteCommandParams, GotoDefinitionParams, HoverParams, InlayHint, InlayHintParams, Position,
+    Range, ReferenceParams, RenameFilesParams, RenameParams, SemanticTokensParams,
+    SemanticTokensRangeParams, SignatureHelpParams, TextDocumentPositionParams,
+};
+use tower_service::Service;
+use tracing::warn;
+
+use crate::analysis_passes::RunAnalysisParams;
+use crate::document_text::DocumentTextParams;
+use crate::scopes::ScopesParams;
+use crate::text_pos::{Bounds, Encoding, TextPos, line_units};
+
+/// Service rejecting messages with unknown fields before they reach the LSP service.
+///
+/// Requests are answered with `InvalidParams`. Notifications can't be answered, so
+/// they are logged and still handled, to keep the server in sync with the client.
+/// Without strict mode, messages are passed through unchecked.
+#[derive(Debug)]
+pub struct StrictProtocol<S> {
+    /// The wrapped service
+    inner: S,
+    /// Whether messages are checked
+    enabled: bool,
+}
+
+impl<S> StrictProtocol<S> {
+    /// Wrap a service, checking its messages if `enabled` is set.
+    pub const fn new(inner: S, enabled: bool) -> Self {
+        Self { inner, enabled }
+    }
+}
+
+impl<S> Service<Request> for StrictProtocol<S>
+where
+    S: Service<Request, Response = Option<Response>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Option<Response>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        if self.enabled
+            && let Some(problem) = check_params(request.method(), request.params())
+        {
+            let message = format!("{}: {problem}", request.method());
+            match request.id() {
+                Some(id) => {
+                    let response = Response::from_error(id.clone(), Error::invalid_params(message));
+                    return Box::pin(std::future::ready(Ok(Some(response))));
+                }
+                None => warn!("Invalid notification: {message}"),
+            }
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+/// Check the params of a message for unknown fields.
+///
+/// Returns a description of the problem, or `None` if the params are valid or the
+/// method isn't checked.
+fn check_params(method: &str, params: Option<&Value>) -> Option<String> {
+    let params = params.unwrap_or(&Value::Null);
+    match method {
+        "textDocument/didOpen" => unknown_fields::<DidOpenTextDocumentParams>(params),
+        "textDocument/didChange" => unknown_fields::<DidChangeTextDocumentParams>(params),
+        "textDocument/didSave" => unknown_fields::<DidSaveTextDocumentParams>(params),
+        "textDocument/didClose" => unknown_fields::<DidCloseTextDocumentParams>(params),
+        "textDocument/definition" => unknown_fields::<GotoDefinitionParams>(params),
+        "textDocument/hover" => unknown_fields::<HoverParams>(params),
+        "textDocument/signatureHelp" => unknown_fields::<SignatureHelpParams>(params),
+        "textDocument/references" => unknown_fields::<ReferenceParams>(params),
+        "textDocument/rename" => unknown_fields::<RenameParams>(params),
+        "textDocument/completion" => unknown_fields::<CompletionParams>(params),
+        "completionItem/resolve" => unknown_fields::<CompletionItem>(params),
+        "textDocument/formatting" => unknown_fields::<DocumentFormattingParams>(params),
+        "textDocument/codeAction" => unknown_fields::<CodeActionParams>(params),
+        "textDocument/inlayHint" => unknown_fields::<InlayHintParams>(params),
+        "inlayHint/resolve" => unknown_fields::<InlayHint>(params),
+        "textDocument/semanticTokens/full" => unknown_fields::<SemanticTokensParams>(params),
+        "textDocument/semanticTokens/range" => unknown_fields::<SemanticTokensRangeParams>(params),
+        "workspace/executeCommand" => unknown_fields::<ExecuteCommandParams>(params),
+        "workspace/didChangeWorkspaceFolders" => {
+            unknown_fields::<DidChangeWorkspaceFoldersParams>(params)
+        }
+        "workspace/didChangeWatchedFiles" => unknown_fields::<DidChangeWatchedFilesParams>(params),
+        "workspace/didRenameFiles" => unknown_fields::<RenameFilesParams>(params),
+        "l/semanticInfo" => unknown_fields::<TextDocumentPositionParams>(params),
+        "l/scopes" => unknown_fields::<ScopesParams>(params),
+        "l/documentText" => unknown_fields::<DocumentTextParams>(params),
+        "l/runAnalysis" => unknown_fields::<RunAnalysisParams>(params),
+        _ => None,
+    }
+}
+
+/// Find the fields of `params` that `T` doesn't know.
+///
+/// The params are deserialized and serialized again; fields lost on the way are
+/// unknown. Explicit `null` values are accepted, as they are equivalent to omitting an
+/// optional field.
+fn unknown_fields<T: DeserializeOwned + Serialize>(params: &Value) -> Option<String> {
+    let typed = match serde_json::from_value::<T>(params.clone()) {
+        Ok(typed) => typed,
+        Err(err) => return Some(format!("invalid params: {err}")),
+    };
+    let round_trip = serde_json::to_value(typed).ok()?;
+    let mut unknown = Vec::new();
+    collect_unknown_fields(params, &round_trip, "", &mut unknown);
+    (!unknown.is_empty()).then(|| format!("unknown fields: {}", unknown.join(", ")))
+}
+
+/// Collect the paths of the fields of `input` missing from `known`.
+fn collect_unknown_fields(input: &Value, known: &Value, path: &str, unknown: &mut Vec<String>) {
+    match (input, known) {
+        (Value::Object(input), Value::Object(known)) => {
+            for (key, value) in input {
+                let field_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                match known.get(key) {
+                    Some(known) => collect_unknown_fields(value, known, &field_path, unknown),
+                    None if value.is_null() => {}
+                    None => unknown.push(field_path),
+                }
+            }
+        }
+        (Value::Array(input), Value::Array(known)) => {
+            for (index, (value, known)) in input.iter().zip(known).enumerate() {
+                collect_unknown_fields(value, known, &format!("{path}[{index}]"), unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Check that a position lies within a document.
+///
+/// Characters are counted in `encoding` up to the end of the line, excluding the line
+/// terminator.
+pub fn validate_position(
+    position: Position,
+    rope: &Rope,
+    encoding: Encoding,
+) -> Result<(), String> {
+    let line = position.line as usize;
+    if line >= rope.len_lines() {
+        return Err(format!(
+            "line {line} is past the end of the document, which has {} lines",
+            rope.len_lines()
+        ));
+    }
+    let line_len = line_units(rope, line, encoding);
+    if position.character as usize > line_len {
+        return Err(format!(
+            "character {} is past the end of line {line}, which has {line_len} characters",
+            position.character
+        ));
+    }
+    if TextPos::new(rope, encoding, Bounds::Strict)
+        .offset(position)
+        .is_none()
+    {
+        return Err(format!(
+            "character {} of line {line} is inside a surrogate pair",
+            position.character
+        ));
+    }
+    Ok(())
+}
+
+/// Check that a range lies within a document and doesn't end before it starts.
+pub fn validate_range(range: Range, rope: &Rope, encoding: Encoding) -> Result<(), String> {
+    validate_position(range.start, rope, encoding).map_err(|err| format!("range start: {err}"))?;
+    validate_position(range.end, rope, encoding).map_err(|err| format!("range end: {err}"))?;
+    if (range.end.line, range.end.character) < (range.start.line, range.start.character) {
+        return Err("range ends before it starts".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+//! Refactoring generating a constructor function for a struct.
+//!
+//! L has no associated functions, so the constructor of `Point` is a top-level
+//! `new_point` function taking every field as a parameter, in declaration order, and
+//! returning the struct literal. It is inserted after the struct definition, and isn't
+//! offered if a symbol with that name already exists.
+
+use l_lang::{SymbolId, SymbolKind, Type};
+
+use crate::document_store::Document;
+use crate::extract_function::unused_name;
+use crate::function_stub::FALLBACK_TYPE;
+use crate::scopes::{ScopeKind, scope_tree};
+use crate::symbol_at::{Occurrence, pick_symbol_at};
+
+/// Kind of the code action generating a constructor.
+pub const GENERATE_CONSTRUCTOR_KIND: &str = "refactor.generate.constructor";
+
+/// A constructor generated for a struct.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructConstructor {
+    /// Name of the struct
+    pub struct_name: String,
+    /// Name of the constructor
+    pub name: String,
+    /// Byte offset the constructor is inserted at, the end of the struct definition
+    pub insert_at: usize,
+    /// Text of the constructor, including the blank line separating it from the struct
+    pub text: String,
+}
+
+/// Generate the constructor of the struct defined at a byte offset of a document.
+///
+/// The offset may be on the struct name or anywhere in its body. Returns `None` if no
+/// struct is defined there or if its constructor already exists.
+pub fn struct_constructor(document: &Document, offset: usize) -> Option<StructConstructor> {
+    let semantic = &document.analysis.semantic;
+    let tree = scope_tree(document);
+    let bodies = tree
+        .children
+        .iter()
+        .filter(|scope| scope.kind == ScopeKind::Struct)
+        .collect::<Vec<_>>();
+    let struct_id = match pick_symbol_at(&document.analysis, offset) {
+        Some(at)
+            if at.occurrence == Occurrence::Definition
+                && semantic.get_symbol_kind(at.symbol_id) == SymbolKind::Struct =>
+        {
+            at.symbol_id
+        }
+        _ => {
+            let body = bodies.iter().find(|scope| scope.span.contains(&offset))?;
+            struct_defined_before(document, body.span.start)?
+        }
+    };
+    let name_end = semantic.get_symbol_span(struct_id).end as usize;
+    let body = bodies
+        .iter()
+        .filter(|scope| scope.span.start >= name_end)
+        .min_by_key(|scope| scope.span.start)?;
+
+    let struct_name = document.symbol_name(struct_id)?.to_string();
+    let name = format!("new_{}", snake_case(&struct_name));
+    if unused_name(document, &name) != name {
+        return None;
+    }
+    let fields = &semantic.structs.get(&struct_id)?.fields;
+    let params = fields
+        .iter()
+        .map(|field| {
+            let ty = match &field.ty {
+                Type::Unknown => FALLBACK_TYPE.to_string(),
+                ty => ty.format_literal_type(semantic),
+            };
+            format!("{}: {ty}", field.name)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let initializers = fields
+        .iter()
+        .map(|field| format!("{0}: {0}", field.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let literal = if initializers.is_empty() {
+        format!("{struct_name} {{}}")
+    } else {
+        format!("{struct_name} {{ {initializers} }}")
+    };
+    Some(StructConstructor {
+        text: format!("\n\nfn {name}({params}) -> {struct_name} {{\n    return {literal};\n}}"),
+        struct_name,
+        name,
+        insert_at: body.span.end,
+    })
+}
+
+/// Find the struct whose name is the closest before a byte offset.
+fn struct_defined_before(document: &Document, offset: usize) -> Option<SymbolId> {
+    let semantic = &document.analysis.semantic;
+    semantic
+        .bindings
+        .iter_enumerated()
+        .map(|(symbol, _)| symbol)
+        .filter(|symbol| semantic.get_symbol_kind(*symbol) == SymbolKind::Struct)
+        .filter(|symbol| semantic.get_symbol_span(*symbol).end as usize <= offset)
+        .max_by_key(|symbol| semantic.get_symbol_span(*symbol).end)
+}
+
+/// Convert a `CamelCase` struct name to `snake_case`.
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if previous.is_some_and(|previous| previous.is_lowercase() || previous.is_numeric()) {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+        previous = Some(c);
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use l_lang::compile;
+    use ropey::Rope;
+
+    use super::*;
+
+    const TEXT: &str =
+        "struct Point {\n    x: int,\n    y: int,\n}\n\nstruct Line {\n    start: Point,\n}\n";
+
+    /// Generate the constructor of the struct at the first occurrence of `at` in
+    /// `text`, returning the edited text.
+    fn with_constructor(text: &str, at: &str) -> Option<String> {
+        let offset = text.find(at).expect("the offset is in the text");
+        let document = Document::new(Rope::from_str(text), compile(text), None);
+        let constructor = struct_constructor(&document, offset)?;
+        let mut edited = text.to_string();
+        edited.insert_str(constructor.insert_at, &constructor.text);
+        Some(edited)
+    }
+
+    #[test]
+    fn constructor_takes_every_field_in_order() {
+        assert_eq!(
+            with_constructor(TEXT, "Point").as_deref(),
+            Some(
+                "struct Point {\n    x: int,\n    y: int,\n}\n\nfn new_point(x: int, y: int) -> Point {\n    return Point { x: x, y: y };\n}\n\nstruct Line {\n    start: Point,\n}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn offsets_in_the_body_generate_the_constructor_of_the_struct() {
+        assert_eq!(
+            with_constructor(TEXT, "start").as_deref(),
+            Some(
+                "struct Point {\n    x: int,\n    y: int,\n}\n\nstruct Line {\n    start: Point,\n}\n\nfn new_line(start: Point) -> Line {\n    return Line { start: start };\n}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn existing_constructors_are_not_generated_again() {
+        let text = "struct Point {\n    x: int,\n}\n\nfn new_point(x: int) -> Point {\n    return Point { x: x };\n}\n";
+        assert_eq!(with_constructor(text, "Point"), None);
+    }
+
+    #[test]
+    fn struct_names_are_converted_to_snake_case() {
+        assert_eq!(snake_case("Point"), "point");
+        assert_eq!(snake_case("BoundingBox"), "bounding_box");
+        assert_eq!(snake_case("Vec3D"), "vec3_d");
+    }
+}
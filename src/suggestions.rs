@@ -0,0 +1,243 @@
+//! "Did you mean" suggestions for names that don't resolve.
+//!
+//! For every reference the semantic analysis couldn't resolve, the symbols in scope at
+//! the reference are compared to its name by edit distance and the closest one is
+//! suggested. The suggestion is appended to the message of the diagnostic covering the
+//! reference and stored in the diagnostic's `data`, from which a quick-fix code action
+//! rewrites the identifier.
+//!
+//! The suggestions are the only fixes that can be applied without review, so the
+//! `source.fixAll.l` code action and the `l.fixAll` command apply all suggestions of a
+//! document in one edit, e.g. on save.
+
+use std::ops::Range;
+
+use l_lang::{SymbolId, SymbolKind};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp_server::ls_types::{self, CodeAction, CodeActionKind, Diagnostic, Uri};
+
+use crate::document_store::Document;
+use crate::scopes::{ScopeSpan, scope_tree};
+use crate::workspace_edit::WorkspaceEditBuilder;
+
+/// Name of the command applying every suggestion of a document.
+pub const FIX_ALL_COMMAND: &str = "l.fixAll";
+
+/// Kind of the code action applying every suggestion of a document.
+pub const FIX_ALL_KIND: &str = "source.fixAll.l";
+
+/// Check if a code action request filtered by `only` asks for actions of a kind.
+///
+/// Kinds are hierarchical, so `source` and `source.fixAll` include `source.fixAll.l`
+/// too.
+pub fn wants_kind(only: Option<&[CodeActionKind]>, wanted: &str) -> bool {
+    only.is_none_or(|only| {
+        only.iter().any(|kind| {
+            wanted == kind.as_str()
+                || wanted
+                    .strip_prefix(kind.as_str())
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    })
+}
+
+/// A replacement for an unresolved name.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suggestion {
+    /// Range of the unresolved identifier
+    pub range: ls_types::Range,
+    /// The suggested name
+    pub name: String,
+}
+
+impl Suggestion {
+    /// Read the suggestion stored in a diagnostic's `data`.
+    pub fn from_diagnostic(diagnostic: &Diagnostic) -> Option<Self> {
+        let data = diagnostic.data.as_ref()?.get("didYouMean")?;
+        serde_json::from_value(data.clone()).ok()
+    }
+
+    /// The `data` of a diagnostic carrying the suggestion.
+    pub fn to_data(&self) -> Option<Value> {
+        Some(serde_json::json!({ "didYouMean": serde_json::to_value(self).ok()? }))
+    }
+
+    /// The quick fix replacing the identifier with the suggested name.
+    pub fn code_action(self, uri: &Uri, diagnostic: Diagnostic) -> CodeAction {
+        let mut edit = WorkspaceEditBuilder::new();
+        edit.replace(uri, self.range, self.name.clone());
+        CodeAction {
+            title: format!("Change to `{}`", self.name),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic]),
+            edit: edit.build().ok(),
+            is_preferred: Some(true),
+            ..Default::default()
+        }
+    }
+}
+
+/// The byte spans of the references of a document the semantic analysis couldn't
+/// resolve.
+pub fn unresolved_references(document: &Document) -> Vec<Range<usize>> {
+    let semantic = &document.analysis.semantic;
+    semantic
+        .reference_spans
+        .iter_enumerated()
+        .filter(|(ref_id, _)| {
+            semantic
+                .references
+                .get(*ref_id)
+                .copied()
+                .flatten()
+                .is_none()
+        })
+        .map(|(_, span)| span.start as usize..span.end as usize)
+        .collect()
+}
+
+/// Suggest a name for every unresolved reference of a document.
+///
+/// Returns the byte span of each unresolved reference with a close enough symbol in
+/// scope, together with the name of that symbol.
+pub fn suggest_names(document: &Document) -> Vec<(Range<usize>, String)> {
+    let unresolved = unresolved_references(document);
+    if unresolved.is_empty() {
+        return Vec::new();
+    }
+
+    let tree = scope_tree(document);
+    unresolved
+        .into_iter()
+        .filter_map(|span| {
+            let name = document.rope.get_byte_slice(span.clone())?.to_string();
+            let candidates = visible_symbols(&tree, span.start)
+                .into_iter()
+                .filter(|symbol_id| is_visible_at(document, *symbol_id, span.start))
+                .filter_map(|symbol_id| document.symbol_name(symbol_id));
+            let suggestion = closest_name(&name, candidates)?.to_string();
+            Some((span, suggestion))
+        })
+        .collect()
+}
+
+/// The symbols declared in the scopes enclosing an offset, innermost last.
+fn visible_symbols(tree: &ScopeSpan, offset: usize) -> Vec<SymbolId> {
+    let mut symbols = Vec::new();
+    let mut scope = tree;
+    loop {
+        symbols.extend(&scope.symbols);
+        match scope
+            .children
+            .iter()
+            .find(|child| child.span.contains(&offset))
+        {
+            Some(child) => scope = child,
+            None => return symbols,
+        }
+    }
+}
+
+/// Check if a symbol of an enclosing scope can be referenced at an offset.
+///
+/// Functions and structs can be used anywhere, variables and parameters only after
+/// their definition, and fields only through field accesses.
+fn is_visible_at(document: &Document, symbol_id: SymbolId, offset: usize) -> bool {
+    let semantic = &document.analysis.semantic;
+    match semantic.get_symbol_kind(symbol_id) {
+        SymbolKind::Function | SymbolKind::Struct => true,
+        SymbolKind::Variable | SymbolKind::Parameter => {
+            (semantic.get_symbol_span(symbol_id).end as usize) <= offset
+        }
+        SymbolKind::Field => false,
+    }
+}
+
+/// Pick the candidate closest to a name, if any is close enough to be a likely typo.
+///
+/// A candidate qualifies if its edit distance to the name is at most a third of the
+/// name's length, and at least one. Ties are broken alphabetically.
+fn closest_name<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+fn edit_distance(left: &str, right: &str) -> usize {
+    let right = right.chars().collect::<Vec<_>>();
+    let mut previous = (0..=right.len()).collect::<Vec<_>>();
+    let mut current = vec![0; right.len() + 1];
+    for (i, left_char) in left.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, right_char) in right.iter().enumerate() {
+            let substitution = previous[j] + usize::from(left_char != *right_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[right.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use l_lang::compile;
+    use ropey::Rope;
+
+    use super::*;
+
+    /// Apply every suggestion for `text`, as the fix-all action does.
+    fn fixed(text: &str) -> String {
+        let document = Document::new(Rope::from_str(text), compile(text), None);
+        let mut edited = text.to_string();
+        for (span, name) in suggest_names(&document).into_iter().rev() {
+            edited.replace_range(span, &name);
+        }
+        edited
+    }
+
+    #[test]
+    fn misspelled_names_are_replaced_by_the_closest_symbol_in_scope() {
+        let text = "fn increment(count: int) -> int {\n    return count + 1;\n}\n\nfn main() {\n    let total = 1;\n    return incremnt(totl);\n}\n";
+        assert_eq!(
+            fixed(text),
+            "fn increment(count: int) -> int {\n    return count + 1;\n}\n\nfn main() {\n    let total = 1;\n    return increment(total);\n}\n"
+        );
+    }
+
+    #[test]
+    fn variables_out_of_scope_or_too_different_are_not_suggested() {
+        let text = "fn other() {\n    let total = 1;\n    return total;\n}\n\nfn main() {\n    let later = totl;\n    let total = 2;\n    return unrelated;\n}\n";
+        assert_eq!(fixed(text), text);
+    }
+
+    #[test]
+    fn closest_name_allows_a_third_of_the_name_in_edits() {
+        let candidates = ["count", "counter", "amount"];
+        assert_eq!(closest_name("cont", candidates.into_iter()), Some("count"));
+        assert_eq!(
+            closest_name("countr", candidates.into_iter()),
+            Some("count")
+        );
+        assert_eq!(closest_name("x", ["y", "x"].into_iter()), Some("y"));
+        assert_eq!(closest_name("total", candidates.into_iter()), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("größe", "grösse"), 2);
+    }
+
+    #[test]
+    fn fix_all_is_included_by_its_parent_kinds() {
+        let only = |kind: &str| vec![CodeActionKind::from(kind.to_string())];
+        assert!(wants_kind(None, FIX_ALL_KIND));
+        assert!(wants_kind(Some(&only("source")), FIX_ALL_KIND));
+        assert!(wants_kind(Some(&only("source.fixAll")), FIX_ALL_KIND));
+        assert!(!wants_kind(Some(&only("source.fix")), FIX_ALL_KIND));
+        assert!(!wants_kind(Some(&only("quickfix")), FIX_ALL_KIND));
+    }
+}
@@ -0,0 +1,205 @@
+//! Resolution of the symbol designated by a cursor offset.
+//!
+//! Cursor offsets sit between characters, so an offset at the boundary of a token could
+//! designate the token before or the token after it. Every position-based feature goes
+//! through [`pick_symbol_at`], so that they all agree on the following policy:
+//!
+//! - An offset belongs to a token if it is at the token's first character, inside it,
+//!   or right after its last character.
+//! - A token containing the offset wins over a token ending at it, so between adjacent
+//!   tokens, such as `a` and `.` in `a.b`, the token after the cursor wins.
+//! - Among equally matching tokens, references win over definitions, then narrower
+//!   tokens over wider ones, then earlier tokens over later ones.
+
+use std::ops::Range;
+
+use l_lang::{CompileResult, SymbolId};
+
+/// How a token relates to its symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Occurrence {
+    /// The token uses the symbol
+    Reference,
+    /// The token defines the symbol
+    Definition,
+}
+
+/// A token that may designate a symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate<T> {
+    /// Byte range of the token
+    pub span: Range<usize>,
+    /// How the token relates to its symbol
+    pub occurrence: Occurrence,
+    /// The symbol of the token
+    pub value: T,
+}
+
+/// The symbol designated by a cursor offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolAt {
+    /// The designated symbol
+    pub symbol_id: SymbolId,
+    /// Byte range of the token the offset belongs to
+    pub span: Range<usize>,
+    /// Whether the token is a reference to the symbol or its definition
+    pub occurrence: Occurrence,
+}
+
+/// Resolve the symbol defined or referenced at a byte offset.
+///
+/// Returns `None` if no token matches the offset, or if the winning token is a
+/// reference the semantic analysis couldn't resolve.
+pub fn pick_symbol_at(analysis: &CompileResult, offset: usize) -> Option<SymbolAt> {
+    let semantic = &analysis.semantic;
+    let (start, stop) = (offset.saturating_sub(1), offset + 1);
+    let references = semantic
+        .span_to_reference
+        .find(start, stop)
+        .map(|interval| Candidate {
+            span: interval.start..interval.stop,
+            occurrence: Occurrence::Reference,
+            value: semantic.references.get(interval.val).copied().flatten(),
+        });
+    let definitions = semantic
+        .span_to_symbol
+        .find(start, stop)
+        .map(|interval| Candidate {
+            span: interval.start..interval.stop,
+            occurrence: Occurrence::Definition,
+            value: Some(interval.val),
+        });
+
+    let candidate = pick(offset, references.chain(definitions))?;
+    Some(SymbolAt {
+        symbol_id: candidate.value?,
+        span: candidate.span,
+        occurrence: candidate.occurrence,
+    })
+}
+
+/// Pick the token an offset belongs to among candidates, following the module policy.
+pub fn pick<T>(
+    offset: usize,
+    candidates: impl IntoIterator<Item = Candidate<T>>,
+) -> Option<Candidate<T>> {
+    candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let span = &candidate.span;
+            let containment = if span.start >= span.end {
+                return None;
+            } else if span.contains(&offset) {
+                0
+            } else if span.end == offset {
+                1
+            } else {
+                return None;
+            };
+            let key = (containment, candidate.occurrence, span.len(), span.start);
+            Some((key, candidate))
+        })
+        .min_by_key(|(key, _)| *key)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(span: Range<usize>, value: &'static str) -> Candidate<&'static str> {
+        Candidate {
+            span,
+            occurrence: Occurrence::Reference,
+            value,
+        }
+    }
+
+    fn definition(span: Range<usize>, value: &'static str) -> Candidate<&'static str> {
+        Candidate {
+            span,
+            occurrence: Occurrence::Definition,
+            value,
+        }
+    }
+
+    fn picked(offset: usize, candidates: Vec<Candidate<&'static str>>) -> Option<&'static str> {
+        pick(offset, candidates).map(|candidate| candidate.value)
+    }
+
+    #[test]
+    fn offset_at_first_character_belongs_to_token() {
+        assert_eq!(picked(4, vec![reference(4..7, "foo")]), Some("foo"));
+    }
+
+    #[test]
+    fn offset_inside_token_belongs_to_token() {
+        assert_eq!(picked(5, vec![reference(4..7, "foo")]), Some("foo"));
+    }
+
+    #[test]
+    fn offset_at_last_character_belongs_to_token() {
+        assert_eq!(picked(6, vec![reference(4..7, "foo")]), Some("foo"));
+    }
+
+    #[test]
+    fn offset_right_after_token_belongs_to_token() {
+        assert_eq!(picked(7, vec![reference(4..7, "foo")]), Some("foo"));
+    }
+
+    #[test]
+    fn offset_before_or_past_token_matches_nothing() {
+        assert_eq!(picked(3, vec![reference(4..7, "foo")]), None);
+        assert_eq!(picked(8, vec![reference(4..7, "foo")]), None);
+    }
+
+    #[test]
+    fn token_after_cursor_wins_between_adjacent_tokens() {
+        let candidates = || vec![reference(0..1, "a"), reference(1..2, "b")];
+        assert_eq!(picked(1, candidates()), Some("b"));
+        assert_eq!(
+            picked(1, candidates().into_iter().rev().collect()),
+            Some("b")
+        );
+    }
+
+    #[test]
+    fn token_after_cursor_wins_even_if_it_is_a_definition() {
+        let candidates = vec![reference(0..3, "arg"), definition(3..6, "def")];
+        assert_eq!(picked(3, candidates), Some("def"));
+    }
+
+    #[test]
+    fn reference_wins_over_definition_on_same_span() {
+        let candidates = || vec![definition(4..7, "definition"), reference(4..7, "reference")];
+        assert_eq!(picked(5, candidates()), Some("reference"));
+        assert_eq!(picked(7, candidates()), Some("reference"));
+        assert_eq!(
+            picked(5, candidates().into_iter().rev().collect()),
+            Some("reference")
+        );
+    }
+
+    #[test]
+    fn narrower_token_wins() {
+        let candidates = vec![reference(0..10, "wide"), reference(2..4, "narrow")];
+        assert_eq!(picked(3, candidates), Some("narrow"));
+    }
+
+    #[test]
+    fn earlier_token_wins_among_equal_tokens() {
+        let candidates = vec![reference(3..5, "later"), reference(2..4, "earlier")];
+        assert_eq!(picked(3, candidates), Some("earlier"));
+    }
+
+    #[test]
+    fn empty_spans_are_ignored() {
+        let candidates = vec![reference(4..4, "empty"), definition(2..4, "def")];
+        assert_eq!(picked(4, candidates), Some("def"));
+    }
+
+    #[test]
+    fn no_candidates_match_nothing() {
+        assert_eq!(picked(0, Vec::new()), None);
+    }
+}
@@ -0,0 +1,154 @@
+//! Documentation of symbols, read from their declarations.
+//!
+//! A block of `///` comment lines directly above a declaration documents it. The
+//! compiler drops comments, so [`doc_comments`] reads them from the text and pairs them
+//! with the declared symbols; documents cache the result, see
+//! [`Document::doc_comment`]. The declaration itself is shown as a code block: the
+//! signature of a function, the whole definition of a struct, and the name and type of
+//! a variable or parameter.
+
+use std::collections::HashSet;
+
+use l_lang::{SymbolId, SymbolKind};
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::ls_types::Uri;
+
+use crate::document_store::Document;
+use crate::lexer::tokenize;
+
+/// Where a symbol is declared, kept in a completion item or inlay hint until the client
+/// resolves it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResolveData {
+    /// The document declaring the symbol
+    pub uri: Uri,
+    /// Byte offset of the symbol's name in the declaration
+    pub offset: usize,
+}
+
+impl ResolveData {
+    /// Record where a symbol of a document is declared.
+    pub fn new(uri: &Uri, document: &Document, symbol: SymbolId) -> Self {
+        Self {
+            uri: uri.clone(),
+            offset: document.analysis.semantic.get_symbol_span(symbol).start as usize,
+        }
+    }
+
+    /// Find the symbol in the current version of its document.
+    ///
+    /// Returns `None` if no symbol is declared at the recorded offset anymore, because
+    /// the document changed since.
+    pub fn symbol(&self, document: &Document) -> Option<SymbolId> {
+        let semantic = &document.analysis.semantic;
+        semantic
+            .bindings
+            .iter_enumerated()
+            .map(|(symbol_id, _)| symbol_id)
+            .find(|symbol_id| semantic.get_symbol_span(*symbol_id).start as usize == self.offset)
+    }
+}
+
+/// Describe a symbol as Markdown, with its declaration and its comment.
+pub fn symbol_documentation(document: &Document, symbol: SymbolId) -> Option<String> {
+    let declaration = declaration_text(document, symbol)?;
+    let mut markdown = format!("```l\n{declaration}\n```");
+    if let Some(comment) = document.doc_comment(symbol) {
+        markdown.push_str("\n\n");
+        markdown.push_str(comment);
+    }
+    Some(markdown)
+}
+
+/// The source of the declaration of a symbol, as shown in its documentation.
+///
+/// Functions are cut before their body and structs include their fields.
+pub fn declaration_text(document: &Document, symbol: SymbolId) -> Option<String> {
+    let semantic = &document.analysis.semantic;
+    let name = document.symbol_name(symbol)?;
+    let kind = semantic.get_symbol_kind(symbol);
+    if !matches!(kind, SymbolKind::Function | SymbolKind::Struct) {
+        return Some(match document.type_label(symbol) {
+            Some(ty) => format!("{name}: {ty}"),
+            None => name.to_string(),
+        });
+    }
+
+    let text = document.rope.to_string();
+    let tokens = tokenize(&text);
+    let span = semantic.get_symbol_span(symbol);
+    let index = tokens
+        .iter()
+        .position(|token| token.span.start == span.start as usize)?;
+    let keyword = index.checked_sub(1).map(|index| &tokens[index])?;
+    let open = index + tokens[index..].iter().position(|token| token.text == "{")?;
+    let end = if kind == SymbolKind::Function {
+        tokens[open].span.start
+    } else {
+        let mut depth = 0usize;
+        tokens[open..]
+            .iter()
+            .find(|token| {
+                match token.text {
+                    "{" => depth += 1,
+                    "}" => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })?
+            .span
+            .end
+    };
+    Some(text[keyword.span.start..end].trim().to_string())
+}
+
+/// Find the doc comment of every declaration of a document, indexed by symbol id.
+///
+/// A doc comment is a block of consecutive `///` lines. It documents the first symbol
+/// declared on the line right after it, so in `fn add(a: int)` it belongs to `add`
+/// and not to `a`. The markers and one following space are stripped from each line.
+pub fn doc_comments(document: &Document) -> Vec<Option<String>> {
+    let rope = &document.rope;
+    let semantic = &document.analysis.semantic;
+    let mut comments = vec![None; semantic.bindings.len()];
+    let mut documented_lines = HashSet::new();
+    let mut symbols = semantic
+        .bindings
+        .iter_enumerated()
+        .map(|(symbol_id, _)| {
+            (
+                semantic.get_symbol_span(symbol_id).start as usize,
+                symbol_id,
+            )
+        })
+        .collect::<Vec<_>>();
+    symbols.sort_by_key(|(start, _)| *start);
+    for (start, symbol_id) in symbols {
+        let Ok(line) = rope.try_byte_to_line(start) else {
+            continue;
+        };
+        if !documented_lines.insert(line) {
+            continue;
+        }
+        let mut lines = (0..line)
+            .rev()
+            .map_while(|line| {
+                let text = rope.line(line).to_string();
+                let comment = text.trim().strip_prefix("///")?;
+                Some(
+                    comment
+                        .strip_prefix(' ')
+                        .unwrap_or(comment)
+                        .trim_end()
+                        .to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            continue;
+        }
+        lines.reverse();
+        comments[symbol_id.index()] = Some(lines.join("\n"));
+    }
+    comments
+}
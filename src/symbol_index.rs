@@ -0,0 +1,310 @@
+//! The symbols the files of the workspace define, remembered across sessions.
+//!
+//! Every analyzed document records the functions, structs and fields it defines, with
+//! the hash of the text they were found in, and `workspace/symbol` searches them. The
+//! index is written to the cache directory on shutdown, in a file keyed by the
+//! workspace folders, and loaded again when the server starts on the same workspace.
+//!
+//! Indexing a workspace folder skips compiling the files whose text still hashes to
+//! the recorded one, so a large workspace opens without recompiling every file. Only
+//! files that published no diagnostics at all are skipped, since the diagnostics of
+//! the others must be published again; a skipped file is compiled once it is opened or
+//! changes on disk.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use anyhow::Context;
+use dashmap::DashMap;
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::ls_types::{SymbolKind, Uri};
+
+use crate::analyzer::AnalyzedSymbol;
+use crate::document_store::normalize_uri;
+use crate::document_text::content_hash;
+use crate::enabled_analyses::workspace_key;
+use crate::server_meta::SERVER_VERSION;
+
+/// Name of the file the index is stored in.
+const STATE_FILE: &str = "symbols.json";
+
+/// Maximum number of symbols returned for a `workspace/symbol` query.
+pub const MAX_WORKSPACE_SYMBOLS: usize = 256;
+
+/// A symbol defined at the top of a file, or a field of one of its structs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedSymbol {
+    /// Name of the symbol
+    pub name: String,
+    /// Index of the semantic token type of the symbol in the legend of the server
+    pub token_type: u32,
+    /// Byte span of the name at the definition
+    pub span: Range<usize>,
+}
+
+impl IndexedSymbol {
+    /// The LSP kind of the symbol.
+    ///
+    /// Token type indices correspond to `LEGEND_TYPE` order: 0: FUNCTION, 3: STRUCT,
+    /// 4: PROPERTY (field); variables and parameters aren't indexed.
+    pub const fn kind(&self) -> SymbolKind {
+        match self.token_type {
+            0 => SymbolKind::FUNCTION,
+            3 => SymbolKind::STRUCT,
+            _ => SymbolKind::FIELD,
+        }
+    }
+}
+
+/// The indexed symbols of a file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexedFile {
+    /// Hash of the text the symbols were found in, see [`content_hash`]
+    hash: String,
+    /// Whether no diagnostics were published for the file
+    clean: bool,
+    /// The symbols, in the order the compiler defined them
+    symbols: Vec<IndexedSymbol>,
+}
+
+/// Contents of the state file of a workspace.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedIndex {
+    /// Version of the server that wrote the index, whose compiler found the symbols
+    version: String,
+    /// Workspace folders the index belongs to, for people inspecting the cache
+    roots: Vec<PathBuf>,
+    /// The indexed files, by URI
+    files: Vec<(String, IndexedFile)>,
+}
+
+/// The symbols defined by the analyzed files, keyed by normalized URI.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    /// Workspace folders and state file, once the workspace is known
+    workspace: RwLock<Option<(Vec<PathBuf>, PathBuf)>>,
+    /// The indexed files
+    files: DashMap<Uri, IndexedFile>,
+}
+
+impl SymbolIndex {
+    /// Load the index saved by a previous session on the same workspace folders.
+    ///
+    /// An index written by another version of the server is discarded, since its
+    /// compiler may find other symbols. Without workspace folders nothing is loaded and
+    /// the index isn't saved.
+    pub fn load(&self, cache_dir: &Path, roots: &[PathBuf]) -> anyhow::Result<()> {
+        if roots.is_empty() {
+            return Ok(());
+        }
+        let mut roots = roots.to_vec();
+        roots.sort();
+        let path = cache_dir
+            .join("workspaces")
+            .join(workspace_key(&roots))
+            .join(STATE_FILE);
+        *self.workspace.write().expect("symbol index lock poisoned") = Some((roots, path.clone()));
+
+        if !path.exists() {
+            return Ok(());
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let state: PersistedIndex = serde_json::from_str(&text)
+            .with_context(|| format!("invalid symbol index {}", path.display()))?;
+        if state.version != SERVER_VERSION {
+            return Ok(());
+        }
+        for (uri, file) in state.files {
+            if let Ok(uri) = uri.parse::<Uri>() {
+                self.files.entry(uri).or_insert(file);
+            }
+        }
+        Ok(())
+    }
+
+    /// Save the index for the next session on the same workspace folders.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let workspace = self
+            .workspace
+            .read()
+            .expect("symbol index lock poisoned")
+            .clone();
+        let Some((roots, path)) = workspace else {
+            return Ok(());
+        };
+        let mut files = self
+            .files
+            .iter()
+            .map(|entry| (entry.key().to_string(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let state = PersistedIndex {
+            version: SERVER_VERSION.to_string(),
+            roots,
+            files,
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let text = serde_json::to_string(&state)?;
+        std::fs::write(&path, text)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Record the symbols of an analyzed version of a file, `clean` if no diagnostics
+    /// were published for it.
+    pub fn record(&self, uri: &Uri, rope: &Rope, symbols: Vec<AnalyzedSymbol>, clean: bool) {
+        let symbols = symbols
+            .into_iter()
+            .filter(|symbol| matches!(symbol.token_type, 0 | 3 | 4))
+            .filter_map(|symbol| {
+                let name = rope.get_byte_slice(symbol.span.clone())?.to_string();
+                Some(IndexedSymbol {
+                    name,
+                    token_type: symbol.token_type,
+                    span: symbol.span,
+                })
+            })
+            .collect();
+        self.files.insert(
+            normalize_uri(uri).into_owned(),
+            IndexedFile {
+                hash: content_hash(rope),
+                clean,
+                symbols,
+            },
+        );
+    }
+
+    /// Number of indexed files.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Forget the symbols of a file.
+    pub fn remove(&self, uri: &Uri) {
+        self.files.remove(normalize_uri(uri).as_ref());
+    }
+
+    /// Move the symbols of a file to a new URI.
+    pub fn rename(&self, old_uri: &Uri, new_uri: &Uri) {
+        if let Some((_, file)) = self.files.remove(normalize_uri(old_uri).as_ref()) {
+            self.files.insert(normalize_uri(new_uri).into_owned(), file);
+        }
+    }
+
+    /// Forget the symbols of the files for which `keep` returns false.
+    pub fn retain(&self, keep: impl Fn(&Uri) -> bool) {
+        self.files.retain(|uri, _| keep(uri));
+    }
+
+    /// Whether the recorded symbols of a clean file were found in `rope`, so
+    /// that compiling it again can be skipped.
+    pub fn is_current(&self, uri: &Uri, rope: &Rope) -> bool {
+        self.files
+            .get(normalize_uri(uri).as_ref())
+            .is_some_and(|file| file.clean && file.hash == content_hash(rope))
+    }
+
+    /// The symbols whose name contains `query`, ignoring case, with their file.
+    ///
+    /// Returns at most [`MAX_WORKSPACE_SYMBOLS`] symbols, sorted by file and position.
+    pub fn search(&self, query: &str) -> Vec<(Uri, IndexedSymbol)> {
+        let query = query.to_lowercase();
+        let mut found = self
+            .files
+            .iter()
+            .flat_map(|entry| {
+                let uri = entry.key().clone();
+                entry
+                    .value()
+                    .symbols
+                    .iter()
+                    .filter(|symbol| symbol.name.to_lowercase().contains(&query))
+                    .map(|symbol| (uri.clone(), symbol.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| (a.0.as_str(), a.1.span.start).cmp(&(b.0.as_str(), b.1.span.start)));
+        found.truncate(MAX_WORKSPACE_SYMBOLS);
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(span: Range<usize>, token_type: u32) -> AnalyzedSymbol {
+        AnalyzedSymbol { span, token_type }
+    }
+
+    #[test]
+    fn index_survives_a_restart_while_the_text_is_unchanged() {
+        let cache_dir = std::env::temp_dir().join(format!("l-symbol-index-{}", std::process::id()));
+        let roots = [PathBuf::from("/workspace")];
+        let uri = "file:///workspace/a.l".parse::<Uri>().expect("valid URI");
+        let rope = Rope::from_str("struct Point { x: int }\nfn area(p: Point) {}\n");
+
+        let index = SymbolIndex::default();
+        index.load(&cache_dir, &roots).expect("nothing to load");
+        // `Point`, `x`, `area` and the parameter `p`
+        let symbols = vec![
+            symbol(7..12, 3),
+            symbol(15..16, 4),
+            symbol(27..31, 0),
+            symbol(32..33, 2),
+        ];
+        index.record(&uri, &rope, symbols, true);
+        index.save().expect("the index is saved");
+
+        let restarted = SymbolIndex::default();
+        restarted
+            .load(&cache_dir, &roots)
+            .expect("the index is loaded");
+        assert!(restarted.is_current(&uri, &rope));
+        assert!(!restarted.is_current(&uri, &Rope::from_str("fn area() {}\n")));
+        let names = restarted
+            .search("")
+            .into_iter()
+            .map(|(_, symbol)| symbol.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Point", "x", "area"]);
+        let found = restarted.search("AR");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1.kind(), SymbolKind::FUNCTION);
+
+        // Files with errors are compiled again
+        restarted.record(&uri, &rope, Vec::new(), false);
+        assert!(!restarted.is_current(&uri, &rope));
+
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn renamed_files_are_searched_under_their_new_uri() {
+        let old_uri = "file:///workspace/a.l".parse::<Uri>().expect("valid URI");
+        let new_uri = "file:///workspace/b.l".parse::<Uri>().expect("valid URI");
+        let rope = Rope::from_str("fn area() {}\n");
+        let index = SymbolIndex::default();
+        index.record(&old_uri, &rope, vec![symbol(3..7, 0)], true);
+
+        index.rename(&old_uri, &new_uri);
+        let uris = index
+            .search("area")
+            .into_iter()
+            .map(|(uri, _)| uri)
+            .collect::<Vec<_>>();
+        assert_eq!(uris, std::slice::from_ref(&new_uri));
+        assert!(!index.is_current(&old_uri, &rope));
+        assert!(index.is_current(&new_uri, &rope));
+    }
+}
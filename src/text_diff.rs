@@ -0,0 +1,285 @@
+//! Line-based diffing of document texts.
+//!
+//! Used to turn the output of the formatter into minimal text edits instead of a
+//! single edit replacing the whole document, and to render a unified diff of what
+//! formatting would change for `l.previewFormat`.
+
+use std::ops::Range;
+
+use tower_lsp_server::ls_types::{self, Position, TextEdit};
+
+use crate::text_pos::Encoding;
+
+/// Name of the command that previews the changes formatting would make.
+pub const PREVIEW_FORMAT_COMMAND: &str = "l.previewFormat";
+
+/// Edit distance above which the changed region is reported as a single hunk.
+///
+/// Bounds the time and memory spent on texts that differ almost everywhere.
+const MAX_EDIT_DISTANCE: usize = 2000;
+
+/// Number of unchanged lines shown around each change in a unified diff.
+const CONTEXT_LINES: usize = 3;
+
+/// A region of consecutive changed lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// The replaced lines of the old text
+    pub old: Range<usize>,
+    /// The replacement lines of the new text
+    pub new: Range<usize>,
+}
+
+/// A single step of an edit script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// The line is in both texts
+    Equal,
+    /// The line is only in the old text
+    Delete,
+    /// The line is only in the new text
+    Insert,
+}
+
+/// Split a text into lines, keeping their line terminators.
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Compute the changed regions between two lists of lines.
+fn diff(old: &[&str], new: &[&str]) -> Vec<Hunk> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    if old_middle.is_empty() && new_middle.is_empty() {
+        return Vec::new();
+    }
+
+    let Some(ops) = edit_script(old_middle, new_middle) else {
+        return vec![Hunk {
+            old: prefix..old.len() - suffix,
+            new: prefix..new.len() - suffix,
+        }];
+    };
+
+    let mut hunks = Vec::new();
+    let (mut old_line, mut new_line) = (prefix, prefix);
+    let mut current: Option<Hunk> = None;
+    for op in ops {
+        match op {
+            Op::Equal => {
+                hunks.extend(current.take());
+                old_line += 1;
+                new_line += 1;
+            }
+            Op::Delete => {
+                current
+                    .get_or_insert(Hunk {
+                        old: old_line..old_line,
+                        new: new_line..new_line,
+                    })
+                    .old
+                    .end += 1;
+                old_line += 1;
+            }
+            Op::Insert => {
+                current
+                    .get_or_insert(Hunk {
+                        old: old_line..old_line,
+                        new: new_line..new_line,
+                    })
+                    .new
+                    .end += 1;
+                new_line += 1;
+            }
+        }
+    }
+    hunks.extend(current);
+    hunks
+}
+
+/// Compute a shortest edit script with Myers' algorithm.
+///
+/// Returns `None` if the edit distance exceeds [`MAX_EDIT_DISTANCE`].
+fn edit_script(old: &[&str], new: &[&str]) -> Option<Vec<Op>> {
+    let n = isize::try_from(old.len()).ok()?;
+    let m = isize::try_from(new.len()).ok()?;
+    let max = usize::try_from(n + m).ok()?.min(MAX_EDIT_DISTANCE);
+    let offset = isize::try_from(max).ok()? + 1;
+    let index = |k: isize| usize::try_from(k + offset).expect("diagonal out of range");
+
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = Vec::new();
+    for d in 0..=isize::try_from(max).ok()? {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m, index));
+            }
+        }
+    }
+    None
+}
+
+/// Walk the recorded search back from the end to recover the edit script.
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize, index: impl Fn(isize) -> usize) -> Vec<Op> {
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = isize::try_from(d).expect("edit distance out of range");
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[index(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            ops.push(if x == prev_x { Op::Insert } else { Op::Delete });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    ops
+}
+
+/// The position right after the last character of a text.
+fn end_position(text: &str, encoding: Encoding) -> Position {
+    let line = text.matches('\n').count();
+    let column = text
+        .rsplit('\n')
+        .next()
+        .map_or(0, |last| encoding.text_units(last));
+    Position::new(
+        u32::try_from(line).expect("line out of range"),
+        u32::try_from(column).expect("column out of range"),
+    )
+}
+
+/// Compute the minimal line-based text edits turning `old` into `new`, with positions
+/// counted in `encoding`.
+pub fn text_edits(old: &str, new: &str, encoding: Encoding) -> Vec<TextEdit> {
+    let old_lines = lines(old);
+    let new_lines = lines(new);
+    let position = |line: usize| {
+        if line < old_lines.len() {
+            Position::new(u32::try_from(line).expect("line out of range"), 0)
+        } else {
+            end_position(old, encoding)
+        }
+    };
+
+    diff(&old_lines, &new_lines)
+        .into_iter()
+        .map(|hunk| TextEdit {
+            range: ls_types::Range::new(position(hunk.old.start), position(hunk.old.end)),
+            new_text: new_lines[hunk.new].concat(),
+        })
+        .collect()
+}
+
+/// Render a unified diff of the changes turning `old` into `new`.
+///
+/// Returns an empty string if the texts are equal.
+pub fn unified_diff(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    let old_lines = lines(old);
+    let new_lines = lines(new);
+    let hunks = diff(&old_lines, &new_lines);
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut output = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut groups: Vec<Vec<Hunk>> = Vec::new();
+    for hunk in hunks {
+        match groups.last_mut() {
+            Some(group)
+                if hunk.old.start
+                    <= group.last().expect("groups are never empty").old.end
+                        + 2 * CONTEXT_LINES =>
+            {
+                group.push(hunk);
+            }
+            _ => groups.push(vec![hunk]),
+        }
+    }
+
+    for group in groups {
+        let first = &group[0];
+        let last = &group[group.len() - 1];
+        let old_start = first.old.start.saturating_sub(CONTEXT_LINES);
+        let old_end = (last.old.end + CONTEXT_LINES).min(old_lines.len());
+        let new_start = first.new.start - (first.old.start - old_start);
+        let new_end = last.new.end + (old_end - last.old.end);
+        output.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_end),
+            hunk_range(new_start, new_end)
+        ));
+
+        let mut old_line = old_start;
+        for hunk in &group {
+            for line in &old_lines[old_line..hunk.old.start] {
+                push_line(&mut output, ' ', line);
+            }
+            for line in &old_lines[hunk.old.clone()] {
+                push_line(&mut output, '-', line);
+            }
+            for line in &new_lines[hunk.new.clone()] {
+                push_line(&mut output, '+', line);
+            }
+            old_line = hunk.old.end;
+        }
+        for line in &old_lines[old_line..old_end] {
+            push_line(&mut output, ' ', line);
+        }
+    }
+    output
+}
+
+/// Format the line range of a hunk header, e.g. `3,4` for lines 3 to 6.
+fn hunk_range(start: usize, end: usize) -> String {
+    match end - start {
+        // An empty range is anchored at the line before it
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        len => format!("{},{len}", start + 1),
+    }
+}
+
+/// Append a diff line, marking a missing line terminator.
+fn push_line(output: &mut String, marker: char, line: &str) {
+    output.push(marker);
+    output.push_str(line);
+    if !line.ends_with('\n') {
+        output.push_str("\n\\ No newline at end of file\n");
+    }
+}
@@ -0,0 +1,339 @@
+//! Conversions between LSP positions and byte offsets of a document.
+//!
+//! Every conversion states what happens at the edges of the document through
+//! [`Bounds`]. Handlers pick the mode matching where the position comes from:
+//!
+//! - Cursor positions and viewport ranges sent by the client use [`Bounds::Clamp`], as
+//!   a cursor after trailing whitespace the client trimmed, or a viewport reaching past
+//!   the last line, still designates the end of that line or of the document. This
+//!   covers hover, definition, references, rename, completion, signature help, code
+//!   actions, inlay hints and range semantic tokens.
+//! - Edits use [`Bounds::Strict`], since an edit outside the document means the client
+//!   and the server disagree about its content, and applying it elsewhere would corrupt
+//!   it. The `l/scopes` range is strict too, and rejected when it is out of bounds.
+//! - Spans of the analysis are converted with [`Bounds::Strict`]; they come from the
+//!   same text, so one that doesn't fit is dropped rather than moved.
+//!
+//! Characters are counted in the [`Encoding`] agreed on with the client: UTF-16 code
+//! units, the default of the protocol, or Unicode scalar values. A position inside a
+//! surrogate pair is handled like an offset inside a character. A line ends before its
+//! line terminator. Like in the protocol, lines end at `\n`, `\r\n` and `\r` only, and an
+//! offset between the `\r` and the `\n` of a terminator is handled like an offset inside
+//! a character.
+
+use ropey::Rope;
+use tower_lsp_server::ls_types::{Position, Range};
+
+/// How positions and offsets outside of a document are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bounds {
+    /// Move them to the closest point of the document
+    Clamp,
+    /// Reject them
+    Strict,
+}
+
+/// The unit the character offsets of positions are counted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-16 code units, which every client supports
+    Utf16,
+    /// Unicode scalar values
+    Utf32,
+}
+
+impl Encoding {
+    /// Number of units from the start of a document to a char index.
+    pub fn units(self, rope: &Rope, char_index: usize) -> usize {
+        match self {
+            Self::Utf16 => rope.char_to_utf16_cu(char_index),
+            Self::Utf32 => char_index,
+        }
+    }
+
+    /// Number of units of a text.
+    pub fn text_units(self, text: &str) -> usize {
+        match self {
+            Self::Utf16 => text.encode_utf16().count(),
+            Self::Utf32 => text.chars().count(),
+        }
+    }
+
+    /// Char index at a number of units from the start of a document, or of the
+    /// character containing it.
+    fn char_index(self, rope: &Rope, units: usize) -> usize {
+        match self {
+            Self::Utf16 => rope.utf16_cu_to_char(units),
+            Self::Utf32 => units,
+        }
+    }
+}
+
+/// Converter between the positions and byte offsets of a document.
+#[derive(Debug, Clone, Copy)]
+pub struct TextPos<'a> {
+    /// The document text
+    rope: &'a Rope,
+    /// The unit characters are counted in
+    encoding: Encoding,
+    /// What happens to positions and offsets outside of the document
+    bounds: Bounds,
+}
+
+impl<'a> TextPos<'a> {
+    /// Create a converter for a document.
+    pub fn new(rope: &'a Rope, encoding: Encoding, bounds: Bounds) -> Self {
+        Self {
+            rope,
+            encoding,
+            bounds,
+        }
+    }
+
+    /// Convert a position to a byte offset.
+    ///
+    /// A character past the end of its line, or a line past the end of the document,
+    /// is clamped to the end of the line or document, or rejected in strict mode. So is
+    /// a character inside a surrogate pair, clamped to the start of the pair.
+    pub fn offset(&self, position: Position) -> Option<usize> {
+        let line = position.line as usize;
+        if line >= self.rope.len_lines() {
+            return match self.bounds {
+                Bounds::Clamp => Some(self.rope.len_bytes()),
+                Bounds::Strict => None,
+            };
+        }
+        let line_start = self.encoding.units(self.rope, self.rope.line_to_char(line));
+        let len = line_units(self.rope, line, self.encoding);
+        let character = position.character as usize;
+        if character > len && self.bounds == Bounds::Strict {
+            return None;
+        }
+        let units = line_start + character.min(len);
+        let char_offset = self.encoding.char_index(self.rope, units);
+        if self.bounds == Bounds::Strict && self.encoding.units(self.rope, char_offset) != units {
+            return None;
+        }
+        Some(self.rope.char_to_byte(char_offset))
+    }
+
+    /// Convert a byte offset to a position.
+    ///
+    /// An offset past the end of the document, inside a character or inside a `\r\n`
+    /// terminator, is clamped to the end of the document, the start of the character or
+    /// the end of the line, or rejected in strict mode.
+    pub fn position(&self, offset: usize) -> Option<Position> {
+        let len = self.rope.len_bytes();
+        if self.bounds == Bounds::Strict
+            && (offset > len || self.rope.char_to_byte(self.rope.byte_to_char(offset)) != offset)
+        {
+            return None;
+        }
+        let char_offset = self.rope.byte_to_char(offset.min(len));
+        let line = self.rope.char_to_line(char_offset);
+        let line_len = line_units(self.rope, line, self.encoding);
+        let column = self.encoding.units(self.rope, char_offset)
+            - self.encoding.units(self.rope, self.rope.line_to_char(line));
+        // Only an offset between `\r` and `\n` lies past the end of its line
+        if column > line_len && self.bounds == Bounds::Strict {
+            return None;
+        }
+        let column = column.min(line_len);
+        Some(Position::new(
+            u32::try_from(line).ok()?,
+            u32::try_from(column).ok()?,
+        ))
+    }
+
+    /// Convert a range to byte offsets, see [`Self::offset`].
+    ///
+    /// Returns `None` if the range ends before it starts.
+    pub fn offsets(&self, range: Range) -> Option<std::ops::Range<usize>> {
+        let start = self.offset(range.start)?;
+        let end = self.offset(range.end)?;
+        (start <= end).then_some(start..end)
+    }
+
+    /// Convert byte offsets to a range, see [`Self::position`].
+    pub fn range(&self, span: std::ops::Range<usize>) -> Option<Range> {
+        Some(Range::new(
+            self.position(span.start)?,
+            self.position(span.end)?,
+        ))
+    }
+}
+
+/// Count the units of a line in an encoding, excluding its terminator.
+pub fn line_units(rope: &Rope, line: usize, encoding: Encoding) -> usize {
+    let start = rope.line_to_char(line);
+    encoding.units(rope, start + line_len(rope, line)) - encoding.units(rope, start)
+}
+
+/// Count the characters of a line, excluding its terminator.
+pub fn line_len(rope: &Rope, line: usize) -> usize {
+    let text = rope.line(line);
+    let len = text.len_chars();
+    let terminator = match (
+        len.checked_sub(2).map(|i| text.char(i)),
+        len.checked_sub(1).map(|i| text.char(i)),
+    ) {
+        (Some('\r'), Some('\n')) => 2,
+        (_, Some('\n' | '\r')) => 1,
+        _ => 0,
+    };
+    len - terminator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn position(line: u32, character: u32) -> Position {
+        Position::new(line, character)
+    }
+
+    #[test]
+    fn offsets_within_the_document_are_the_same_in_both_modes() {
+        let rope = Rope::from_str("let é = 1;\r\nfn f() {}\n");
+        for bounds in [Bounds::Clamp, Bounds::Strict] {
+            let pos = TextPos::new(&rope, Encoding::Utf32, bounds);
+            assert_eq!(pos.offset(position(0, 5)), Some(6));
+            assert_eq!(pos.offset(position(0, 10)), Some(11));
+            assert_eq!(pos.offset(position(1, 3)), Some(16));
+            assert_eq!(pos.position(16), Some(position(1, 3)));
+            assert_eq!(pos.position(rope.len_bytes()), Some(position(2, 0)));
+        }
+    }
+
+    #[test]
+    fn clamp_moves_positions_to_the_end_of_the_line_or_document() {
+        let rope = Rope::from_str("let a = 1;\r\nlet b = 2;");
+        let pos = TextPos::new(&rope, Encoding::Utf32, Bounds::Clamp);
+        assert_eq!(pos.offset(position(0, 80)), Some(10));
+        assert_eq!(pos.offset(position(1, 80)), Some(rope.len_bytes()));
+        assert_eq!(pos.offset(position(7, 0)), Some(rope.len_bytes()));
+        assert_eq!(pos.position(500), Some(position(1, 10)));
+    }
+
+    #[test]
+    fn strict_rejects_positions_outside_of_the_document() {
+        let rope = Rope::from_str("let é = 1;\nlet b = 2;");
+        let pos = TextPos::new(&rope, Encoding::Utf32, Bounds::Strict);
+        assert_eq!(pos.offset(position(0, 11)), None);
+        assert_eq!(pos.offset(position(2, 0)), None);
+        assert_eq!(pos.position(rope.len_bytes() + 1), None);
+        // The second byte of `é`
+        assert_eq!(pos.position(5), None);
+    }
+
+    #[test]
+    fn utf16_counts_astral_characters_twice() {
+        // `🎉` is two UTF-16 code units and four bytes
+        let rope = Rope::from_str("a🎉 = b;");
+        for bounds in [Bounds::Clamp, Bounds::Strict] {
+            let utf16 = TextPos::new(&rope, Encoding::Utf16, bounds);
+            assert_eq!(utf16.position(5), Some(position(0, 3)));
+            assert_eq!(utf16.offset(position(0, 3)), Some(5));
+            assert_eq!(utf16.offset(position(0, 8)), Some(10));
+            let utf32 = TextPos::new(&rope, Encoding::Utf32, bounds);
+            assert_eq!(utf32.position(5), Some(position(0, 2)));
+        }
+        // Inside the surrogate pair
+        let strict = TextPos::new(&rope, Encoding::Utf16, Bounds::Strict);
+        assert_eq!(strict.offset(position(0, 2)), None);
+        let clamp = TextPos::new(&rope, Encoding::Utf16, Bounds::Clamp);
+        assert_eq!(clamp.offset(position(0, 2)), Some(1));
+    }
+
+    #[test]
+    fn ranges_must_not_end_before_they_start() {
+        let rope = Rope::from_str("let a = 1;");
+        let pos = TextPos::new(&rope, Encoding::Utf32, Bounds::Clamp);
+        assert_eq!(
+            pos.offsets(Range::new(position(0, 8), position(0, 4))),
+            None
+        );
+        assert_eq!(
+            pos.offsets(Range::new(position(0, 4), position(0, 80))),
+            Some(4..10)
+        );
+        assert_eq!(
+            pos.range(4..5),
+            Some(Range::new(position(0, 4), position(0, 5)))
+        );
+    }
+
+    /// Documents made of ASCII, multibyte and astral characters, the line terminators
+    /// of the protocol, and Unicode separators which don't end lines in it.
+    fn document() -> impl Strategy<Value = String> {
+        const PIECES: &[&str] = &[
+            "a", " ", "é", "漢", "🎉", "\n", "\r\n", "\r", "\u{2028}", "\u{85}",
+        ];
+        prop::collection::vec(prop::sample::select(PIECES), 0..48)
+            .prop_map(|pieces| pieces.concat())
+    }
+
+    /// The position of every character boundary of `text`, except the one between the
+    /// `\r` and `\n` of a terminator, computed the way the protocol defines lines.
+    fn expected_positions(text: &str, encoding: Encoding) -> Vec<(usize, Position)> {
+        let mut positions = Vec::new();
+        let (mut line, mut character) = (0, 0);
+        let mut chars = text.char_indices().peekable();
+        while let Some((offset, c)) = chars.next() {
+            positions.push((offset, position(line, character)));
+            match c {
+                '\r' if chars.peek().is_some_and(|(_, next)| *next == '\n') => {
+                    chars.next();
+                    (line, character) = (line + 1, 0);
+                }
+                '\n' | '\r' => (line, character) = (line + 1, 0),
+                _ if encoding == Encoding::Utf16 => {
+                    character += u32::try_from(c.len_utf16()).unwrap_or(2);
+                }
+                _ => character += 1,
+            }
+        }
+        positions.push((text.len(), position(line, character)));
+        positions
+    }
+
+    proptest! {
+        #[test]
+        fn offsets_round_trip_through_positions(text in document()) {
+            let rope = Rope::from_str(&text);
+            for encoding in [Encoding::Utf16, Encoding::Utf32] {
+                for (offset, expected) in expected_positions(&text, encoding) {
+                    for bounds in [Bounds::Clamp, Bounds::Strict] {
+                        let pos = TextPos::new(&rope, encoding, bounds);
+                        prop_assert_eq!(pos.position(offset), Some(expected));
+                        prop_assert_eq!(pos.offset(expected), Some(offset));
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn offsets_between_boundaries_are_clamped_or_rejected(text in document()) {
+            let rope = Rope::from_str(&text);
+            for encoding in [Encoding::Utf16, Encoding::Utf32] {
+                let boundaries = expected_positions(&text, encoding);
+                for offset in 0..=text.len() {
+                    if boundaries.iter().any(|(boundary, _)| *boundary == offset) {
+                        continue;
+                    }
+                    let strict = TextPos::new(&rope, encoding, Bounds::Strict);
+                    prop_assert_eq!(strict.position(offset), None);
+                    // Clamped to the start of the character or the end of the line
+                    let clamped = TextPos::new(&rope, encoding, Bounds::Clamp).position(offset);
+                    let before = boundaries
+                        .iter()
+                        .rev()
+                        .find(|(boundary, _)| *boundary < offset)
+                        .map(|(_, position)| *position);
+                    prop_assert_eq!(clamped, before);
+                }
+            }
+        }
+    }
+}
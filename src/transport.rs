@@ -0,0 +1,61 @@
+//! The connection the server speaks LSP over.
+//!
+//! The server uses stdin and stdout by default. It can also wait for a single TCP
+//! connection instead, so editors on another machine can connect to it and the traffic
+//! can be inspected with socket tools while debugging, and serve that connection over
+//! WebSocket for browser-based editors. See [`crate::cli`] for the options choosing one.
+
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::info;
+
+use crate::websocket;
+
+/// The connection to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transport {
+    /// Messages are read from stdin and written to stdout
+    #[default]
+    Stdio,
+    /// Messages are exchanged with the first client connecting to the address
+    Tcp(SocketAddr),
+    /// Messages are exchanged over a WebSocket with the first client connecting to the
+    /// address
+    WebSocket(SocketAddr),
+}
+
+/// Reading half of a connection.
+pub type Input = Box<dyn AsyncRead + Unpin + Send>;
+
+/// Writing half of a connection.
+pub type Output = Box<dyn AsyncWrite + Unpin + Send>;
+
+impl Transport {
+    /// Open the connection, waiting for the client to connect over TCP or WebSocket.
+    pub async fn connect(self) -> std::io::Result<(Input, Output)> {
+        match self {
+            Self::Stdio => Ok((Box::new(tokio::io::stdin()), Box::new(tokio::io::stdout()))),
+            Self::Tcp(address) => {
+                let (input, output) = accept(address).await?.into_split();
+                Ok((Box::new(input), Box::new(output)))
+            }
+            Self::WebSocket(address) => {
+                let stream = websocket::accept(accept(address).await?).await?;
+                info!("WebSocket handshake completed");
+                let (input, output) = tokio::io::split(stream);
+                Ok((Box::new(input), Box::new(output)))
+            }
+        }
+    }
+}
+
+/// Wait for the first client connecting to an address.
+async fn accept(address: SocketAddr) -> std::io::Result<TcpStream> {
+    let listener = TcpListener::bind(address).await?;
+    info!("Listening on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept().await?;
+    info!("Client connected from {peer}");
+    Ok(stream)
+}
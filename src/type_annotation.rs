@@ -0,0 +1,103 @@
+//! Explicit type annotations for variables and functions whose type is inferred.
+//!
+//! The annotation shown by a type inlay hint can be written into the source, either by
+//! accepting the hint, which carries it as a text edit, or, for variables, through the
+//! `refactor.rewrite` code action on the variable. Variable and return type hints are
+//! turned on and off separately with the `variableTypeHints` and `returnTypeHints`
+//! settings.
+
+use l_lang::{SymbolId, SymbolKind, Type};
+use serde_json::Value;
+
+use crate::document_store::Document;
+use crate::settings::section;
+
+/// An annotation to insert after the name of a variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeAnnotation {
+    /// Byte offset the annotation is inserted at, the end of the variable name
+    pub insert_at: usize,
+    /// Text of the annotation, such as `: int`
+    pub text: String,
+}
+
+/// Build the annotation a variable lacks.
+///
+/// Returns `None` for symbols other than variables, variables that are already
+/// annotated and variables whose type the semantic analysis couldn't infer.
+pub fn missing_annotation(document: &Document, symbol: SymbolId) -> Option<TypeAnnotation> {
+    let semantic = &document.analysis.semantic;
+    if semantic.get_symbol_kind(symbol) != SymbolKind::Variable
+        || matches!(semantic.get_symbol_type(symbol)?.ty, Type::Unknown)
+    {
+        return None;
+    }
+    let insert_at = semantic.get_symbol_span(symbol).end as usize;
+    let annotated = document
+        .rope
+        .get_byte_slice(insert_at..)?
+        .chars()
+        .find(|c| !c.is_whitespace())
+        == Some(':');
+    if annotated {
+        return None;
+    }
+    Some(TypeAnnotation {
+        insert_at,
+        text: format!(": {}", document.type_label(symbol)?),
+    })
+}
+
+/// Build the return type annotation a function lacks, inserted after its parameters.
+///
+/// Returns `None` for symbols other than functions, functions whose return type is
+/// annotated, and functions returning nothing or a type the analysis couldn't infer.
+pub fn missing_return_type(document: &Document, symbol: SymbolId) -> Option<TypeAnnotation> {
+    let semantic = &document.analysis.semantic;
+    if semantic.get_symbol_kind(symbol) != SymbolKind::Function
+        || matches!(
+            semantic.get_symbol_type(symbol)?.ty,
+            Type::Unit | Type::Unknown
+        )
+    {
+        return None;
+    }
+    let name_end = semantic.get_symbol_span(symbol).end as usize;
+    let text = document.rope.get_byte_slice(name_end..)?.to_string();
+    let after_name = text.trim_start();
+    if !after_name.starts_with('(') {
+        return None;
+    }
+    let open = text.len() - after_name.len();
+    let mut depth = 0usize;
+    let close = open
+        + text[open..].find(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        })?;
+    if text[close + 1..].trim_start().starts_with("->") {
+        return None;
+    }
+    Some(TypeAnnotation {
+        insert_at: name_end + close + 1,
+        text: format!(" -> {}", document.type_label(symbol)?),
+    })
+}
+
+/// Read whether variables get type hints from a settings object.
+pub fn variable_type_hints_from_settings(settings: &Value) -> Option<bool> {
+    section(settings)
+        .get("variableTypeHints")
+        .and_then(Value::as_bool)
+}
+
+/// Read whether functions get return type hints from a settings object.
+pub fn return_type_hints_from_settings(settings: &Value) -> Option<bool> {
+    section(settings)
+        .get("returnTypeHints")
+        .and_then(Value::as_bool)
+}
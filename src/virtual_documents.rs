@@ -0,0 +1,63 @@
+//! In-memory documents generated by the server, such as AST dumps.
+//!
+//! Virtual documents live under server-specific URI schemes (`l-ast:`) and have no
+//! file on disk. The server opens them through `window/showDocument`; the client then
+//! fetches their content with the `l/virtualDocument` request from a text document
+//! content provider registered for the scheme.
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tower_lsp_server::ls_types::Uri;
+
+/// URI scheme of the documents holding a pretty-printed AST.
+pub const AST_SCHEME: &str = "l-ast";
+
+/// Name of the custom request returning the content of a virtual document.
+pub const VIRTUAL_DOCUMENT_METHOD: &str = "l/virtualDocument";
+
+/// Parameters of the `l/virtualDocument` request.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VirtualDocumentParams {
+    /// The URI of the virtual document
+    pub uri: Uri,
+}
+
+/// Thread-safe map of virtual document URIs to their content.
+#[derive(Debug, Default)]
+pub struct VirtualDocuments {
+    /// Maps virtual document URIs to their content
+    documents: DashMap<String, String>,
+}
+
+impl VirtualDocuments {
+    /// The content of the virtual document with the given URI.
+    pub fn get(&self, uri: &str) -> Option<String> {
+        self.documents.get(uri).map(|content| content.clone())
+    }
+
+    /// Insert or replace the virtual document with the given URI.
+    pub fn insert(&self, uri: String, content: String) {
+        self.documents.insert(uri, content);
+    }
+
+    /// Remove the virtual document with the given URI.
+    pub fn remove(&self, uri: &str) {
+        self.documents.remove(uri);
+    }
+
+    /// Remove all virtual documents.
+    pub fn clear(&self) {
+        self.documents.clear();
+    }
+}
+
+/// The URI of the virtual document holding the AST of a source document.
+///
+/// `file:///path/to/main.l` maps to `l-ast:/path/to/main.l.ast`, so editors show the
+/// source file name in the tab title.
+pub fn ast_uri(uri: &str) -> String {
+    let path = uri
+        .split_once(':')
+        .map_or(uri, |(_, rest)| rest.trim_start_matches("//"));
+    format!("{AST_SCHEME}:{path}.ast")
+}
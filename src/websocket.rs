@@ -0,0 +1,123 @@
+//! LSP over WebSocket, for browser-based editors.
+//!
+//! Browser clients such as Monaco or CodeMirror can't spawn the server or open a raw
+//! socket, but they can open a WebSocket. Each WebSocket message carries one JSON-RPC
+//! message, without the `Content-Length` header of the base protocol, so the
+//! connection is bridged to an in-memory stream speaking the base protocol, which the
+//! LSP server reads and writes like stdio.
+
+use std::io;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream,
+};
+use tokio::net::TcpStream;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::debug;
+
+/// Size of the buffer between the WebSocket and the LSP server.
+pub const BRIDGE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Accept the WebSocket handshake of a client, and return the stream the LSP server
+/// is served over.
+pub async fn accept(stream: TcpStream) -> io::Result<DuplexStream> {
+    let websocket = tokio_tungstenite::accept_async(stream)
+        .await
+        .map_err(io::Error::other)?;
+    let (server_side, bridge_side) = tokio::io::duplex(BRIDGE_BUFFER_SIZE);
+    tokio::spawn(bridge(websocket, bridge_side));
+    Ok(server_side)
+}
+
+/// Forward messages between a WebSocket and the LSP server until either side closes.
+async fn bridge(websocket: WebSocketStream<TcpStream>, connection: DuplexStream) {
+    let (mut sink, mut stream) = websocket.split();
+    let (reader, mut writer) = tokio::io::split(connection);
+
+    let incoming = async {
+        while let Some(message) = stream.next().await {
+            let body = match message.map_err(io::Error::other)? {
+                Message::Text(text) => text.as_str().to_string(),
+                Message::Binary(bytes) => {
+                    String::from_utf8(bytes.to_vec()).map_err(io::Error::other)?
+                }
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            writer.write_all(frame(&body).as_bytes()).await?;
+        }
+        writer.shutdown().await
+    };
+
+    let outgoing = async {
+        let mut reader = BufReader::new(reader);
+        while let Some(body) = read_message(&mut reader).await? {
+            sink.send(Message::text(body))
+                .await
+                .map_err(io::Error::other)?;
+        }
+        sink.close().await.map_err(io::Error::other)
+    };
+
+    let (incoming, outgoing) = tokio::join!(incoming, outgoing);
+    if let Err(err) = incoming.and(outgoing) {
+        debug!("WebSocket connection closed: {err}");
+    }
+}
+
+/// Frame a JSON-RPC message for the base protocol.
+fn frame(body: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+/// Read the body of the next message framed for the base protocol, `None` at the end
+/// of the stream.
+async fn read_message(reader: &mut (impl AsyncBufRead + Unpin)) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = Some(value.trim().parse().map_err(io::Error::other)?);
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "message without Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    String::from_utf8(body).map(Some).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn framed_messages_are_read_back() {
+        let first = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+        let second = r#"{"jsonrpc":"2.0","id":1,"result":"é"}"#;
+        let framed = frame(first) + "Content-Type: application/vscode-jsonrpc\r\n" + &frame(second);
+        let mut reader = framed.as_bytes();
+        assert_eq!(
+            read_message(&mut reader).await.ok().flatten().as_deref(),
+            Some(first)
+        );
+        assert_eq!(
+            read_message(&mut reader).await.ok().flatten().as_deref(),
+            Some(second)
+        );
+        assert_eq!(read_message(&mut reader).await.ok(), Some(None));
+        assert!(read_message(&mut "\r\n{}".as_bytes()).await.is_err());
+    }
+}
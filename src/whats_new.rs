@@ -0,0 +1,224 @@
+//! Discovery of what changed since the last server version that ran.
+//!
+//! On every start the server describes what it offers as a [`CapabilitySet`]: the
+//! capabilities announced in the `initialize` result, the commands it serves and the
+//! settings it reads. The set is stored in the cache directory. When a newer binary
+//! starts, it compares its set with the stored one, sends the additions to the client
+//! in an `l/serverUpgraded` notification and keeps them for the `l.whatsNew` command,
+//! also in later sessions, until the next upgrade.
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp_server::ls_types::ServerCapabilities;
+use tower_lsp_server::ls_types::notification::Notification;
+
+use crate::server_meta::SERVER_VERSION;
+
+/// Name of the command listing what the last upgrade added.
+pub const WHATS_NEW_COMMAND: &str = "l.whatsNew";
+
+/// Name of the file the capability set of the last version that ran is stored in.
+const STATE_FILE: &str = "capabilities.json";
+
+/// Everything a server version offers, as stable strings that can be compared.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitySet {
+    /// Version of the server
+    pub version: String,
+    /// Paths of the announced capabilities, such as `renameProvider` or
+    /// `codeActionProvider.codeActionKinds=quickfix`
+    pub capabilities: BTreeSet<String>,
+    /// Names of the commands served through `workspace/executeCommand`
+    pub commands: BTreeSet<String>,
+    /// Keys of the settings the server reads
+    pub settings: BTreeSet<String>,
+}
+
+impl CapabilitySet {
+    /// Describe the running server version.
+    pub fn current(
+        capabilities: &ServerCapabilities,
+        commands: &[&str],
+        settings: &[&str],
+    ) -> Self {
+        let mut paths = BTreeSet::new();
+        if let Ok(capabilities) = serde_json::to_value(capabilities) {
+            collect_paths(&capabilities, "", &mut paths);
+        }
+        Self {
+            version: SERVER_VERSION.to_string(),
+            capabilities: paths,
+            commands: commands.iter().map(|name| (*name).to_string()).collect(),
+            settings: settings.iter().map(|key| (*key).to_string()).collect(),
+        }
+    }
+
+    /// List what this set offers that an older one doesn't.
+    pub fn additions_since(&self, previous: &Self) -> WhatsNew {
+        let added = |current: &BTreeSet<String>, previous: &BTreeSet<String>| {
+            current.difference(previous).cloned().collect()
+        };
+        WhatsNew {
+            previous_version: previous.version.clone(),
+            version: self.version.clone(),
+            capabilities: added(&self.capabilities, &previous.capabilities),
+            commands: added(&self.commands, &previous.commands),
+            settings: added(&self.settings, &previous.settings),
+        }
+    }
+}
+
+/// Collect the paths of the non-null values of a serialized capabilities object.
+///
+/// Objects contribute the paths of their fields and arrays the values of their string
+/// elements, so that new code action kinds or trigger characters are listed too.
+fn collect_paths(value: &Value, path: &str, paths: &mut BTreeSet<String>) {
+    match value {
+        Value::Null => {}
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                collect_paths(value, &path, paths);
+            }
+        }
+        Value::Array(items) => {
+            paths.insert(path.to_string());
+            for item in items {
+                if let Some(item) = item.as_str() {
+                    paths.insert(format!("{path}={item}"));
+                }
+            }
+        }
+        _ => {
+            paths.insert(path.to_string());
+        }
+    }
+}
+
+/// What an upgrade added, the result of `l.whatsNew`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhatsNew {
+    /// Version of the server that ran before the upgrade
+    pub previous_version: String,
+    /// Version of the server after the upgrade
+    pub version: String,
+    /// Newly announced capabilities
+    pub capabilities: Vec<String>,
+    /// Newly served commands
+    pub commands: Vec<String>,
+    /// Newly read settings
+    pub settings: Vec<String>,
+}
+
+/// Custom notification announcing an upgrade of the server.
+#[derive(Debug)]
+pub enum ServerUpgraded {}
+
+impl Notification for ServerUpgraded {
+    type Params = WhatsNew;
+    const METHOD: &'static str = "l/serverUpgraded";
+}
+
+/// Contents of the state file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedCapabilities {
+    /// Capability set of the newest version that ran
+    current: CapabilitySet,
+    /// What the upgrade to that version added, if it was an upgrade
+    last_upgrade: Option<WhatsNew>,
+}
+
+/// What the last upgrade added, as far as known to this session.
+#[derive(Debug, Default)]
+pub struct Upgrades {
+    /// What the last upgrade added, if the server was ever upgraded
+    last_upgrade: RwLock<Option<WhatsNew>>,
+    /// Whether the upgrade happened in this session, so the client wasn't told yet
+    fresh: AtomicBool,
+}
+
+impl Upgrades {
+    /// Compare the running version with the one that ran before and store it.
+    ///
+    /// Older binaries don't overwrite the state of newer ones, so switching back and
+    /// forth between versions doesn't report the same additions again.
+    pub fn record(&self, cache_dir: &Path, current: CapabilitySet) -> anyhow::Result<()> {
+        let path = cache_dir.join(STATE_FILE);
+        let last_upgrade = match read_state(&path)? {
+            None => None,
+            Some(previous) => match compare_versions(&current.version, &previous.current.version) {
+                Ordering::Less => return Ok(()),
+                Ordering::Equal => previous.last_upgrade,
+                Ordering::Greater => {
+                    self.fresh.store(true, AtomicOrdering::Relaxed);
+                    Some(current.additions_since(&previous.current))
+                }
+            },
+        };
+        *self.last_upgrade.write().expect("upgrades lock poisoned") = last_upgrade.clone();
+
+        let state = PersistedCapabilities {
+            current,
+            last_upgrade,
+        };
+        std::fs::create_dir_all(cache_dir)
+            .with_context(|| format!("failed to create {}", cache_dir.display()))?;
+        std::fs::write(&path, serde_json::to_string_pretty(&state)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// What the last upgrade added, if the server was ever upgraded.
+    pub fn last_upgrade(&self) -> Option<WhatsNew> {
+        self.last_upgrade
+            .read()
+            .expect("upgrades lock poisoned")
+            .clone()
+    }
+
+    /// Take what an upgrade in this session added, to announce it once.
+    pub fn take_fresh(&self) -> Option<WhatsNew> {
+        if !self.fresh.swap(false, AtomicOrdering::Relaxed) {
+            return None;
+        }
+        self.last_upgrade()
+    }
+}
+
+/// Read the state file, if it exists.
+fn read_state(path: &Path) -> anyhow::Result<Option<PersistedCapabilities>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let state = serde_json::from_str(&text)
+        .with_context(|| format!("invalid capability state file {}", path.display()))?;
+    Ok(Some(state))
+}
+
+/// Compare dotted version numbers, such as `0.2.0` and `0.10.1`, numerically.
+///
+/// Components that aren't numbers, such as pre-release tags, compare as zero.
+fn compare_versions(left: &str, right: &str) -> Ordering {
+    let components = |version: &str| {
+        version
+            .split(['.', '-', '+'])
+            .map(|component| component.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    };
+    components(left).cmp(&components(right))
+}
@@ -0,0 +1,397 @@
+//! Construction of workspace edits touching several documents.
+//!
+//! [`WorkspaceEditBuilder`] collects text edits per document and file operations in
+//! the order they are added, and checks the edits of each document against each other
+//! before producing the [`WorkspaceEdit`]: overlapping edits are rejected, as clients
+//! apply them in unspecified ways, and the remaining ones are sorted from the end of
+//! the document to its start, so applying them one by one in that order never shifts
+//! the range of the next. Insertions at the same position keep the order they were
+//! added in, which is the order clients insert their text in.
+//!
+//! Plain text edits produce the `changes` map every client understands; file
+//! operations and change annotations need `documentChanges`, so a builder using them
+//! produces that form instead.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+use tower_lsp_server::jsonrpc;
+use tower_lsp_server::ls_types::{
+    AnnotatedTextEdit, ChangeAnnotation, ChangeAnnotationIdentifier, DocumentChangeOperation,
+    DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, Position, Range, ResourceOp,
+    TextDocumentEdit, TextEdit, Uri, WorkspaceEdit,
+};
+
+/// Two edits of a document that change the same text.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+    "edits of {uri} overlap at {}:{} and {}:{}",
+    .first.start.line,
+    .first.start.character,
+    .second.start.line,
+    .second.start.character
+)]
+pub struct OverlappingEdits {
+    /// The document
+    pub uri: Uri,
+    /// Range of the edit added first
+    pub first: Range,
+    /// Range of the edit added second
+    pub second: Range,
+}
+
+impl From<OverlappingEdits> for jsonrpc::Error {
+    fn from(err: OverlappingEdits) -> Self {
+        Self {
+            message: err.to_string().into(),
+            ..Self::internal_error()
+        }
+    }
+}
+
+/// A text edit with the identifier of the annotation describing it.
+type AnnotatedEdit = (TextEdit, Option<ChangeAnnotationIdentifier>);
+
+/// A step of the edit, in the order it was added.
+#[derive(Debug)]
+enum Step {
+    /// The text edits of a document, by index into the documents
+    Edits(usize),
+    /// A file operation
+    Operation(ResourceOp),
+}
+
+/// Builder of a [`WorkspaceEdit`] checking its text edits for overlaps.
+#[derive(Debug, Default)]
+pub struct WorkspaceEditBuilder {
+    /// The edited documents, with their edits in the order they were added
+    documents: Vec<(Uri, Vec<AnnotatedEdit>)>,
+    /// Edits and file operations, in the order they were added
+    steps: Vec<Step>,
+    /// Change annotations, by identifier
+    annotations: HashMap<ChangeAnnotationIdentifier, ChangeAnnotation>,
+}
+
+impl WorkspaceEditBuilder {
+    /// Create an empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace a range of a document.
+    pub fn replace(&mut self, uri: &Uri, range: Range, new_text: impl Into<String>) -> &mut Self {
+        self.push(uri, range, new_text.into(), None)
+    }
+
+    /// Insert text at a position of a document.
+    pub fn insert(&mut self, uri: &Uri, position: Position, text: impl Into<String>) -> &mut Self {
+        self.replace(uri, Range::new(position, position), text)
+    }
+
+    /// Delete a range of a document.
+    pub fn delete(&mut self, uri: &Uri, range: Range) -> &mut Self {
+        self.replace(uri, range, String::new())
+    }
+
+    /// Add text edits of a document computed elsewhere.
+    pub fn extend(&mut self, uri: &Uri, edits: impl IntoIterator<Item = TextEdit>) -> &mut Self {
+        for edit in edits {
+            self.push(uri, edit.range, edit.new_text, None);
+        }
+        self
+    }
+
+    /// Replace a range of a document with an edit described by an annotation.
+    ///
+    /// The annotation must be registered with [`Self::annotation`].
+    pub fn replace_annotated(
+        &mut self,
+        uri: &Uri,
+        range: Range,
+        new_text: impl Into<String>,
+        annotation: &str,
+    ) -> &mut Self {
+        self.push(uri, range, new_text.into(), Some(annotation.to_string()))
+    }
+
+    /// Register a change annotation under an identifier.
+    pub fn annotation(&mut self, id: &str, annotation: ChangeAnnotation) -> &mut Self {
+        self.annotations.insert(id.to_string(), annotation);
+        self
+    }
+
+    /// Create, rename or delete a file.
+    pub fn operation(&mut self, operation: ResourceOp) -> &mut Self {
+        self.steps.push(Step::Operation(operation));
+        self
+    }
+
+    /// Check if nothing was added to the builder.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Build the workspace edit.
+    ///
+    /// Fails if two text edits of a document overlap. An insertion overlaps an edit
+    /// whose range strictly contains its position, while insertions at the same
+    /// position, and edits that merely touch, don't overlap.
+    pub fn build(self) -> Result<WorkspaceEdit, OverlappingEdits> {
+        let mut documents = Vec::with_capacity(self.documents.len());
+        for (uri, mut edits) in self.documents {
+            for (index, (edit, _)) in edits.iter().enumerate() {
+                let first = edit.range;
+                if let Some((second, _)) = edits[index + 1..]
+                    .iter()
+                    .find(|(other, _)| overlaps(first, other.range))
+                {
+                    return Err(OverlappingEdits {
+                        uri,
+                        first,
+                        second: second.range,
+                    });
+                }
+            }
+            // Stable, so edits at the same position keep their order
+            edits.sort_by(|(a, _), (b, _)| b.range.start.cmp(&a.range.start));
+            documents.push(Some((uri, edits)));
+        }
+
+        let plain = self.annotations.is_empty()
+            && self.steps.iter().all(|step| matches!(step, Step::Edits(_)));
+        if plain {
+            let changes = documents
+                .into_iter()
+                .flatten()
+                .map(|(uri, edits)| (uri, edits.into_iter().map(|(edit, _)| edit).collect()))
+                .collect();
+            return Ok(WorkspaceEdit::new(changes));
+        }
+
+        let operations = self
+            .steps
+            .into_iter()
+            .filter_map(|step| match step {
+                Step::Operation(operation) => Some(DocumentChangeOperation::Op(operation)),
+                Step::Edits(index) => {
+                    let (uri, edits) = documents[index].take()?;
+                    Some(DocumentChangeOperation::Edit(TextDocumentEdit {
+                        text_document: OptionalVersionedTextDocumentIdentifier {
+                            uri,
+                            version: None,
+                        },
+                        edits: edits
+                            .into_iter()
+                            .map(|(text_edit, annotation)| match annotation {
+                                Some(annotation_id) => OneOf::Right(AnnotatedTextEdit {
+                                    text_edit,
+                                    annotation_id,
+                                }),
+                                None => OneOf::Left(text_edit),
+                            })
+                            .collect(),
+                    }))
+                }
+            })
+            .collect();
+        Ok(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            change_annotations: (!self.annotations.is_empty()).then_some(self.annotations),
+            ..Default::default()
+        })
+    }
+
+    /// Add a text edit to the edits of its document.
+    fn push(
+        &mut self,
+        uri: &Uri,
+        range: Range,
+        new_text: String,
+        annotation: Option<ChangeAnnotationIdentifier>,
+    ) -> &mut Self {
+        let edit = (TextEdit { range, new_text }, annotation);
+        match self
+            .documents
+            .iter_mut()
+            .position(|(known, _)| known == uri)
+        {
+            Some(index) => self.documents[index].1.push(edit),
+            None => {
+                self.steps.push(Step::Edits(self.documents.len()));
+                self.documents.push((uri.clone(), vec![edit]));
+            }
+        }
+        self
+    }
+}
+
+/// The text edits of a workspace edit per document, in the order they apply.
+///
+/// Edits come from `changes` and from the text document edits of `documentChanges`,
+/// with their annotations dropped; file operations are skipped. A document edited by
+/// several text document edits appears once for each of them.
+pub fn text_edits(edit: &WorkspaceEdit) -> Vec<(Uri, Vec<TextEdit>)> {
+    let mut documents = edit
+        .changes
+        .iter()
+        .flatten()
+        .map(|(uri, edits)| (uri.clone(), edits.clone()))
+        .collect::<Vec<_>>();
+    let document_edits = match &edit.document_changes {
+        None => Vec::new(),
+        Some(DocumentChanges::Edits(edits)) => edits.iter().collect(),
+        Some(DocumentChanges::Operations(operations)) => operations
+            .iter()
+            .filter_map(|operation| match operation {
+                DocumentChangeOperation::Edit(edit) => Some(edit),
+                DocumentChangeOperation::Op(_) => None,
+            })
+            .collect(),
+    };
+    documents.extend(document_edits.into_iter().map(|edit| {
+        let edits = edit
+            .edits
+            .iter()
+            .map(|edit| match edit {
+                OneOf::Left(text_edit) => text_edit.clone(),
+                OneOf::Right(annotated) => annotated.text_edit.clone(),
+            })
+            .collect();
+        (edit.text_document.uri.clone(), edits)
+    }));
+    documents
+}
+
+/// Check if two edit ranges change the same text.
+fn overlaps(a: Range, b: Range) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use tower_lsp_server::ls_types::CreateFile;
+
+    use super::*;
+
+    fn range(start: u32, end: u32) -> Range {
+        Range::new(Position::new(0, start), Position::new(0, end))
+    }
+
+    fn uri(name: &str) -> Uri {
+        Uri::from_str(&format!("file:///{name}.l")).expect("valid URI")
+    }
+
+    #[test]
+    fn edits_are_sorted_from_the_end_keeping_insertions_in_order() {
+        let main = uri("main");
+        let mut builder = WorkspaceEditBuilder::new();
+        builder
+            .replace(&main, range(0, 3), "fn")
+            .insert(&main, Position::new(0, 10), "a")
+            .replace(&main, range(3, 5), "b")
+            .insert(&main, Position::new(0, 10), "c");
+        let edit = builder.build().expect("no overlap");
+        let changes = edit.changes.expect("plain edits");
+        let texts = changes[&main]
+            .iter()
+            .map(|edit| edit.new_text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["a", "c", "b", "fn"]);
+    }
+
+    #[test]
+    fn overlapping_edits_are_rejected() {
+        let main = uri("main");
+        let mut builder = WorkspaceEditBuilder::new();
+        builder
+            .replace(&main, range(0, 5), "x")
+            .insert(&main, Position::new(0, 3), "y");
+        let err = builder
+            .build()
+            .expect_err("the insertion is inside the replacement");
+        assert_eq!(err.first, range(0, 5));
+        assert_eq!(err.second, range(3, 3));
+
+        let mut builder = WorkspaceEditBuilder::new();
+        builder
+            .replace(&main, range(0, 5), "x")
+            .replace(&uri("other"), range(2, 4), "y");
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn file_operations_produce_document_changes_in_order() {
+        let created = uri("created");
+        let mut builder = WorkspaceEditBuilder::new();
+        builder
+            .operation(ResourceOp::Create(CreateFile {
+                uri: created.clone(),
+                options: None,
+                annotation_id: None,
+            }))
+            .insert(&created, Position::new(0, 0), "fn main() {}")
+            .annotation(
+                "generated",
+                ChangeAnnotation {
+                    label: "Generated".to_string(),
+                    needs_confirmation: None,
+                    description: None,
+                },
+            )
+            .replace_annotated(&uri("main"), range(0, 2), "x", "generated");
+        let edit = builder.build().expect("no overlap");
+        assert!(edit.changes.is_none());
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected document changes");
+        };
+        assert!(matches!(
+            operations[0],
+            DocumentChangeOperation::Op(ResourceOp::Create(_))
+        ));
+        assert!(matches!(
+            &operations[2],
+            DocumentChangeOperation::Edit(edit) if matches!(edit.edits[0], OneOf::Right(_))
+        ));
+        assert!(
+            edit.change_annotations
+                .is_some_and(|annotations| annotations.len() == 1)
+        );
+    }
+    #[test]
+    fn text_edits_are_read_from_either_form() {
+        let main = uri("main");
+        let mut builder = WorkspaceEditBuilder::new();
+        builder.replace(&main, range(0, 2), "x");
+        let plain = builder.build().expect("no overlap");
+        let expected = vec![(
+            main.clone(),
+            vec![TextEdit {
+                range: range(0, 2),
+                new_text: "x".to_string(),
+            }],
+        )];
+        assert_eq!(text_edits(&plain), expected);
+
+        let mut builder = WorkspaceEditBuilder::new();
+        builder
+            .operation(ResourceOp::Create(CreateFile {
+                uri: uri("created"),
+                options: None,
+                annotation_id: None,
+            }))
+            .annotation(
+                "renamed",
+                ChangeAnnotation {
+                    label: "Renamed".to_string(),
+                    needs_confirmation: None,
+                    description: None,
+                },
+            )
+            .replace_annotated(&main, range(0, 2), "x", "renamed");
+        let annotated = builder.build().expect("no overlap");
+        assert!(annotated.changes.is_none());
+        assert_eq!(text_edits(&annotated), expected);
+    }
+}
@@ -0,0 +1,256 @@
+//! A minimal LSP client driving the server binary over stdio.
+//!
+//! Integration tests start the server with [`TestServer::start`], which performs the
+//! initialize handshake with a workspace folder, and then exchange JSON-RPC messages
+//! with it. Requests the server sends to the client are answered with `null`, and
+//! notifications are kept until a test waits for them.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+/// How long to wait for a message from the server before failing the test.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Counter keeping the cache directories of concurrently running servers apart.
+static SERVERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Path of the sample project shared by the integration tests.
+pub fn sample_project() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/sample-project")
+}
+
+/// The `file` URI of a path.
+pub fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{path}")
+    } else {
+        format!("file:///{path}")
+    }
+}
+
+/// Find the LSP position of the `nth` occurrence of `needle` in `text`.
+pub fn position_of(text: &str, needle: &str, nth: usize) -> Value {
+    let offset = text
+        .match_indices(needle)
+        .nth(nth)
+        .unwrap_or_else(|| panic!("`{needle}` occurs fewer than {} times", nth + 1))
+        .0;
+    let line = text[..offset].matches('\n').count();
+    let line_start = text[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let character = text[line_start..offset].encode_utf16().count();
+    json!({ "line": line, "character": character })
+}
+
+/// A running server and the client side of its connection.
+#[derive(Debug)]
+pub struct TestServer {
+    /// The server process
+    child: Child,
+    /// Standard input of the server, where messages are written
+    stdin: ChildStdin,
+    /// Messages read from the standard output of the server
+    messages: Receiver<Value>,
+    /// Notifications received while waiting for a response
+    notifications: Vec<Value>,
+    /// Id of the next request
+    next_id: i64,
+    /// Cache directory given to the server, removed on drop
+    cache: PathBuf,
+}
+
+impl TestServer {
+    /// Start the server and initialize it with `root` as the only workspace folder.
+    pub fn start(root: &Path) -> Self {
+        let cache = std::env::temp_dir().join(format!(
+            "l-language-server-test-{}-{}",
+            std::process::id(),
+            SERVERS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut child = Command::new(env!("CARGO_BIN_EXE_l-language-server"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("the server binary starts");
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, messages) = channel();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Some(message) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut server = Self {
+            child,
+            stdin,
+            messages,
+            notifications: Vec::new(),
+            next_id: 1,
+            cache,
+        };
+        let root_uri = file_uri(root);
+        server.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "capabilities": {},
+                "workspaceFolders": [{ "uri": root_uri, "name": "sample-project" }],
+                "initializationOptions": { "cacheDirectory": server.cache },
+            }),
+        );
+        server.notify("initialized", json!({}));
+        server
+    }
+
+    /// Send a request and wait for its result.
+    ///
+    /// Panics if the server answers with an error.
+    pub fn request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self.receive();
+            if message.get("method").is_some() {
+                self.notifications.push(message);
+            } else if message.get("id") == Some(&json!(id)) {
+                if let Some(error) = message.get("error") {
+                    panic!("`{method}` failed: {error}");
+                }
+                return message.get("result").cloned().unwrap_or(Value::Null);
+            }
+        }
+    }
+
+    /// Send a notification.
+    pub fn notify(&mut self, method: &str, params: Value) {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Open a document with the text it has on disk, returning its URI and text.
+    pub fn open(&mut self, path: &Path) -> (String, String) {
+        let uri = file_uri(path);
+        let text = std::fs::read_to_string(path).expect("the fixture is readable");
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "l", "version": 1, "text": text },
+            }),
+        );
+        (uri, text)
+    }
+
+    /// Wait for the diagnostics published for the document whose URI ends with
+    /// `suffix`.
+    pub fn wait_for_diagnostics(&mut self, suffix: &str) -> Vec<Value> {
+        let matches = |notification: &Value| {
+            notification["method"] == "textDocument/publishDiagnostics"
+                && notification["params"]["uri"]
+                    .as_str()
+                    .is_some_and(|uri| uri.ends_with(suffix))
+        };
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            if let Some(index) = self.notifications.iter().position(matches) {
+                let notification = self.notifications.remove(index);
+                return notification["params"]["diagnostics"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let message = self
+                .receive_within(remaining)
+                .unwrap_or_else(|| panic!("no diagnostics were published for `{suffix}`"));
+            self.notifications.push(message);
+        }
+    }
+
+    /// Write a message to the server.
+    fn send(&mut self, message: &Value) {
+        self.try_send(message).expect("the server reads its input");
+    }
+
+    /// Write a message to the server, which may have exited already.
+    fn try_send(&mut self, message: &Value) -> std::io::Result<()> {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.stdin.flush()
+    }
+
+    /// Wait for the next response or notification, answering requests from the server.
+    fn receive(&mut self) -> Value {
+        self.receive_within(TIMEOUT)
+            .expect("the server answers in time")
+    }
+
+    /// Like [`Self::receive`], returning `None` once `timeout` passes.
+    fn receive_within(&mut self, timeout: Duration) -> Option<Value> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let message = match self.messages.recv_timeout(remaining) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => return None,
+                Err(RecvTimeoutError::Disconnected) => panic!("the server exited"),
+            };
+            match (message.get("id"), message.get("method")) {
+                (Some(id), Some(_)) => {
+                    let id = id.clone();
+                    self.send(&json!({ "jsonrpc": "2.0", "id": id, "result": null }));
+                }
+                _ => return Some(message),
+            }
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let id = self.next_id;
+        let _ = self.try_send(&json!({ "jsonrpc": "2.0", "id": id, "method": "shutdown" }));
+        let _ = self.try_send(&json!({ "jsonrpc": "2.0", "method": "exit" }));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let _ = self.child.kill();
+        let _ = std::fs::remove_dir_all(&self.cache);
+    }
+}
+
+/// Read one message framed with a `Content-Length` header.
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
@@ -0,0 +1,241 @@
+//! Snapshots of what the server answers for the fixtures in `testdata/snapshots`.
+//!
+//! Each fixture is opened in a fresh backend, and its published diagnostics, semantic
+//! tokens, inlay hints and formatted text are rendered as plain text, one item per line,
+//! with the text each range covers. A regression in the offset math or the order of the
+//! token legend changes the rendered text, so it shows up in the diff of the snapshot.
+//! Run `cargo insta review` to inspect and accept changed snapshots.
+
+use std::path::Path;
+
+use insta::{assert_snapshot, glob};
+use serde_json::{Value, json};
+
+use crate::{Connection, URI};
+
+/// Capabilities of a client supporting semantic tokens and inlay hints, so that the
+/// server doesn't fall back to decorations and hint diagnostics, and counting
+/// characters in Unicode scalar values.
+fn capabilities() -> Value {
+    json!({
+        "general": { "positionEncodings": ["utf-32"] },
+        "textDocument": {
+            "semanticTokens": {
+                "requests": { "full": true },
+                "tokenTypes": [],
+                "tokenModifiers": [],
+                "formats": ["relative"],
+            },
+            "inlayHint": {},
+        },
+    })
+}
+
+/// Open a fixture in a fresh backend, waiting until it is analyzed.
+///
+/// Returns the connection, the `initialize` result and the text of the fixture.
+async fn open(path: &Path) -> (Connection, Value, String) {
+    let text = std::fs::read_to_string(path).expect("the fixture is readable");
+    let (mut connection, initialize) = Connection::initialized_with(capabilities()).await;
+    connection.open(&text).await;
+    (connection, initialize, text)
+}
+
+/// Run a future to completion on a runtime of its own, for the synchronous `glob!`.
+fn block_on<T>(future: impl Future<Output = T>) -> T {
+    tokio::runtime::Runtime::new()
+        .expect("the runtime starts")
+        .block_on(future)
+}
+
+/// Byte offset of an LSP position in `text`, with characters counted in Unicode scalar
+/// values like the server does.
+fn offset_of(text: &str, position: &Value) -> usize {
+    let line = usize::try_from(position["line"].as_u64().unwrap_or_default()).unwrap_or_default();
+    let character =
+        usize::try_from(position["character"].as_u64().unwrap_or_default()).unwrap_or_default();
+    let line_start = match line.checked_sub(1) {
+        Some(previous) => text
+            .match_indices('\n')
+            .nth(previous)
+            .map_or(text.len(), |(index, _)| index + 1),
+        None => 0,
+    };
+    text[line_start..]
+        .char_indices()
+        .nth(character)
+        .map_or(text.len(), |(index, _)| line_start + index)
+}
+
+/// Render a range as `line:character-line:character` followed by the text it covers.
+fn render_range(text: &str, range: &Value) -> String {
+    let (start, end) = (&range["start"], &range["end"]);
+    let covered = text
+        .get(offset_of(text, start)..offset_of(text, end))
+        .unwrap_or("<invalid range>");
+    format!(
+        "{}:{}-{}:{} {covered:?}",
+        start["line"], start["character"], end["line"], end["character"]
+    )
+}
+
+#[test]
+fn diagnostics() {
+    glob!("../../testdata/snapshots", "*.l", |path| {
+        let rendered = block_on(async {
+            let (mut connection, _, text) = open(path).await;
+            let params = connection
+                .notification("textDocument/publishDiagnostics")
+                .await;
+            let diagnostics = params["diagnostics"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            diagnostics
+                .iter()
+                .map(|diagnostic| {
+                    format!(
+                        "{} severity={} code={} {}",
+                        render_range(&text, &diagnostic["range"]),
+                        diagnostic["severity"],
+                        diagnostic["code"],
+                        diagnostic["message"]
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+        assert_snapshot!(rendered);
+    });
+}
+
+#[test]
+fn semantic_tokens() {
+    glob!("../../testdata/snapshots", "*.l", |path| {
+        let rendered = block_on(async {
+            let (mut connection, initialize, text) = open(path).await;
+            connection
+                .notification("textDocument/publishDiagnostics")
+                .await;
+            let legend =
+                &initialize["capabilities"]["semanticTokensProvider"]["legend"]["tokenTypes"];
+            let tokens = connection
+                .request(
+                    "textDocument/semanticTokens/full",
+                    json!({ "textDocument": { "uri": URI } }),
+                )
+                .await;
+            let data = tokens["data"].as_array().cloned().unwrap_or_default();
+            let data = data.iter().filter_map(Value::as_u64).collect::<Vec<_>>();
+            let (mut line, mut character) = (0, 0);
+            let mut lines = Vec::new();
+            for token in data.chunks_exact(5) {
+                let &[delta_line, delta_start, length, token_type, _] = token else {
+                    unreachable!("the chunks have five elements");
+                };
+                if delta_line > 0 {
+                    character = 0;
+                }
+                line += delta_line;
+                character += delta_start;
+                let range = json!({
+                    "start": { "line": line, "character": character },
+                    "end": { "line": line, "character": character + length },
+                });
+                let token_type = usize::try_from(token_type).unwrap_or(usize::MAX);
+                lines.push(format!(
+                    "{} {}",
+                    render_range(&text, &range),
+                    legend[token_type].as_str().unwrap_or("<unknown type>")
+                ));
+            }
+            lines.join("\n")
+        });
+        assert_snapshot!(rendered);
+    });
+}
+
+#[test]
+fn inlay_hints() {
+    glob!("../../testdata/snapshots", "*.l", |path| {
+        let rendered = block_on(async {
+            let (mut connection, _, text) = open(path).await;
+            connection
+                .notification("textDocument/publishDiagnostics")
+                .await;
+            let end_line = text.lines().count();
+            let hints = connection
+                .request(
+                    "textDocument/inlayHint",
+                    json!({
+                        "textDocument": { "uri": URI },
+                        "range": {
+                            "start": { "line": 0, "character": 0 },
+                            "end": { "line": end_line, "character": 0 },
+                        },
+                    }),
+                )
+                .await;
+            let hints = hints.as_array().cloned().unwrap_or_default();
+            hints
+                .iter()
+                .map(|hint| {
+                    let label = match &hint["label"] {
+                        Value::Array(parts) => parts
+                            .iter()
+                            .filter_map(|part| part["value"].as_str())
+                            .collect(),
+                        label => label.as_str().unwrap_or_default().to_string(),
+                    };
+                    let position = &hint["position"];
+                    format!("{}:{} {label:?}", position["line"], position["character"])
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+        assert_snapshot!(rendered);
+    });
+}
+
+#[test]
+fn formatting() {
+    glob!("../../testdata/snapshots", "*.l", |path| {
+        let formatted = block_on(async {
+            let (mut connection, _, text) = open(path).await;
+            connection
+                .notification("textDocument/publishDiagnostics")
+                .await;
+            let edits = connection
+                .request(
+                    "textDocument/formatting",
+                    json!({
+                        "textDocument": { "uri": URI },
+                        "options": { "tabSize": 4, "insertSpaces": true },
+                    }),
+                )
+                .await;
+            let mut edits = edits
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+                .iter()
+                .map(|edit| {
+                    let range = &edit["range"];
+                    let span = offset_of(&text, &range["start"])..offset_of(&text, &range["end"]);
+                    (
+                        span,
+                        edit["newText"].as_str().unwrap_or_default().to_string(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            // Apply the edits from the end, so the offsets of the others stay valid
+            edits.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+            let mut formatted = text;
+            for (span, new_text) in edits {
+                formatted.replace_range(span, &new_text);
+            }
+            formatted
+        });
+        assert_snapshot!(formatted);
+    });
+}
@@ -0,0 +1,246 @@
+//! End-to-end tests of the protocol wiring, serving the backend in process.
+//!
+//! The `LspService` of [`Backend`] is served over an in-memory duplex stream, and the
+//! tests drive it with raw JSON-RPC messages the way a client would: `initialize`, then
+//! `textDocument/didOpen`, then the requests under test. Unlike the tests running the
+//! binary, failures point at a handler instead of a process that stopped answering, and
+//! the tests need no workspace on disk.
+//!
+//! [`protocol`] checks the handshake and the language features one request at a time,
+//! and [`fixtures`] records what the server answers for the `.l` files of
+//! `testdata/snapshots` in `insta` snapshots.
+
+mod fixtures;
+mod protocol;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use l_language_server::{Backend, Lifecycle, Metrics};
+use serde_json::{Value, json};
+use tokio::io::{
+    AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream, ReadHalf, WriteHalf,
+};
+use tokio::time::timeout;
+use tower_lsp_server::Server;
+
+/// How long to wait for a message from the server before failing the test.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Counter keeping the cache directories of concurrently running backends apart.
+static BACKENDS: AtomicUsize = AtomicUsize::new(0);
+
+/// URI of the document the tests open.
+const URI: &str = "file:///workspace/main.l";
+
+/// The client side of a connection to a backend served in process.
+struct Connection {
+    /// Messages from the server
+    reader: BufReader<ReadHalf<DuplexStream>>,
+    /// Messages to the server
+    writer: WriteHalf<DuplexStream>,
+    /// Notifications received while waiting for a response
+    notifications: Vec<Value>,
+    /// Id of the next request
+    next_id: i64,
+    /// Cache directory given to the backend, removed on drop
+    cache: PathBuf,
+}
+
+impl Connection {
+    /// Serve a new backend on one end of a duplex stream, returning the other end.
+    fn start() -> Self {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (service, socket) = Backend::service(|client| {
+            Backend::new(
+                client,
+                false,
+                Arc::new(Lifecycle::default()),
+                Arc::new(Metrics::default()),
+            )
+        });
+        let (input, output) = tokio::io::split(server);
+        tokio::spawn(Server::new(input, output, socket).serve(service));
+        let (reader, writer) = tokio::io::split(client);
+        Self {
+            reader: BufReader::new(reader),
+            writer,
+            notifications: Vec::new(),
+            next_id: 1,
+            cache: std::env::temp_dir().join(format!(
+                "l-language-server-in-process-{}-{}",
+                std::process::id(),
+                BACKENDS.fetch_add(1, Ordering::Relaxed)
+            )),
+        }
+    }
+
+    /// Start a backend and complete the initialize handshake.
+    async fn initialized() -> (Self, Value) {
+        Self::initialized_with(json!({})).await
+    }
+
+    /// Start a backend and complete the initialize handshake for a client with
+    /// `capabilities`.
+    ///
+    /// The backend caches in a temporary directory of its own, so tests don't write to
+    /// the cache of the user running them.
+    async fn initialized_with(capabilities: Value) -> (Self, Value) {
+        let mut connection = Self::start();
+        let result = connection.initialize(capabilities, json!(null)).await;
+        (connection, result)
+    }
+
+    /// Start a backend caching in `cache` and complete the initialize handshake for the
+    /// workspace folder at `root`.
+    ///
+    /// Backends started with the same `cache` share what they persist, so a test can
+    /// reopen a workspace the way a client does in its next session.
+    async fn initialized_in(root: &Path, cache: &Path) -> (Self, Value) {
+        let mut connection = Self::start();
+        connection.cache = cache.to_path_buf();
+        let folders = json!([{ "uri": file_uri(root), "name": "workspace" }]);
+        let result = connection.initialize(json!({}), folders).await;
+        (connection, result)
+    }
+
+    /// Send `initialize` and `initialized`, returning the result of `initialize`.
+    async fn initialize(&mut self, capabilities: Value, workspace_folders: Value) -> Value {
+        let result = self
+            .request(
+                "initialize",
+                json!({
+                    "processId": null,
+                    "rootUri": null,
+                    "workspaceFolders": workspace_folders,
+                    "capabilities": capabilities,
+                    "initializationOptions": { "cacheDirectory": self.cache },
+                }),
+            )
+            .await;
+        self.notify("initialized", json!({})).await;
+        result
+    }
+
+    /// Open [`URI`] with `text`.
+    async fn open(&mut self, text: &str) {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": URI, "languageId": "l", "version": 1, "text": text },
+            }),
+        )
+        .await;
+    }
+
+    /// Send a request and wait for its result.
+    ///
+    /// Panics if the server answers with an error.
+    async fn request(&mut self, method: &str, params: Value) -> Value {
+        let response = self.call(method, params).await;
+        if let Some(error) = response.get("error") {
+            panic!("`{method}` failed: {error}");
+        }
+        response.get("result").cloned().unwrap_or(Value::Null)
+    }
+
+    /// Send a request and wait for the whole response message.
+    async fn call(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .await;
+        loop {
+            let message = self.receive().await;
+            if message.get("method").is_some() {
+                self.notifications.push(message);
+            } else if message.get("id") == Some(&json!(id)) {
+                return message;
+            }
+        }
+    }
+
+    /// Send a notification.
+    async fn notify(&mut self, method: &str, params: Value) {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+            .await;
+    }
+
+    /// Wait for the next notification with `method`, returning its parameters.
+    async fn notification(&mut self, method: &str) -> Value {
+        loop {
+            if let Some(index) = self
+                .notifications
+                .iter()
+                .position(|notification| notification["method"] == method)
+            {
+                return self.notifications.remove(index)["params"].take();
+            }
+            let message = self.receive().await;
+            self.notifications.push(message);
+        }
+    }
+
+    /// Write a message framed with a `Content-Length` header.
+    async fn send(&mut self, message: &Value) {
+        let body = message.to_string();
+        let framed = format!("Content-Length: {}\r\n\r\n{body}", body.len());
+        self.writer
+            .write_all(framed.as_bytes())
+            .await
+            .expect("the server reads its input");
+    }
+
+    /// Wait for the next response or notification, answering requests from the server
+    /// with `null`.
+    async fn receive(&mut self) -> Value {
+        loop {
+            let message = timeout(TIMEOUT, self.read_message())
+                .await
+                .expect("the server answers in time")
+                .expect("the server is still running");
+            match (message.get("id"), message.get("method")) {
+                (Some(id), Some(_)) => {
+                    let id = id.clone();
+                    self.send(&json!({ "jsonrpc": "2.0", "id": id, "result": null }))
+                        .await;
+                }
+                _ => return message,
+            }
+        }
+    }
+
+    /// Read one message framed with a `Content-Length` header.
+    async fn read_message(&mut self) -> Option<Value> {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).await.ok()? == 0 {
+                return None;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+        let mut body = vec![0; length?];
+        self.reader.read_exact(&mut body).await.ok()?;
+        serde_json::from_slice(&body).ok()
+    }
+}
+
+/// The `file` URI of the absolute `path`.
+fn file_uri(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.cache);
+    }
+}
@@ -0,0 +1,219 @@
+//! The handshake, the routing of methods and the standard language features.
+
+use serde_json::{Value, json};
+
+use crate::{Connection, URI, file_uri};
+
+/// The document the tests open, without errors.
+const TEXT: &str = "struct Point {
+    x: int,
+    y: int,
+}
+
+fn norm(p: Point) -> int {
+    return p.x + p.y;
+}
+
+fn main() -> int {
+    let origin = Point { x: 0, y: 0 };
+    return norm(origin);
+}
+";
+
+/// Find the LSP position of the first occurrence of `needle` in [`TEXT`].
+fn position_of(needle: &str) -> Value {
+    let offset = TEXT.find(needle).expect("the needle occurs in the text");
+    let line = TEXT[..offset].matches('\n').count();
+    let line_start = TEXT[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let character = TEXT[line_start..offset].encode_utf16().count();
+    json!({ "line": line, "character": character })
+}
+
+#[tokio::test]
+async fn requests_before_initialize_are_rejected() {
+    let mut connection = Connection::start();
+    let response = connection
+        .call(
+            "textDocument/hover",
+            json!({ "textDocument": { "uri": URI }, "position": { "line": 0, "character": 0 } }),
+        )
+        .await;
+    assert_eq!(response["error"]["code"], -32002, "{response}");
+}
+
+#[tokio::test]
+async fn initialize_reports_the_server_and_its_capabilities() {
+    let (_connection, result) = Connection::initialized().await;
+    assert_eq!(result["serverInfo"]["name"], "l-language-server");
+    assert_eq!(result["capabilities"]["positionEncoding"], "utf-16");
+    let capabilities = &result["capabilities"];
+    for provider in ["completionProvider", "definitionProvider", "hoverProvider"] {
+        assert!(
+            !capabilities[provider].is_null(),
+            "{provider} is missing: {capabilities}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn unknown_methods_are_not_found() {
+    let (mut connection, _) = Connection::initialized().await;
+    let response = connection.call("l/noSuchMethod", json!({})).await;
+    assert_eq!(response["error"]["code"], -32601, "{response}");
+}
+
+#[tokio::test]
+async fn opened_documents_are_analyzed() {
+    let (mut connection, _) = Connection::initialized().await;
+    connection.open(TEXT).await;
+    let params = connection
+        .notification("textDocument/publishDiagnostics")
+        .await;
+    assert_eq!(params["uri"], URI);
+    assert_eq!(params["diagnostics"], json!([]), "{params}");
+
+    connection
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": TEXT.replace("norm(origin)", "norm(orgin)") }],
+            }),
+        )
+        .await;
+    let params = connection
+        .notification("textDocument/publishDiagnostics")
+        .await;
+    let messages = params["diagnostics"].to_string();
+    assert!(messages.contains("orgin"), "{params}");
+}
+
+#[tokio::test]
+async fn completion_lists_the_fields_of_a_struct() {
+    let (mut connection, _) = Connection::initialized().await;
+    connection.open(TEXT).await;
+    connection
+        .notification("textDocument/publishDiagnostics")
+        .await;
+
+    let result = connection
+        .request(
+            "textDocument/completion",
+            json!({
+                "textDocument": { "uri": URI },
+                "position": position_of("x + p.y"),
+            }),
+        )
+        .await;
+    let items = result
+        .get("items")
+        .unwrap_or(&result)
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let labels = items
+        .iter()
+        .filter_map(|item| item["label"].as_str())
+        .collect::<Vec<_>>();
+    assert!(labels.contains(&"x") && labels.contains(&"y"), "{labels:?}");
+}
+
+#[tokio::test]
+async fn definition_and_hover_resolve_symbols() {
+    let (mut connection, _) = Connection::initialized().await;
+    connection.open(TEXT).await;
+    connection
+        .notification("textDocument/publishDiagnostics")
+        .await;
+
+    let definition = connection
+        .request(
+            "textDocument/definition",
+            json!({
+                "textDocument": { "uri": URI },
+                "position": position_of("norm(origin)"),
+            }),
+        )
+        .await;
+    let location = match &definition {
+        Value::Array(locations) => &locations[0],
+        location => location,
+    };
+    let range = location
+        .get("targetSelectionRange")
+        .or_else(|| location.get("range"))
+        .expect("the definition has a range");
+    assert_eq!(range["start"], position_of("norm(p"));
+
+    let hover = connection
+        .request(
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": URI },
+                "position": position_of("origin)"),
+            }),
+        )
+        .await;
+    assert!(hover["contents"].to_string().contains("Point"), "{hover}");
+}
+
+#[tokio::test]
+async fn workspace_symbols_follow_renamed_files() {
+    let (mut connection, _) = Connection::initialized().await;
+    connection.open(TEXT).await;
+    connection
+        .notification("textDocument/publishDiagnostics")
+        .await;
+
+    let renamed = "file:///workspace/renamed.l";
+    connection
+        .notify(
+            "workspace/didRenameFiles",
+            json!({ "files": [{ "oldUri": URI, "newUri": renamed }] }),
+        )
+        .await;
+    let symbols = connection
+        .request("workspace/symbol", json!({ "query": "norm" }))
+        .await;
+    let uris = symbols
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .iter()
+        .map(|symbol| symbol["location"]["uri"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(uris, [json!(renamed)], "{symbols}");
+}
+
+#[tokio::test]
+async fn files_with_only_hint_diagnostics_are_analyzed_again_when_reopened() {
+    let root =
+        std::env::temp_dir().join(format!("l-language-server-reopened-{}", std::process::id()));
+    let cache = root.join("cache");
+    std::fs::create_dir_all(&root).expect("the workspace is created");
+    // Compiles cleanly, but a client without inlay hints gets the type of `x` as a
+    // hint diagnostic
+    std::fs::write(
+        root.join("main.l"),
+        "fn main() -> int {\n    let x = 1;\n    x\n}\n",
+    )
+    .expect("the source file is written");
+
+    let (mut first, _) = Connection::initialized_in(&root, &cache).await;
+    let published = first.notification("textDocument/publishDiagnostics").await;
+    assert!(
+        !published["diagnostics"]
+            .as_array()
+            .is_none_or(Vec::is_empty),
+        "{published}"
+    );
+    first.request("shutdown", Value::Null).await;
+
+    let (mut second, _) = Connection::initialized_in(&root, &cache).await;
+    let republished = second.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(republished["uri"], json!(file_uri(&root.join("main.l"))));
+    assert_eq!(republished["diagnostics"], published["diagnostics"]);
+
+    drop((first, second));
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,132 @@
+//! End-to-end tests running the server on `testdata/sample-project`.
+//!
+//! L has no imports yet, so every file of the sample project is analyzed on its own;
+//! cross-file definition and rename get tests here once the server resolves symbols
+//! across files. Workspace symbols are searched in the symbol index of every indexed
+//! file, so they already span the whole project.
+
+mod common;
+
+use common::{TestServer, position_of, sample_project};
+use serde_json::{Value, json};
+
+/// Keep the diagnostics with the error severity.
+fn errors(diagnostics: &[Value]) -> Vec<&Value> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic["severity"] == 1)
+        .collect()
+}
+
+#[test]
+fn workspace_diagnostics_report_the_intentional_errors() {
+    let mut server = TestServer::start(&sample_project());
+
+    let diagnostics = server.wait_for_diagnostics("src/errors.l");
+    let messages = errors(&diagnostics)
+        .iter()
+        .filter_map(|diagnostic| diagnostic["message"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert!(messages.contains("step"), "undefined variable: {messages}");
+    assert!(
+        messages.contains("incremnt"),
+        "undefined function: {messages}"
+    );
+
+    for file in ["src/main.l", "src/geometry.l", "src/shapes/rectangle.l"] {
+        let diagnostics = server.wait_for_diagnostics(file);
+        assert!(errors(&diagnostics).is_empty(), "{file}: {diagnostics:?}");
+    }
+}
+
+#[test]
+fn workspace_symbols_come_from_every_indexed_file() {
+    let mut server = TestServer::start(&sample_project());
+    for file in [
+        "src/main.l",
+        "src/geometry.l",
+        "src/shapes/rectangle.l",
+        "src/errors.l",
+    ] {
+        server.wait_for_diagnostics(file);
+    }
+
+    let symbols = server.request("workspace/symbol", json!({ "query": "Point" }));
+    let mut files = symbols
+        .as_array()
+        .expect("the symbols are a list")
+        .iter()
+        .filter(|symbol| symbol["name"] == "Point")
+        .filter_map(|symbol| symbol["location"]["uri"].as_str())
+        .map(|uri| uri.rsplit_once("/src/").map_or(uri, |(_, file)| file))
+        .collect::<Vec<_>>();
+    files.sort_unstable();
+    assert_eq!(
+        files,
+        ["geometry.l", "main.l", "shapes/rectangle.l"],
+        "{symbols}"
+    );
+
+    let symbols = server.request("workspace/symbol", json!({ "query": "area" }));
+    let names = symbols
+        .as_array()
+        .expect("the symbols are a list")
+        .iter()
+        .filter_map(|symbol| symbol["name"].as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["area"], "{symbols}");
+}
+
+#[test]
+fn definition_of_a_call_is_the_function() {
+    let mut server = TestServer::start(&sample_project());
+    let (uri, text) = server.open(&sample_project().join("src/main.l"));
+    server.wait_for_diagnostics("src/main.l");
+
+    let definition = server.request(
+        "textDocument/definition",
+        json!({
+            "textDocument": { "uri": uri },
+            "position": position_of(&text, "offset(start", 0),
+        }),
+    );
+    let location = match &definition {
+        Value::Array(locations) => &locations[0],
+        location => location,
+    };
+    let uri_field = location
+        .get("targetUri")
+        .or_else(|| location.get("uri"))
+        .and_then(Value::as_str)
+        .expect("the definition has a URI");
+    assert!(uri_field.ends_with("src/main.l"), "{definition}");
+    let range = location
+        .get("targetSelectionRange")
+        .or_else(|| location.get("range"))
+        .expect("the definition has a range");
+    assert_eq!(range["start"], position_of(&text, "offset(p", 0));
+}
+
+#[test]
+fn rename_replaces_every_reference_in_the_file() {
+    let mut server = TestServer::start(&sample_project());
+    let (uri, text) = server.open(&sample_project().join("src/main.l"));
+    server.wait_for_diagnostics("src/main.l");
+
+    let edit = server.request(
+        "textDocument/rename",
+        json!({
+            "textDocument": { "uri": uri },
+            "position": position_of(&text, "start", 0),
+            "newName": "origin_point",
+        }),
+    );
+    let changes = edit["changes"].as_object().expect("the rename has changes");
+    assert_eq!(changes.len(), 1, "{edit}");
+    let (changed, edits) = changes.iter().next().expect("one document changes");
+    assert!(changed.ends_with("src/main.l"), "{edit}");
+    let edits = edits.as_array().expect("the document has edits");
+    assert_eq!(edits.len(), text.matches("start").count(), "{edit}");
+    assert!(edits.iter().all(|edit| edit["newText"] == "origin_point"));
+}